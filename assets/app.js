@@ -3,14 +3,42 @@
  * A minimal label → content viewer/editor for Theater's content-addressed store
  */
 
+/**
+ * Glyphs for the file-type categories the server reports per label
+ */
+const LABEL_ICONS = {
+    json: '{}',
+    image: '▣',
+    archive: '▤',
+    wasm: '◆',
+    text: '≡',
+    binary: '▪',
+};
+
+/**
+ * Labels larger than this are paged through with range requests rather than
+ * loaded into the editor whole
+ */
+const LARGE_LABEL_BYTES = 5 * 1024 * 1024;
+const PAGE_BYTES = 256 * 1024;
+
 class StoreViewer {
     constructor() {
         this.labels = [];
         this.currentLabel = null;
+        this.currentRef = null;
+        this.icons = {};
+        this.sizes = {};
+        this.pageDecoder = null;
+        this.store = null; // another registered store being browsed
         this.editor = null;
         this.saveTimeout = null;
         this.isDirty = false;
         this.isLoading = false;
+        this.readOnly = false;
+        // Until chosen, the server's default view decides
+        this.sortOrder = null;
+        this.expiring = {};
     }
 
     /**
@@ -51,8 +79,12 @@ class StoreViewer {
         }
 
         // Load labels from the API
+        await this.loadStores();
         await this.loadLabels();
 
+        // Listen for server events
+        this.connectEvents();
+
         console.log('Store Viewer initialized');
     }
 
@@ -65,6 +97,26 @@ class StoreViewer {
             this.promptCreateLabel();
         });
 
+        // Undo button
+        document.getElementById('undo-btn').addEventListener('click', () => {
+            this.undoLastChange();
+        });
+
+        // Rename button
+        document.getElementById('rename-btn').addEventListener('click', () => {
+            this.promptRenameLabel();
+        });
+
+        // Download button
+        document.getElementById('download-btn').addEventListener('click', () => {
+            this.downloadLabel();
+        });
+
+        // Format button
+        document.getElementById('format-btn').addEventListener('click', () => {
+            this.formatJson();
+        });
+
         // Save button
         document.getElementById('save-btn').addEventListener('click', () => {
             this.saveLabel();
@@ -75,6 +127,17 @@ class StoreViewer {
             this.filterLabels(e.target.value);
         });
 
+        // Store selector
+        document.getElementById('store-select').addEventListener('change', (e) => {
+            this.switchStore(e.target.value);
+        });
+
+        // Sort selector
+        document.getElementById('sort-select').addEventListener('change', (e) => {
+            this.sortOrder = e.target.value;
+            this.loadLabels();
+        });
+
         // Sidebar toggle buttons
         const toggleSidebar = () => {
             const sidebar = document.querySelector('.sidebar');
@@ -102,19 +165,73 @@ class StoreViewer {
         });
     }
 
+    /**
+     * Offer the stores the server knows about, if there is more than one
+     */
+    async loadStores() {
+        try {
+            const response = await this.apiFetch('/api/stores');
+            if (!response.ok) {
+                throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+            }
+            const stores = await response.json();
+            const select = document.getElementById('store-select');
+            select.innerHTML = stores.map(store => {
+                const value = store.default ? '' : this.escapeHtml(store.id);
+                const name = this.escapeHtml(store.name || store.id);
+                return `<option value="${value}">Store: ${name}</option>`;
+            }).join('');
+            select.classList.toggle('hidden', stores.length < 2);
+        } catch (error) {
+            console.error('Failed to load stores:', error);
+        }
+    }
+
+    /**
+     * Browse another store, leaving the open label
+     */
+    async switchStore(store) {
+        if (this.isDirty && this.currentLabel) {
+            await this.saveLabel();
+        }
+        this.store = store || null;
+        this.currentLabel = null;
+        this.currentRef = null;
+        this.isDirty = false;
+        document.getElementById('editor-view').classList.add('hidden');
+        document.getElementById('empty-state').classList.remove('hidden');
+        await this.loadLabels();
+    }
+
+    /**
+     * Path of the label API for the store being browsed
+     */
+    labelsPath() {
+        return this.store ? `/api/stores/${encodeURIComponent(this.store)}/labels` : '/api/labels';
+    }
+
     /**
      * Load all labels from the API
      */
     async loadLabels() {
         try {
             console.log('Loading labels...');
-            const response = await fetch('/api/labels');
+            const sort = this.sortOrder ? `&sort=${this.sortOrder}` : '';
+            const response = await this.apiFetch(`${this.labelsPath()}?details=true${sort}`);
 
             if (!response.ok) {
                 throw new Error(`HTTP ${response.status}: ${response.statusText}`);
             }
 
-            this.labels = await response.json();
+            const listing = await response.json();
+            const items = listing.items;
+            if (!this.sortOrder) {
+                this.sortOrder = listing.sort || 'name';
+                document.getElementById('sort-select').value = this.sortOrder;
+            }
+            this.labels = items.map(item => item.name);
+            this.icons = Object.fromEntries(items.map(item => [item.name, item.icon]));
+            this.sizes = Object.fromEntries(items.map(item => [item.name, item.size_bytes]));
             console.log(`Loaded ${this.labels.length} labels`);
 
             this.renderLabelList();
@@ -150,17 +267,24 @@ class StoreViewer {
             return;
         }
 
-        // Sort labels alphabetically
-        const sorted = [...filtered].sort((a, b) => a.localeCompare(b));
+        // Sort labels alphabetically unless the server already ordered them
+        const sorted = this.sortOrder === 'name'
+            ? [...filtered].sort((a, b) => a.localeCompare(b))
+            : filtered;
 
         // Generate HTML for each label
         const html = sorted.map(label => {
             const isActive = this.currentLabel === label;
             const escapedLabel = this.escapeHtml(label);
+            const expiresAt = this.expiring[label];
+            const title = expiresAt
+                ? `${escapedLabel} (expires ${new Date(expiresAt).toLocaleString()})`
+                : escapedLabel;
             return `
-                <div class="label-item ${isActive ? 'active' : ''}"
+                <div class="label-item ${isActive ? 'active' : ''} ${expiresAt ? 'expiring' : ''}"
                      data-name="${escapedLabel}"
-                     title="${escapedLabel}">
+                     title="${title}">
+                    <span class="label-icon" data-icon="${this.icons[label] || 'text'}">${LABEL_ICONS[this.icons[label]] || LABEL_ICONS.text}</span>
                     <span class="label-name">${escapedLabel}</span>
                 </div>
             `;
@@ -191,11 +315,16 @@ class StoreViewer {
             }
         }
 
+        if (this.sizes[name] > LARGE_LABEL_BYTES) {
+            await this.selectLargeLabel(name);
+            return;
+        }
+
         try {
             console.log(`Selecting label: ${name}`);
             this.isLoading = true;
 
-            const response = await fetch(`/api/labels/${encodeURIComponent(name)}`);
+            const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(name)}`);
 
             if (!response.ok) {
                 throw new Error(`HTTP ${response.status}: ${response.statusText}`);
@@ -205,6 +334,7 @@ class StoreViewer {
 
             // Update current label
             this.currentLabel = name;
+            this.currentRef = data.content_ref;
             this.isDirty = false;
 
             // Show editor view
@@ -224,8 +354,12 @@ class StoreViewer {
                 this.editor.setValue(data.content);
                 this.editor.clearHistory();
 
-                // Detect and set mode based on file extension
-                this.setEditorMode(name);
+                // Apply the server's editor hints, falling back to the file extension
+                this.setEditorMode(name, data.editor, data.detected_format);
+
+                // JSON can be checked and pretty-printed by the server
+                const looksLikeJson = name.toLowerCase().endsWith('.json') || /^\s*[{[]/.test(data.content);
+                document.getElementById('format-btn').classList.toggle('hidden', !looksLikeJson);
 
                 // Enable save button
                 document.getElementById('save-btn').disabled = false;
@@ -236,12 +370,25 @@ class StoreViewer {
                 document.getElementById('editor-wrapper').classList.add('hidden');
                 document.getElementById('binary-view').classList.remove('hidden');
 
+                document.getElementById('binary-title').textContent = 'Binary Content';
+                document.getElementById('binary-note').textContent =
+                    'This label contains binary data and cannot be edited in the text editor.';
+
                 // Display binary info
-                const info = `Size: ${this.formatBytes(data.size_bytes)}\nEncoding: Base64`;
+                let info = `Size: ${this.formatBytes(data.size_bytes)}\nEncoding: Base64`;
+                if (data.wasm) {
+                    info += await this.describeWasm(name, data.wasm);
+                }
+                if (data.archive) {
+                    info += await this.describeArchive(name, data.archive);
+                }
                 document.getElementById('binary-info').textContent = info;
+                document.getElementById('hex-view').textContent = '';
+                await this.showHex(name, 0);
 
                 // Disable save button
                 document.getElementById('save-btn').disabled = true;
+                document.getElementById('format-btn').classList.add('hidden');
 
                 console.log(`Loaded binary content for: ${name} (${data.size_bytes} bytes)`);
             }
@@ -264,6 +411,145 @@ class StoreViewer {
         }
     }
 
+    /**
+     * Show a label too large for the editor read-only, one page at a time
+     */
+    async selectLargeLabel(name) {
+        console.log(`Selecting large label: ${name}`);
+        this.currentLabel = name;
+        this.currentRef = null;
+        this.isDirty = false;
+        this.pageDecoder = null;
+
+        document.getElementById('empty-state').classList.add('hidden');
+        document.getElementById('editor-view').classList.remove('hidden');
+        document.getElementById('label-name').value = name;
+        document.getElementById('editor-wrapper').classList.add('hidden');
+        document.getElementById('binary-view').classList.remove('hidden');
+        document.getElementById('save-btn').disabled = true;
+        document.getElementById('format-btn').classList.add('hidden');
+
+        document.getElementById('binary-title').textContent = 'Large Content';
+        document.getElementById('binary-note').textContent =
+            'This label is too large to edit here; it is shown a page at a time.';
+        document.getElementById('binary-info').textContent = `Size: ${this.formatBytes(this.sizes[name])}`;
+        document.getElementById('hex-view').textContent = '';
+        await this.showPage(name, 0);
+
+        this.renderLabelList(document.getElementById('search-input').value);
+    }
+
+    /**
+     * Append one page of a large label: as text when the server says it is
+     * text, as a hex dump otherwise
+     */
+    async showPage(name, offset) {
+        const moreBtn = document.getElementById('hex-more-btn');
+        moreBtn.classList.add('hidden');
+        try {
+            const end = offset + PAGE_BYTES - 1;
+            const response = await this.apiFetch(
+                `${this.labelsPath()}/${encodeURIComponent(name)}/raw`,
+                { headers: { 'Range': `bytes=${offset}-${end}` } }
+            );
+            if (!response.ok) {
+                throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+            }
+            if (name !== this.currentLabel) {
+                return;
+            }
+            const charset = /charset=([^;]+)/.exec(response.headers.get('Content-Type') || '');
+            if (!charset) {
+                await this.showHex(name, offset);
+                return;
+            }
+            // Pages can split a character, so one decoder carries across them
+            if (offset === 0 || !this.pageDecoder) {
+                this.pageDecoder = new TextDecoder(charset[1].trim());
+            }
+            const bytes = new Uint8Array(await response.arrayBuffer());
+            const total = Number((response.headers.get('Content-Range') || '').split('/')[1]) || bytes.length;
+            const next = offset + bytes.length;
+            document.getElementById('hex-view').textContent +=
+                this.pageDecoder.decode(bytes, { stream: next < total });
+            if (next < total) {
+                moreBtn.onclick = () => this.showPage(name, next);
+                moreBtn.classList.remove('hidden');
+            }
+        } catch (error) {
+            console.error('Failed to load page:', error);
+        }
+    }
+
+    /**
+     * Append a hex dump of a binary label, starting at offset
+     */
+    async showHex(name, offset) {
+        const moreBtn = document.getElementById('hex-more-btn');
+        moreBtn.classList.add('hidden');
+        try {
+            const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(name)}/hex?offset=${offset}`);
+            if (!response.ok) {
+                throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+            }
+            const dump = await response.json();
+            if (name !== this.currentLabel) {
+                return;
+            }
+            const lines = dump.lines
+                .map(line => `${line.offset.toString(16).padStart(8, '0')}  ${line.hex.padEnd(48)}  ${line.ascii}\n`)
+                .join('');
+            document.getElementById('hex-view').textContent += lines;
+            if (dump.next_offset !== undefined) {
+                moreBtn.onclick = () => this.showHex(name, dump.next_offset);
+                moreBtn.classList.remove('hidden');
+            }
+        } catch (error) {
+            console.error('Failed to load hex dump:', error);
+        }
+    }
+
+    /**
+     * Summarize a WebAssembly label's imports, exports and custom sections
+     */
+    async describeWasm(name, kind) {
+        try {
+            const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(name)}/wasm-info`);
+            if (!response.ok) {
+                throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+            }
+            const wasm = await response.json();
+            const list = (items) => items.map(item => `\n  ${item.kind} ${item.name}`).join('');
+            return `\nWebAssembly ${wasm.kind} (version ${wasm.version})` +
+                `\nImports: ${wasm.imports.length}${list(wasm.imports)}` +
+                `\nExports: ${wasm.exports.length}${list(wasm.exports)}` +
+                `\nCustom sections: ${wasm.custom_sections.map(s => s.name).join(', ') || 'none'}`;
+        } catch (error) {
+            console.error('Failed to inspect WebAssembly label:', error);
+            return `\nWebAssembly ${kind}`;
+        }
+    }
+
+    /**
+     * List the members of an archive label
+     */
+    async describeArchive(name, format) {
+        try {
+            const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(name)}/entries`);
+            if (!response.ok) {
+                throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+            }
+            const archive = await response.json();
+            const entries = archive.entries
+                .map(entry => `\n  ${entry.path} (${this.formatBytes(entry.size_bytes)})`)
+                .join('');
+            return `\nArchive: ${archive.format}, ${archive.entries.length} entries${entries}`;
+        } catch (error) {
+            console.error('Failed to list archive entries:', error);
+            return `\nArchive: ${format}`;
+        }
+    }
+
     /**
      * Save the current label's content
      */
@@ -280,16 +566,31 @@ class StoreViewer {
 
             statusEl.textContent = 'Saving...';
 
-            const response = await fetch(`/api/labels/${encodeURIComponent(this.currentLabel)}`, {
+            const headers = { 'Content-Type': 'application/json' };
+            if (this.currentRef) {
+                // Only replace the version this edit started from
+                headers['If-Match'] = `"${this.currentRef}"`;
+            }
+
+            const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(this.currentLabel)}`, {
                 method: 'PUT',
-                headers: { 'Content-Type': 'application/json' },
+                headers,
                 body: JSON.stringify({ content }),
             });
 
+            if (response.status === 412) {
+                await this.mergeConflictingSave(content);
+                return;
+            }
+
             if (!response.ok) {
                 throw new Error(`HTTP ${response.status}: ${response.statusText}`);
             }
 
+            const etag = response.headers.get('ETag');
+            if (etag) {
+                this.currentRef = etag.replace(/"/g, '');
+            }
             this.isDirty = false;
             this.updateSaveButton();
 
@@ -307,6 +608,43 @@ class StoreViewer {
         }
     }
 
+    /**
+     * Merge an edit whose save was refused because the label changed on the
+     * server, then save again if the merge was clean
+     */
+    async mergeConflictingSave(content) {
+        const statusEl = document.getElementById('save-status');
+        const name = this.currentLabel;
+
+        const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(name)}/merge`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ base_ref: this.currentRef, content }),
+        });
+        if (!response.ok) {
+            throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+        }
+        const merge = await response.json();
+        if (name !== this.currentLabel) {
+            return;
+        }
+
+        // The merge result is an edit against the current version
+        this.currentRef = merge.current_ref;
+        const cursor = this.editor.getCursor();
+        this.editor.setValue(merge.content);
+        this.editor.setCursor(cursor);
+        this.isDirty = true;
+        this.updateSaveButton();
+
+        if (merge.clean) {
+            await this.saveLabel();
+        } else {
+            statusEl.textContent = 'Changed on the server';
+            this.showError(`"${name}" was changed on the server. ${merge.conflicts} conflicting region(s) are marked in the editor; resolve them and save again.`);
+        }
+    }
+
     /**
      * Prompt user to create a new label
      */
@@ -336,7 +674,7 @@ class StoreViewer {
         try {
             console.log(`Creating label: ${name}`);
 
-            const response = await fetch('/api/labels', {
+            const response = await this.apiFetch(this.labelsPath(), {
                 method: 'POST',
                 headers: { 'Content-Type': 'application/json' },
                 body: JSON.stringify({
@@ -361,6 +699,154 @@ class StoreViewer {
         }
     }
 
+    /**
+     * Prompt user for a new name for the current label
+     */
+    async promptRenameLabel() {
+        const from = this.currentLabel;
+        if (!from) {
+            return;
+        }
+
+        const to = prompt('Enter new label name:', from);
+        if (!to || to.trim() === '' || to === from) {
+            return; // User cancelled or kept the name
+        }
+
+        if (this.labels.includes(to)) {
+            alert(`Label "${to}" already exists`);
+            return;
+        }
+
+        // Keep unsaved edits under the old name before it moves
+        if (this.isDirty) {
+            await this.saveLabel();
+        }
+
+        try {
+            console.log(`Renaming label: ${from} -> ${to}`);
+
+            const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(from)}/rename`, {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ to }),
+            });
+
+            if (!response.ok) {
+                throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+            }
+
+            console.log(`Renamed label: ${from} -> ${to}`);
+
+            await this.loadLabels();
+            await this.selectLabel(to);
+
+        } catch (error) {
+            console.error('Failed to rename label:', error);
+            this.showError(`Failed to rename label: ${from}`);
+        }
+    }
+
+    /**
+     * Save the stored bytes of the current label as a file. Fetched rather
+     * than linked so the request carries the API token
+     */
+    async downloadLabel() {
+        const name = this.currentLabel;
+        if (!name) {
+            return;
+        }
+
+        try {
+            const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(name)}/raw?download=true`);
+            if (!response.ok) {
+                throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+            }
+            const url = URL.createObjectURL(await response.blob());
+            const link = document.createElement('a');
+            link.href = url;
+            link.download = name.split('/').filter(Boolean).pop() || 'download';
+            link.click();
+            URL.revokeObjectURL(url);
+        } catch (error) {
+            console.error('Failed to download label:', error);
+            this.showError(`Failed to download label: ${name}`);
+        }
+    }
+
+    /**
+     * Replace the editor's JSON with the server's pretty-printed version, or
+     * point at the first syntax error
+     */
+    async formatJson() {
+        const name = this.currentLabel;
+        if (!name || this.readOnly) {
+            return;
+        }
+        // The server formats what is stored
+        if (this.isDirty) {
+            await this.saveLabel();
+        }
+
+        try {
+            const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(name)}/json`);
+            if (response.status === 422) {
+                const error = await response.json();
+                this.editor.setCursor({ line: error.line - 1, ch: Math.max(error.column - 1, 0) });
+                this.editor.focus();
+                this.showError(`Invalid JSON at line ${error.line}, column ${error.column}`);
+                return;
+            }
+            if (!response.ok) {
+                throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+            }
+            const pretty = await response.text();
+            if (name === this.currentLabel && pretty !== this.editor.getValue()) {
+                // Saved like any other edit
+                this.editor.setValue(pretty);
+            }
+        } catch (error) {
+            console.error('Failed to format JSON:', error);
+            this.showError(`Failed to format JSON: ${name}`);
+        }
+    }
+
+    /**
+     * Revert the most recent change made from this tab
+     */
+    async undoLastChange() {
+        try {
+            const response = await this.apiFetch('/api/undo', { method: 'POST' });
+
+            if (response.status === 404) {
+                alert('Nothing to undo');
+                return;
+            }
+            if (!response.ok) {
+                const data = await response.json().catch(() => ({}));
+                throw new Error(data.error || `HTTP ${response.status}: ${response.statusText}`);
+            }
+
+            const { undone } = await response.json();
+            console.log(`Undid: ${undone.operation}`);
+
+            await this.loadLabels();
+            if (undone.changes.some(change => change.label === this.currentLabel)) {
+                if (this.labels.includes(this.currentLabel)) {
+                    // The reverted content replaces the editor's
+                    this.isDirty = false;
+                    await this.selectLabel(this.currentLabel);
+                } else {
+                    document.getElementById('save-status').textContent = 'Deleted on the server';
+                }
+            }
+
+        } catch (error) {
+            console.error('Failed to undo:', error);
+            this.showError(`Failed to undo: ${error.message}`);
+        }
+    }
+
     /**
      * Filter labels by search query
      */
@@ -396,9 +882,23 @@ class StoreViewer {
     }
 
     /**
-     * Set CodeMirror mode based on file extension
+     * Set CodeMirror mode, tab width and read-only state from the label's
+     * editor hints, guessing the mode from the file extension or the
+     * server's detected format without one
      */
-    setEditorMode(filename) {
+    setEditorMode(filename, hints = {}, detectedFormat = null) {
+        this.readOnly = Boolean(hints.read_only);
+        this.editor.setOption('readOnly', this.readOnly);
+
+        const tabWidth = hints.tab_width || 2;
+        this.editor.setOption('tabSize', tabWidth);
+        this.editor.setOption('indentUnit', tabWidth);
+
+        if (hints.mode) {
+            this.editor.setOption('mode', hints.mode);
+            return;
+        }
+
         const ext = filename.split('.').pop().toLowerCase();
 
         const modeMap = {
@@ -416,13 +916,200 @@ class StoreViewer {
             'css': 'css',
             'scss': 'css',
             'sass': 'css',
+            'yaml': 'yaml',
+            'yml': 'yaml',
             'txt': 'text',
         };
 
-        const mode = modeMap[ext] || 'javascript';
+        const formatModes = {
+            'json': { name: 'javascript', json: true },
+            'yaml': 'yaml',
+            'markdown': 'markdown',
+            'html': 'htmlmixed',
+            'xml': 'xml',
+            'svg': 'xml',
+            'toml': 'text',
+            'csv': 'text',
+            'text': 'text',
+        };
+
+        const mode = modeMap[ext] || formatModes[detectedFormat] || 'javascript';
         this.editor.setOption('mode', mode);
     }
 
+    /**
+     * Fetch wrapper that tells the server the client's current time,
+     * which the actor uses for access timestamps
+     */
+    async apiFetch(url, options = {}, retry = true) {
+        const headers = {
+            ...(options.headers || {}),
+            'X-Client-Time': String(Date.now()),
+            'X-Session-Id': this.sessionId(),
+        };
+        const token = localStorage.getItem('store-viewer-token');
+        if (token) {
+            headers['Authorization'] = `Bearer ${token}`;
+        }
+
+        const response = await fetch(url, { ...options, headers });
+
+        // The server wants a token: ask once, then try again with it
+        if (response.status === 401 && retry) {
+            const entered = prompt('This store needs an access token:');
+            if (entered) {
+                localStorage.setItem('store-viewer-token', entered.trim());
+                return this.apiFetch(url, options, false);
+            }
+        }
+        return response;
+    }
+
+    /**
+     * Id naming this tab's session, under which the server keeps undo history
+     */
+    sessionId() {
+        let id = sessionStorage.getItem('store-viewer-session');
+        if (!id) {
+            id = crypto.randomUUID();
+            sessionStorage.setItem('store-viewer-session', id);
+        }
+        return id;
+    }
+
+    /**
+     * Connect to the server's WebSocket event stream, reconnecting on close
+     */
+    connectEvents() {
+        const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+        // Browsers cannot send the token as a header on a WebSocket
+        const token = localStorage.getItem('store-viewer-token');
+        const query = token ? `?token=${encodeURIComponent(token)}` : '';
+        const socket = new WebSocket(`${protocol}//${window.location.host}/ws${query}`);
+
+        socket.addEventListener('open', () => {
+            socket.send(JSON.stringify({ type: 'expiring' }));
+        });
+        socket.addEventListener('message', (e) => {
+            try {
+                this.handleEvent(JSON.parse(e.data));
+            } catch (error) {
+                console.error('Invalid event from server:', error);
+            }
+        });
+        socket.addEventListener('close', () => {
+            setTimeout(() => this.connectEvents(), 5000);
+        });
+    }
+
+    /**
+     * Apply an event pushed by the server
+     */
+    handleEvent(event) {
+        switch (event.type) {
+            case 'batch':
+                // Events were lost, so the local view can't be patched up
+                if (event.dropped > 0) {
+                    this.loadLabels();
+                    if (this.currentLabel) {
+                        this.refreshCurrentLabel();
+                    }
+                    return;
+                }
+                event.events.forEach(e => this.handleEvent(e));
+                return;
+            case 'expiring_labels':
+                this.expiring = {};
+                event.labels.forEach(l => { this.expiring[l.label] = l.expires_at; });
+                break;
+            case 'expiry_warning':
+                this.expiring[event.label] = event.expires_at;
+                console.warn(`Label ${event.label} expires at ${new Date(event.expires_at).toLocaleString()}`);
+                break;
+            case 'label_created':
+            case 'label_updated':
+            case 'label_deleted':
+                // Changes to other stores than the one being browsed don't show
+                if ((event.store || null) !== this.store) {
+                    return;
+                }
+                this.applyLabelEvent(event);
+                return;
+            default:
+                return;
+        }
+        this.renderLabelList(document.getElementById('search-input').value);
+    }
+
+    /**
+     * Apply a change to a label of the store being browsed
+     */
+    applyLabelEvent(event) {
+        switch (event.type) {
+            case 'label_created':
+                if (this.labels.includes(event.label)) {
+                    return;
+                }
+                // Server-side orderings need the server to place the label
+                if (this.sortOrder !== 'name') {
+                    this.loadLabels();
+                    return;
+                }
+                this.labels.push(event.label);
+                break;
+            case 'label_updated':
+                if (event.label === this.currentLabel) {
+                    this.refreshCurrentLabel();
+                }
+                return;
+            case 'label_deleted':
+                this.labels = this.labels.filter(label => label !== event.label);
+                delete this.expiring[event.label];
+                if (event.label === this.currentLabel) {
+                    document.getElementById('save-status').textContent = 'Deleted on the server';
+                }
+                break;
+        }
+        this.renderLabelList(document.getElementById('search-input').value);
+    }
+
+    /**
+     * Reload the open label after another client changed it, unless
+     * there are local edits that would be lost
+     */
+    async refreshCurrentLabel() {
+        if (this.isDirty) {
+            document.getElementById('save-status').textContent = 'Changed on the server';
+            return;
+        }
+        // Large labels are not held in the editor, so there is nothing to refresh
+        if (this.sizes[this.currentLabel] > LARGE_LABEL_BYTES) {
+            return;
+        }
+        try {
+            const name = this.currentLabel;
+            const response = await this.apiFetch(`${this.labelsPath()}/${encodeURIComponent(name)}`);
+            if (!response.ok) {
+                return;
+            }
+            const data = await response.json();
+            if (name !== this.currentLabel || this.isDirty || !data.is_text) {
+                return;
+            }
+            this.currentRef = data.content_ref;
+            // Our own saves come back as updates too; leave the editor alone then
+            if (this.editor.getValue() !== data.content) {
+                const cursor = this.editor.getCursor();
+                this.isLoading = true;
+                this.editor.setValue(data.content);
+                this.editor.setCursor(cursor);
+                this.isLoading = false;
+            }
+        } catch (error) {
+            console.error('Failed to refresh label:', error);
+        }
+    }
+
     /**
      * Show error message to user
      */
@@ -0,0 +1,79 @@
+//! Appending to log-style labels.
+//!
+//! `POST /api/labels/{name}/append` adds the raw request body to the end of
+//! a label, creating it if it does not exist, so a client keeping a
+//! newline-delimited log in a label sends each new record instead of
+//! downloading and re-uploading the whole log. The actor handles one request
+//! at a time, so concurrent appends never lose each other's records.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{dryrun, error_response, etag, json_response, naming, query_param, validate_write, write_label, StoreViewerState};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct AppendResponse<'a> {
+    label: &'a str,
+    #[serde(rename = "ref")]
+    content_ref: String,
+    /// Size of the label after the append
+    size_bytes: usize,
+    appended_bytes: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    created: bool,
+}
+
+/// `POST /api/labels/{name}/append?newline=` with the bytes to append as the body
+pub fn handle_append(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Appending to label: {}", label_name));
+
+    let appended = req.body.as_deref().unwrap_or_default();
+    if appended.is_empty() {
+        return Ok(error_response(400, "Request body is required"));
+    }
+    // Keep each append on lines of its own
+    let newline = query_param(&req.uri, "newline").as_deref() == Some("true");
+
+    let current_ref = store::get_by_label(&state.store_id, label_name)?;
+    let mut content = match &current_ref {
+        Some(current_ref) => store::get(&state.store_id, current_ref)?,
+        None => {
+            if let Err(e) = naming::check_name(&state.naming, label_name) {
+                return Ok(error_response(422, &e));
+            }
+            Vec::new()
+        }
+    };
+    if newline && !content.is_empty() && !content.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+    content.extend_from_slice(appended);
+    if newline && !appended.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+    if let Err((status, e)) = validate_write(state, label_name, &content) {
+        return Ok(error_response(status, &e));
+    }
+
+    if dryrun::is_dry_run(req) {
+        let change = dryrun::plan_write(&state.store_id, label_name, &content)?;
+        return dryrun::dry_run_response(&[change]);
+    }
+    let content_ref = write_label(state, "append", label_name, &content)?;
+
+    log(&format!("Appended {} bytes to {}", appended.len(), label_name));
+
+    let response = AppendResponse {
+        label: label_name,
+        content_ref: content_ref.hash.clone(),
+        size_bytes: content.len(),
+        appended_bytes: appended.len(),
+        created: current_ref.is_none(),
+    };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize append: {}", e))?;
+    let mut response = json_response(200, body);
+    response.headers.push(("ETag".to_string(), etag::for_ref(&content_ref.hash)));
+    Ok(response)
+}
@@ -0,0 +1,246 @@
+//! Archiving of idle labels.
+//!
+//! `POST /api/archive` moves labels nobody has read or written for a number
+//! of days under `archive/`, keeping their content but taking them out of the
+//! default listing. Each move is recorded with the label's original name so
+//! `POST /api/unarchive` can put it back. Moves only re-point labels; no
+//! content is copied.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::dryrun::{self, PlannedChange};
+use crate::events;
+use crate::journal::{self, Step};
+use crate::{error_response, json_response, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+
+/// Namespace archived labels are moved into.
+pub const ARCHIVE_PREFIX: &str = "archive/";
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchivedLabel {
+    /// Name the label had before it was archived
+    pub original: String,
+    pub archived_at: u64,
+    /// Last read or write before archiving, if one was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_access_at: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ArchiveRequest {
+    /// Archive labels not accessed for this many days
+    idle_days: u32,
+    #[serde(default)]
+    prefix: Option<String>,
+    /// Also archive labels with no recorded access at all
+    #[serde(default)]
+    include_never_accessed: bool,
+}
+
+#[derive(Deserialize)]
+struct UnarchiveRequest {
+    /// Original names of the labels to restore
+    labels: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct MovedLabel {
+    label: String,
+    archived_as: String,
+}
+
+#[derive(Serialize)]
+struct ArchiveResponse {
+    archived: Vec<MovedLabel>,
+    /// Labels whose archive name is already taken
+    skipped: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct UnarchiveResponse {
+    restored: Vec<MovedLabel>,
+}
+
+#[derive(Serialize)]
+struct ArchiveEntry<'a> {
+    label: &'a str,
+    #[serde(flatten)]
+    record: &'a ArchivedLabel,
+}
+
+/// Name a label is archived under.
+fn archive_name(label: &str) -> String {
+    format!("{}{}", ARCHIVE_PREFIX, label)
+}
+
+/// Whether `label` was put in the archive by `POST /api/archive`.
+pub fn is_archived(state: &StoreViewerState, label: &str) -> bool {
+    state.archived.contains_key(label)
+}
+
+/// Re-point every `(from, to)` pair through one journal entry, carrying
+/// statistics and language metadata along.
+fn apply_moves(state: &mut StoreViewerState, operation: &str, moves: &[(String, String, store::ContentRef)]) -> Result<(), String> {
+    let mut steps = Vec::with_capacity(moves.len() * 2);
+    for (from, to, content_ref) in moves {
+        steps.push(Step::point(to, content_ref));
+        steps.push(Step::delete(from));
+    }
+    let journal_id = journal::begin(state, operation, steps)?;
+    for (from, to, content_ref) in moves {
+        store::replace_at_label(&state.store_id, to, content_ref)?;
+        store::remove_label(&state.store_id, from)?;
+        state.stats.rename(from, to);
+        if let Some(meta) = state.label_meta.remove(from) {
+            state.label_meta.insert(to.clone(), meta);
+        }
+        events::label_deleted(state, from, Some(&content_ref.hash));
+        events::label_written(state, to, None, content_ref);
+    }
+    journal::complete(state, journal_id)
+}
+
+/// `POST /api/archive`
+pub fn handle_archive(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Archiving idle labels");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let archive_req: ArchiveRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if archive_req.idle_days == 0 {
+        return Ok(error_response(400, "idle_days must be at least 1"));
+    }
+
+    let idle_since = state.clock_ms.saturating_sub(u64::from(archive_req.idle_days) * DAY_MS);
+    let prefix = archive_req.prefix.as_deref().unwrap_or("");
+
+    let mut moves = Vec::new();
+    let mut skipped = Vec::new();
+    for label in store::list_labels(&state.store_id)? {
+        if !label.starts_with(prefix)
+            || label.starts_with(SYSTEM_LABEL_PREFIX)
+            || label.starts_with(ARCHIVE_PREFIX)
+        {
+            continue;
+        }
+        let idle = match state.stats.get(&label).last_access_at() {
+            Some(last_access_at) => last_access_at < idle_since,
+            None => archive_req.include_never_accessed,
+        };
+        if !idle {
+            continue;
+        }
+        let target = archive_name(&label);
+        if store::get_by_label(&state.store_id, &target)?.is_some() {
+            skipped.push(label);
+            continue;
+        }
+        if let Some(content_ref) = store::get_by_label(&state.store_id, &label)? {
+            moves.push((label, target, content_ref));
+        }
+    }
+
+    if dryrun::is_dry_run(req) {
+        let mut planned: Vec<PlannedChange> = Vec::new();
+        for (from, to, content_ref) in &moves {
+            planned.extend(dryrun::plan_move(&state.store_id, from, to, content_ref)?);
+        }
+        return dryrun::dry_run_response(&planned);
+    }
+
+    if !moves.is_empty() {
+        apply_moves(state, "archive", &moves)?;
+    }
+
+    let mut archived = Vec::with_capacity(moves.len());
+    for (label, target, _) in moves {
+        let record = ArchivedLabel {
+            last_access_at: state.stats.get(&target).last_access_at(),
+            original: label.clone(),
+            archived_at: state.clock_ms,
+        };
+        state.archived.insert(target.clone(), record);
+        archived.push(MovedLabel { label, archived_as: target });
+    }
+
+    log(&format!("Archived {} labels ({} skipped)", archived.len(), skipped.len()));
+
+    let body = serde_json::to_vec(&ArchiveResponse { archived, skipped })
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/unarchive`
+pub fn handle_unarchive(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Restoring archived labels");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let unarchive_req: UnarchiveRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if unarchive_req.labels.is_empty() {
+        return Ok(error_response(400, "At least one label is required"));
+    }
+
+    let mut moves = Vec::new();
+    for label in &unarchive_req.labels {
+        let target = archive_name(label);
+        let content_ref = match store::get_by_label(&state.store_id, &target)? {
+            Some(content_ref) if is_archived(state, &target) => content_ref,
+            _ => return Ok(error_response(404, &format!("Label is not archived: {}", label))),
+        };
+        if store::get_by_label(&state.store_id, label)?.is_some() {
+            return Ok(error_response(409, &format!("A label named {} exists again; move it first", label)));
+        }
+        moves.push((target, label.clone(), content_ref));
+    }
+
+    if dryrun::is_dry_run(req) {
+        let mut planned: Vec<PlannedChange> = Vec::new();
+        for (from, to, content_ref) in &moves {
+            planned.extend(dryrun::plan_move(&state.store_id, from, to, content_ref)?);
+        }
+        return dryrun::dry_run_response(&planned);
+    }
+
+    apply_moves(state, "unarchive", &moves)?;
+
+    let restored: Vec<MovedLabel> = moves
+        .into_iter()
+        .map(|(archived_as, label, _)| {
+            state.archived.remove(&archived_as);
+            MovedLabel { label, archived_as }
+        })
+        .collect();
+
+    log(&format!("Restored {} archived labels", restored.len()));
+
+    let body = serde_json::to_vec(&UnarchiveResponse { restored })
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `GET /api/archive`: every archived label with its original name.
+pub fn handle_list_archived(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing archived labels");
+
+    let entries: Vec<ArchiveEntry> = state
+        .archived
+        .iter()
+        .map(|(label, record)| ArchiveEntry { label, record })
+        .collect();
+
+    let body = serde_json::to_vec(&entries)
+        .map_err(|e| format!("Failed to serialize archived labels: {}", e))?;
+
+    Ok(json_response(200, body))
+}
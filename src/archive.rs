@@ -0,0 +1,391 @@
+//! Browsing of archives stored as single labels.
+//!
+//! Zip files, tar files and gzip-compressed tar files are read in place: their
+//! member list is parsed from the archive headers and a single member can be
+//! extracted on request, without unpacking the archive into separate labels.
+//! Zip members may be stored or deflated; zip64 and encrypted archives are not
+//! supported.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, json_response, mime, StoreViewerState};
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Largest amount of data decompressed for a single request.
+const MAX_INFLATED_BYTES: usize = 64 * 1024 * 1024;
+
+const TAR_BLOCK: usize = 512;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar")]
+    Tar,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub kind: EntryKind,
+    pub size_bytes: u64,
+    /// Size inside the archive, for compressed zip members
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compressed_size_bytes: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct EntriesResponse {
+    format: ArchiveFormat,
+    entries: Vec<ArchiveEntry>,
+}
+
+/// An entry together with where its data lives in the archive.
+struct Member {
+    entry: ArchiveEntry,
+    /// Zip: offset of the local file header; tar: offset of the data
+    offset: usize,
+    /// Zip compression method (0 stored, 8 deflate)
+    method: u16,
+}
+
+/// Recognise an archive from its leading bytes. Gzip streams only count as
+/// archives when they contain a tar file.
+pub fn detect(bytes: &[u8]) -> Option<ArchiveFormat> {
+    if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+        Some(ArchiveFormat::Zip)
+    } else if is_tar(bytes) {
+        Some(ArchiveFormat::Tar)
+    } else if bytes.starts_with(&[0x1f, 0x8b]) {
+        // Inflating the first block is enough to see the tar header
+        let head = gunzip(bytes, TAR_BLOCK).ok()?;
+        is_tar(&head).then_some(ArchiveFormat::TarGz)
+    } else {
+        None
+    }
+}
+
+fn is_tar(bytes: &[u8]) -> bool {
+    bytes.len() >= TAR_BLOCK && &bytes[257..262] == b"ustar"
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Result<u16, String> {
+    bytes
+        .get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| "Truncated zip archive".to_string())
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32, String> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "Truncated zip archive".to_string())
+}
+
+/// Decompress a gzip stream, producing at most `limit` bytes.
+fn gunzip(bytes: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    if bytes.len() < 10 || bytes[2] != 8 {
+        return Err("Unsupported gzip stream".to_string());
+    }
+    let flags = bytes[3];
+    let mut pos = 10;
+    if flags & 0x04 != 0 {
+        pos += 2 + read_u16(bytes, pos)? as usize;
+    }
+    for flag in [0x08, 0x10] {
+        // Zero-terminated file name and comment
+        if flags & flag != 0 {
+            let end = bytes.get(pos..).and_then(|rest| rest.iter().position(|&b| b == 0));
+            pos += end.ok_or("Truncated gzip header")? + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        pos += 2;
+    }
+    let deflated = bytes.get(pos..).ok_or("Truncated gzip header")?;
+
+    match decompress_to_vec_with_limit(deflated, limit) {
+        Ok(data) => Ok(data),
+        // Hitting the limit is expected when only the start is wanted
+        Err(e) if limit < MAX_INFLATED_BYTES && !e.output.is_empty() => Ok(e.output),
+        Err(e) => Err(format!("Invalid gzip data: {}", e)),
+    }
+}
+
+fn zip_members(bytes: &[u8]) -> Result<Vec<Member>, String> {
+    // The end-of-central-directory record sits at the end, before an
+    // optional comment of up to 64 KiB
+    let search_from = bytes.len().saturating_sub(22 + 0xffff);
+    let eocd = (search_from..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|&i| bytes[i..].starts_with(b"PK\x05\x06"))
+        .ok_or("Zip archive has no central directory")?;
+
+    let count = read_u16(bytes, eocd + 10)? as usize;
+    let directory_offset = read_u32(bytes, eocd + 16)?;
+    if directory_offset == u32::MAX || count == 0xffff {
+        return Err("Zip64 archives are not supported".to_string());
+    }
+
+    let mut members = Vec::with_capacity(count);
+    let mut pos = directory_offset as usize;
+    for _ in 0..count {
+        if read_u32(bytes, pos)? != 0x0201_4b50 {
+            return Err("Corrupt zip central directory".to_string());
+        }
+        let flags = read_u16(bytes, pos + 8)?;
+        let method = read_u16(bytes, pos + 10)?;
+        let compressed = read_u32(bytes, pos + 20)?;
+        let size = read_u32(bytes, pos + 24)?;
+        let name_len = read_u16(bytes, pos + 28)? as usize;
+        let extra_len = read_u16(bytes, pos + 30)? as usize;
+        let comment_len = read_u16(bytes, pos + 32)? as usize;
+        let external_attrs = read_u32(bytes, pos + 38)?;
+        let local_offset = read_u32(bytes, pos + 42)?;
+        let name = bytes
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or("Truncated zip archive")?;
+        let path = String::from_utf8_lossy(name).into_owned();
+
+        let kind = if path.ends_with('/') {
+            EntryKind::Directory
+        } else if (external_attrs >> 16) & 0o170000 == 0o120000 {
+            EntryKind::Symlink
+        } else if flags & 0x1 != 0 {
+            // Encrypted members are listed but cannot be read
+            EntryKind::Other
+        } else {
+            EntryKind::File
+        };
+
+        members.push(Member {
+            entry: ArchiveEntry {
+                path,
+                kind,
+                size_bytes: u64::from(size),
+                compressed_size_bytes: Some(u64::from(compressed)),
+            },
+            offset: local_offset as usize,
+            method,
+        });
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(members)
+}
+
+fn zip_member_data(bytes: &[u8], member: &Member) -> Result<Vec<u8>, String> {
+    let pos = member.offset;
+    if read_u32(bytes, pos)? != 0x0403_4b50 {
+        return Err("Corrupt zip local header".to_string());
+    }
+    let name_len = read_u16(bytes, pos + 26)? as usize;
+    let extra_len = read_u16(bytes, pos + 28)? as usize;
+    let start = pos + 30 + name_len + extra_len;
+    let compressed = member.entry.compressed_size_bytes.unwrap_or_default() as usize;
+    let data = bytes
+        .get(start..start + compressed)
+        .ok_or("Truncated zip archive")?;
+
+    match member.method {
+        0 => Ok(data.to_vec()),
+        8 if member.entry.size_bytes == 0 => Ok(Vec::new()),
+        8 => {
+            let limit = (member.entry.size_bytes as usize).min(MAX_INFLATED_BYTES);
+            decompress_to_vec_with_limit(data, limit).map_err(|e| format!("Invalid deflate data: {}", e))
+        }
+        other => Err(format!("Unsupported zip compression method: {}", other)),
+    }
+}
+
+/// Parse an octal (or GNU base-256) numeric tar header field.
+fn tar_number(field: &[u8]) -> Result<u64, String> {
+    if field.first().is_some_and(|b| b & 0x80 != 0) {
+        return Ok(field[1..].iter().fold(0u64, |n, &b| (n << 8) | u64::from(b)));
+    }
+    let digits = String::from_utf8_lossy(field);
+    let digits = digits.trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| format!("Invalid tar header number: {}", digits))
+}
+
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn tar_members(bytes: &[u8]) -> Result<Vec<Member>, String> {
+    let mut members = Vec::new();
+    let mut pos = 0;
+    // Name overrides from GNU long-name and pax extended headers
+    let mut long_name: Option<String> = None;
+
+    while pos + TAR_BLOCK <= bytes.len() {
+        let header = &bytes[pos..pos + TAR_BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = tar_number(&header[124..136])?;
+        let data_start = pos + TAR_BLOCK;
+        let data_end = data_start
+            .checked_add(size as usize)
+            .filter(|&end| end <= bytes.len())
+            .ok_or("Truncated tar archive")?;
+        let data = &bytes[data_start..data_end];
+
+        let typeflag = header[156];
+        match typeflag {
+            b'L' => long_name = Some(tar_string(data)),
+            b'x' => {
+                // Records are "<len> <key>=<value>\n"
+                let records = String::from_utf8_lossy(data);
+                if let Some(path) = records
+                    .lines()
+                    .filter_map(|line| line.split_once(' ').map(|(_, record)| record))
+                    .find_map(|record| record.strip_prefix("path="))
+                {
+                    long_name = Some(path.to_string());
+                }
+            }
+            b'g' => {}
+            _ => {
+                let path = long_name.take().unwrap_or_else(|| {
+                    let name = tar_string(&header[0..100]);
+                    let prefix = tar_string(&header[345..500]);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                });
+                let kind = match typeflag {
+                    b'0' | b'\0' | b'7' => EntryKind::File,
+                    b'5' => EntryKind::Directory,
+                    b'2' => EntryKind::Symlink,
+                    _ => EntryKind::Other,
+                };
+                members.push(Member {
+                    entry: ArchiveEntry {
+                        path,
+                        kind,
+                        size_bytes: size,
+                        compressed_size_bytes: None,
+                    },
+                    offset: data_start,
+                    method: 0,
+                });
+            }
+        }
+
+        pos = data_start + (size as usize).div_ceil(TAR_BLOCK) * TAR_BLOCK;
+    }
+    Ok(members)
+}
+
+/// Parse the member list of an archive, returning the buffer the member
+/// offsets refer to (the inflated tar for gzip-compressed archives).
+fn members(bytes: &[u8], format: ArchiveFormat) -> Result<(Cow<'_, [u8]>, Vec<Member>), String> {
+    match format {
+        ArchiveFormat::Zip => Ok((Cow::Borrowed(bytes), zip_members(bytes)?)),
+        ArchiveFormat::Tar => Ok((Cow::Borrowed(bytes), tar_members(bytes)?)),
+        ArchiveFormat::TarGz => {
+            let tar = gunzip(bytes, MAX_INFLATED_BYTES)?;
+            let members = tar_members(&tar)?;
+            Ok((Cow::Owned(tar), members))
+        }
+    }
+}
+
+/// Load a label and recognise it as an archive, or produce the error response.
+fn load_archive(state: &StoreViewerState, label_name: &str) -> Result<Result<(Vec<u8>, ArchiveFormat), HttpResponse>, String> {
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(Err(error_response(404, &format!("Label not found: {}", label_name)))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    match detect(&content) {
+        Some(format) => Ok(Ok((content, format))),
+        None => Ok(Err(error_response(415, &format!("Label {} is not a zip or tar archive", label_name)))),
+    }
+}
+
+pub fn handle_list_entries(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Listing archive entries: {}", label_name));
+
+    let (content, format) = match load_archive(state, label_name)? {
+        Ok(archive) => archive,
+        Err(resp) => return Ok(resp),
+    };
+
+    let entries = match members(&content, format) {
+        Ok((_, members)) => members.into_iter().map(|m| m.entry).collect(),
+        Err(e) => return Ok(error_response(422, &e)),
+    };
+
+    let body = serde_json::to_vec(&EntriesResponse { format, entries })
+        .map_err(|e| format!("Failed to serialize archive entries: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `GET /api/labels/{name}/entries/{path}`: the raw bytes of one member.
+pub fn handle_get_entry(state: &StoreViewerState, label_name: &str, path: &str) -> Result<HttpResponse, String> {
+    log(&format!("Reading archive entry {} from {}", path, label_name));
+
+    let (content, format) = match load_archive(state, label_name)? {
+        Ok(archive) => archive,
+        Err(resp) => return Ok(resp),
+    };
+
+    let (buffer, members) = match members(&content, format) {
+        Ok(parsed) => parsed,
+        Err(e) => return Ok(error_response(422, &e)),
+    };
+
+    // Later members replace earlier ones with the same path, as on extraction
+    let member = match members.iter().rev().find(|m| m.entry.path.trim_start_matches("./") == path) {
+        Some(member) => member,
+        None => return Ok(error_response(404, &format!("Archive entry not found: {}", path))),
+    };
+    if member.entry.kind != EntryKind::File {
+        return Ok(error_response(400, &format!("Archive entry is not a regular file: {}", path)));
+    }
+
+    let data = match format {
+        ArchiveFormat::Zip => zip_member_data(&buffer, member),
+        ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+            Ok(buffer[member.offset..member.offset + member.entry.size_bytes as usize].to_vec())
+        }
+    };
+    let data = match data {
+        Ok(data) => data,
+        Err(e) => return Ok(error_response(422, &e)),
+    };
+
+    Ok(HttpResponse {
+        status: 200,
+        headers: vec![
+            ("Content-Type".to_string(), mime::content_type_of(path, &data)),
+            ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+            ("Content-Security-Policy".to_string(), "sandbox".to_string()),
+        ],
+        body: Some(data),
+    })
+}
@@ -0,0 +1,288 @@
+//! Audit log of label changes.
+//!
+//! Every create, update and delete of a label is recorded with the refs the
+//! label held before and after, when it happened and the request that did it:
+//! its method and path, the `X-Session-Id` it came with and the `Origin`,
+//! `User-Agent` and `X-Forwarded-For` headers it was sent with. Changes made
+//! by the viewer's own scheduled work, such as retention, have no request.
+//! Unlike the event log this is always on, and it records changes to every
+//! store the viewer reaches, so several people editing live state can find
+//! out who changed what.
+//!
+//! The log is kept in the viewer's own store as JSON lines under
+//! `__system/audit/`, split into segments of `SEGMENT_ENTRIES`; entries are
+//! only ever added. `GET /api/audit` reads it newest first.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{clock, error_response, header, json_response, query_param, stores, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+
+/// Entries per segment label.
+const SEGMENT_ENTRIES: u64 = 500;
+
+/// Entries returned by one read when no limit is given, and the most allowed.
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// Longest header value kept; longer ones are cut.
+const MAX_HEADER_LEN: usize = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuditLog {
+    /// Entries recorded so far, which is also the number of the latest
+    #[serde(default)]
+    pub entries: u64,
+    /// The request being handled, while it is being handled
+    #[serde(skip)]
+    active: Option<RequestOrigin>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum AuditOp {
+    Create,
+    Update,
+    Delete,
+}
+
+impl AuditOp {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "create" => Some(AuditOp::Create),
+            "update" => Some(AuditOp::Update),
+            "delete" => Some(AuditOp::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// The request a change was made by.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct RequestOrigin {
+    method: String,
+    path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    forwarded_for: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct AuditEntry {
+    seq: u64,
+    op: AuditOp,
+    label: String,
+    /// Store the label is in, when it is not the viewer's own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    store: Option<String>,
+    /// Ref before the change; absent when it created the label
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    old_ref: Option<String>,
+    /// Ref after the change; absent when it deleted the label
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    new_ref: Option<String>,
+    at: u64,
+    /// Absent for changes made by the viewer's scheduled work
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request: Option<RequestOrigin>,
+}
+
+/// Conditions entries must meet to be returned.
+struct Filter {
+    label: Option<String>,
+    prefix: Option<String>,
+    op: Option<AuditOp>,
+    session: Option<String>,
+    since: u64,
+    until: u64,
+}
+
+impl Filter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.label.as_ref().is_none_or(|label| &entry.label == label)
+            && self.prefix.as_ref().is_none_or(|prefix| entry.label.starts_with(prefix.as_str()))
+            && self.op.is_none_or(|op| entry.op == op)
+            && self.session.as_ref().is_none_or(|session| {
+                entry.request.as_ref().and_then(|request| request.session.as_ref()) == Some(session)
+            })
+            && entry.at >= self.since
+            && entry.at <= self.until
+    }
+}
+
+#[derive(Serialize)]
+struct AuditResponse {
+    /// Newest first
+    entries: Vec<AuditEntry>,
+    /// Entries recorded in all
+    total: u64,
+    /// Where the next read continues with `?before=`, if there may be more
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_before: Option<u64>,
+}
+
+fn segment_label(segment: u64) -> String {
+    format!("{}audit/{:06}", SYSTEM_LABEL_PREFIX, segment)
+}
+
+fn segment_of(seq: u64) -> u64 {
+    (seq - 1) / SEGMENT_ENTRIES
+}
+
+/// Entries of one segment, oldest first.
+fn read_segment(store_id: &str, segment: u64) -> Result<Vec<AuditEntry>, String> {
+    let content_ref = match store::get_by_label(store_id, &segment_label(segment))? {
+        Some(content_ref) => content_ref,
+        None => return Ok(Vec::new()),
+    };
+    let content = store::get(store_id, &content_ref)?;
+    String::from_utf8_lossy(&content)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| format!("Corrupt audit log segment {}: {}", segment, e)))
+        .collect()
+}
+
+fn append(store_id: &str, entry: &AuditEntry) -> Result<(), String> {
+    let label = segment_label(segment_of(entry.seq));
+    let mut content = match store::get_by_label(store_id, &label)? {
+        Some(content_ref) => store::get(store_id, &content_ref)?,
+        None => Vec::new(),
+    };
+    serde_json::to_writer(&mut content, entry).map_err(|e| format!("Failed to serialize audit entry: {}", e))?;
+    content.push(b'\n');
+    store::store_at_label(store_id, &label, &content)?;
+    Ok(())
+}
+
+fn header_value(req: &HttpRequest, name: &str) -> Option<String> {
+    let value = header(req, name)?.trim();
+    if value.is_empty() {
+        return None;
+    }
+    let mut end = value.len().min(MAX_HEADER_LEN);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(value[..end].to_string())
+}
+
+/// Attribute the changes the request makes to it.
+pub fn begin(state: &mut StoreViewerState, req: &HttpRequest, method: &str, path: &str) {
+    state.audit.active = Some(RequestOrigin {
+        method: method.to_string(),
+        path: path.to_string(),
+        session: header_value(req, "x-session-id"),
+        origin: header_value(req, "origin"),
+        user_agent: header_value(req, "user-agent"),
+        forwarded_for: header_value(req, "x-forwarded-for"),
+    });
+}
+
+/// Stop attributing changes to the request just handled.
+pub fn finish(state: &mut StoreViewerState) {
+    state.audit.active = None;
+}
+
+/// Append a change of `label` from `old_ref` to `new_ref` to the log. The
+/// viewer's own bookkeeping is not recorded.
+pub fn record(state: &mut StoreViewerState, label: &str, old_ref: Option<&str>, new_ref: Option<&str>) {
+    if label.starts_with(SYSTEM_LABEL_PREFIX) {
+        return;
+    }
+    let op = match (old_ref, new_ref) {
+        (_, None) => AuditOp::Delete,
+        (None, Some(_)) => AuditOp::Create,
+        (Some(_), Some(_)) => AuditOp::Update,
+    };
+    let entry = AuditEntry {
+        seq: state.audit.entries + 1,
+        op,
+        label: label.to_string(),
+        store: stores::selected(state).map(str::to_string),
+        old_ref: old_ref.map(str::to_string),
+        new_ref: new_ref.map(str::to_string),
+        at: state.clock_ms,
+        request: state.audit.active.clone(),
+    };
+    // The log lives in the viewer's own store whichever one was changed
+    match append(stores::home_id(state), &entry) {
+        Ok(()) => state.audit.entries = entry.seq,
+        Err(e) => log(&format!("Error appending to the audit log: {}", e)),
+    }
+}
+
+/// `GET /api/audit?label=&prefix=&op=&session=&since=&until=&before=&limit=`
+pub fn handle_audit(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Reading audit log");
+
+    let op = match query_param(&req.uri, "op") {
+        None => None,
+        Some(value) => match AuditOp::parse(&value) {
+            Some(op) => Some(op),
+            None => return Ok(error_response(400, &format!("Unknown op: {} (create, update or delete)", value))),
+        },
+    };
+    let since = match query_param(&req.uri, "since").map(|value| clock::parse_time(&value)) {
+        None => 0,
+        Some(Some(since)) => since,
+        Some(None) => return Ok(error_response(400, "since must be epoch milliseconds or an ISO 8601 time")),
+    };
+    let until = match query_param(&req.uri, "until").map(|value| clock::parse_time(&value)) {
+        None => u64::MAX,
+        Some(Some(until)) => until,
+        Some(None) => return Ok(error_response(400, "until must be epoch milliseconds or an ISO 8601 time")),
+    };
+    let before = match query_param(&req.uri, "before").map(|value| value.parse::<u64>()) {
+        None => state.audit.entries + 1,
+        Some(Ok(before)) => before.min(state.audit.entries + 1),
+        Some(Err(_)) => return Ok(error_response(400, "before must be an entry number")),
+    };
+    let limit = match query_param(&req.uri, "limit").map(|limit| limit.parse::<usize>()) {
+        None => DEFAULT_LIMIT,
+        Some(Ok(limit)) if limit > 0 => limit.min(MAX_LIMIT),
+        Some(_) => return Ok(error_response(400, "limit must be a positive number")),
+    };
+    let filter = Filter {
+        label: query_param(&req.uri, "label"),
+        prefix: query_param(&req.uri, "prefix"),
+        op,
+        session: query_param(&req.uri, "session"),
+        since,
+        until,
+    };
+
+    // Walk the segments back from the newest entry before `before`
+    let mut entries = Vec::new();
+    if before > 1 {
+        for segment in (0..=segment_of(before - 1)).rev() {
+            let mut segment_entries = read_segment(&state.store_id, segment)?;
+            segment_entries.reverse();
+            entries.extend(
+                segment_entries
+                    .into_iter()
+                    .filter(|entry| entry.seq < before && filter.matches(entry)),
+            );
+            if entries.len() >= limit {
+                break;
+            }
+        }
+    }
+    entries.truncate(limit);
+    let next_before = match entries.last() {
+        Some(entry) if entries.len() == limit && entry.seq > 1 => Some(entry.seq),
+        _ => None,
+    };
+
+    let response = AuditResponse { entries, total: state.audit.entries, next_before };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize audit log: {}", e))?;
+
+    Ok(json_response(200, body))
+}
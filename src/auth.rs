@@ -0,0 +1,181 @@
+//! Bearer token authentication for the API.
+//!
+//! When an `auth_token` is configured at init, every request to `/api/*`
+//! must carry it as `Authorization: Bearer <token>`. The check runs in the
+//! HTTP middleware, which can only let a request through or drop it without
+//! saying why. So the middleware lets every request through and marks those
+//! that passed, and `handle_request` answers any unmarked API request with
+//! `401` before it reaches a handler; a request the middleware never saw is
+//! refused too. Static assets, the API explorer page, shared labels under
+//! `/public/` and CORS preflights (which browsers send without credentials)
+//! stay open; the explorer's own requests carry the token the UI stored.
+//! Browsers cannot set headers on a WebSocket, so the event socket takes the
+//! token as `/ws?token=` instead.
+//!
+//! With `anonymous_access` configured as well, requests without the token are
+//! not refused outright: they may read (`GET` outside `/api/admin/`) at a
+//! shared rate of `requests_per_minute`, and everything else still needs the
+//! token. The rate is counted in fixed windows of the actor's clock, which
+//! anonymous clients are not allowed to move.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{cors, error_response, header, query_param};
+use serde::{Deserialize, Serialize};
+
+/// Header the middleware adds to requests that passed authentication.
+const AUTHENTICATED_HEADER: &str = "x-store-viewer-authenticated";
+
+/// Path prefix the middleware is registered for.
+pub const PROTECTED_PATH: &str = "/api";
+
+/// Descriptions of the API under `/api/`, which browsers navigate to and
+/// tools fetch without the token.
+pub const OPEN_PATHS: &[&str] = &["/api/docs", "/api/openapi.json"];
+
+const WINDOW_MS: u64 = 60 * 1000;
+
+fn default_requests_per_minute() -> u64 {
+    30
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AnonymousAccess {
+    /// Reads all clients without the token may make together per minute
+    #[serde(default = "default_requests_per_minute")]
+    pub requests_per_minute: u64,
+}
+
+impl AnonymousAccess {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.requests_per_minute == 0 {
+            return Err("anonymous_access.requests_per_minute must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Anonymous reads counted in the current window.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct AnonymousWindow {
+    start: u64,
+    requests: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Auth {
+    /// Token API requests must present; `None` leaves the API open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Read-only access for requests without the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anonymous: Option<AnonymousAccess>,
+    #[serde(default)]
+    anonymous_window: AnonymousWindow,
+}
+
+impl Auth {
+    pub fn new(token: Option<String>, anonymous: Option<AnonymousAccess>) -> Self {
+        Auth { token, anonymous, anonymous_window: AnonymousWindow::default() }
+    }
+}
+
+/// Compare without stopping at the first differing byte, so response times
+/// do not reveal how much of a guess was right.
+fn same_token(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn requires_token(req: &HttpRequest) -> bool {
+    let path = req.uri.split('?').next().unwrap_or("/");
+    (path == PROTECTED_PATH || path.starts_with("/api/"))
+        && !OPEN_PATHS.contains(&path)
+        && !(req.method == "OPTIONS" && cors::is_preflight(req))
+}
+
+/// Whether an anonymous client may make the request at all.
+fn is_anonymous_read(req: &HttpRequest) -> bool {
+    let path = req.uri.split('?').next().unwrap_or("/");
+    matches!(req.method.as_str(), "GET" | "HEAD") && !path.starts_with("/api/admin/")
+}
+
+/// Whether the request presents the configured token.
+fn presents_token(auth: &Auth, req: &HttpRequest) -> bool {
+    let expected = match &auth.token {
+        Some(token) => token,
+        None => return true,
+    };
+    header(req, "authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|presented| same_token(presented.trim(), expected))
+}
+
+/// Whether a WebSocket connection to `path` may receive events: it must
+/// carry the token in its query, as anonymous clients get no events.
+pub fn admits_socket(auth: &Auth, path: &str) -> bool {
+    match &auth.token {
+        Some(expected) => query_param(path, "token").is_some_and(|presented| same_token(&presented, expected)),
+        None => true,
+    }
+}
+
+/// Middleware step: pass the request on, marked if it presents the token.
+pub fn mark(auth: &Auth, mut req: HttpRequest) -> HttpRequest {
+    // Clients cannot mark requests themselves
+    req.headers.retain(|(name, _)| !name.eq_ignore_ascii_case(AUTHENTICATED_HEADER));
+    if presents_token(auth, &req) {
+        req.headers.push((AUTHENTICATED_HEADER.to_string(), "true".to_string()));
+    }
+    req
+}
+
+/// Whether the request presented the token, or none is needed.
+pub fn is_authenticated(auth: &Auth, req: &HttpRequest) -> bool {
+    auth.token.is_none() || header(req, AUTHENTICATED_HEADER).is_some()
+}
+
+/// Count an anonymous read; returns the `429` response once the window's
+/// reads are used up.
+fn admit_anonymous(auth: &mut Auth, limit: u64, now_ms: u64) -> Option<HttpResponse> {
+    let window = &mut auth.anonymous_window;
+    let start = now_ms - now_ms % WINDOW_MS;
+    if window.start < start {
+        *window = AnonymousWindow { start, requests: 0 };
+    }
+    if window.requests >= limit {
+        log("Rejected anonymous read over the rate limit");
+        let mut response = error_response(429, "Anonymous read rate exceeded; send a bearer token for full access");
+        let retry_after = (window.start + WINDOW_MS).saturating_sub(now_ms).div_ceil(1000);
+        response.headers.push(("Retry-After".to_string(), retry_after.to_string()));
+        return Some(response);
+    }
+    window.requests += 1;
+    None
+}
+
+/// `401` for an API request the middleware did not mark, unless anonymous
+/// reads are allowed and this is one within the rate limit.
+pub fn rejection(auth: &mut Auth, req: &HttpRequest, now_ms: u64) -> Option<HttpResponse> {
+    if !requires_token(req) || is_authenticated(auth, req) {
+        return None;
+    }
+    let message = match &auth.anonymous {
+        Some(anonymous) if is_anonymous_read(req) => {
+            let limit = anonymous.requests_per_minute;
+            return admit_anonymous(auth, limit, now_ms);
+        }
+        Some(_) => "Anonymous access is read-only; a valid bearer token is required",
+        None => "A valid bearer token is required",
+    };
+    let mut response = error_response(401, message);
+    response
+        .headers
+        .push(("WWW-Authenticate".to_string(), "Bearer realm=\"store-viewer\"".to_string()));
+    Some(response)
+}
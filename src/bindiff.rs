@@ -0,0 +1,162 @@
+//! Byte-range diff between two versions of a label.
+//!
+//! A version is identified by its content ref; the store keeps old content
+//! around, so any ref a label pointed at in the past can be compared with its
+//! current content. The diff is a summary rather than a patch: it reports how
+//! many bytes were added, removed or changed and roughly where.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::{error_response, json_response, query_param, StoreViewerState};
+use serde::{Deserialize, Serialize};
+
+/// Differing runs closer together than this are reported as one region.
+const MERGE_GAP: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionKind {
+    Changed,
+    Added,
+    Removed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiffRegion {
+    pub kind: RegionKind,
+    /// Offset of the region in the old content
+    pub old_offset: usize,
+    pub old_length: usize,
+    /// Offset of the region in the new content
+    pub new_offset: usize,
+    pub new_length: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BinaryDiff {
+    pub identical: bool,
+    pub old_size: usize,
+    pub new_size: usize,
+    pub bytes_added: usize,
+    pub bytes_removed: usize,
+    pub bytes_changed: usize,
+    pub regions: Vec<DiffRegion>,
+}
+
+/// Compare two byte strings.
+///
+/// The common prefix and suffix are trimmed first. If what remains has the
+/// same length on both sides it is compared byte by byte and the differing
+/// runs are reported as changed regions; otherwise it is reported as a single
+/// region where bytes were replaced, inserted or deleted.
+pub fn diff(old: &[u8], new: &[u8]) -> BinaryDiff {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut regions = Vec::new();
+    let mut bytes_changed = 0;
+
+    if old_mid.len() == new_mid.len() {
+        let mut start: Option<usize> = None;
+        let mut last_diff = 0;
+        for (i, (a, b)) in old_mid.iter().zip(new_mid).enumerate() {
+            if a == b {
+                continue;
+            }
+            bytes_changed += 1;
+            match start {
+                Some(s) if i - last_diff > MERGE_GAP => {
+                    regions.push(changed_region(prefix + s, last_diff - s + 1));
+                    start = Some(i);
+                }
+                Some(_) => {}
+                None => start = Some(i),
+            }
+            last_diff = i;
+        }
+        if let Some(s) = start {
+            regions.push(changed_region(prefix + s, last_diff - s + 1));
+        }
+    } else {
+        let kind = if old_mid.is_empty() {
+            RegionKind::Added
+        } else if new_mid.is_empty() {
+            RegionKind::Removed
+        } else {
+            RegionKind::Changed
+        };
+        bytes_changed = old_mid.len().min(new_mid.len());
+        regions.push(DiffRegion {
+            kind,
+            old_offset: prefix,
+            old_length: old_mid.len(),
+            new_offset: prefix,
+            new_length: new_mid.len(),
+        });
+    }
+
+    BinaryDiff {
+        identical: old == new,
+        old_size: old.len(),
+        new_size: new.len(),
+        bytes_added: new.len().saturating_sub(old.len()),
+        bytes_removed: old.len().saturating_sub(new.len()),
+        bytes_changed,
+        regions,
+    }
+}
+
+fn changed_region(offset: usize, length: usize) -> DiffRegion {
+    DiffRegion {
+        kind: RegionKind::Changed,
+        old_offset: offset,
+        old_length: length,
+        new_offset: offset,
+        new_length: length,
+    }
+}
+
+/// `GET /api/labels/{name}/bindiff?ref=<hash>` or `?label=<other>`: diff the
+/// given version (old side) against the label's current content (new side).
+pub fn handle_label_bindiff(state: &StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Binary diff for label: {}", label_name));
+
+    let current_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+
+    let base_ref = if let Some(hash) = query_param(&req.uri, "ref") {
+        let base_ref = ContentRef { hash };
+        if !store::exists(&state.store_id, &base_ref)? {
+            return Ok(error_response(404, &format!("Content not found: {}", base_ref.hash)));
+        }
+        base_ref
+    } else if let Some(other) = query_param(&req.uri, "label") {
+        match store::get_by_label(&state.store_id, &other)? {
+            Some(content_ref) => content_ref,
+            None => return Ok(error_response(404, &format!("Label not found: {}", other))),
+        }
+    } else {
+        return Ok(error_response(400, "Either ref or label query parameter is required"));
+    };
+
+    let old = store::get(&state.store_id, &base_ref)?;
+    let new = store::get(&state.store_id, &current_ref)?;
+
+    let body = serde_json::to_vec(&diff(&old, &new))
+        .map_err(|e| format!("Failed to serialize diff: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,334 @@
+//! Operations on many labels in one request.
+//!
+//! `POST /api/labels/bulk-delete` deletes a list of labels, or every label
+//! under a prefix or matching a glob, and reports the outcome per label: one
+//! label failing does not stop the others. Deletions go through the trash
+//! like single ones, so they can still be undone during the grace period,
+//! and a label that labels outside the selection still reference fails
+//! unless `?force=true` is given.
+//! `POST /api/labels/meta/bulk` edits the metadata of such a selection the
+//! same way.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::dryrun::{self, PlannedChange};
+use crate::charset::Charset;
+use crate::labelmeta::{self, present};
+use crate::{error_response, glob, json_response, references, trash, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Which labels a bulk request covers.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Selection {
+    Labels(Vec<String>),
+    Prefix { prefix: String },
+    Pattern { pattern: String },
+}
+
+/// Body of `POST /api/labels/meta/bulk`: one of `labels`, `prefix` or
+/// `pattern`, and the changes to make. A missing field is left unchanged;
+/// `null` clears it.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BulkMetaRequest {
+    #[serde(default)]
+    labels: Option<Vec<String>>,
+    #[serde(default)]
+    prefix: Option<String>,
+    #[serde(default)]
+    pattern: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    language: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    charset: Option<Option<String>>,
+    /// Shorthand for the `Content-Type` custom header
+    #[serde(default, deserialize_with = "present")]
+    content_type: Option<Option<String>>,
+    /// Custom headers to set, or with `null` to remove; others are kept
+    #[serde(default)]
+    headers: BTreeMap<String, Option<String>>,
+    #[serde(default)]
+    tags: Option<serde_json::Value>,
+    #[serde(default)]
+    acl: Option<serde_json::Value>,
+}
+
+impl BulkMetaRequest {
+    fn selection(&mut self) -> Result<Selection, String> {
+        match (self.labels.take(), self.prefix.take(), self.pattern.take()) {
+            (Some(labels), None, None) => Ok(Selection::Labels(labels)),
+            (None, Some(prefix), None) => Ok(Selection::Prefix { prefix }),
+            (None, None, Some(pattern)) => Ok(Selection::Pattern { pattern }),
+            _ => Err("Give exactly one of labels, prefix or pattern".to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MetaResult {
+    label: String,
+    success: bool,
+    /// Whether the label's metadata differs from before
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BulkMetaResponse {
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    dry_run: bool,
+    updated: usize,
+    unchanged: usize,
+    failed: usize,
+    results: Vec<MetaResult>,
+}
+
+#[derive(Serialize)]
+struct DeleteResult {
+    label: String,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// When the label stops being restorable, for labels moved to the trash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purge_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct BulkDeleteResponse {
+    deleted: usize,
+    failed: usize,
+    results: Vec<DeleteResult>,
+}
+
+/// The labels `selection` names, in order. Selections by prefix or glob never
+/// reach the viewer's own bookkeeping. On failure, returns the HTTP status
+/// and message to report.
+fn resolve(state: &StoreViewerState, selection: Selection) -> Result<Vec<String>, (u16, String)> {
+    let matches: Box<dyn Fn(&str) -> bool> = match selection {
+        Selection::Labels(labels) => return Ok(labels),
+        Selection::Prefix { prefix } if prefix.is_empty() => {
+            return Err((400, "prefix cannot be empty; use the pattern ** to select every label".to_string()));
+        }
+        Selection::Prefix { prefix } => Box::new(move |label| label.starts_with(&prefix)),
+        Selection::Pattern { pattern } => {
+            let regex = glob::compile(&pattern).map_err(|e| (400, e))?;
+            Box::new(move |label| regex.is_match(label))
+        }
+    };
+    Ok(store::list_labels(&state.store_id)
+        .map_err(|e| (500, e))?
+        .into_iter()
+        .filter(|label| !label.starts_with(SYSTEM_LABEL_PREFIX) && matches(label))
+        .collect())
+}
+
+fn failure(label: String, error: String) -> DeleteResult {
+    DeleteResult { label, success: false, error: Some(error), purge_at: None }
+}
+
+/// `POST /api/labels/bulk-delete` with `[names...]`, `{ "prefix" }` or
+/// `{ "pattern" }`
+pub fn handle_bulk_delete(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Deleting labels in bulk");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let selection: Selection = serde_json::from_slice(body)
+        .map_err(|_| "Expected an array of label names, { \"prefix\" } or { \"pattern\" }".to_string())?;
+
+    let labels = match resolve(state, selection) {
+        Ok(labels) => labels,
+        Err((500, e)) => return Err(e),
+        Err((status, e)) => return Ok(error_response(status, &e)),
+    };
+
+    // Labels deleted together may reference each other
+    let dependents = if references::is_forced(req) {
+        BTreeMap::new()
+    } else {
+        let selected: BTreeSet<String> = labels.iter().cloned().collect();
+        references::dependents_outside(&state.store_id, &selected)?
+    };
+
+    if dryrun::is_dry_run(req) {
+        let mut changes: Vec<PlannedChange> = Vec::new();
+        for label in &labels {
+            if label.starts_with(SYSTEM_LABEL_PREFIX) || dependents.contains_key(label) {
+                continue;
+            }
+            if let Some(current) = store::get_by_label(&state.store_id, label)? {
+                changes.push(dryrun::plan_delete(&state.store_id, label, &current)?);
+            }
+        }
+        return dryrun::dry_run_response(&changes);
+    }
+
+    let mut results = Vec::with_capacity(labels.len());
+    for label in labels {
+        if label.starts_with(SYSTEM_LABEL_PREFIX) {
+            results.push(failure(label, format!("Labels under {} are reserved", SYSTEM_LABEL_PREFIX)));
+            continue;
+        }
+        if let Some(referencing) = dependents.get(&label) {
+            let error = references::still_referenced(&label, referencing);
+            results.push(failure(label, error));
+            continue;
+        }
+        let current = match store::get_by_label(&state.store_id, &label) {
+            Ok(Some(current)) => current,
+            Ok(None) => {
+                results.push(failure(label, "Label not found".to_string()));
+                continue;
+            }
+            Err(e) => {
+                results.push(failure(label, e));
+                continue;
+            }
+        };
+        match trash::delete(state, "bulk-delete", &label, &current) {
+            Ok(purge_at) => results.push(DeleteResult { label, success: true, error: None, purge_at }),
+            Err(e) => results.push(failure(label, e)),
+        }
+    }
+
+    let deleted = results.iter().filter(|result| result.success).count();
+    let failed = results.len() - deleted;
+    log(&format!("Bulk delete: {} deleted, {} failed", deleted, failed));
+
+    let body = serde_json::to_vec(&BulkDeleteResponse { deleted, failed, results })
+        .map_err(|e| format!("Failed to serialize bulk delete: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// Custom headers after setting or removing `changes`; names compare without
+/// regard to case.
+fn merge_headers(current: &BTreeMap<String, String>, changes: &BTreeMap<String, Option<String>>) -> BTreeMap<String, String> {
+    let mut headers = current.clone();
+    for (name, value) in changes {
+        headers.retain(|existing, _| !existing.eq_ignore_ascii_case(name));
+        if let Some(value) = value {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+    headers
+}
+
+/// `POST /api/labels/meta/bulk` with a selection and `{ "language"?,
+/// "charset"?, "content_type"?, "headers"? }`
+pub fn handle_bulk_meta(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Editing label metadata in bulk");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let mut request: BulkMetaRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if request.tags.is_some() {
+        return Ok(error_response(400, "Editing tags is not supported: labels have no tags"));
+    }
+    if request.acl.is_some() {
+        return Ok(error_response(400, "Editing ACLs is not supported: access is granted by the API token, not per label"));
+    }
+    let selection = match request.selection() {
+        Ok(selection) => selection,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+
+    // Check the changes once, rather than failing every label the same way
+    if let Some(Some(tag)) = &request.language {
+        if let Err(e) = labelmeta::validate_language(tag) {
+            return Ok(error_response(400, &e));
+        }
+    }
+    let charset = match request.charset.take() {
+        Some(Some(name)) => match Charset::parse(&name) {
+            Some(charset) => Some(Some(charset)),
+            None => return Ok(error_response(400, &format!("Unsupported charset: {}", name))),
+        },
+        Some(None) => Some(None),
+        None => None,
+    };
+    if let Some(content_type) = request.content_type.take() {
+        request.headers.retain(|name, _| !name.eq_ignore_ascii_case("Content-Type"));
+        request.headers.insert("Content-Type".to_string(), content_type);
+    }
+    let set: BTreeMap<String, String> = request
+        .headers
+        .iter()
+        .filter_map(|(name, value)| value.clone().map(|value| (name.clone(), value)))
+        .collect();
+    if let Err(e) = labelmeta::validate_headers(&set) {
+        return Ok(error_response(400, &e));
+    }
+
+    let labels = match resolve(state, selection) {
+        Ok(labels) => labels,
+        Err((500, e)) => return Err(e),
+        Err((status, e)) => return Ok(error_response(status, &e)),
+    };
+
+    let dry_run = dryrun::is_dry_run(req);
+    let mut results = Vec::with_capacity(labels.len());
+    for label in labels {
+        let failure = |label, error| MetaResult { label, success: false, changed: false, error: Some(error) };
+        if label.starts_with(SYSTEM_LABEL_PREFIX) {
+            results.push(failure(label, format!("Labels under {} are reserved", SYSTEM_LABEL_PREFIX)));
+            continue;
+        }
+        match store::get_by_label(&state.store_id, &label) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                results.push(failure(label, "Label not found".to_string()));
+                continue;
+            }
+            Err(e) => {
+                results.push(failure(label, e));
+                continue;
+            }
+        }
+
+        let current = labelmeta::meta_for(state, &label).cloned().unwrap_or_default();
+        let mut meta = current.clone();
+        if let Some(language) = &request.language {
+            meta.language = language.clone();
+        }
+        if let Some(charset) = charset {
+            meta.charset = charset;
+        }
+        if !request.headers.is_empty() {
+            meta.headers = merge_headers(&meta.headers, &request.headers);
+            if let Err(e) = labelmeta::validate_headers(&meta.headers) {
+                results.push(failure(label, e));
+                continue;
+            }
+        }
+
+        let changed = meta != current;
+        if changed && !dry_run {
+            if meta.is_empty() {
+                state.label_meta.remove(&label);
+            } else {
+                state.label_meta.insert(label.clone(), meta);
+            }
+        }
+        results.push(MetaResult { label, success: true, changed, error: None });
+    }
+
+    let updated = results.iter().filter(|result| result.changed).count();
+    let failed = results.iter().filter(|result| !result.success).count();
+    let unchanged = results.len() - updated - failed;
+    log(&format!("Bulk metadata edit: {} updated, {} unchanged, {} failed", updated, unchanged, failed));
+
+    let response = BulkMetaResponse { dry_run, updated, unchanged, failed, results };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize bulk metadata edit: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,208 @@
+//! Release channels of labels.
+//!
+//! A label's current content is its `latest` channel. Other channels, such
+//! as `stable`, pin the label at a ref chosen by promoting from `latest` (or
+//! from another channel) with `POST /api/labels/{name}/channels`, so
+//! consumers reading `GET /api/labels/{name}?channel=stable` only see vetted
+//! versions while writers keep updating the label. Pins live in the actor
+//! state and name content by ref, which the store keeps however the label
+//! changes afterwards.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::{dryrun, error_response, json_response, query_param, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The channel that follows the label's current content.
+pub const LATEST: &str = "latest";
+
+/// Channel promoted to when the request names none.
+const DEFAULT_CHANNEL: &str = "stable";
+
+/// Channels one label may have besides `latest`.
+const MAX_CHANNELS: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelPin {
+    #[serde(rename = "ref")]
+    pub content_ref: String,
+    pub promoted_at: u64,
+    /// Channel the ref was promoted from, if it was not set directly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct PromoteRequest {
+    #[serde(default)]
+    channel: Option<String>,
+    /// Channel whose ref is promoted; `latest` when neither it nor `ref` is given
+    #[serde(default)]
+    from: Option<String>,
+    /// Pin this ref directly, e.g. to roll a channel back
+    #[serde(default, rename = "ref")]
+    content_ref: Option<String>,
+    /// Remove the channel instead
+    #[serde(default)]
+    remove: bool,
+}
+
+#[derive(Serialize)]
+struct ChannelsResponse<'a> {
+    label: &'a str,
+    /// Current ref of the label; absent once it has been deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest: Option<String>,
+    channels: BTreeMap<&'a str, &'a ChannelPin>,
+}
+
+#[derive(Serialize)]
+struct PromoteResponse<'a> {
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    dry_run: bool,
+    label: &'a str,
+    channel: &'a str,
+    /// Ref the channel now points at; absent after removal
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    content_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_ref: Option<String>,
+}
+
+fn validate_channel(channel: &str) -> Result<(), String> {
+    let valid = !channel.is_empty()
+        && channel.len() <= 32
+        && channel.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!("Invalid channel name: {} (lowercase letters, digits, - and _)", channel));
+    }
+    Ok(())
+}
+
+/// Ref `channel` of `label` points at, if it has one.
+fn channel_ref(state: &StoreViewerState, label: &str, channel: &str) -> Result<Option<String>, String> {
+    if channel == LATEST {
+        return Ok(store::get_by_label(&state.store_id, label)?.map(|r| r.hash));
+    }
+    Ok(state
+        .channels
+        .get(label)
+        .and_then(|channels| channels.get(channel))
+        .map(|pin| pin.content_ref.clone()))
+}
+
+/// Ref a read of `label` should serve: the label's current content, or with
+/// `?channel=` the ref that channel is pinned at. On failure, returns the HTTP
+/// status and message to report.
+pub fn resolve(state: &StoreViewerState, label: &str, req: &HttpRequest) -> Result<ContentRef, (u16, String)> {
+    let channel = query_param(&req.uri, "channel");
+    let channel = channel.as_deref().unwrap_or(LATEST);
+    match channel_ref(state, label, channel).map_err(|e| (500, e))? {
+        Some(hash) => Ok(ContentRef { hash }),
+        None if channel == LATEST => Err((404, format!("Label not found: {}", label))),
+        None => Err((404, format!("Label {} has no channel {}", label, channel))),
+    }
+}
+
+/// `GET /api/labels/{name}/channels`
+pub fn handle_list(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Listing channels of label: {}", label_name));
+
+    let latest = store::get_by_label(&state.store_id, label_name)?.map(|r| r.hash);
+    let pins = state.channels.get(label_name);
+    if latest.is_none() && pins.is_none() {
+        return Ok(error_response(404, &format!("Label not found: {}", label_name)));
+    }
+
+    let response = ChannelsResponse {
+        label: label_name,
+        latest,
+        channels: pins
+            .map(|pins| pins.iter().map(|(channel, pin)| (channel.as_str(), pin)).collect())
+            .unwrap_or_default(),
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize channels: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/labels/{name}/channels` with `{ "channel"?, "from"?, "ref"?, "remove"? }`
+pub fn handle_promote(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Promoting channel of label: {}", label_name));
+
+    let promote: PromoteRequest = match req.body.as_deref() {
+        Some(body) if !body.is_empty() => serde_json::from_slice(body)
+            .map_err(|e| format!("Invalid JSON: {}", e))?,
+        _ => PromoteRequest::default(),
+    };
+    let channel = promote.channel.as_deref().unwrap_or(DEFAULT_CHANNEL);
+    if let Err(e) = validate_channel(channel) {
+        return Ok(error_response(400, &e));
+    }
+    if channel == LATEST {
+        return Ok(error_response(400, "latest always follows the label; write the label to change it"));
+    }
+    let previous_ref = channel_ref(state, label_name, channel)?;
+
+    let content_ref = if promote.remove {
+        if previous_ref.is_none() {
+            return Ok(error_response(404, &format!("Label {} has no channel {}", label_name, channel)));
+        }
+        None
+    } else if let Some(hash) = &promote.content_ref {
+        if promote.from.is_some() {
+            return Ok(error_response(400, "Give either from or ref, not both"));
+        }
+        if !store::exists(&state.store_id, &ContentRef { hash: hash.clone() })? {
+            return Ok(error_response(404, &format!("Content not found: {}", hash)));
+        }
+        Some(hash.clone())
+    } else {
+        let from = promote.from.as_deref().unwrap_or(LATEST);
+        match channel_ref(state, label_name, from)? {
+            Some(hash) => Some(hash),
+            None if from == LATEST => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+            None => return Ok(error_response(404, &format!("Label {} has no channel {}", label_name, from))),
+        }
+    };
+
+    let pins = state.channels.get(label_name);
+    if content_ref.is_some() && previous_ref.is_none() && pins.is_some_and(|pins| pins.len() >= MAX_CHANNELS) {
+        return Ok(error_response(400, &format!("A label can have at most {} channels", MAX_CHANNELS)));
+    }
+
+    let dry_run = dryrun::is_dry_run(req);
+    if !dry_run {
+        match &content_ref {
+            Some(hash) => {
+                let pin = ChannelPin {
+                    content_ref: hash.clone(),
+                    promoted_at: state.clock_ms,
+                    from: promote.content_ref.is_none().then(|| promote.from.clone().unwrap_or_else(|| LATEST.to_string())),
+                };
+                state.channels.entry(label_name.to_string()).or_default().insert(channel.to_string(), pin);
+                log(&format!("Channel {} of {} now at {}", channel, label_name, hash));
+            }
+            None => {
+                if let Some(pins) = state.channels.get_mut(label_name) {
+                    pins.remove(channel);
+                    if pins.is_empty() {
+                        state.channels.remove(label_name);
+                    }
+                }
+                log(&format!("Removed channel {} of {}", channel, label_name));
+            }
+        }
+    }
+
+    let response = PromoteResponse { dry_run, label: label_name, channel, content_ref, previous_ref };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize channel promotion: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,127 @@
+//! Text encodings of label content.
+//!
+//! Content written through the API is UTF-8, but other actors may store text
+//! in the encoding it originally had. The encodings found in practice (UTF-8,
+//! UTF-16 in either byte order, Latin-1 and Windows-1252) can be detected and
+//! decoded so text previews always work on UTF-8.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Charset {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "iso-8859-1")]
+    Latin1,
+    #[serde(rename = "windows-1252")]
+    Windows1252,
+}
+
+/// Characters for bytes 0x80..=0x9F in Windows-1252; the five unassigned
+/// bytes map to the C1 control with the same value, as browsers do.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+impl Charset {
+    /// Parse an encoding name as used in `Content-Type` charset parameters.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Charset::Utf8),
+            "utf-16le" | "utf-16" => Some(Charset::Utf16Le),
+            "utf-16be" => Some(Charset::Utf16Be),
+            "iso-8859-1" | "latin1" | "latin-1" => Some(Charset::Latin1),
+            "windows-1252" | "cp1252" => Some(Charset::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// Name for `Content-Type` charset parameters.
+    pub fn name(self) -> &'static str {
+        match self {
+            Charset::Utf8 => "utf-8",
+            Charset::Utf16Le => "utf-16le",
+            Charset::Utf16Be => "utf-16be",
+            Charset::Latin1 => "iso-8859-1",
+            Charset::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// Decode `bytes` to a string; `None` if they are not valid in this
+    /// encoding. A leading byte-order mark is dropped.
+    pub fn decode(self, bytes: &[u8]) -> Option<Cow<'_, str>> {
+        match self {
+            Charset::Utf8 => {
+                let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+                std::str::from_utf8(bytes).ok().map(Cow::Borrowed)
+            }
+            Charset::Utf16Le | Charset::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return None;
+                }
+                let units = bytes.chunks_exact(2).map(|pair| match self {
+                    Charset::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+                let text: String = char::decode_utf16(units).collect::<Result<_, _>>().ok()?;
+                let text = text.strip_prefix('\u{FEFF}').map(str::to_string).unwrap_or(text);
+                Some(Cow::Owned(text))
+            }
+            Charset::Latin1 => Some(Cow::Owned(bytes.iter().map(|&b| char::from(b)).collect())),
+            Charset::Windows1252 => Some(Cow::Owned(
+                bytes
+                    .iter()
+                    .map(|&b| match b {
+                        0x80..=0x9F => WINDOWS_1252_HIGH[usize::from(b - 0x80)],
+                        _ => char::from(b),
+                    })
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// Guess the encoding of text content; `None` for content that does not look
+/// like text in any supported encoding.
+pub fn detect(bytes: &[u8]) -> Option<Charset> {
+    if bytes.starts_with(b"\xEF\xBB\xBF") {
+        return Some(Charset::Utf8);
+    }
+    if bytes.starts_with(b"\xFF\xFE") {
+        return Some(Charset::Utf16Le);
+    }
+    if bytes.starts_with(b"\xFE\xFF") {
+        return Some(Charset::Utf16Be);
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return Some(Charset::Utf8);
+    }
+
+    // UTF-16 text without a BOM: mostly-ASCII text has a zero in every
+    // other byte
+    if bytes.len() >= 4 && bytes.len().is_multiple_of(2) {
+        let pairs = bytes.len() / 2;
+        let zero_high = bytes.chunks_exact(2).filter(|p| p[1] == 0 && p[0] != 0).count();
+        let zero_low = bytes.chunks_exact(2).filter(|p| p[0] == 0 && p[1] != 0).count();
+        if zero_high * 10 >= pairs * 9 {
+            return Some(Charset::Utf16Le);
+        }
+        if zero_low * 10 >= pairs * 9 {
+            return Some(Charset::Utf16Be);
+        }
+    }
+
+    // Single-byte text has no control bytes besides whitespace
+    let single_byte_text = bytes
+        .iter()
+        .all(|&b| b >= 0x20 || matches!(b, b'\t' | b'\n' | b'\r' | 0x0C));
+    single_byte_text.then_some(Charset::Windows1252)
+}
@@ -0,0 +1,177 @@
+//! Command-style access to the store.
+//!
+//! `POST /api/cli` takes a single shell-like command and answers with its
+//! text output, so the web UI can offer a terminal or command palette without
+//! a dedicated call per operation. Commands go through the same checks as the
+//! equivalent API endpoints.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::dryrun::{self, ChangeAction};
+use crate::{
+    archival, error_response, labelmeta, naming, trash, validate_write, write_label, StoreViewerState,
+    SYSTEM_LABEL_PREFIX,
+};
+use serde::Deserialize;
+
+const HELP: &str = "\
+ls [prefix]      list labels, optionally only those under a prefix
+cat <label>      print a text label
+rm <label>       delete a label
+cp <from> <to>   copy a label's content to another label
+help             show this help
+";
+
+#[derive(Deserialize)]
+struct CliRequest {
+    command: String,
+}
+
+/// Split a command line into words; single or double quotes group words
+/// containing spaces.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err("Unterminated quote".to_string());
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn text_response(output: String) -> HttpResponse {
+    HttpResponse {
+        status: 200,
+        headers: vec![("Content-Type".to_string(), "text/plain; charset=utf-8".to_string())],
+        body: Some(output.into_bytes()),
+    }
+}
+
+fn ls(state: &StoreViewerState, prefix: &str) -> Result<HttpResponse, String> {
+    let mut output = String::new();
+    for label in store::list_labels(&state.store_id)? {
+        if label.starts_with(prefix) && !label.starts_with(SYSTEM_LABEL_PREFIX) && !archival::is_archived(state, &label) {
+            output.push_str(&label);
+            output.push('\n');
+        }
+    }
+    Ok(text_response(output))
+}
+
+fn cat(state: &mut StoreViewerState, label: &str) -> Result<HttpResponse, String> {
+    if label.starts_with(SYSTEM_LABEL_PREFIX) {
+        return Ok(error_response(403, &format!("cat: {}: reserved label", label)));
+    }
+    let content_ref = match store::get_by_label(&state.store_id, label)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("cat: {}: no such label", label))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+    let text = match labelmeta::decode_text(state, label, &content) {
+        Some(text) => text.into_owned(),
+        None => return Ok(error_response(415, &format!("cat: {}: binary content", label))),
+    };
+    state.stats.record_read(label, state.clock_ms);
+    Ok(text_response(text))
+}
+
+fn rm(state: &mut StoreViewerState, req: &HttpRequest, label: &str) -> Result<HttpResponse, String> {
+    if label.starts_with(SYSTEM_LABEL_PREFIX) {
+        return Ok(error_response(403, &format!("rm: {}: reserved label", label)));
+    }
+    let current_ref = match store::get_by_label(&state.store_id, label)? {
+        Some(current_ref) => current_ref,
+        None => return Ok(error_response(404, &format!("rm: {}: no such label", label))),
+    };
+    if dryrun::is_dry_run(req) {
+        let change = dryrun::plan_delete(&state.store_id, label, &current_ref)?;
+        return dryrun::dry_run_response(&[change]);
+    }
+    trash::delete(state, "cli_rm", label, &current_ref)?;
+    Ok(text_response(format!("removed {}\n", label)))
+}
+
+fn cp(state: &mut StoreViewerState, req: &HttpRequest, from: &str, to: &str) -> Result<HttpResponse, String> {
+    if from.starts_with(SYSTEM_LABEL_PREFIX) {
+        return Ok(error_response(403, &format!("cp: {}: reserved label", from)));
+    }
+    let content_ref = match store::get_by_label(&state.store_id, from)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("cp: {}: no such label", from))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    let change = dryrun::plan_write(&state.store_id, to, &content)?;
+    if change.action == ChangeAction::Create {
+        if let Err(e) = naming::check_name(&state.naming, to) {
+            return Ok(error_response(422, &format!("cp: {}", e)));
+        }
+    }
+    if let Err((status, e)) = validate_write(state, to, &content) {
+        return Ok(error_response(status, &format!("cp: {}", e)));
+    }
+    if dryrun::is_dry_run(req) {
+        return dryrun::dry_run_response(&[change]);
+    }
+
+    write_label(state, "cli_cp", to, &content)?;
+    state.stats.record_read(from, state.clock_ms);
+    if let Some(meta) = labelmeta::meta_for(state, from).cloned() {
+        state.label_meta.insert(to.to_string(), meta);
+    }
+    Ok(text_response(format!("copied {} to {}\n", from, to)))
+}
+
+/// `POST /api/cli` with `{ "command" }`
+pub fn handle_cli(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let cli_req: CliRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    log(&format!("Running command: {}", cli_req.command));
+
+    let words = match split_words(&cli_req.command) {
+        Ok(words) => words,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    let args: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] | ["help"] => Ok(text_response(HELP.to_string())),
+        ["ls"] => ls(state, ""),
+        ["ls", prefix] => ls(state, prefix),
+        ["cat", label] => cat(state, label),
+        ["rm", label] => rm(state, req, label),
+        ["cp", from, to] => cp(state, req, from, to),
+        [command @ ("ls" | "cat" | "rm" | "cp"), ..] => {
+            Ok(error_response(400, &format!("{}: wrong number of arguments; see help", command)))
+        }
+        [command, ..] => Ok(error_response(400, &format!("{}: unknown command; see help", command))),
+    }
+}
@@ -0,0 +1,261 @@
+//! Wall-clock time for an actor that has no clock import.
+//!
+//! The runtime does not expose the current time to the component, so the
+//! viewer learns it from the requests it serves: the bundled UI sends its own
+//! time in `X-Client-Time` (milliseconds since the Unix epoch), and other
+//! clients may send a standard HTTP `Date` header. The latest observed value is
+//! kept in the actor state and only ever moves forward, and at most
+//! `MAX_STEP_MS` per request, so one client with a wrong clock cannot throw
+//! the actor's time (and every deadline measured on it) far into the future.
+
+use crate::bindings::theater::simple::http_framework::HttpRequest;
+use crate::header;
+
+/// Header carrying the client's clock in milliseconds since the Unix epoch.
+pub const CLIENT_TIME_HEADER: &str = "x-client-time";
+
+/// Furthest a single request may move the clock forward once it is set.
+const MAX_STEP_MS: u64 = 60 * 60 * 1000;
+
+/// Extract the time a request was sent at, in milliseconds since the epoch.
+pub fn observed_time_ms(req: &HttpRequest) -> Option<u64> {
+    if let Some(ms) = header(req, CLIENT_TIME_HEADER).and_then(|v| v.parse::<u64>().ok()) {
+        return Some(ms);
+    }
+
+    header(req, "date").and_then(parse_http_date)
+}
+
+/// The clock after observing `observed` at `current`. Times from the past are
+/// ignored and times far ahead are only caught up with a step at a time; the
+/// first observation sets the clock outright.
+pub fn advance(current: u64, observed: u64) -> u64 {
+    if current == 0 {
+        return observed;
+    }
+    observed.clamp(current, current.saturating_add(MAX_STEP_MS))
+}
+
+/// Parse an IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`) into epoch millis.
+fn parse_http_date(value: &str) -> Option<u64> {
+    let mut parts = value.split_whitespace();
+    let _weekday = parts.next()?;
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month = match parts.next()? {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    let year: i64 = parts.next()?.parse().ok()?;
+
+    let mut hms = parts.next()?.split(':');
+    let hours: u64 = hms.next()?.parse().ok()?;
+    let minutes: u64 = hms.next()?.parse().ok()?;
+    let seconds: u64 = hms.next()?.parse().ok()?;
+
+    if parts.next()? != "GMT" {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+
+    let secs = days as u64 * 86_400 + hours * 3_600 + minutes * 60 + seconds;
+    Some(secs * 1000)
+}
+
+/// Parse an ISO 8601 / RFC 3339 timestamp (`2024-05-01T12:30:00.250Z`, also
+/// with a space separator, a comma before the fraction or a numeric offset)
+/// at the start of `value`. Timestamps without an offset are taken as UTC.
+/// Returns epoch millis and the number of bytes consumed.
+pub fn parse_iso8601_prefix(value: &str) -> Option<(u64, usize)> {
+    let b = value.as_bytes();
+    let digits = |from: usize, len: usize| -> Option<u64> {
+        let field = b.get(from..from + len)?;
+        if !field.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(field).ok()?.parse().ok()
+    };
+
+    let year = digits(0, 4)? as i64;
+    let month = digits(5, 2)?;
+    let day = digits(8, 2)?;
+    if b[4] != b'-' || b[7] != b'-' || !matches!(b.get(10), Some(b'T' | b't' | b' ')) {
+        return None;
+    }
+    let hours = digits(11, 2)?;
+    let minutes = digits(14, 2)?;
+    let seconds = digits(17, 2)?;
+    if b[13] != b':' || b[16] != b':' || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut pos = 19;
+    let mut millis = 0;
+    if matches!(b.get(pos), Some(b'.' | b',')) {
+        let start = pos + 1;
+        let end = start + b[start..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if end == start {
+            return None;
+        }
+        // Keep millisecond precision
+        let fraction = &value[start..end.min(start + 3)];
+        millis = fraction.parse::<u64>().ok()? * 10u64.pow(3 - fraction.len() as u32);
+        pos = end;
+    }
+
+    let mut offset_secs: i64 = 0;
+    match b.get(pos) {
+        Some(b'Z' | b'z') => pos += 1,
+        Some(&sign @ (b'+' | b'-')) => {
+            let offset_hours = digits(pos + 1, 2)? as i64;
+            let (offset_minutes, len) = if b.get(pos + 3) == Some(&b':') {
+                (digits(pos + 4, 2)? as i64, 6)
+            } else {
+                (digits(pos + 3, 2)? as i64, 5)
+            };
+            offset_secs = (offset_hours * 3_600 + offset_minutes * 60) * if sign == b'+' { 1 } else { -1 };
+            pos += len;
+        }
+        _ => {}
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400 + (hours * 3_600 + minutes * 60 + seconds) as i64 - offset_secs;
+    if secs < 0 {
+        return None;
+    }
+    Some((secs as u64 * 1000 + millis, pos))
+}
+
+/// Parse a point in time given as epoch millis or an ISO 8601 timestamp.
+pub fn parse_time(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(ms) = value.parse::<u64>() {
+        return Some(ms);
+    }
+    match parse_iso8601_prefix(value)? {
+        (ms, len) if len == value.len() => Some(ms),
+        _ => None,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian calendar date.
+fn days_from_civil(year: i64, month: u64, day: u64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Proleptic Gregorian calendar date of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u64, u64) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u64;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u64;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Format epoch millis as an IMF-fixdate, as HTTP date headers carry them.
+pub fn format_http_date(ms: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let secs = ms / 1000;
+    let days = secs / 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+    let time = secs % 86_400;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        time / 3_600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_epoch_millis_and_iso8601() {
+        assert_eq!(parse_time("1714566600250"), Some(1_714_566_600_250));
+        assert_eq!(parse_time(" 2024-05-01T12:30:00.250Z "), Some(1_714_566_600_250));
+        assert_eq!(parse_time("2024-05-01 12:30:00,25z"), Some(1_714_566_600_250));
+        assert_eq!(parse_time("2024-05-01T14:30:00+02:00"), Some(1_714_566_600_000));
+        assert_eq!(parse_time("2024-05-01T07:30:00-0500"), Some(1_714_566_600_000));
+        assert_eq!(parse_time("2024-05-01T12:30:00"), Some(1_714_566_600_000));
+        assert_eq!(parse_time("1970-01-01T00:00:00Z"), Some(0));
+    }
+
+    #[test]
+    fn rejects_malformed_times() {
+        let malformed = ["", "yesterday", "2024-05-01", "2024-13-01T00:00:00Z", "2024-05-01T12:30:00Zjunk", "2024-05-01T12:30:00."];
+        for value in malformed {
+            assert_eq!(parse_time(value), None, "{}", value);
+        }
+        // Before the epoch
+        assert_eq!(parse_time("1969-12-31T23:59:59Z"), None);
+    }
+
+    #[test]
+    fn iso8601_prefix_reports_bytes_consumed() {
+        let line = "2024-05-01T12:30:00.123456Z INFO started";
+        assert_eq!(parse_iso8601_prefix(line), Some((1_714_566_600_123, 27)));
+    }
+
+    #[test]
+    fn http_dates_round_trip() {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784_111_777_000));
+        assert_eq!(format_http_date(784_111_777_000), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format_http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn civil_dates_convert_both_ways() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        for days in [-719_468, -1, 0, 59, 11_016, 11_017, 19_844, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(19_844), (2024, 5, 1));
+    }
+
+    #[test]
+    fn clock_moves_forward_a_step_at_a_time() {
+        assert_eq!(advance(0, 5_000), 5_000);
+        assert_eq!(advance(5_000, 4_000), 5_000);
+        assert_eq!(advance(5_000, 6_000), 6_000);
+        assert_eq!(advance(5_000, u64::MAX), 5_000 + MAX_STEP_MS);
+    }
+}
@@ -0,0 +1,105 @@
+//! Comparing the labels of two stores.
+//!
+//! `GET /api/compare?left=&right=` lists the labels found in only one of two
+//! stores and those whose refs differ. Refs are content hashes, so equal refs
+//! mean equal content and nothing needs to be read; this makes it cheap to
+//! check that a migration or replication between stores is complete.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, json_response, query_param, stores, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+struct DifferingLabel {
+    label: String,
+    left_ref: String,
+    right_ref: String,
+}
+
+#[derive(Serialize)]
+struct CompareResponse {
+    left: String,
+    right: String,
+    only_left: Vec<String>,
+    only_right: Vec<String>,
+    different: Vec<DifferingLabel>,
+    /// Labels present in both stores with the same ref
+    identical: usize,
+    /// Whether the stores hold the same labels with the same content
+    equal: bool,
+}
+
+/// Ref of every label in `store_id` under `prefix`, leaving out the viewer's
+/// own bookkeeping.
+fn label_refs(store_id: &str, prefix: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut refs = BTreeMap::new();
+    for label in store::list_labels(store_id)? {
+        if label.starts_with(SYSTEM_LABEL_PREFIX) || !label.starts_with(prefix) {
+            continue;
+        }
+        if let Some(content_ref) = store::get_by_label(store_id, &label)? {
+            refs.insert(label, content_ref.hash);
+        }
+    }
+    Ok(refs)
+}
+
+/// `GET /api/compare?left=&right=&prefix=`; a side left out is the viewer's
+/// own store. Only the viewer's own and registered stores can be compared.
+pub fn handle_compare(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    let left = query_param(&req.uri, "left").unwrap_or_else(|| state.store_id.clone());
+    let right = query_param(&req.uri, "right").unwrap_or_else(|| state.store_id.clone());
+    let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+
+    log(&format!("Comparing store {} with {}", left, right));
+
+    if left.is_empty() || right.is_empty() {
+        return Ok(error_response(400, "left and right cannot be empty"));
+    }
+    if left == right {
+        return Ok(error_response(400, "left and right name the same store"));
+    }
+    if let Some(unknown) = [&left, &right].into_iter().find(|id| !stores::is_known(state, id)) {
+        return Ok(error_response(404, &format!("Store not registered: {}", unknown)));
+    }
+
+    let mut left_refs = match label_refs(&left, &prefix) {
+        Ok(refs) => refs,
+        Err(e) => return Ok(error_response(404, &format!("Store {} is not accessible: {}", left, e))),
+    };
+    let right_refs = match label_refs(&right, &prefix) {
+        Ok(refs) => refs,
+        Err(e) => return Ok(error_response(404, &format!("Store {} is not accessible: {}", right, e))),
+    };
+
+    let mut only_right = Vec::new();
+    let mut different = Vec::new();
+    let mut identical = 0;
+    for (label, right_ref) in right_refs {
+        match left_refs.remove(&label) {
+            None => only_right.push(label),
+            Some(left_ref) if left_ref == right_ref => identical += 1,
+            Some(left_ref) => different.push(DifferingLabel { label, left_ref, right_ref }),
+        }
+    }
+    // What is left appears on the left only
+    let only_left: Vec<String> = left_refs.into_keys().collect();
+
+    let response = CompareResponse {
+        equal: only_left.is_empty() && only_right.is_empty() && different.is_empty(),
+        left,
+        right,
+        only_left,
+        only_right,
+        different,
+        identical,
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize comparison: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,135 @@
+//! Building a label from the content of others.
+//!
+//! `POST /api/labels/{name}/compose` concatenates a list of labels and content
+//! refs in order and stores the result at `name`, so chunked uploads can be
+//! assembled and config fragments stitched together without the content
+//! making a round trip through the client.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::dryrun::{self, ChangeAction};
+use crate::duplicates::{self, DedupReport};
+use crate::{
+    error_response, json_response, labelmeta, naming, validate_write, write_label, StoreViewerState, SYSTEM_LABEL_PREFIX,
+};
+use serde::{Deserialize, Serialize};
+
+/// Parts a single composition may list.
+const MAX_PARTS: usize = 1000;
+
+#[derive(Deserialize)]
+struct Part {
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default, rename = "ref")]
+    content_ref: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ComposeRequest {
+    parts: Vec<Part>,
+    /// Inserted between consecutive parts
+    #[serde(default)]
+    separator: String,
+    #[serde(default)]
+    language: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ComposeResponse {
+    name: String,
+    #[serde(rename = "ref")]
+    content_ref: String,
+    size_bytes: usize,
+    parts: usize,
+    #[serde(flatten)]
+    dedup: DedupReport,
+}
+
+/// Content of one part, or the client error explaining why it has none.
+fn part_content(state: &StoreViewerState, index: usize, part: &Part) -> Result<Result<Vec<u8>, String>, String> {
+    let content_ref = match (&part.label, &part.content_ref) {
+        // The viewer's own bookkeeping is not readable through the API
+        (Some(label), None) if label.starts_with(SYSTEM_LABEL_PREFIX) => {
+            return Ok(Err(format!("Part {}: label not found: {}", index, label)));
+        }
+        (Some(label), None) => match store::get_by_label(&state.store_id, label)? {
+            Some(content_ref) => content_ref,
+            None => return Ok(Err(format!("Part {}: label not found: {}", index, label))),
+        },
+        (None, Some(hash)) => {
+            let content_ref = ContentRef { hash: hash.clone() };
+            if !store::exists(&state.store_id, &content_ref)? {
+                return Ok(Err(format!("Part {}: content not found: {}", index, hash)));
+            }
+            content_ref
+        }
+        _ => return Ok(Err(format!("Part {}: exactly one of label and ref is required", index))),
+    };
+    Ok(Ok(store::get(&state.store_id, &content_ref)?))
+}
+
+/// `POST /api/labels/{name}/compose`
+pub fn handle_compose(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Composing label: {}", label_name));
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let compose_req: ComposeRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if compose_req.parts.is_empty() {
+        return Ok(error_response(400, "At least one part is required"));
+    }
+    if compose_req.parts.len() > MAX_PARTS {
+        return Ok(error_response(400, &format!("At most {} parts can be composed", MAX_PARTS)));
+    }
+
+    let language = match labelmeta::request_language(req, compose_req.language) {
+        Ok(language) => language,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+
+    let mut content = Vec::new();
+    for (index, part) in compose_req.parts.iter().enumerate() {
+        if index > 0 {
+            content.extend_from_slice(compose_req.separator.as_bytes());
+        }
+        match part_content(state, index, part)? {
+            Ok(part) => content.extend(part),
+            Err(e) => return Ok(error_response(404, &e)),
+        }
+    }
+
+    let change = dryrun::plan_write(&state.store_id, label_name, &content)?;
+    if change.action == ChangeAction::Create {
+        if let Err(e) = naming::check_name(&state.naming, label_name) {
+            return Ok(error_response(422, &e));
+        }
+    }
+    if let Err((status, e)) = validate_write(state, label_name, &content) {
+        return Ok(error_response(status, &e));
+    }
+
+    if dryrun::is_dry_run(req) {
+        return dryrun::dry_run_response(&[change]);
+    }
+    let content_ref = write_label(state, "compose", label_name, &content)?;
+    labelmeta::record_write(state, label_name, language);
+
+    log(&format!("Composed {} from {} parts", label_name, compose_req.parts.len()));
+
+    let response = ComposeResponse {
+        name: label_name.to_string(),
+        dedup: duplicates::report(&state.store_id, Some(label_name), &content_ref.hash),
+        content_ref: content_ref.hash,
+        size_bytes: content.len(),
+        parts: compose_req.parts.len(),
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
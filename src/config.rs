@@ -0,0 +1,171 @@
+//! Configuration read at startup.
+//!
+//! Theater passes `init` the initial state named by the manifest's
+//! `init_state`. The viewer reads it as a JSON object of settings; every field
+//! is optional and falls back to the built-in default, so an actor started
+//! without any initial state behaves as before.
+
+use crate::bindings::theater::simple::http_framework::{ServerConfig, TlsConfig};
+use crate::auth::AnonymousAccess;
+use crate::cors::CorsConfig;
+use crate::mirror::MirrorConfig;
+use crate::views::ListingDefaults;
+use serde::{Deserialize, Serialize};
+
+/// Store the viewer opens when none is configured.
+const DEFAULT_STORE_ID: &str = "store-viewer";
+
+const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_HOST: &str = "0.0.0.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+/// Where the HTTP server listens, as chosen at startup.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerSettings {
+    pub port: u16,
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsSettings>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            port: DEFAULT_PORT,
+            host: DEFAULT_HOST.to_string(),
+            tls: None,
+        }
+    }
+}
+
+impl ServerSettings {
+    pub fn server_config(&self) -> ServerConfig {
+        ServerConfig {
+            port: Some(self.port),
+            host: Some(self.host.clone()),
+            tls_config: self.tls.as_ref().map(|tls| TlsConfig {
+                cert_path: tls.cert_path.clone(),
+                key_path: tls.key_path.clone(),
+            }),
+        }
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        if self.tls.is_some() {
+            "https"
+        } else {
+            "http"
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct InitConfig {
+    /// Existing store to browse and edit
+    #[serde(default)]
+    pub store_id: Option<String>,
+    /// Port to listen on; 0 lets the system pick one
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Address to bind to
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Certificate and key to serve HTTPS with
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+    /// Seconds a deleted label can still be restored; 0 deletes immediately
+    #[serde(default)]
+    pub deletion_grace_seconds: Option<u64>,
+    /// Origins allowed to call the API from the browser
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+    /// Bearer token required on every API request
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    /// Rate-limited read access for requests without `auth_token`
+    #[serde(default)]
+    pub anonymous_access: Option<AnonymousAccess>,
+    /// Further stores to offer besides `store_id`
+    #[serde(default)]
+    pub stores: Vec<String>,
+    /// Record every label change in the event log
+    #[serde(default)]
+    pub event_log: bool,
+    /// Secondary store every label change is also applied to
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+    /// Order, detail level and hidden prefixes of listings that do not ask
+    #[serde(default)]
+    pub listing: Option<ListingDefaults>,
+}
+
+impl InitConfig {
+    /// Parse the initial state given to `init`; absent or empty state means
+    /// all defaults.
+    pub fn parse(state: Option<&[u8]>) -> Result<Self, String> {
+        let bytes = match state {
+            Some(bytes) if !bytes.iter().all(u8::is_ascii_whitespace) => bytes,
+            _ => return Ok(InitConfig::default()),
+        };
+        let config: InitConfig = serde_json::from_slice(bytes)
+            .map_err(|e| format!("Invalid init config: {}", e))?;
+        if config.store_id.as_deref() == Some("") {
+            return Err("Invalid init config: store_id cannot be empty".to_string());
+        }
+        if config.host.as_deref() == Some("") {
+            return Err("Invalid init config: host cannot be empty".to_string());
+        }
+        if let Some(tls) = &config.tls {
+            if tls.cert_path.is_empty() || tls.key_path.is_empty() {
+                return Err("Invalid init config: tls needs cert_path and key_path".to_string());
+            }
+        }
+        if config.auth_token.as_deref().is_some_and(|token| token.trim().is_empty()) {
+            return Err("Invalid init config: auth_token cannot be empty".to_string());
+        }
+        if let Some(anonymous) = &config.anonymous_access {
+            if config.auth_token.is_none() {
+                return Err("Invalid init config: anonymous_access needs an auth_token".to_string());
+            }
+            anonymous.validate().map_err(|e| format!("Invalid init config: {}", e))?;
+        }
+        if config.stores.iter().any(String::is_empty) {
+            return Err("Invalid init config: stores cannot contain an empty id".to_string());
+        }
+        if let Some(cors) = &config.cors {
+            cors.validate().map_err(|e| format!("Invalid init config: {}", e))?;
+        }
+        if let Some(listing) = &config.listing {
+            listing.validate().map_err(|e| format!("Invalid init config: {}", e))?;
+        }
+        if let Some(mirror) = &config.mirror {
+            mirror.validate(&config.store_id()).map_err(|e| format!("Invalid init config: {}", e))?;
+        }
+        Ok(config)
+    }
+
+    pub fn store_id(&self) -> String {
+        self.store_id.clone().unwrap_or_else(|| DEFAULT_STORE_ID.to_string())
+    }
+
+    pub fn server(&self) -> ServerSettings {
+        let defaults = ServerSettings::default();
+        ServerSettings {
+            port: self.port.unwrap_or(defaults.port),
+            host: self.host.clone().unwrap_or(defaults.host),
+            tls: self.tls.clone(),
+        }
+    }
+
+    /// Grace period before deleted labels are purged, in milliseconds.
+    pub fn deletion_grace_ms(&self) -> Option<u64> {
+        self.deletion_grace_seconds.map(|seconds| seconds.saturating_mul(1000))
+    }
+}
@@ -0,0 +1,74 @@
+//! Direct access to content by ref.
+//!
+//! The store is content-addressed underneath its labels. `GET
+//! /api/content/{hash}` returns the bytes a ref names and `POST /api/content`
+//! stores the request body as is and answers with its ref, so other tools can
+//! exchange content with the store without creating labels. A ref always
+//! names the same bytes, so responses may be cached indefinitely.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::duplicates::{self, DedupReport};
+use crate::{error_response, etag, json_response, range, scanning, StoreViewerState};
+use serde::Serialize;
+
+/// Name used for unlabelled content in scan reports.
+const UNLABELLED: &str = "(unlabelled content)";
+
+#[derive(Serialize)]
+struct StoredContent {
+    #[serde(rename = "ref")]
+    content_ref: String,
+    size_bytes: usize,
+    /// Set when labels already hold the content
+    #[serde(flatten)]
+    dedup: DedupReport,
+}
+
+/// `GET /api/content/{hash}`, whole or in part (see `range`)
+pub fn handle_get_content(state: &StoreViewerState, hash: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Getting content: {}", hash));
+
+    let content_ref = ContentRef { hash: hash.to_string() };
+    if hash.is_empty() || !store::exists(&state.store_id, &content_ref)? {
+        return Ok(error_response(404, &format!("Content not found: {}", hash)));
+    }
+    let etag = etag::for_ref(hash);
+    if etag::none_match(req, &etag) {
+        return Ok(etag::not_modified(etag));
+    }
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    let headers = vec![
+        ("Content-Type".to_string(), "application/octet-stream".to_string()),
+        ("ETag".to_string(), etag.clone()),
+        ("Cache-Control".to_string(), "max-age=31536000, immutable".to_string()),
+    ];
+    Ok(range::respond(req, &etag, headers, content))
+}
+
+/// `POST /api/content` with the raw bytes as the body
+pub fn handle_store_content(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    let content = req.body.as_deref().unwrap_or_default();
+
+    log(&format!("Storing {} bytes of unlabelled content", content.len()));
+
+    // Content stored this way is still subject to the store-wide checks
+    if let Err((status, e)) = scanning::check_write(&state.scanning, UNLABELLED, content) {
+        return Ok(error_response(status, &e));
+    }
+
+    let content_ref = store::store(&state.store_id, content)?;
+
+    let response = StoredContent {
+        dedup: duplicates::report(&state.store_id, None, &content_ref.hash),
+        content_ref: content_ref.hash,
+        size_bytes: content.len(),
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,206 @@
+//! Cross-origin access to the API.
+//!
+//! Browsers only let a page on another origin call the API when the response
+//! says that origin may. The allowed origins, methods and request headers
+//! come from the `cors` init setting and can be changed at
+//! `/api/admin/cors`; with no origins configured (the default) no
+//! cross-origin access is granted. Preflight requests to any API path are
+//! answered here without reaching the handlers (other `OPTIONS` requests
+//! still get the endpoint description), and responses to
+//! allowed origins carry the `Access-Control-*` headers that let the page
+//! read them.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{error_response, header, json_response, StoreViewerState};
+use serde::{Deserialize, Serialize};
+
+/// Origin entry that allows every origin.
+const ANY: &str = "*";
+
+fn default_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "DELETE"].iter().map(|m| m.to_string()).collect()
+}
+
+fn default_headers() -> Vec<String> {
+    ["Content-Type", "Authorization", "X-API-Key", "X-Session-Id", "X-Client-Time", "If-Match", "If-None-Match", "Range", "If-Range"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect()
+}
+
+fn default_max_age_seconds() -> u64 {
+    600
+}
+
+/// Response headers pages on other origins may read.
+const EXPOSED_HEADERS: &str = "ETag, Content-Range, Retry-After, X-RateLimit-Limit, X-RateLimit-Remaining, \
+     X-RateLimit-Bytes-Limit, X-RateLimit-Bytes-Remaining, X-RateLimit-Reset";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins such as `https://app.example.com`, or `*` for any
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_methods")]
+    pub allowed_methods: Vec<String>,
+    /// Request headers a page may send, or `*` for any
+    #[serde(default = "default_headers")]
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache a preflight answer
+    #[serde(default = "default_max_age_seconds")]
+    pub max_age_seconds: u64,
+    /// Let pages send cookies and HTTP authentication
+    #[serde(default)]
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: default_methods(),
+            allowed_headers: default_headers(),
+            max_age_seconds: default_max_age_seconds(),
+            allow_credentials: false,
+        }
+    }
+}
+
+impl CorsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for origin in &self.allowed_origins {
+            if origin == ANY {
+                if self.allow_credentials {
+                    return Err("allow_credentials cannot be used with the * origin".to_string());
+                }
+                continue;
+            }
+            let host = origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"))
+                .unwrap_or_default();
+            if host.is_empty() || host.contains('/') {
+                return Err(format!("Invalid origin: {} (expected scheme://host[:port])", origin));
+            }
+        }
+        if let Some(method) = self.allowed_methods.iter().find(|m| m.is_empty() || m.to_uppercase() != **m) {
+            return Err(format!("Invalid method: {} (methods are upper case)", method));
+        }
+        Ok(())
+    }
+
+    fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == ANY || allowed == origin)
+    }
+
+    fn allows_header(&self, name: &str) -> bool {
+        self.allowed_headers.iter().any(|allowed| allowed == ANY || allowed.eq_ignore_ascii_case(name))
+    }
+
+    /// Value of `Access-Control-Allow-Origin` for an allowed origin.
+    fn allow_origin_value(&self, origin: &str) -> String {
+        if self.allowed_origins.iter().any(|allowed| allowed == ANY) {
+            ANY.to_string()
+        } else {
+            origin.to_string()
+        }
+    }
+}
+
+/// Whether an `OPTIONS` request is a browser's CORS preflight.
+pub fn is_preflight(req: &HttpRequest) -> bool {
+    header(req, "origin").is_some() && header(req, "access-control-request-method").is_some()
+}
+
+/// Answer a preflight. Preflights from origins, methods or headers that are
+/// not allowed get `403`.
+pub fn preflight(config: &CorsConfig, req: &HttpRequest) -> HttpResponse {
+    let methods = config.allowed_methods.join(", ");
+    let origin = header(req, "origin").unwrap_or_default();
+
+    if !config.allows_origin(origin) {
+        log(&format!("Refused CORS preflight from origin {}", origin));
+        return error_response(403, &format!("Origin not allowed: {}", origin));
+    }
+    let method = header(req, "access-control-request-method").unwrap_or_default();
+    if !config.allowed_methods.iter().any(|allowed| allowed == method) {
+        return error_response(403, &format!("Method not allowed for cross-origin requests: {}", method));
+    }
+    let requested_headers = header(req, "access-control-request-headers").unwrap_or_default();
+    let requested: Vec<&str> = requested_headers
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if let Some(name) = requested.iter().find(|name| !config.allows_header(name)) {
+        return error_response(403, &format!("Header not allowed for cross-origin requests: {}", name));
+    }
+
+    let allowed_headers = if config.allowed_headers.iter().any(|allowed| allowed == ANY) {
+        requested.join(", ")
+    } else {
+        config.allowed_headers.join(", ")
+    };
+    let mut headers = vec![
+        ("Access-Control-Allow-Origin".to_string(), config.allow_origin_value(origin)),
+        ("Access-Control-Allow-Methods".to_string(), methods),
+        ("Access-Control-Allow-Headers".to_string(), allowed_headers),
+        ("Access-Control-Max-Age".to_string(), config.max_age_seconds.to_string()),
+        ("Vary".to_string(), "Origin".to_string()),
+    ];
+    if config.allow_credentials {
+        headers.push(("Access-Control-Allow-Credentials".to_string(), "true".to_string()));
+    }
+    HttpResponse { status: 204, headers, body: None }
+}
+
+/// Add the CORS headers to a response for a request from an allowed origin.
+/// A label served with its own `Access-Control-Allow-Origin` keeps it.
+pub fn apply(config: &CorsConfig, req: &HttpRequest, response: &mut HttpResponse) {
+    let origin = match header(req, "origin") {
+        Some(origin) if config.allows_origin(origin) => origin,
+        _ => return,
+    };
+    if response.headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("access-control-allow-origin")) {
+        return;
+    }
+    let headers = &mut response.headers;
+    headers.push(("Access-Control-Allow-Origin".to_string(), config.allow_origin_value(origin)));
+    headers.push(("Access-Control-Expose-Headers".to_string(), EXPOSED_HEADERS.to_string()));
+    headers.push(("Vary".to_string(), "Origin".to_string()));
+    if config.allow_credentials {
+        headers.push(("Access-Control-Allow-Credentials".to_string(), "true".to_string()));
+    }
+}
+
+/// `GET /api/admin/cors`
+pub fn handle_get_config(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting CORS configuration");
+
+    let body = serde_json::to_vec(&state.cors)
+        .map_err(|e| format!("Failed to serialize CORS configuration: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/admin/cors`
+pub fn handle_put_config(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving CORS configuration");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let config: CorsConfig = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err(e) = config.validate() {
+        return Ok(error_response(400, &e));
+    }
+
+    log(&format!("Allowing cross-origin requests from: {}", config.allowed_origins.join(", ")));
+    state.cors = config;
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,313 @@
+//! Diff between two labels or versions.
+//!
+//! `GET /api/diff` compares any two pieces of content the store holds: the
+//! current content of a label, an earlier version of it from its history, or
+//! a bare content ref. Text is compared line by line and returned as a
+//! unified diff, the format code review tools and `patch` understand; binary
+//! content gets the byte-range summary of `bindiff`.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::bindiff::{self, BinaryDiff};
+use crate::{error_response, history, json_response, labelmeta, merge, query_param, StoreViewerState};
+use serde::Serialize;
+
+/// Unchanged lines shown around each change unless `?context=` says otherwise.
+const DEFAULT_CONTEXT: usize = 3;
+
+/// One side of a diff as resolved from the query.
+#[derive(Serialize)]
+struct Side {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    /// Writes back from the label's current content, when a version was asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<usize>,
+    #[serde(rename = "ref")]
+    content_ref: String,
+    size_bytes: usize,
+    #[serde(skip)]
+    content: Vec<u8>,
+}
+
+impl Side {
+    /// Name of the side in the diff's file headers.
+    fn path(&self) -> String {
+        match (&self.label, self.version) {
+            (Some(label), Some(version)) if version > 0 => format!("{}@{}", label, version),
+            (Some(label), _) => label.clone(),
+            (None, _) => self.content_ref.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Comparison {
+    Text {
+        lines_added: usize,
+        lines_removed: usize,
+        /// Unified diff from `a` to `b`; empty when they are identical
+        unified: String,
+    },
+    Binary {
+        #[serde(flatten)]
+        summary: BinaryDiff,
+    },
+}
+
+#[derive(Serialize)]
+struct DiffResponse {
+    a: Side,
+    b: Side,
+    identical: bool,
+    #[serde(flatten)]
+    comparison: Comparison,
+}
+
+enum Line {
+    Same(usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// Resolve side `name` (`a` or `b`) from `?{name}=<label>`, optionally with
+/// `?{name}_version=<n>` to go `n` writes back, or `?{name}_ref=<hash>`.
+/// `default_label` stands in when the side names neither. On failure,
+/// returns the HTTP status and message to report.
+fn resolve_side(state: &StoreViewerState, req: &HttpRequest, name: &str, default_label: Option<&str>) -> Result<Side, (u16, String)> {
+    let label = query_param(&req.uri, name);
+    let hash = query_param(&req.uri, &format!("{}_ref", name));
+    let version = match query_param(&req.uri, &format!("{}_version", name)) {
+        Some(value) => match value.parse::<usize>() {
+            Ok(version) => Some(version),
+            Err(_) => return Err((400, format!("{}_version must be a number of writes back", name))),
+        },
+        None => None,
+    };
+
+    let (label, hash) = match (label, hash) {
+        (Some(_), Some(_)) => return Err((400, format!("Give either {} or {}_ref, not both", name, name))),
+        (None, Some(_)) if version.is_some() => {
+            return Err((400, format!("{}_version needs {} to name a label", name, name)));
+        }
+        (None, Some(hash)) => (None, hash),
+        (label, None) => {
+            let label = match label.or_else(|| default_label.map(str::to_string)) {
+                Some(label) => label,
+                None => return Err((400, format!("{} or {}_ref is required", name, name))),
+            };
+            let current = store::get_by_label(&state.store_id, &label).map_err(|e| (500, e))?.map(|r| r.hash);
+            // The history's newest entry is normally the current content, but
+            // labels written before history was kept have no entry for it
+            let mut refs = history::refs(&state.store_id, &label).map_err(|e| (500, e))?;
+            if let Some(current) = &current {
+                if refs.first() != Some(current) {
+                    refs.insert(0, current.clone());
+                }
+            }
+            let back = version.unwrap_or(0);
+            let hash = match (back, current) {
+                (0, Some(current)) => current,
+                (0, None) => return Err((404, format!("Label not found: {}", label))),
+                _ => match refs.into_iter().nth(back) {
+                    Some(hash) => hash,
+                    None => return Err((404, format!("{} has no version {} writes back", label, back))),
+                },
+            };
+            (Some(label), hash)
+        }
+    };
+
+    let content_ref = ContentRef { hash };
+    if !store::exists(&state.store_id, &content_ref).map_err(|e| (500, e))? {
+        return Err((404, format!("Content not found: {}", content_ref.hash)));
+    }
+    let content = store::get(&state.store_id, &content_ref).map_err(|e| (500, e))?;
+    Ok(Side {
+        label,
+        version,
+        content_ref: content_ref.hash,
+        size_bytes: content.len(),
+        content,
+    })
+}
+
+/// Line-level edit script turning `a` into `b`.
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<Line> {
+    let mut script = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (x, y) in merge::common_lines(a, b).into_iter().chain(std::iter::once((a.len(), b.len()))) {
+        script.extend((i..x).map(Line::Removed));
+        script.extend((j..y).map(Line::Added));
+        if x < a.len() && y < b.len() {
+            script.push(Line::Same(x));
+        }
+        (i, j) = (x + 1, y + 1);
+    }
+    script
+}
+
+/// Start of a hunk range in a unified diff header: 1-based, or the line
+/// before an empty range.
+fn hunk_start(first: usize, len: usize) -> usize {
+    if len == 0 {
+        first
+    } else {
+        first + 1
+    }
+}
+
+/// Unified diff of `a` and `b`, given their edit script, with `context`
+/// unchanged lines around each change.
+fn unified(a_path: &str, b_path: &str, a: &[&str], b: &[&str], script: &[Line], context: usize) -> String {
+    let changes: Vec<usize> = script
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(..)))
+        .map(|(index, _)| index)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Hunks as ranges of the script, merged where their context overlaps
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for index in changes {
+        let (start, end) = (index.saturating_sub(context), (index + context + 1).min(script.len()));
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", a_path, b_path);
+    for (start, end) in hunks {
+        // Position in both files where the hunk starts
+        let (a_line, b_line) = script[..start].iter().fold((0, 0), |(a_line, b_line), line| match line {
+            Line::Same(..) => (a_line + 1, b_line + 1),
+            Line::Removed(_) => (a_line + 1, b_line),
+            Line::Added(_) => (a_line, b_line + 1),
+        });
+        let hunk = &script[start..end];
+        let a_len = hunk.iter().filter(|line| !matches!(line, Line::Added(_))).count();
+        let b_len = hunk.iter().filter(|line| !matches!(line, Line::Removed(_))).count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk_start(a_line, a_len),
+            a_len,
+            hunk_start(b_line, b_len),
+            b_len
+        ));
+        for line in hunk {
+            match *line {
+                Line::Same(i) => {
+                    out.push(' ');
+                    out.push_str(a[i]);
+                }
+                Line::Removed(i) => {
+                    out.push('-');
+                    out.push_str(a[i]);
+                }
+                Line::Added(j) => {
+                    out.push('+');
+                    out.push_str(b[j]);
+                }
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// `GET /api/diff?a=<label>&b=<label>`; each side may instead be a
+/// `_ref=<hash>`, or go back through the label's history with `_version=<n>`.
+/// `b` defaults to the current content of `a`'s label.
+pub fn handle_diff(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Diffing content");
+
+    let context = match query_param(&req.uri, "context").map(|value| value.parse::<usize>()) {
+        None => DEFAULT_CONTEXT,
+        Some(Ok(context)) => context,
+        Some(Err(_)) => return Ok(error_response(400, "context must be a number of lines")),
+    };
+    let a = match resolve_side(state, req, "a", None) {
+        Ok(side) => side,
+        Err((500, e)) => return Err(e),
+        Err((status, e)) => return Ok(error_response(status, &e)),
+    };
+    let b = match resolve_side(state, req, "b", a.label.as_deref()) {
+        Ok(side) => side,
+        Err((500, e)) => return Err(e),
+        Err((status, e)) => return Ok(error_response(status, &e)),
+    };
+
+    let identical = a.content == b.content;
+    let a_text = labelmeta::decode_text(state, a.label.as_deref().unwrap_or_default(), &a.content);
+    let b_text = labelmeta::decode_text(state, b.label.as_deref().unwrap_or_default(), &b.content);
+    let comparison = match (&a_text, &b_text) {
+        (Some(a_text), Some(b_text)) => {
+            let (a_lines, b_lines): (Vec<&str>, Vec<&str>) = (a_text.lines().collect(), b_text.lines().collect());
+            if a_lines.len().max(b_lines.len()) > merge::MAX_LINES {
+                return Ok(error_response(413, &format!("Text diffs are limited to {} lines per side", merge::MAX_LINES)));
+            }
+            let script = edit_script(&a_lines, &b_lines);
+            Comparison::Text {
+                lines_added: script.iter().filter(|line| matches!(line, Line::Added(_))).count(),
+                lines_removed: script.iter().filter(|line| matches!(line, Line::Removed(_))).count(),
+                unified: unified(&a.path(), &b.path(), &a_lines, &b_lines, &script, context),
+            }
+        }
+        _ => Comparison::Binary { summary: bindiff::diff(&a.content, &b.content) },
+    };
+
+    log(&format!("Diffed {} against {}", a.content_ref, b.content_ref));
+
+    let response = DiffResponse { a, b, identical, comparison };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize diff: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(a: &str, b: &str, context: usize) -> String {
+        let a: Vec<&str> = a.lines().collect();
+        let b: Vec<&str> = b.lines().collect();
+        unified("a", "b", &a, &b, &edit_script(&a, &b), context)
+    }
+
+    #[test]
+    fn identical_content_has_an_empty_diff() {
+        assert_eq!(diff("one\ntwo\n", "one\ntwo\n", 3), "");
+    }
+
+    #[test]
+    fn changes_come_with_context() {
+        let a = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let b = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+        assert_eq!(diff(a, b, 1), "--- a/a\n+++ b/b\n@@ -4,3 +4,3 @@\n 4\n-5\n+five\n 6\n");
+    }
+
+    #[test]
+    fn distant_changes_get_separate_hunks() {
+        let a = "1\n2\n3\n4\n5\n6\n7\n";
+        let b = "one\n2\n3\n4\n5\n6\nseven\n";
+        assert_eq!(
+            diff(a, b, 1),
+            "--- a/a\n+++ b/b\n@@ -1,2 +1,2 @@\n-1\n+one\n 2\n@@ -6,2 +6,2 @@\n 6\n-7\n+seven\n"
+        );
+        // With more context the hunks merge
+        assert_eq!(diff(a, b, 3).matches("@@ -").count(), 1);
+    }
+
+    #[test]
+    fn empty_sides_start_before_the_first_line() {
+        assert_eq!(diff("", "new\n", 3), "--- a/a\n+++ b/b\n@@ -0,0 +1,1 @@\n+new\n");
+        assert_eq!(diff("old\n", "", 3), "--- a/a\n+++ b/b\n@@ -1,1 +0,0 @@\n-old\n");
+    }
+}
@@ -0,0 +1,462 @@
+//! Self-description of the HTTP API.
+//!
+//! Every API endpoint is listed here with its method, a one-line summary and
+//! the query parameters it understands. `OPTIONS` on an API path answers with
+//! the entries for that path, so a client can find out what an instance
+//! supports before using it. `GET /api/docs` renders the same entries as a
+//! page with a form per endpoint, so they can be tried from a browser, and
+//! `GET /api` returns all of them at once together with the viewer's version
+//! and store, for actors and tools that work against whichever instance they
+//! are pointed at.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{auth, error_response, escape_html, json_response, maintenance, StoreViewerState};
+use serde::Serialize;
+
+/// Content type of the request bodies the API accepts.
+const JSON: &str = "application/json";
+
+/// Content type the API explorer sends raw bodies with.
+const RAW_BODY: &str = "application/octet-stream";
+
+/// Endpoints taking the request body as raw bytes rather than JSON.
+const RAW_BODY_PATHS: &[&str] = &["/api/content", "/api/labels/{name}/append"];
+
+const DRY_RUN: (&str, &str) = ("dry_run", "`true` to validate and report the changes without writing");
+
+const FORCE: (&str, &str) = ("force", "`true` to delete labels that other labels still reference");
+
+const STAGE: (&str, &str) = ("stage", "`true` to hold the labels for review under /api/staging instead of writing them");
+
+pub struct Endpoint {
+    pub method: &'static str,
+    /// Path with `{placeholders}`; a placeholder spans one or more segments
+    pub path: &'static str,
+    pub summary: &'static str,
+    /// Query parameters as `(name, description)`
+    pub params: &'static [(&'static str, &'static str)],
+}
+
+const fn endpoint(
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    params: &'static [(&'static str, &'static str)],
+) -> Endpoint {
+    Endpoint { method, path, summary, params }
+}
+
+pub const ENDPOINTS: &[Endpoint] = &[
+    endpoint("GET", "/api", "Every endpoint with its parameters, and the viewer's version and store", &[]),
+    endpoint("GET", "/api/docs", "This API as a page of forms to try each endpoint from", &[]),
+    endpoint("GET", "/api/openapi.json", "This API as an OpenAPI 3 document", &[]),
+    endpoint("GET", "/api/labels", "List label names", &[
+        ("prefix", "Only labels starting with this"),
+        ("glob", "Only labels matching this pattern (`*`, `**`, `?`)"),
+        ("sort", "`name`, `most_accessed` or `least_accessed`; the default view's otherwise"),
+        ("front_matter", "`true` to include front-matter fields"),
+        ("expiry", "`true` to include retention expiry times"),
+        ("details", "`true` to include size, content ref and text flag; `false` overrides the default view"),
+        ("system", "`true` to include `__system/` labels"),
+        ("archived", "`true` to include archived labels"),
+        ("hidden", "`true` to include labels under the default view's hidden prefixes"),
+        ("fm.<field>", "Only labels whose front matter has this field value"),
+        ("offset", "Matching labels to skip (default 0)"),
+        ("limit", "Most labels to return; all of them without it"),
+        ("legacy", "`true` for a bare array instead of `{ items, total, offset, limit, generated_at }`"),
+        ("fields", "Comma-separated fields to return for each label, e.g. `name,size_bytes,content_ref`"),
+        ("store", "A registered store to list instead; accepted by every /api/labels endpoint"),
+    ]),
+    endpoint("POST", "/api/labels", "Create a label from `{ name, content, language? }`", &[DRY_RUN]),
+    endpoint("POST", "/api/labels/bulk-delete", "Delete a list of labels, or those under a prefix or matching a glob", &[DRY_RUN, FORCE]),
+    endpoint("POST", "/api/labels/meta/bulk", "Set language, charset, content type or headers of a list of labels, or those under a prefix or matching a glob", &[DRY_RUN]),
+    endpoint("POST", "/api/labels/head", "Compare client refs with the server's", &[]),
+    endpoint("GET", "/api/labels/{name}", "Fetch a label's content", &[
+        ("diff_since", "Content ref to return a JSON Patch from instead"),
+        ("channel", "Release channel to read, e.g. `stable`; `latest` by default"),
+        ("fields", "Comma-separated fields to return, e.g. `name,size_bytes,content_ref`"),
+    ]),
+    endpoint("HEAD", "/api/labels/{name}", "Status, Content-Type, Content-Length and ETag of a label without its content", &[
+        ("channel", "Release channel to check, e.g. `stable`; `latest` by default"),
+    ]),
+    endpoint("PUT", "/api/labels/{name}", "Replace a label's content with `{ content, condition?, language? }`; honors `If-Match`", &[DRY_RUN]),
+    endpoint("DELETE", "/api/labels/{name}", "Delete a label, restorable until its grace period ends", &[DRY_RUN, FORCE]),
+    endpoint("GET", "/api/labels/{name}/stats", "Read and write counts of a label", &[]),
+    endpoint("GET", "/api/labels/{name}/dependencies", "Labels this label references", &[]),
+    endpoint("GET", "/api/labels/{name}/dependents", "Labels that reference this label", &[]),
+    endpoint("GET", "/api/labels/{name}/bindiff", "Byte-range diff against another version", &[
+        ("ref", "Content ref to compare with"),
+        ("label", "Other label to compare with"),
+    ]),
+    endpoint("GET", "/api/labels/{name}/raw", "Stored bytes with the detected Content-Type; honours Range", &[
+        ("offset", "First byte to return, for clients that cannot send Range"),
+        ("length", "Bytes to return from offset"),
+        ("channel", "Release channel to serve"),
+        ("download", "`true` to send it as an attachment named after the label"),
+    ]),
+    endpoint("GET", "/api/labels/{name}/hex", "Hex and ASCII dump of a byte range", &[
+        ("offset", "First byte to dump (default 0)"),
+        ("length", "Bytes to dump (default 1024, at most 65536)"),
+    ]),
+    endpoint("GET", "/api/labels/{name}/wasm-info", "Imports, exports and sections of a WebAssembly binary", &[]),
+    endpoint("GET", "/api/labels/{name}/manifest-info", "Parsed Theater actor manifest", &[]),
+    endpoint("GET", "/api/labels/{name}/entries", "Members of an archive label", &[]),
+    endpoint("GET", "/api/labels/{name}/entries/{path}", "Content of one archive member", &[]),
+    endpoint("GET", "/api/labels/{name}/outline", "Key tree of a JSON label", &[
+        ("depth", "Levels to expand (default 2)"),
+        ("path", "Subtree to expand"),
+    ]),
+    endpoint("GET", "/api/labels/{name}/json", "Pretty-printed JSON, or the line and column of the first syntax error", &[]),
+    endpoint("GET", "/api/labels/{name}/resolved", "Content with `{{label:<name>}}` references inlined recursively", &[]),
+    endpoint("GET", "/api/labels/{name}/search", "Line numbers and byte offsets of a string in a text label", &[
+        ("q", "Text to find"),
+        ("regex", "`true` to take `q` as a regular expression"),
+        ("case_sensitive", "`true` to match case"),
+        ("from", "Byte offset to continue from, the previous page's `next_offset`"),
+        ("limit", "Most matches to return (default 100, at most 1000)"),
+    ]),
+    endpoint("GET", "/api/labels/{name}/loglines", "Parsed entries of a log label", &[
+        ("level", "Minimum level"),
+        ("since", "Only entries at or after this time"),
+        ("limit", "Maximum entries (default 1000)"),
+    ]),
+    endpoint("POST", "/api/labels/{name}/merge", "Three-way merge of an edit with the current content", &[]),
+    endpoint("POST", "/api/labels/{name}/compose", "Store the concatenation of other labels and refs", &[DRY_RUN]),
+    endpoint("POST", "/api/labels/{name}/rename", "Move a label to `{ to }` without copying its content", &[DRY_RUN]),
+    endpoint("POST", "/api/labels/{name}/undelete", "Restore a deleted label within its grace period", &[
+        ("id", "Pending deletion to restore, as listed in /api/trash; the latest without it"),
+        DRY_RUN,
+    ]),
+    endpoint("GET", "/api/labels/{name}/history", "Earlier versions of a label, newest first", &[]),
+    endpoint("POST", "/api/labels/{name}/restore", "Write an earlier version `{ ref }` back to the label", &[DRY_RUN]),
+    endpoint("GET", "/api/labels/{name}/channels", "Release channels of a label and the refs they are pinned at", &[]),
+    endpoint("POST", "/api/labels/{name}/channels", "Promote `{ channel?, from? | ref? }` (stable from latest by default), or `remove` a channel", &[DRY_RUN]),
+    endpoint("GET", "/api/labels/{name}/meta", "Recorded language and charset", &[]),
+    endpoint("PUT", "/api/labels/{name}/meta", "Set language and charset", &[DRY_RUN]),
+    endpoint("POST", "/api/labels/{name}/append", "Append the raw request body to a label, creating it if needed", &[
+        ("newline", "`true` to keep each append on lines of its own"),
+        DRY_RUN,
+    ]),
+    endpoint("PUT", "/api/labels/{name}/field", "Set one field of a JSON label to the JSON value in the body", &[
+        ("path", "Field to set, as keys separated by `.`; numbers index arrays"),
+        DRY_RUN,
+    ]),
+    endpoint("GET", "/api/admin/cors", "Cross-origin configuration", &[]),
+    endpoint("POST", "/api/admin/cors", "Set the allowed origins, methods and headers", &[]),
+    endpoint("GET", "/api/admin/error-budget", "Failure rates per route and recent alerts", &[]),
+    endpoint("POST", "/api/admin/error-budget", "Set the error budget window and thresholds", &[]),
+    endpoint("GET", "/api/admin/maintenance", "Whether the store is in maintenance, why and until when", &[]),
+    endpoint("POST", "/api/admin/maintenance", "Start or end maintenance, during which writes get `503`", &[]),
+    endpoint("GET", "/api/admin/event-log", "Whether the event log is enabled and how many events it holds", &[]),
+    endpoint("POST", "/api/admin/event-log", "Enable or disable the event log", &[]),
+    endpoint("GET", "/api/admin/health", "Thresholds of the health grade", &[]),
+    endpoint("POST", "/api/admin/health", "Set the size quota and the health thresholds", &[]),
+    endpoint("GET", "/api/admin/health/backups", "Backups reported by backup jobs", &[]),
+    endpoint("POST", "/api/admin/health/backups", "Report a backup, or that it was verified", &[]),
+    endpoint("GET", "/api/admin/listing", "Default sort, detail level and hidden prefixes of the label listing", &[]),
+    endpoint("POST", "/api/admin/listing", "Set the default listing view", &[]),
+    endpoint("GET", "/api/admin/mirror", "Mirror store, lag and queued changes", &[]),
+    endpoint("POST", "/api/admin/mirror", "Set or clear the store changes are mirrored to", &[]),
+    endpoint("GET", "/api/admin/quotas", "Default and per-key quotas", &[]),
+    endpoint("POST", "/api/admin/quotas", "Set a key's quota or the default", &[]),
+    endpoint("DELETE", "/api/admin/quotas", "Remove a quota", &[("key", "Key whose quota to remove; the default without it")]),
+    endpoint("GET", "/api/admin/routes", "Registered routes with their handlers, middleware and features", &[]),
+    endpoint("GET", "/api/admin/scanning", "Content scan configuration", &[]),
+    endpoint("POST", "/api/admin/scanning", "Set the content scan configuration", &[]),
+    endpoint("POST", "/api/admin/scanning/check", "Scan content without writing it", &[]),
+    endpoint("GET", "/api/admin/startup", "Self-test results and capabilities from startup", &[]),
+    endpoint("GET", "/api/admin/usage", "Request and byte usage per key", &[]),
+    endpoint("GET", "/api/archive", "Archived labels", &[]),
+    endpoint("POST", "/api/archive", "Move idle labels under `archive/`", &[DRY_RUN]),
+    endpoint("POST", "/api/unarchive", "Move archived labels back", &[DRY_RUN]),
+    endpoint("POST", "/api/cli", "Run `{ command }` (`ls`, `cat`, `rm`, `cp`, `help`) and return its text output", &[DRY_RUN]),
+    endpoint("POST", "/api/content", "Store the request body and return its ref", &[]),
+    endpoint("GET", "/api/content/{hash}", "Bytes stored under a content ref; honours Range", &[
+        ("offset", "First byte to return, for clients that cannot send Range"),
+        ("length", "Bytes to return from offset"),
+    ]),
+    endpoint("GET", "/api/duplicates", "Groups of labels with identical content", &[]),
+    endpoint("POST", "/api/duplicates/dedup", "Alias duplicate labels to one ref", &[DRY_RUN]),
+    endpoint("GET", "/api/editor", "Editor rules", &[]),
+    endpoint("POST", "/api/editor", "Save an editor rule", &[]),
+    endpoint("DELETE", "/api/editor", "Remove an editor rule", &[("prefix", "Prefix of the rule")]),
+    endpoint("GET", "/api/audit", "Label changes with the request that made each, newest first", &[
+        ("label", "Only changes to this label"),
+        ("prefix", "Only changes to labels under this prefix"),
+        ("op", "Only `create`, `update` or `delete`"),
+        ("session", "Only changes made with this `X-Session-Id`"),
+        ("since", "Only changes at or after this time, as epoch milliseconds or ISO 8601"),
+        ("until", "Only changes at or before this time"),
+        ("before", "Only entries numbered below this, to read on from `next_before`"),
+        ("limit", "Most entries to return (default 100, at most 1000)"),
+    ]),
+    endpoint("GET", "/api/event-log", "Recorded label changes, oldest first", &[
+        ("since", "Only events after this number"),
+        ("limit", "Most events to return (default 1000)"),
+    ]),
+    endpoint("POST", "/api/event-log/replay", "Rebuild the labels as of a moment in a new store", &[DRY_RUN]),
+    endpoint("GET", "/api/health", "Green, yellow or red grade of the store with the reasons", &[]),
+    endpoint("GET", "/api/lint", "Lint rules", &[]),
+    endpoint("POST", "/api/lint", "Save a lint rule", &[]),
+    endpoint("DELETE", "/api/lint", "Remove a lint rule", &[("prefix", "Prefix of the rule")]),
+    endpoint("GET", "/api/diff", "Unified diff of two labels, versions or refs", &[
+        ("a", "Label to diff from"),
+        ("a_version", "Writes back through a's history (default 0, the current content)"),
+        ("a_ref", "Content ref to diff from instead of a label"),
+        ("b", "Label to diff to; a's label by default"),
+        ("b_version", "Writes back through b's history"),
+        ("b_ref", "Content ref to diff to instead of a label"),
+        ("context", "Unchanged lines around each change (default 3)"),
+    ]),
+    endpoint("GET", "/api/compare", "Labels that differ between two stores", &[("left", "First store; the viewer's by default"), ("right", "Second store; the viewer's by default"), ("prefix", "Only compare labels under this prefix")]),
+    endpoint("GET", "/api/stores", "The viewer's store and the registered ones", &[]),
+    endpoint("POST", "/api/stores", "Register a store, or create one when no id is given", &[]),
+    endpoint("DELETE", "/api/stores/{id}", "Stop offering a registered store", &[]),
+    endpoint("GET", "/api/stores/{id}/labels", "List the labels of a registered store; every /api/labels endpoint works under /api/stores/{id}", &[]),
+    endpoint("GET", "/api/export", "Every label as a file of a tar archive", &[
+        ("prefix", "Only export labels under this prefix"),
+        ("format", "`tar` (default) or `tar.gz`"),
+    ]),
+    endpoint("GET", "/api/history", "Labels and bytes taken up by label history", &[]),
+    endpoint("POST", "/api/history/prune", "Apply history limits now", &[
+        ("prefix", "Only prune the history of labels under this prefix"),
+        ("deleted", "`true` to drop the history of labels that no longer exist"),
+        DRY_RUN,
+    ]),
+    endpoint("POST", "/api/import", "Write the labels of a JSON object mapping names to content", &[DRY_RUN, STAGE]),
+    endpoint("POST", "/api/import/store", "Copy labels from another store", &[DRY_RUN, STAGE]),
+    endpoint("GET", "/api/journal", "Pending and interrupted journal entries", &[]),
+    endpoint("GET", "/api/metadata/export", "Export the metadata index", &[]),
+    endpoint("POST", "/api/metadata/import", "Import a metadata index", &[("mode", "`replace` (default) or `merge`")]),
+    endpoint("GET", "/api/naming", "Naming rules", &[]),
+    endpoint("POST", "/api/naming", "Save a naming rule", &[]),
+    endpoint("DELETE", "/api/naming", "Remove a naming rule", &[("prefix", "Prefix of the rule")]),
+    endpoint("GET", "/api/notifications", "Rules routing label changes to WebSocket topics", &[]),
+    endpoint("POST", "/api/notifications", "Route changes under `{ prefix }` to `{ topic }`", &[]),
+    endpoint("DELETE", "/api/notifications", "Remove a notification rule", &[("prefix", "Prefix of the rule")]),
+    endpoint("POST", "/api/promote", "Copy labels from one prefix to another", &[DRY_RUN]),
+    endpoint("GET", "/api/promotions", "Past promotions", &[]),
+    endpoint("GET", "/api/promotions/{id}", "One promotion with per-label diffs", &[]),
+    endpoint("GET", "/api/retention", "Retention policies", &[]),
+    endpoint("POST", "/api/retention", "Save a retention policy", &[]),
+    endpoint("DELETE", "/api/retention", "Remove a retention policy", &[("prefix", "Prefix of the policy")]),
+    endpoint("GET", "/api/retention/report", "Upcoming retention actions", &[]),
+    endpoint("GET", "/api/schedule", "Scheduled writes", &[]),
+    endpoint("POST", "/api/schedule", "Schedule a write", &[DRY_RUN]),
+    endpoint("DELETE", "/api/schedule/{id}", "Cancel a scheduled write", &[]),
+    endpoint("GET", "/api/schemas", "Schema rules", &[]),
+    endpoint("POST", "/api/schemas", "Register a schema rule", &[]),
+    endpoint("DELETE", "/api/schemas", "Remove a schema rule", &[("prefix", "Prefix of the rule")]),
+    endpoint("GET", "/api/shares", "Publicly shared prefixes", &[]),
+    endpoint("POST", "/api/shares", "Share a prefix under `/public/`", &[]),
+    endpoint("DELETE", "/api/shares", "Stop sharing a prefix", &[("prefix", "Shared prefix")]),
+    endpoint("GET", "/api/staging", "Imports staged for review", &[]),
+    endpoint("GET", "/api/staging/{id}", "Review a staged import: planned changes and rejections", &[]),
+    endpoint("POST", "/api/staging/{id}/commit", "Write every label of a staged import, or none", &[DRY_RUN]),
+    endpoint("DELETE", "/api/staging/{id}", "Discard a staged import", &[]),
+    endpoint("GET", "/api/stats/timeline", "Daily samples of store usage", &[("since", "Only samples from this time on")]),
+    endpoint("GET", "/api/trash", "Deleted labels that can still be restored", &[]),
+    endpoint("GET", "/api/undo", "Changes the session can undo", &[]),
+    endpoint("POST", "/api/undo", "Revert the session's most recent change", &[DRY_RUN]),
+    endpoint("GET", "/api/views", "Saved views", &[]),
+    endpoint("POST", "/api/views", "Save a view", &[]),
+    endpoint("GET", "/api/views/{name}/run", "Run a saved view", &[]),
+    endpoint("DELETE", "/api/views/{name}", "Delete a saved view", &[]),
+];
+
+#[derive(Serialize)]
+struct ParamDoc {
+    name: &'static str,
+    description: &'static str,
+}
+
+#[derive(Serialize)]
+struct MethodDoc {
+    method: &'static str,
+    summary: &'static str,
+    /// Content types accepted as the request body
+    accepts: Vec<&'static str>,
+    params: Vec<ParamDoc>,
+}
+
+/// An endpoint described on its own, with its path.
+#[derive(Serialize)]
+struct EndpointDoc {
+    path: &'static str,
+    #[serde(flatten)]
+    doc: MethodDoc,
+}
+
+#[derive(Serialize)]
+struct RootResponse<'a> {
+    name: &'static str,
+    version: &'static str,
+    /// The viewer's own store
+    store_id: &'a str,
+    /// Further stores reachable with `?store=` or `/api/stores/{id}/`
+    stores: Vec<&'a str>,
+    /// Whether writes from this client are refused: during maintenance, or
+    /// when it reads anonymously
+    read_only: bool,
+    endpoints: Vec<EndpointDoc>,
+}
+
+#[derive(Serialize)]
+struct OptionsResponse {
+    path: &'static str,
+    methods: Vec<&'static str>,
+    endpoints: Vec<MethodDoc>,
+}
+
+fn matches(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((segment, rest)) if segment.starts_with('{') => (1..=path.len()).any(|n| matches(rest, &path[n..])),
+        Some((segment, rest)) => path.first() == Some(segment) && matches(rest, &path[1..]),
+    }
+}
+
+/// Path pattern documenting `path`; the most specific one when several match.
+pub fn pattern_for(path: &str) -> Option<&'static str> {
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    ENDPOINTS
+        .iter()
+        .map(|endpoint| endpoint.path)
+        .filter(|pattern| matches(&pattern.split('/').collect::<Vec<_>>(), &segments))
+        .max_by_key(|pattern| pattern.split('/').filter(|s| !s.starts_with('{')).count())
+}
+
+/// Endpoints served at exactly `pattern`.
+pub fn endpoints_at(pattern: &str) -> impl Iterator<Item = &'static Endpoint> + '_ {
+    ENDPOINTS.iter().filter(move |endpoint| endpoint.path == pattern)
+}
+
+/// Content types an endpoint accepts as the request body.
+pub fn accepts(endpoint: &Endpoint) -> Vec<&'static str> {
+    match endpoint.method {
+        "POST" | "PUT" if RAW_BODY_PATHS.contains(&endpoint.path) => vec!["*/*"],
+        "POST" | "PUT" => vec![JSON],
+        _ => Vec::new(),
+    }
+}
+
+fn method_doc(endpoint: &Endpoint) -> MethodDoc {
+    MethodDoc {
+        method: endpoint.method,
+        summary: endpoint.summary,
+        accepts: accepts(endpoint),
+        params: endpoint
+            .params
+            .iter()
+            .map(|&(name, description)| ParamDoc { name, description })
+            .collect(),
+    }
+}
+
+/// `GET /api`
+pub fn handle_root(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Describing the API");
+
+    let response = RootResponse {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        store_id: &state.store_id,
+        stores: state.stores.registered.keys().map(String::as_str).collect(),
+        read_only: maintenance::is_active(state) || !auth::is_authenticated(&state.auth, req),
+        endpoints: ENDPOINTS
+            .iter()
+            .map(|endpoint| EndpointDoc { path: endpoint.path, doc: method_doc(endpoint) })
+            .collect(),
+    };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize API description: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `OPTIONS /api/*`
+pub fn handle_options(path: &str) -> Result<HttpResponse, String> {
+    log(&format!("Describing API path: {}", path));
+
+    let pattern = match pattern_for(path) {
+        Some(pattern) => pattern,
+        None => return Ok(error_response(404, &format!("No API endpoint at {}", path))),
+    };
+
+    let endpoints: Vec<MethodDoc> = endpoints_at(pattern).map(method_doc).collect();
+    let mut methods: Vec<&'static str> = endpoints.iter().map(|doc| doc.method).collect();
+    methods.push("OPTIONS");
+
+    let allow = methods.join(", ");
+    let body = serde_json::to_vec(&OptionsResponse { path: pattern, methods, endpoints })
+        .map_err(|e| format!("Failed to serialize API description: {}", e))?;
+
+    let mut response = json_response(200, body);
+    response.headers.push(("Allow".to_string(), allow));
+    Ok(response)
+}
+
+/// Form for trying `endpoint` on the API explorer page.
+fn render_endpoint(endpoint: &Endpoint) -> String {
+    let accepts = accepts(endpoint);
+    let content_type = match accepts.first() {
+        Some(&"*/*") => RAW_BODY,
+        _ => JSON,
+    };
+    let search = format!("{} {} {}", endpoint.method, endpoint.path, endpoint.summary).to_lowercase();
+
+    let mut html = format!(
+        "<section class=\"docs-endpoint\" data-search=\"{}\">\n<form data-method=\"{}\" data-path=\"{}\" data-accepts=\"{}\">\n",
+        escape_html(&search),
+        endpoint.method,
+        escape_html(endpoint.path),
+        content_type
+    );
+    html.push_str(&format!(
+        "<div class=\"docs-summary\"><span class=\"docs-method docs-method-{}\">{}</span><code>{}</code><span>{}</span></div>\n",
+        endpoint.method.to_lowercase(),
+        endpoint.method,
+        escape_html(endpoint.path),
+        escape_html(endpoint.summary)
+    ));
+    let placeholders = endpoint
+        .path
+        .split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'));
+    for name in placeholders {
+        html.push_str(&format!(
+            "<label class=\"docs-param\"><span>{{{0}}}</span><input data-kind=\"path\" name=\"{0}\" required></label>\n",
+            escape_html(name)
+        ));
+    }
+    for &(name, description) in endpoint.params {
+        html.push_str(&format!(
+            "<label class=\"docs-param\"><span>?{0}</span><input data-kind=\"query\" name=\"{0}\" placeholder=\"{1}\" title=\"{1}\"></label>\n",
+            escape_html(name),
+            escape_html(description)
+        ));
+    }
+    if !accepts.is_empty() {
+        html.push_str(&format!(
+            "<textarea class=\"docs-body\" placeholder=\"Request body ({})\"></textarea>\n",
+            content_type
+        ));
+    }
+    html.push_str("<button type=\"submit\" class=\"btn-primary\">Send</button>\n<pre class=\"docs-response hidden\"></pre>\n</form>\n</section>\n");
+    html
+}
+
+/// `GET /api/docs`
+pub fn handle_docs() -> Result<HttpResponse, String> {
+    log("Rendering API explorer");
+
+    let endpoints: String = ENDPOINTS.iter().map(render_endpoint).collect();
+    let html = include_str!("../assets/docs.html").replace("{{endpoints}}", &endpoints);
+
+    Ok(HttpResponse {
+        status: 200,
+        headers: vec![("Content-Type".to_string(), "text/html; charset=utf-8".to_string())],
+        body: Some(html.into_bytes()),
+    })
+}
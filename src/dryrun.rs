@@ -0,0 +1,111 @@
+//! Dry-run support for mutating endpoints.
+//!
+//! Any mutating label endpoint accepts `?dry_run=true`. The request goes
+//! through exactly the same validation as a real one, but instead of writing,
+//! the handler answers with the list of changes it would have made.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::store;
+use crate::{json_response, query_param};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    /// The label does not exist yet
+    Create,
+    /// The label exists and its content would change
+    Overwrite,
+    /// The label exists with identical content
+    Unchanged,
+    /// The label would be re-pointed at another ref
+    Alias,
+    /// The label would be removed
+    Delete,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PlannedChange {
+    pub label: String,
+    pub action: ChangeAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<usize>,
+    /// Ref the label would point at, when known before writing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_size_bytes: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct DryRunResponse<'a> {
+    dry_run: bool,
+    changes: &'a [PlannedChange],
+}
+
+/// Whether the request asks for a dry run.
+pub fn is_dry_run(req: &HttpRequest) -> bool {
+    matches!(query_param(&req.uri, "dry_run").as_deref(), Some("true") | Some("1"))
+}
+
+/// Describe what writing `content` to `label` would do.
+pub fn plan_write(store_id: &str, label: &str, content: &[u8]) -> Result<PlannedChange, String> {
+    let (action, previous_ref, previous_size_bytes) = match store::get_by_label(store_id, label)? {
+        Some(current) => {
+            let existing = store::get(store_id, &current)?;
+            let action = if existing == content {
+                ChangeAction::Unchanged
+            } else {
+                ChangeAction::Overwrite
+            };
+            (action, Some(current.hash), Some(existing.len()))
+        }
+        None => (ChangeAction::Create, None, None),
+    };
+
+    Ok(PlannedChange {
+        label: label.to_string(),
+        action,
+        size_bytes: Some(content.len()),
+        content_ref: None,
+        previous_ref,
+        previous_size_bytes,
+    })
+}
+
+/// Describe deleting `label`, which currently points at `current`.
+pub fn plan_delete(store_id: &str, label: &str, current: &store::ContentRef) -> Result<PlannedChange, String> {
+    let existing = store::get(store_id, current)?;
+    Ok(PlannedChange {
+        label: label.to_string(),
+        action: ChangeAction::Delete,
+        size_bytes: None,
+        content_ref: None,
+        previous_ref: Some(current.hash.clone()),
+        previous_size_bytes: Some(existing.len()),
+    })
+}
+
+/// Describe moving `from`, which currently points at `current`, to `to`.
+pub fn plan_move(store_id: &str, from: &str, to: &str, current: &store::ContentRef) -> Result<Vec<PlannedChange>, String> {
+    let removal = plan_delete(store_id, from, current)?;
+    let size = removal.previous_size_bytes;
+    let target = PlannedChange {
+        label: to.to_string(),
+        action: ChangeAction::Alias,
+        size_bytes: size,
+        content_ref: Some(current.hash.clone()),
+        previous_ref: None,
+        previous_size_bytes: None,
+    };
+    Ok(vec![removal, target])
+}
+
+/// Response body reporting the planned changes of a dry run.
+pub fn dry_run_response(changes: &[PlannedChange]) -> Result<HttpResponse, String> {
+    let body = serde_json::to_vec(&DryRunResponse { dry_run: true, changes })
+        .map_err(|e| format!("Failed to serialize dry run: {}", e))?;
+    Ok(json_response(200, body))
+}
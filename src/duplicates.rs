@@ -0,0 +1,239 @@
+//! Duplicate-content finder.
+//!
+//! Labels pointing at the same content ref already share storage, but the same
+//! bytes can also end up under several refs (for instance after a store
+//! migration). Both cases are reported here, grouped by content, together with
+//! the space that could be reclaimed by aliasing every label in a group to a
+//! single ref. Writes report on the spot when the content they stored is
+//! already held by other labels, so re-uploads of existing artifacts show.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::dryrun::{self, ChangeAction, PlannedChange};
+use crate::{json_response, write_label, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+pub struct DuplicateGroup {
+    /// Ref every label in the group is aliased to by a dedup
+    pub canonical_ref: String,
+    /// All refs holding this content
+    pub refs: Vec<String>,
+    pub labels: Vec<String>,
+    pub size_bytes: usize,
+    /// Bytes held by the non-canonical refs
+    pub reclaimable_bytes: usize,
+}
+
+/// Part of a write response telling whether other labels already held the
+/// content written.
+#[derive(Serialize, Debug, Default)]
+pub struct DedupReport {
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub deduplicated: bool,
+    /// Other labels pointing at the same ref
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub duplicate_of: Vec<String>,
+}
+
+impl DedupReport {
+    fn new(duplicate_of: Vec<String>) -> Self {
+        DedupReport { deduplicated: !duplicate_of.is_empty(), duplicate_of }
+    }
+}
+
+#[derive(Serialize)]
+struct DuplicatesResponse {
+    groups: Vec<DuplicateGroup>,
+    total_reclaimable_bytes: usize,
+}
+
+#[derive(Deserialize, Default)]
+struct DedupRequest {
+    /// Limit the dedup to the group with this canonical ref
+    #[serde(default)]
+    canonical_ref: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AliasedLabel {
+    label: String,
+    from_ref: String,
+    to_ref: String,
+}
+
+#[derive(Serialize)]
+struct DedupResponse {
+    aliased: Vec<AliasedLabel>,
+    reclaimable_bytes: usize,
+}
+
+/// Every label of the store, grouped by the ref it points at.
+pub fn labels_by_ref(store_id: &str) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let mut by_ref: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for label in store::list_labels(store_id)? {
+        if let Some(content_ref) = store::get_by_label(store_id, &label)? {
+            by_ref.entry(content_ref.hash).or_default().push(label);
+        }
+    }
+    Ok(by_ref)
+}
+
+/// Which labels besides `label` hold `content_ref`, given the store's labels
+/// by ref. The viewer's own bookkeeping is not reported.
+pub fn report_from(by_ref: &BTreeMap<String, Vec<String>>, label: Option<&str>, content_ref: &str) -> DedupReport {
+    let mut others: Vec<String> = by_ref
+        .get(content_ref)
+        .into_iter()
+        .flatten()
+        .filter(|other| Some(other.as_str()) != label && !other.starts_with(SYSTEM_LABEL_PREFIX))
+        .cloned()
+        .collect();
+    others.sort();
+    DedupReport::new(others)
+}
+
+/// Which labels besides `label` hold `content_ref`. Called once the write has
+/// happened, so a failure is logged rather than failing the request.
+pub fn report(store_id: &str, label: Option<&str>, content_ref: &str) -> DedupReport {
+    match labels_by_ref(store_id) {
+        Ok(by_ref) => report_from(&by_ref, label, content_ref),
+        Err(e) => {
+            log(&format!("Error checking for duplicate content: {}", e));
+            DedupReport::default()
+        }
+    }
+}
+
+/// Group every label in the store by content, keeping only groups of two or
+/// more labels.
+pub fn find_duplicates(store_id: &str) -> Result<Vec<DuplicateGroup>, String> {
+    // Labels sharing a ref; the viewer's own bookkeeping is never aliased
+    let mut by_ref = labels_by_ref(store_id)?;
+    for labels in by_ref.values_mut() {
+        labels.retain(|label| !label.starts_with(SYSTEM_LABEL_PREFIX));
+    }
+    by_ref.retain(|_, labels| !labels.is_empty());
+
+    // Refs holding identical bytes
+    let mut by_content: BTreeMap<Vec<u8>, Vec<String>> = BTreeMap::new();
+    for hash in by_ref.keys() {
+        let content = store::get(store_id, &ContentRef { hash: hash.clone() })?;
+        by_content.entry(content).or_default().push(hash.clone());
+    }
+
+    let mut groups = Vec::new();
+    for (content, refs) in by_content {
+        let mut labels: Vec<String> = refs.iter().flat_map(|r| by_ref[r].clone()).collect();
+        if labels.len() < 2 {
+            continue;
+        }
+        labels.sort();
+
+        // The ref with the most labels is kept so the fewest labels move
+        let canonical_ref = refs
+            .iter()
+            .max_by(|a, b| by_ref[*a].len().cmp(&by_ref[*b].len()).then_with(|| b.cmp(a)))
+            .cloned()
+            .unwrap_or_default();
+
+        groups.push(DuplicateGroup {
+            canonical_ref,
+            reclaimable_bytes: content.len() * (refs.len() - 1),
+            size_bytes: content.len(),
+            refs,
+            labels,
+        });
+    }
+
+    groups.sort_by(|a, b| {
+        b.reclaimable_bytes
+            .cmp(&a.reclaimable_bytes)
+            .then_with(|| b.labels.len().cmp(&a.labels.len()))
+    });
+    Ok(groups)
+}
+
+pub fn handle_list_duplicates(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Finding duplicate content");
+
+    let groups = find_duplicates(&state.store_id)?;
+    let total_reclaimable_bytes = groups.iter().map(|g| g.reclaimable_bytes).sum();
+
+    let body = serde_json::to_vec(&DuplicatesResponse { groups, total_reclaimable_bytes })
+        .map_err(|e| format!("Failed to serialize duplicates: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_dedup(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Deduplicating content");
+
+    let dedup_req: DedupRequest = match req.body.as_deref() {
+        Some(body) if !body.is_empty() => {
+            serde_json::from_slice(body).map_err(|e| format!("Invalid JSON: {}", e))?
+        }
+        _ => DedupRequest::default(),
+    };
+
+    let dry_run = dryrun::is_dry_run(req);
+    let mut planned = Vec::new();
+    let mut aliased = Vec::new();
+    let mut reclaimable_bytes = 0;
+
+    for group in find_duplicates(&state.store_id)? {
+        if matches!(&dedup_req.canonical_ref, Some(r) if *r != group.canonical_ref) {
+            continue;
+        }
+
+        for label in &group.labels {
+            let current = match store::get_by_label(&state.store_id, label)? {
+                Some(current) => current,
+                None => continue,
+            };
+            if current.hash == group.canonical_ref {
+                continue;
+            }
+            planned.push(PlannedChange {
+                label: label.clone(),
+                action: ChangeAction::Alias,
+                size_bytes: Some(group.size_bytes),
+                content_ref: Some(group.canonical_ref.clone()),
+                previous_ref: Some(current.hash.clone()),
+                previous_size_bytes: Some(group.size_bytes),
+            });
+            aliased.push(AliasedLabel {
+                label: label.clone(),
+                from_ref: current.hash,
+                to_ref: group.canonical_ref.clone(),
+            });
+        }
+        reclaimable_bytes += group.reclaimable_bytes;
+    }
+
+    if dry_run {
+        return dryrun::dry_run_response(&planned);
+    }
+
+    // Each label is rewritten with the canonical content, so it is counted,
+    // undoable and announced like any other write
+    let mut canonical: Option<(String, Vec<u8>)> = None;
+    for a in &mut aliased {
+        if canonical.as_ref().is_none_or(|(hash, _)| *hash != a.to_ref) {
+            let content = store::get(&state.store_id, &ContentRef { hash: a.to_ref.clone() })?;
+            canonical = Some((a.to_ref.clone(), content));
+        }
+        if let Some((_, content)) = &canonical {
+            a.to_ref = write_label(state, "dedup", &a.label, content)?.hash;
+        }
+    }
+
+    log(&format!("Aliased {} labels to canonical refs", aliased.len()));
+
+    let body = serde_json::to_vec(&DedupResponse { aliased, reclaimable_bytes })
+        .map_err(|e| format!("Failed to serialize dedup result: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,131 @@
+//! Editor defaults per prefix.
+//!
+//! An editor rule tells clients how to present labels under a prefix: the
+//! content type to assume, the editor mode to highlight with, the tab width
+//! and whether the content may be edited at all. As with schemas, the rule
+//! with the longest matching prefix applies. The resolved hints are returned
+//! with a label's content and metadata, so every client opens a label the
+//! same way instead of guessing from its name.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{error_response, json_response, longest_prefix_match, StoreViewerState};
+use serde::{Deserialize, Serialize};
+
+/// Widest tab a rule may ask for.
+const MAX_TAB_WIDTH: u32 = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EditorRule {
+    pub prefix: String,
+    /// MIME type of labels under the prefix, e.g. `application/yaml`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Editor mode to highlight with, e.g. `yaml`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tab_width: Option<u32>,
+    /// Whether clients should refuse edits; binary labels are read-only when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+}
+
+/// How a client should present one label.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EditorHints {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tab_width: Option<u32>,
+    #[serde(default)]
+    pub read_only: bool,
+    /// Prefix of the rule the hints come from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+}
+
+pub fn rule_for<'a>(rules: &'a [EditorRule], label: &str) -> Option<&'a EditorRule> {
+    longest_prefix_match(rules, label, |rule| &rule.prefix)
+}
+
+/// Hints for `label`, whose content is text when `is_text` is set.
+pub fn hints_for(rules: &[EditorRule], label: &str, is_text: bool) -> EditorHints {
+    match rule_for(rules, label) {
+        Some(rule) => EditorHints {
+            content_type: rule.content_type.clone(),
+            mode: rule.mode.clone(),
+            tab_width: rule.tab_width,
+            read_only: rule.read_only.unwrap_or(!is_text),
+            prefix: Some(rule.prefix.clone()),
+        },
+        None => EditorHints { read_only: !is_text, ..EditorHints::default() },
+    }
+}
+
+pub fn validate_rule(rule: &EditorRule) -> Result<(), String> {
+    if rule.content_type.is_none() && rule.mode.is_none() && rule.tab_width.is_none() && rule.read_only.is_none() {
+        return Err("An editor rule needs a content_type, mode, tab_width or read_only".to_string());
+    }
+    if let Some(content_type) = &rule.content_type {
+        if content_type.split_once('/').is_none_or(|(kind, sub)| kind.is_empty() || sub.is_empty()) {
+            return Err(format!("Invalid content type: {}", content_type));
+        }
+    }
+    if rule.mode.as_deref() == Some("") {
+        return Err("Mode cannot be empty".to_string());
+    }
+    if let Some(tab_width) = rule.tab_width {
+        if tab_width == 0 || tab_width > MAX_TAB_WIDTH {
+            return Err(format!("tab_width must be between 1 and {}", MAX_TAB_WIDTH));
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_list_rules(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing editor rules");
+
+    let body = serde_json::to_vec(&state.editor)
+        .map_err(|e| format!("Failed to serialize editor rules: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_put_rule(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving editor rule");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let rule: EditorRule = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err(e) = validate_rule(&rule) {
+        return Ok(error_response(400, &e));
+    }
+
+    log(&format!("Saved editor rule for prefix: {}", rule.prefix));
+
+    // Saving a prefix again replaces its previous rule
+    state.editor.retain(|existing| existing.prefix != rule.prefix);
+    state.editor.push(rule);
+    state.editor.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+pub fn handle_delete_rule(state: &mut StoreViewerState, prefix: &str) -> Result<HttpResponse, String> {
+    log(&format!("Deleting editor rule for prefix: {}", prefix));
+
+    let before = state.editor.len();
+    state.editor.retain(|rule| rule.prefix != prefix);
+    if state.editor.len() == before {
+        return Ok(error_response(404, &format!("No editor rule for prefix: {}", prefix)));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,236 @@
+//! Error budgets per API route.
+//!
+//! Every API response is counted against its route (the documented path
+//! pattern plus method) in fixed windows of the actor's clock. When a route's
+//! share of failed responses in the current window goes over the configured
+//! rate, once it has seen enough requests for the rate to mean something, an
+//! alert is raised: it is logged, pushed to WebSocket clients as an
+//! `error_budget_exceeded` event and kept in a short history. A route alerts
+//! at most once per window. Only server errors count unless client errors are
+//! asked for.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::events::{self, Event};
+use crate::{discovery, error_response, json_response, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Alerts kept for the status report.
+const MAX_ALERTS: usize = 100;
+
+fn default_window_ms() -> u64 {
+    5 * 60 * 1000
+}
+
+fn default_max_error_rate() -> f64 {
+    0.1
+}
+
+fn default_min_requests() -> u64 {
+    20
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetConfig {
+    #[serde(default = "default_window_ms")]
+    pub window_ms: u64,
+    /// Share of failed responses, between 0 and 1, a window may have
+    #[serde(default = "default_max_error_rate")]
+    pub max_error_rate: f64,
+    /// Requests a window needs before its rate is judged
+    #[serde(default = "default_min_requests")]
+    pub min_requests: u64,
+    /// Count `4xx` responses as failures too
+    #[serde(default)]
+    pub count_client_errors: bool,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        BudgetConfig {
+            window_ms: default_window_ms(),
+            max_error_rate: default_max_error_rate(),
+            min_requests: default_min_requests(),
+            count_client_errors: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RouteWindow {
+    pub window_start: u64,
+    pub requests: u64,
+    pub errors: u64,
+    /// Whether this window has already raised an alert
+    #[serde(default)]
+    pub alerted: bool,
+}
+
+impl RouteWindow {
+    fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alert {
+    pub route: String,
+    pub at: u64,
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ErrorBudget {
+    #[serde(default)]
+    pub config: BudgetConfig,
+    /// Current window of each route that has been requested
+    #[serde(default)]
+    routes: BTreeMap<String, RouteWindow>,
+    /// Recent alerts, oldest first
+    #[serde(default)]
+    alerts: VecDeque<Alert>,
+}
+
+#[derive(Serialize)]
+struct RouteStatus<'a> {
+    route: &'a str,
+    #[serde(flatten)]
+    window: &'a RouteWindow,
+    error_rate: f64,
+    exceeded: bool,
+}
+
+#[derive(Serialize)]
+struct BudgetStatus<'a> {
+    config: &'a BudgetConfig,
+    routes: Vec<RouteStatus<'a>>,
+    alerts: &'a VecDeque<Alert>,
+}
+
+impl BudgetConfig {
+    fn is_failure(&self, status: u16) -> bool {
+        status >= 500 || (self.count_client_errors && status >= 400)
+    }
+
+    fn exceeded(&self, window: &RouteWindow) -> bool {
+        window.requests >= self.min_requests && window.error_rate() > self.max_error_rate
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.window_ms == 0 {
+            return Err("window_ms must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.max_error_rate) {
+            return Err("max_error_rate must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Requests and failures across the routes' current windows; windows that
+/// have ended by `now` are left out.
+pub fn current_totals(budget: &ErrorBudget, now: u64) -> (u64, u64) {
+    budget
+        .routes
+        .values()
+        .filter(|window| now < window.window_start + budget.config.window_ms)
+        .fold((0, 0), |(requests, errors), window| (requests + window.requests, errors + window.errors))
+}
+
+/// Count a response to `method path`; paths outside the documented API are
+/// not tracked.
+pub fn record(state: &mut StoreViewerState, method: &str, path: &str, status: u16) {
+    let pattern = match discovery::pattern_for(path) {
+        Some(pattern) => pattern,
+        None => return,
+    };
+    let route = format!("{} {}", method, pattern);
+    let now = state.clock_ms;
+    let config = &state.errorbudget.config;
+
+    let window = state.errorbudget.routes.entry(route.clone()).or_default();
+    if now < window.window_start || now >= window.window_start + config.window_ms {
+        *window = RouteWindow { window_start: now, ..RouteWindow::default() };
+    }
+    window.requests += 1;
+    if config.is_failure(status) {
+        window.errors += 1;
+    }
+    if window.alerted || !config.exceeded(window) {
+        return;
+    }
+    window.alerted = true;
+
+    let alert = Alert {
+        route,
+        at: now,
+        requests: window.requests,
+        errors: window.errors,
+        error_rate: window.error_rate(),
+    };
+    log(&format!(
+        "ERROR: error budget exceeded for {}: {} of {} requests failed",
+        alert.route, alert.errors, alert.requests
+    ));
+    events::broadcast(state, &Event::ErrorBudgetExceeded {
+        route: alert.route.clone(),
+        errors: alert.errors,
+        requests: alert.requests,
+        error_rate: alert.error_rate,
+    });
+    let alerts = &mut state.errorbudget.alerts;
+    if alerts.len() >= MAX_ALERTS {
+        alerts.pop_front();
+    }
+    alerts.push_back(alert);
+}
+
+/// `GET /api/admin/error-budget`
+pub fn handle_status(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting error budget status");
+
+    let budget = &state.errorbudget;
+    let routes = budget
+        .routes
+        .iter()
+        .map(|(route, window)| RouteStatus {
+            route,
+            window,
+            error_rate: window.error_rate(),
+            exceeded: budget.config.exceeded(window),
+        })
+        .collect();
+
+    let status = BudgetStatus { config: &budget.config, routes, alerts: &budget.alerts };
+    let body = serde_json::to_vec(&status)
+        .map_err(|e| format!("Failed to serialize error budget: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/admin/error-budget`
+pub fn handle_put_config(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving error budget configuration");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let config: BudgetConfig = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err(e) = config.validate() {
+        return Ok(error_response(400, &e));
+    }
+
+    state.errorbudget.config = config;
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
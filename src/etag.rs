@@ -0,0 +1,73 @@
+//! Entity tags for conditional requests.
+//!
+//! A content ref is a hash of the bytes it names, so it serves directly as a
+//! strong entity tag: responses carrying a label's content are tagged with
+//! its ref, and a client that still holds that ref can revalidate with
+//! `If-None-Match` instead of downloading the content again. Writers send the
+//! tag they read in `If-Match` so an update made in the meantime is not
+//! silently overwritten.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::header;
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct PreconditionFailed<'a> {
+    error: &'a str,
+    /// Ref the label points at now, if it exists
+    current_ref: Option<&'a str>,
+}
+
+/// Entity tag for the content a ref names.
+pub fn for_ref(hash: &str) -> String {
+    format!("\"{}\"", hash)
+}
+
+/// Whether the tags listed in a conditional header include `etag`. `*`
+/// matches any tag; weak tags compare by their value.
+fn listed(value: &str, etag: &str) -> bool {
+    value
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Whether `If-None-Match` says the client already holds `etag`.
+pub fn none_match(req: &HttpRequest, etag: &str) -> bool {
+    header(req, "if-none-match").is_some_and(|value| listed(value, etag))
+}
+
+/// The value of `If-Match`, if the request is conditional on it.
+pub fn if_match(req: &HttpRequest) -> Option<&str> {
+    header(req, "if-match")
+}
+
+/// Whether an `If-Match` value admits the current `etag`, which is `None` when
+/// there is no current content. Weak tags never match.
+pub fn match_holds(value: &str, etag: Option<&str>) -> bool {
+    let etag = match etag {
+        Some(etag) => etag,
+        None => return false,
+    };
+    value.split(',').map(|tag| tag.trim()).any(|tag| tag == "*" || tag == etag)
+}
+
+/// `412 Precondition Failed`, telling the client which ref is current.
+pub fn precondition_failed(message: &str, current_ref: Option<&str>) -> HttpResponse {
+    let body = serde_json::to_vec(&PreconditionFailed { error: message, current_ref })
+        .unwrap_or_else(|_| message.as_bytes().to_vec());
+    HttpResponse {
+        status: 412,
+        headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+        body: Some(body),
+    }
+}
+
+/// `304 Not Modified` for a client whose copy is current.
+pub fn not_modified(etag: String) -> HttpResponse {
+    HttpResponse {
+        status: 304,
+        headers: vec![("ETag".to_string(), etag)],
+        body: None,
+    }
+}
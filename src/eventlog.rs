@@ -0,0 +1,319 @@
+//! Event-sourced record of label changes.
+//!
+//! With the event log enabled (`event_log` at init, or
+//! `POST /api/admin/event-log`), every change to a label of the viewer's own
+//! store is appended to a log kept in the store itself: what happened
+//! (`write` or `delete`), to which label, the ref it was left pointing at and
+//! when. The store keeps the content of old refs, so the log is enough to
+//! rebuild the labels as they stood at any moment. `POST /api/event-log/replay`
+//! does this into a fresh store, which is registered for browsing.
+//!
+//! The log is written as JSON lines under `__system/event-log/`, split into
+//! segments of `SEGMENT_EVENTS` so an append rewrites one segment rather
+//! than the whole log. Entries are only ever added.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::dryrun::{self, ChangeAction, PlannedChange};
+use crate::{clock, error_response, json_response, query_param, stores, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Events per segment label.
+const SEGMENT_EVENTS: u64 = 500;
+
+/// Events returned by one read of the log when no limit is given.
+const DEFAULT_LIMIT: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EventLog {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Events recorded so far, which is also the number of the latest
+    #[serde(default)]
+    pub events: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Write,
+    Delete,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LoggedEvent {
+    seq: u64,
+    op: Op,
+    label: String,
+    /// Ref the label points at after the event; absent for deletions
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    content_ref: Option<String>,
+    at: u64,
+}
+
+#[derive(Deserialize)]
+struct ConfigRequest {
+    enabled: bool,
+}
+
+#[derive(Deserialize, Default)]
+struct ReplayRequest {
+    /// Last moment to rebuild, as epoch milliseconds or ISO 8601; the whole
+    /// log when omitted
+    #[serde(default)]
+    until: Option<String>,
+}
+
+#[derive(Serialize)]
+struct StatusResponse<'a> {
+    enabled: bool,
+    events: u64,
+    segments: u64,
+    prefix: &'a str,
+}
+
+#[derive(Serialize)]
+struct EventsResponse {
+    events: Vec<LoggedEvent>,
+    /// Where the next read starts, if there are more events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_since: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ReplayResponse {
+    store_id: String,
+    /// Events applied, the last of them being `last_seq`
+    events_applied: usize,
+    last_seq: u64,
+    labels: usize,
+    /// Labels left out because their content could no longer be read
+    missing: Vec<String>,
+}
+
+fn prefix() -> String {
+    format!("{}event-log/", SYSTEM_LABEL_PREFIX)
+}
+
+fn segment_label(segment: u64) -> String {
+    format!("{}{:06}", prefix(), segment)
+}
+
+fn segment_of(seq: u64) -> u64 {
+    (seq - 1) / SEGMENT_EVENTS
+}
+
+fn segment_count(log: &EventLog) -> u64 {
+    if log.events == 0 {
+        0
+    } else {
+        segment_of(log.events) + 1
+    }
+}
+
+/// Events of one segment, oldest first.
+fn read_segment(store_id: &str, segment: u64) -> Result<Vec<LoggedEvent>, String> {
+    let content_ref = match store::get_by_label(store_id, &segment_label(segment))? {
+        Some(content_ref) => content_ref,
+        None => return Ok(Vec::new()),
+    };
+    let content = store::get(store_id, &content_ref)?;
+    String::from_utf8_lossy(&content)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| format!("Corrupt event log segment {}: {}", segment, e)))
+        .collect()
+}
+
+fn append(store_id: &str, event: &LoggedEvent) -> Result<(), String> {
+    let label = segment_label(segment_of(event.seq));
+    let mut content = match store::get_by_label(store_id, &label)? {
+        Some(content_ref) => store::get(store_id, &content_ref)?,
+        None => Vec::new(),
+    };
+    serde_json::to_writer(&mut content, event).map_err(|e| format!("Failed to serialize event: {}", e))?;
+    content.push(b'\n');
+    store::store_at_label(store_id, &label, &content)?;
+    Ok(())
+}
+
+/// Append a change to the log, if it is enabled. Changes to other stores and
+/// to the viewer's own bookkeeping are not recorded.
+pub fn record(state: &mut StoreViewerState, op: Op, label: &str, content_ref: Option<&ContentRef>) {
+    if !state.event_log.enabled || !stores::is_home(state) || label.starts_with(SYSTEM_LABEL_PREFIX) {
+        return;
+    }
+    let event = LoggedEvent {
+        seq: state.event_log.events + 1,
+        op,
+        label: label.to_string(),
+        content_ref: content_ref.map(|r| r.hash.clone()),
+        at: state.clock_ms,
+    };
+    match append(&state.store_id, &event) {
+        Ok(()) => state.event_log.events = event.seq,
+        Err(e) => log(&format!("Error appending to the event log: {}", e)),
+    }
+}
+
+/// `GET /api/admin/event-log`
+pub fn handle_status(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting event log status");
+
+    let response = StatusResponse {
+        enabled: state.event_log.enabled,
+        events: state.event_log.events,
+        segments: segment_count(&state.event_log),
+        prefix: &prefix(),
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize event log status: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/admin/event-log` with `{ "enabled" }`
+pub fn handle_configure(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Configuring event log");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let config: ConfigRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    log(&format!("Event log {}", if config.enabled { "enabled" } else { "disabled" }));
+    state.event_log.enabled = config.enabled;
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+/// `GET /api/event-log?since=&limit=`: events after `since`, oldest first.
+pub fn handle_events(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Reading event log");
+
+    let since = match query_param(&req.uri, "since").map(|since| since.parse::<u64>()) {
+        None => 0,
+        Some(Ok(since)) => since,
+        Some(Err(_)) => return Ok(error_response(400, "since must be an event number")),
+    };
+    let limit = match query_param(&req.uri, "limit").map(|limit| limit.parse::<usize>()) {
+        None => DEFAULT_LIMIT,
+        Some(Ok(limit)) if limit > 0 => limit,
+        Some(_) => return Ok(error_response(400, "limit must be a positive number")),
+    };
+
+    let mut events = Vec::new();
+    if since < state.event_log.events {
+        for segment in segment_of(since + 1)..segment_count(&state.event_log) {
+            events.extend(read_segment(&state.store_id, segment)?.into_iter().filter(|event| event.seq > since));
+            if events.len() >= limit {
+                break;
+            }
+        }
+    }
+    events.truncate(limit);
+    let next_since = events
+        .last()
+        .map(|event| event.seq)
+        .filter(|&seq| seq < state.event_log.events);
+
+    let body = serde_json::to_vec(&EventsResponse { events, next_since })
+        .map_err(|e| format!("Failed to serialize events: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/event-log/replay` with `{ "until"? }`: rebuild the labels as
+/// they stood at `until` in a new store.
+pub fn handle_replay(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Replaying event log");
+
+    let replay: ReplayRequest = match req.body.as_deref() {
+        Some(body) if !body.is_empty() => serde_json::from_slice(body)
+            .map_err(|e| format!("Invalid JSON: {}", e))?,
+        _ => ReplayRequest::default(),
+    };
+    let until = match replay.until.as_deref().map(clock::parse_time) {
+        None => u64::MAX,
+        Some(Some(until)) => until,
+        Some(None) => return Ok(error_response(400, "until must be epoch milliseconds or an ISO 8601 time")),
+    };
+    if state.event_log.events == 0 {
+        return Ok(error_response(404, "The event log is empty"));
+    }
+
+    // Fold the events into the labels they leave behind
+    let mut labels: BTreeMap<String, Option<String>> = BTreeMap::new();
+    let mut events_applied = 0;
+    let mut last_seq = 0;
+    'segments: for segment in 0..segment_count(&state.event_log) {
+        for event in read_segment(&state.store_id, segment)? {
+            if event.at > until {
+                break 'segments;
+            }
+            labels.insert(event.label, event.content_ref);
+            events_applied += 1;
+            last_seq = event.seq;
+        }
+    }
+    let labels: BTreeMap<String, String> = labels
+        .into_iter()
+        .filter_map(|(label, content_ref)| content_ref.map(|hash| (label, hash)))
+        .collect();
+
+    if dryrun::is_dry_run(req) {
+        let changes: Vec<PlannedChange> = labels
+            .iter()
+            .map(|(label, hash)| PlannedChange {
+                label: label.clone(),
+                action: ChangeAction::Create,
+                size_bytes: None,
+                content_ref: Some(hash.clone()),
+                previous_ref: None,
+                previous_size_bytes: None,
+            })
+            .collect();
+        return dryrun::dry_run_response(&changes);
+    }
+
+    let store_id = store::new()?;
+    let mut missing = Vec::new();
+    for (label, hash) in &labels {
+        let content = match store::get(&state.store_id, &ContentRef { hash: hash.clone() }) {
+            Ok(content) => content,
+            Err(_) => {
+                missing.push(label.clone());
+                continue;
+            }
+        };
+        store::store_at_label(&store_id, label, &content)?;
+    }
+    log(&format!("Replayed {} events into store {}", events_applied, store_id));
+
+    // Offer the rebuilt store for browsing
+    let name = match &replay.until {
+        Some(until) => format!("Replay until {}", until),
+        None => "Replay".to_string(),
+    };
+    state.stores.registered.insert(
+        store_id.clone(),
+        stores::StoreEntry { name: Some(name), added_at: state.clock_ms, ..stores::StoreEntry::default() },
+    );
+
+    let response = ReplayResponse {
+        store_id,
+        events_applied,
+        last_seq,
+        labels: labels.len() - missing.len(),
+        missing,
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize replay: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,335 @@
+//! WebSocket event stream.
+//!
+//! Clients connect to `/ws` to receive events pushed by the viewer as JSON
+//! text messages, each with a `type` field. Connection ids are kept in the
+//! actor state; a connection that can no longer be written to is dropped on
+//! the next delivery.
+//!
+//! Events are queued per client while a request is handled and delivered when
+//! it completes, several at once as a `batch` message, so a bulk operation
+//! does not send one message per label. Each client receives at most a few
+//! messages per second; events arriving faster wait for a later request. A
+//! queue that grows past its limit drops its oldest events and the next batch
+//! says how many were lost, so the client knows to reload instead.
+//!
+//! Label changes are announced on a topic (see `notify`); a client that has
+//! subscribed to topics only receives the changes announced on them.
+
+use crate::bindings::theater::simple::http_framework::{close_websocket, send_websocket_message};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::ContentRef;
+use crate::bindings::theater::simple::websocket_types::{MessageType, WebsocketMessage};
+use crate::eventlog::{self, Op};
+use crate::{audit, expiry, history, mirror, notify, stores, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Path WebSocket clients connect to.
+pub const WS_PATH: &str = "/ws";
+
+/// Events a client may have waiting before the oldest are dropped.
+const MAX_QUEUED_EVENTS: usize = 500;
+
+/// Messages sent to one client per rate window.
+const MAX_MESSAGES_PER_WINDOW: u32 = 4;
+const RATE_WINDOW_MS: u64 = 1000;
+
+/// Delivery state of one connected client.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Subscriber {
+    /// Events not yet sent, oldest first
+    #[serde(default)]
+    queue: VecDeque<Value>,
+    /// Events dropped from the queue since the last message
+    #[serde(default)]
+    dropped: usize,
+    #[serde(default)]
+    window_start: u64,
+    #[serde(default)]
+    sent_in_window: u32,
+    /// Topics of the label changes the client receives; all of them if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    topics: Option<BTreeSet<String>>,
+}
+
+impl Subscriber {
+    fn receives(&self, topic: &str) -> bool {
+        self.topics.as_ref().is_none_or(|topics| topics.contains(topic))
+    }
+
+    fn push(&mut self, event: Value) {
+        if self.queue.len() >= MAX_QUEUED_EVENTS {
+            self.queue.pop_front();
+            self.dropped += 1;
+        }
+        self.queue.push_back(event);
+    }
+
+    /// Whether the rate limit allows a message at `now`.
+    fn may_send(&mut self, now: u64) -> bool {
+        if now < self.window_start || now >= self.window_start + RATE_WINDOW_MS {
+            self.window_start = now;
+            self.sent_in_window = 0;
+        }
+        self.sent_in_window < MAX_MESSAGES_PER_WINDOW
+    }
+
+    /// Everything queued as one message: a lone event as itself, otherwise a
+    /// batch.
+    fn take_message(&mut self) -> Result<WebsocketMessage, String> {
+        let text = if self.queue.len() == 1 && self.dropped == 0 {
+            serde_json::to_string(&self.queue[0])
+        } else {
+            serde_json::to_string(&Batch {
+                events: self.queue.iter().collect(),
+                dropped: self.dropped,
+            })
+        }
+        .map_err(|e| format!("Failed to serialize events: {}", e))?;
+        self.queue.clear();
+        self.dropped = 0;
+        Ok(text_websocket_message(text))
+    }
+}
+
+/// Connected clients by connection id.
+pub type Subscribers = BTreeMap<u64, Subscriber>;
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A label will be removed soon unless it is accessed
+    ExpiryWarning {
+        label: String,
+        expires_at: u64,
+        policy_prefix: String,
+    },
+    /// Reply to an `expiring` request: every label currently due to expire
+    /// within the warning window
+    ExpiringLabels { labels: Vec<expiry::ExpiringLabel> },
+    /// Reply to a `subscribe` request: the topics the client now receives,
+    /// or all of them when absent
+    Subscribed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        topics: Option<Vec<String>>,
+    },
+    /// A label was written for the first time
+    LabelCreated {
+        label: String,
+        content_ref: String,
+        /// Store the label is in, when it is not the viewer's own
+        #[serde(skip_serializing_if = "Option::is_none")]
+        store: Option<String>,
+        /// Topic a notification rule routed the change to
+        #[serde(skip_serializing_if = "Option::is_none")]
+        topic: Option<String>,
+    },
+    /// An existing label now holds different content
+    LabelUpdated {
+        label: String,
+        content_ref: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        store: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        topic: Option<String>,
+    },
+    LabelDeleted {
+        label: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        store: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        topic: Option<String>,
+    },
+    /// A route failed more often than its error budget allows
+    ErrorBudgetExceeded {
+        route: String,
+        errors: u64,
+        requests: u64,
+        error_rate: f64,
+    },
+}
+
+/// Several events delivered in one message.
+#[derive(Serialize)]
+#[serde(tag = "type", rename = "batch")]
+struct Batch<'a> {
+    events: Vec<&'a Value>,
+    /// Events lost because the client's queue overflowed
+    dropped: usize,
+}
+
+/// Requests a client may send over the socket.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Expiring,
+    /// Receive only the label changes announced on `topics`; all of them
+    /// again when empty
+    Subscribe {
+        #[serde(default)]
+        topics: Vec<String>,
+    },
+}
+
+fn text_websocket_message(text: String) -> WebsocketMessage {
+    WebsocketMessage {
+        ty: MessageType::Text,
+        data: None,
+        text: Some(text),
+    }
+}
+
+fn text_message(event: &Event) -> Result<WebsocketMessage, String> {
+    let text = serde_json::to_string(event)
+        .map_err(|e| format!("Failed to serialize event: {}", e))?;
+    Ok(text_websocket_message(text))
+}
+
+/// Queue an event for every connected client; returns how many will get it.
+pub fn broadcast(state: &mut StoreViewerState, event: &Event) -> usize {
+    publish(state, event, None)
+}
+
+/// Queue an event for the clients receiving `topic`, or for every client
+/// when it has none; returns how many will get it.
+fn publish(state: &mut StoreViewerState, event: &Event, topic: Option<&str>) -> usize {
+    if state.subscribers.is_empty() {
+        return 0;
+    }
+    let event = match serde_json::to_value(event) {
+        Ok(event) => event,
+        Err(e) => {
+            log(&format!("Failed to serialize event: {}", e));
+            return 0;
+        }
+    };
+
+    let mut recipients = 0;
+    for subscriber in state.subscribers.values_mut() {
+        if topic.is_none_or(|topic| subscriber.receives(topic)) {
+            subscriber.push(event.clone());
+            recipients += 1;
+        }
+    }
+    recipients
+}
+
+/// Announce a change of `label` on the topic the notification rules route it to.
+fn announce(state: &mut StoreViewerState, label: &str, event: impl FnOnce(Option<String>) -> Event) {
+    let routed = notify::topic_for(&state.notify, label).map(str::to_string);
+    let topic = routed.clone().unwrap_or_else(|| notify::DEFAULT_TOPIC.to_string());
+    publish(state, &event(routed), Some(&topic));
+}
+
+/// Send queued events to every client the rate limit allows; called once a
+/// request has been handled.
+pub fn flush(state: &mut StoreViewerState) {
+    let now = state.clock_ms;
+    let server_id = state.server_id;
+    state.subscribers.retain(|&connection_id, subscriber| {
+        if subscriber.queue.is_empty() || !subscriber.may_send(now) {
+            return true;
+        }
+        let message = match subscriber.take_message() {
+            Ok(message) => message,
+            Err(e) => {
+                log(&e);
+                return true;
+            }
+        };
+        match send_websocket_message(server_id, connection_id, &message) {
+            Ok(()) => {
+                subscriber.sent_in_window += 1;
+                true
+            }
+            Err(e) => {
+                log(&format!("Dropping WebSocket connection {}: {}", connection_id, e));
+                false
+            }
+        }
+    });
+}
+
+/// Announce a write to `label`, which pointed at `previous` before, if it
+/// existed. Every announced change is also recorded in the event log, the
+/// audit log and the label's history, and mirrored.
+pub fn label_written(state: &mut StoreViewerState, label: &str, previous: Option<&str>, content_ref: &ContentRef) {
+    eventlog::record(state, Op::Write, label, Some(content_ref));
+    audit::record(state, label, previous, Some(&content_ref.hash));
+    history::record(state, Op::Write, label, previous, Some(content_ref));
+    mirror::record(state, Op::Write, label, Some(content_ref));
+    let content_ref = content_ref.hash.clone();
+    let store = stores::selected(state).map(str::to_string);
+    announce(state, label, |topic| {
+        let label = label.to_string();
+        if previous.is_none() {
+            Event::LabelCreated { label, content_ref, store, topic }
+        } else {
+            Event::LabelUpdated { label, content_ref, store, topic }
+        }
+    });
+}
+
+pub fn label_deleted(state: &mut StoreViewerState, label: &str, previous: Option<&str>) {
+    eventlog::record(state, Op::Delete, label, None);
+    audit::record(state, label, previous, None);
+    history::record(state, Op::Delete, label, previous, None);
+    mirror::record(state, Op::Delete, label, None);
+    let store = stores::selected(state).map(str::to_string);
+    announce(state, label, |topic| Event::LabelDeleted { label: label.to_string(), store, topic });
+}
+
+pub fn handle_connect(state: &mut StoreViewerState, connection_id: u64) {
+    log(&format!("WebSocket connected: {}", connection_id));
+    state.subscribers.insert(connection_id, Subscriber::default());
+}
+
+/// Close a connection that did not present the API token.
+pub fn refuse(state: &StoreViewerState, connection_id: u64) {
+    log(&format!("WebSocket refused without the token: {}", connection_id));
+    if let Err(e) = close_websocket(state.server_id, connection_id) {
+        log(&format!("Error closing WebSocket {}: {}", connection_id, e));
+    }
+}
+
+pub fn handle_disconnect(state: &mut StoreViewerState, connection_id: u64) {
+    log(&format!("WebSocket disconnected: {}", connection_id));
+    state.subscribers.remove(&connection_id);
+}
+
+/// Answer a message from a client. Unknown messages, and messages on
+/// connections that were refused, are ignored.
+pub fn handle_message(
+    state: &mut StoreViewerState,
+    connection_id: u64,
+    message: &WebsocketMessage,
+) -> Result<Vec<WebsocketMessage>, String> {
+    if !state.subscribers.contains_key(&connection_id) {
+        return Ok(Vec::new());
+    }
+    let request = match (&message.ty, &message.text) {
+        (MessageType::Text, Some(text)) => serde_json::from_str::<ClientMessage>(text).ok(),
+        _ => None,
+    };
+
+    match request {
+        Some(ClientMessage::Expiring) => {
+            let labels = expiry::expiring_labels(state)?;
+            Ok(vec![text_message(&Event::ExpiringLabels { labels })?])
+        }
+        Some(ClientMessage::Subscribe { topics }) => {
+            if let Some(e) = topics.iter().find_map(|topic| notify::validate_topic(topic).err()) {
+                return Err(e);
+            }
+            let topics: Option<BTreeSet<String>> = (!topics.is_empty()).then(|| topics.into_iter().collect());
+            log(&format!("WebSocket {} subscribed to {:?}", connection_id, topics));
+            let reply = Event::Subscribed { topics: topics.as_ref().map(|topics| topics.iter().cloned().collect()) };
+            if let Some(subscriber) = state.subscribers.get_mut(&connection_id) {
+                subscriber.topics = topics;
+            }
+            Ok(vec![text_message(&reply)?])
+        }
+        None => Ok(Vec::new()),
+    }
+}
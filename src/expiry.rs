@@ -0,0 +1,115 @@
+//! Warnings for labels about to expire.
+//!
+//! Retention policies delete labels that go unaccessed for too long. Labels
+//! whose deletion is due within `WARNING_WINDOW_MS` are announced once to
+//! WebSocket clients, so they can be read or renewed in time; a label is
+//! announced again only if its expiry time changes and it re-enters the
+//! window. Reads of such a label carry its expiry time in an `Expires` header,
+//! so caches downstream drop it no later than the store does.
+
+use crate::bindings::theater::simple::store;
+use crate::events::{self, Event};
+use crate::{archival, clock, retention, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How far ahead of an expiry clients are warned.
+pub const WARNING_WINDOW_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// How often the task runner looks for labels entering the window.
+const CHECK_INTERVAL_MS: u64 = 10 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ExpiryWatch {
+    #[serde(default)]
+    last_checked_at: u64,
+    /// Labels already announced, with the expiry time they were announced for
+    #[serde(default)]
+    warned: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExpiringLabel {
+    pub label: String,
+    pub expires_at: u64,
+    pub policy_prefix: String,
+}
+
+/// `Expires` header for content of a label due to expire at `expires_at`.
+pub fn header(expires_at: u64) -> (String, String) {
+    ("Expires".to_string(), clock::format_http_date(expires_at))
+}
+
+/// Every label due to expire within the warning window, soonest first.
+/// System and archived labels are never announced.
+pub fn expiring_labels(state: &StoreViewerState) -> Result<Vec<ExpiringLabel>, String> {
+    let mut expiring = Vec::new();
+    if state.retention.policies.is_empty() {
+        return Ok(expiring);
+    }
+
+    let horizon = state.clock_ms + WARNING_WINDOW_MS;
+    for label in store::list_labels(&state.store_id)? {
+        if label.starts_with(SYSTEM_LABEL_PREFIX) || archival::is_archived(state, &label) {
+            continue;
+        }
+        let expires_at = match retention::expires_at(state, &label) {
+            Some(expires_at) if expires_at <= horizon => expires_at,
+            _ => continue,
+        };
+        let policy_prefix = state
+            .retention
+            .policy_for(&label)
+            .map(|policy| policy.prefix.clone())
+            .unwrap_or_default();
+        expiring.push(ExpiringLabel { label, expires_at, policy_prefix });
+    }
+
+    expiring.sort_by_key(|e| e.expires_at);
+    Ok(expiring)
+}
+
+/// Announce labels that entered the warning window since the last check;
+/// called by the task runner. Returns how many warnings were sent.
+pub fn run_due(state: &mut StoreViewerState) -> Result<usize, String> {
+    let now = state.clock_ms;
+    if state.subscribers.is_empty() || now < state.expiry.last_checked_at + CHECK_INTERVAL_MS {
+        return Ok(0);
+    }
+    state.expiry.last_checked_at = now;
+
+    let expiring = expiring_labels(state)?;
+
+    // Forget labels that were renewed or deleted
+    state
+        .expiry
+        .warned
+        .retain(|label, _| expiring.iter().any(|e| &e.label == label));
+
+    let mut sent = 0;
+    for label in expiring {
+        if state.expiry.warned.get(&label.label) == Some(&label.expires_at) {
+            continue;
+        }
+        state.expiry.warned.insert(label.label.clone(), label.expires_at);
+        let event = Event::ExpiryWarning {
+            label: label.label,
+            expires_at: label.expires_at,
+            policy_prefix: label.policy_prefix,
+        };
+        if events::broadcast(state, &event) > 0 {
+            sent += 1;
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_header_carries_an_http_date() {
+        assert_eq!(header(784_111_777_000), ("Expires".to_string(), "Sun, 06 Nov 1994 08:49:37 GMT".to_string()));
+    }
+}
@@ -0,0 +1,169 @@
+//! Export of label content as an archive.
+//!
+//! `GET /api/export` packs every label, or those under `?prefix=`, into a
+//! tar archive (gzip-compressed with `?format=tar.gz`) with one file per
+//! label, its path being the label name. This backs a store up in one
+//! request instead of a GET and a base64 decode per label. Responses are not
+//! streamed by the runtime, so the archive is built in memory and refused
+//! above `MAX_EXPORT_BYTES`; export large stores a prefix at a time.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, query_param, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use miniz_oxide::deflate::compress_to_vec;
+
+/// Largest archive built for one request, before compression.
+const MAX_EXPORT_BYTES: usize = 256 * 1024 * 1024;
+
+const TAR_BLOCK: usize = 512;
+
+/// Longest name a ustar header holds without the prefix field.
+const TAR_NAME_LEN: usize = 100;
+
+/// Name GNU tar gives the extra entry that carries a long name.
+const LONG_NAME_ENTRY: &str = "././@LongLink";
+
+enum ExportFormat {
+    Tar,
+    TarGz,
+}
+
+/// Whether a label name is safe as a path inside the archive: relative, and
+/// never climbing out of the directory it is extracted to.
+fn is_safe_path(label: &str) -> bool {
+    !label.starts_with('/') && label.split('/').all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+}
+
+/// Write `value` as zero-padded octal filling `field` but its last byte.
+fn octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{:0width$o}", value, width = width);
+    field[..width].copy_from_slice(&digits.as_bytes()[digits.len() - width..]);
+    field[width] = 0;
+}
+
+fn tar_header(name: &[u8], size: usize, mtime: u64, kind: u8) -> [u8; TAR_BLOCK] {
+    let mut header = [0u8; TAR_BLOCK];
+    header[..name.len()].copy_from_slice(name);
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size as u64);
+    octal(&mut header[136..148], mtime);
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field as spaces
+    header[148..156].copy_from_slice(b"        ");
+    let sum: u64 = header.iter().map(|&b| b as u64).sum();
+    octal(&mut header[148..155], sum);
+    header[155] = b' ';
+    header
+}
+
+fn pad_block(tar: &mut Vec<u8>) {
+    let rem = tar.len() % TAR_BLOCK;
+    if rem != 0 {
+        tar.resize(tar.len() + TAR_BLOCK - rem, 0);
+    }
+}
+
+/// Append one file to a tar archive. Names too long for the header are
+/// carried by a GNU long-name entry before it.
+fn append_file(tar: &mut Vec<u8>, name: &str, content: &[u8], mtime: u64) {
+    let name = name.as_bytes();
+    if name.len() > TAR_NAME_LEN {
+        let mut long_name = name.to_vec();
+        long_name.push(0);
+        tar.extend_from_slice(&tar_header(LONG_NAME_ENTRY.as_bytes(), long_name.len(), 0, b'L'));
+        tar.extend_from_slice(&long_name);
+        pad_block(tar);
+    }
+    tar.extend_from_slice(&tar_header(&name[..name.len().min(TAR_NAME_LEN)], content.len(), mtime, b'0'));
+    tar.extend_from_slice(content);
+    pad_block(tar);
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn gzip(bytes: &[u8], mtime: u64) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0];
+    out.extend_from_slice(&(mtime as u32).to_le_bytes());
+    out.extend_from_slice(&[0, 0xff]);
+    out.extend_from_slice(&compress_to_vec(bytes, 6));
+    out.extend_from_slice(&crc32(bytes).to_le_bytes());
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out
+}
+
+/// `GET /api/export?prefix=&format=tar|tar.gz`
+pub fn handle_export(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Exporting labels as an archive");
+
+    let format = match query_param(&req.uri, "format").as_deref() {
+        None | Some("tar") => ExportFormat::Tar,
+        Some("tar.gz") | Some("tgz") => ExportFormat::TarGz,
+        Some(other) => return Ok(error_response(400, &format!("Unknown export format: {} (tar or tar.gz)", other))),
+    };
+    let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+
+    let mut labels = store::list_labels(&state.store_id)?;
+    labels.retain(|label| label.starts_with(&prefix) && !label.starts_with(SYSTEM_LABEL_PREFIX));
+    labels.sort();
+
+    let now = state.clock_ms / 1000;
+    let mut tar = Vec::new();
+    let mut exported = 0;
+    let mut skipped = 0;
+    for label in &labels {
+        if !is_safe_path(label) {
+            log(&format!("Not exporting {}: not a safe path", label));
+            skipped += 1;
+            continue;
+        }
+        let content_ref = match store::get_by_label(&state.store_id, label)? {
+            Some(content_ref) => content_ref,
+            None => continue,
+        };
+        let content = store::get(&state.store_id, &content_ref)?;
+        if tar.len() + content.len() > MAX_EXPORT_BYTES {
+            return Ok(error_response(413, &format!(
+                "Export exceeds {} MiB; narrow it with ?prefix=",
+                MAX_EXPORT_BYTES / (1024 * 1024)
+            )));
+        }
+        let mtime = state.stats.get(label).last_write_at.map_or(now, |at| at / 1000);
+        append_file(&mut tar, label, &content, mtime);
+        exported += 1;
+    }
+    // End of archive: two zero blocks
+    tar.resize(tar.len() + 2 * TAR_BLOCK, 0);
+
+    log(&format!("Exported {} labels, skipped {}", exported, skipped));
+
+    let (body, content_type, extension) = match format {
+        ExportFormat::Tar => (tar, "application/x-tar", "tar"),
+        ExportFormat::TarGz => (gzip(&tar, now), "application/gzip", "tar.gz"),
+    };
+    Ok(HttpResponse {
+        status: 200,
+        headers: vec![
+            ("Content-Type".to_string(), content_type.to_string()),
+            ("Content-Disposition".to_string(), format!("attachment; filename=\"export.{}\"", extension)),
+            ("X-Export-Labels".to_string(), exported.to_string()),
+            ("X-Export-Skipped".to_string(), skipped.to_string()),
+        ],
+        body: Some(body),
+    })
+}
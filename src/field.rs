@@ -0,0 +1,162 @@
+//! Setting one field of a JSON label.
+//!
+//! `PUT /api/labels/{name}/field?path=a.b.c` takes a JSON value as the raw
+//! request body and sets it at `path` inside the label's JSON, creating the
+//! objects on the way that do not exist yet. Scripts can change one setting
+//! without reading, editing and writing back the whole document, or building
+//! a JSON Patch. Path segments are object keys, except that a number indexes
+//! into an array that is already there.
+//!
+//! The document is reserialized: keys keep their order, and a document that
+//! was spread over several lines is written back indented two spaces a level.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{dryrun, error_response, etag, json_response, query_param, validate_write, write_label, StoreViewerState};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+#[derive(Serialize)]
+struct FieldResponse<'a> {
+    label: &'a str,
+    path: &'a str,
+    #[serde(rename = "ref")]
+    content_ref: String,
+    /// Value the field held before; absent when it was created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<Value>,
+}
+
+fn parse_path(path: &str) -> Result<Vec<&str>, String> {
+    let segments: Vec<&str> = path.split('.').collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(format!("Invalid field path: {} (keys separated by .)", path));
+    }
+    Ok(segments)
+}
+
+/// Set `segments` inside `doc` to `value`; returns what was there before. On
+/// failure, returns the HTTP status and message to report.
+fn set(doc: &mut Value, segments: &[&str], value: Value) -> Result<Option<Value>, (u16, String)> {
+    let mut target = doc;
+    for (depth, &segment) in segments.iter().enumerate() {
+        let last = depth == segments.len() - 1;
+        let at = segments[..depth].join(".");
+        target = match target {
+            Value::Object(map) if last => return Ok(map.insert(segment.to_string(), value)),
+            Value::Object(map) => map.entry(segment.to_string()).or_insert_with(|| Value::Object(Map::new())),
+            Value::Array(items) => {
+                let index = match segment.parse::<usize>() {
+                    Ok(index) if index < items.len() => index,
+                    Ok(index) => return Err((409, format!("{} has no element {}", display(&at), index))),
+                    Err(_) => return Err((409, format!("{} is an array; index it with a number", display(&at)))),
+                };
+                if last {
+                    return Ok(Some(std::mem::replace(&mut items[index], value)));
+                }
+                &mut items[index]
+            }
+            other => return Err((409, format!("{} is {}, not an object", display(&at), kind(other)))),
+        };
+    }
+    unreachable!("field paths have at least one segment")
+}
+
+fn display(at: &str) -> &str {
+    if at.is_empty() {
+        "The document"
+    } else {
+        at
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Serialize `doc` the way the document it came from was laid out.
+fn serialize_like(original: &[u8], doc: &Value) -> Result<Vec<u8>, String> {
+    let text = String::from_utf8_lossy(original);
+    let mut content = if text.trim().contains('\n') {
+        serde_json::to_vec_pretty(doc)
+    } else {
+        serde_json::to_vec(doc)
+    }
+    .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+    if text.ends_with('\n') {
+        content.push(b'\n');
+    }
+    Ok(content)
+}
+
+/// `PUT /api/labels/{name}/field?path=a.b.c` with a JSON value as the body
+pub fn handle_put_field(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Setting a field of label: {}", label_name));
+
+    let path = match query_param(&req.uri, "path") {
+        Some(path) => path,
+        None => return Ok(error_response(400, "path is required, e.g. ?path=a.b.c")),
+    };
+    let segments = match parse_path(&path) {
+        Ok(segments) => segments,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+    let value: Value = match serde_json::from_slice(body) {
+        Ok(value) => value,
+        Err(e) => return Ok(error_response(400, &format!("The body must be a JSON value: {}", e))),
+    };
+
+    let current_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(current_ref) => current_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    if let Some(expected) = etag::if_match(req) {
+        if !etag::match_holds(expected, Some(&etag::for_ref(&current_ref.hash))) {
+            let message = format!("Label {} changed since it was read", label_name);
+            return Ok(etag::precondition_failed(&message, Some(&current_ref.hash)));
+        }
+    }
+    let original = store::get(&state.store_id, &current_ref)?;
+    let mut doc: Value = match serde_json::from_slice(&original) {
+        Ok(doc) => doc,
+        Err(_) => return Ok(error_response(415, &format!("Label {} is not JSON", label_name))),
+    };
+
+    let previous = match set(&mut doc, &segments, value) {
+        Ok(previous) => previous,
+        Err((status, e)) => return Ok(error_response(status, &e)),
+    };
+    let content = serialize_like(&original, &doc)?;
+    if let Err((status, e)) = validate_write(state, label_name, &content) {
+        return Ok(error_response(status, &e));
+    }
+
+    if dryrun::is_dry_run(req) {
+        let change = dryrun::plan_write(&state.store_id, label_name, &content)?;
+        return dryrun::dry_run_response(&[change]);
+    }
+    let content_ref = write_label(state, "field", label_name, &content)?;
+
+    log(&format!("Set {} of {}", path, label_name));
+
+    let response = FieldResponse {
+        label: label_name,
+        path: &path,
+        content_ref: content_ref.hash.clone(),
+        previous,
+    };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize field update: {}", e))?;
+    let mut response = json_response(200, body);
+    response.headers.push(("ETag".to_string(), etag::for_ref(&content_ref.hash)));
+    Ok(response)
+}
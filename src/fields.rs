@@ -0,0 +1,71 @@
+//! Field selection.
+//!
+//! `?fields=name,size_bytes,content_ref` on `GET /api/labels` and
+//! `GET /api/labels/{name}` keeps only the named fields of each object
+//! returned, so a client on a slow link receives what it uses and nothing
+//! else. Names are checked against the fields the endpoint can return, and an
+//! unknown one is an error rather than silently dropped. `ref` may be given
+//! for `content_ref`.
+
+use crate::bindings::theater::simple::http_framework::HttpRequest;
+use crate::query_param;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Fields a request asked for, in the order it named them.
+pub struct Fields(Vec<&'static str>);
+
+impl Fields {
+    pub fn includes(&self, name: &str) -> bool {
+        self.0.contains(&name)
+    }
+
+    /// `value` with only the selected fields, if it is an object.
+    pub fn select(&self, value: Value) -> Value {
+        match value {
+            Value::Object(mut map) => {
+                map.retain(|name, _| self.includes(name));
+                Value::Object(map)
+            }
+            other => other,
+        }
+    }
+
+    /// Serialize each item and keep its selected fields.
+    pub fn select_all<T: Serialize>(&self, items: Vec<T>) -> Result<Vec<Value>, String> {
+        items
+            .into_iter()
+            .map(|item| {
+                serde_json::to_value(item)
+                    .map(|value| self.select(value))
+                    .map_err(|e| format!("Failed to serialize item: {}", e))
+            })
+            .collect()
+    }
+}
+
+/// The `?fields=` of a request, checked against the fields the endpoint
+/// returns; `None` when it asks for all of them.
+pub fn from_request(req: &HttpRequest, known: &[&'static str]) -> Result<Option<Fields>, String> {
+    let value = match query_param(&req.uri, "fields") {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let mut selected = Vec::new();
+    let mut unknown = Vec::new();
+    for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let name = if name == "ref" { "content_ref" } else { name };
+        match known.iter().find(|known| **known == name) {
+            Some(known) if !selected.contains(known) => selected.push(*known),
+            Some(_) => {}
+            None => unknown.push(name),
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(format!("Unknown fields: {} (available: {})", unknown.join(", "), known.join(", ")));
+    }
+    if selected.is_empty() {
+        return Err("fields must name at least one field".to_string());
+    }
+    Ok(Some(Fields(selected)))
+}
@@ -0,0 +1,60 @@
+//! YAML front-matter extraction for document labels.
+//!
+//! Markdown and other text documents often start with a YAML block delimited
+//! by `---` lines. The fields declared there are parsed server-side and exposed
+//! as structured metadata, so documents can be listed and filtered by their
+//! own attributes.
+
+use serde_json::Value;
+
+/// Query parameter prefix used to filter listings by front-matter fields,
+/// e.g. `?fm.status=draft`.
+pub const FILTER_PREFIX: &str = "fm.";
+
+/// Parse the front matter at the start of `content`, if there is any.
+///
+/// Returns `None` for binary content, documents without a front-matter block,
+/// and blocks that are not a valid YAML mapping.
+pub fn extract(content: &[u8]) -> Option<Value> {
+    let text = std::str::from_utf8(content).ok()?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+    let mut lines = text.split_inclusive('\n');
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+
+    let mut yaml = String::new();
+    for line in lines {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return match serde_yaml::from_str::<Value>(&yaml).ok()? {
+                value @ Value::Object(_) => Some(value),
+                _ => None,
+            };
+        }
+        yaml.push_str(line);
+    }
+
+    // No closing delimiter
+    None
+}
+
+/// Check whether a front-matter field matches a filter value. Scalars are
+/// compared by value; arrays match if any element does.
+pub fn field_matches(front_matter: &Value, field: &str, expected: &str) -> bool {
+    fn matches(value: &Value, expected: &str) -> bool {
+        match value {
+            Value::String(s) => s == expected,
+            Value::Array(items) => items.iter().any(|item| matches(item, expected)),
+            Value::Bool(b) => expected.parse::<bool>() == Ok(*b),
+            Value::Number(n) => expected.parse::<f64>().ok() == n.as_f64(),
+            Value::Null => expected == "null",
+            Value::Object(_) => false,
+        }
+    }
+
+    front_matter
+        .get(field)
+        .is_some_and(|value| matches(value, expected))
+}
@@ -0,0 +1,64 @@
+//! Glob patterns over label names.
+//!
+//! `*` matches any run of characters within one `/`-separated segment, `**`
+//! matches across segments and `?` matches a single character other than
+//! `/`. Everything else matches itself, and the pattern must match the whole
+//! name.
+
+use regex::Regex;
+
+/// Compile `pattern` into a regular expression matching whole label names.
+pub fn compile(pattern: &str) -> Result<Regex, String> {
+    let mut source = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                source.push_str(".*");
+            }
+            '*' => source.push_str("[^/]*"),
+            '?' => source.push_str("[^/]"),
+            c => source.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    source.push('$');
+    Regex::new(&source).map_err(|e| format!("Invalid glob {}: {}", pattern, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_stays_within_a_segment() {
+        let glob = compile("logs/*.txt").unwrap();
+        assert!(glob.is_match("logs/a.txt"));
+        assert!(glob.is_match("logs/.txt"));
+        assert!(!glob.is_match("logs/2024/a.txt"));
+        assert!(!glob.is_match("logs/a.txt.bak"));
+    }
+
+    #[test]
+    fn double_star_crosses_segments() {
+        let glob = compile("logs/**.txt").unwrap();
+        assert!(glob.is_match("logs/a.txt"));
+        assert!(glob.is_match("logs/2024/05/a.txt"));
+        assert!(!glob.is_match("other/a.txt"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        let glob = compile("v?/app").unwrap();
+        assert!(glob.is_match("v1/app"));
+        assert!(!glob.is_match("v10/app"));
+        assert!(!glob.is_match("v//app"));
+    }
+
+    #[test]
+    fn other_characters_match_literally() {
+        let glob = compile("a.b+(c)[d]").unwrap();
+        assert!(glob.is_match("a.b+(c)[d]"));
+        assert!(!glob.is_match("axb+(c)[d]"));
+    }
+}
@@ -0,0 +1,407 @@
+//! Store health grading.
+//!
+//! `GET /api/health` grades the store `green`, `yellow` or `red` from a few
+//! signals, each with its own thresholds: total stored bytes against a size
+//! quota, bytes held by content no label points at, the share of failed API
+//! responses in the current error budget windows, and the age of the oldest
+//! backup nobody has verified yet. The grade is the worst of the signals, and
+//! every signal that is not green gives a reason, so operators have one URL to
+//! check. Backups are taken outside the actor; backup jobs report them with
+//! `POST /api/admin/health/backups`.
+//!
+//! With a size quota set, writes to the store answer with a `size_warning`
+//! once the store is past the warning share of it, before anything fails.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{clock, error_response, errorbudget, json_response, stores, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+
+/// Backups remembered; the oldest are forgotten first.
+const MAX_BACKUPS: usize = 100;
+
+fn default_size_warning_ratio() -> f64 {
+    0.8
+}
+
+fn default_orphan_warning_ratio() -> f64 {
+    0.25
+}
+
+fn default_orphan_critical_ratio() -> f64 {
+    0.5
+}
+
+fn default_error_warning_rate() -> f64 {
+    0.05
+}
+
+fn default_error_critical_rate() -> f64 {
+    0.25
+}
+
+fn default_backup_warning_hours() -> u64 {
+    24
+}
+
+fn default_backup_critical_hours() -> u64 {
+    72
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Stored bytes the store should stay under; the size signal is off
+    /// without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_quota_bytes: Option<u64>,
+    /// Share of the quota past which the store is `yellow` and writes warn
+    #[serde(default = "default_size_warning_ratio")]
+    pub size_warning_ratio: f64,
+    /// Shares of stored bytes held by unlabelled content
+    #[serde(default = "default_orphan_warning_ratio")]
+    pub orphan_warning_ratio: f64,
+    #[serde(default = "default_orphan_critical_ratio")]
+    pub orphan_critical_ratio: f64,
+    /// Shares of failed responses in the current error budget windows
+    #[serde(default = "default_error_warning_rate")]
+    pub error_warning_rate: f64,
+    #[serde(default = "default_error_critical_rate")]
+    pub error_critical_rate: f64,
+    /// Age of the oldest unverified backup
+    #[serde(default = "default_backup_warning_hours")]
+    pub backup_warning_hours: u64,
+    #[serde(default = "default_backup_critical_hours")]
+    pub backup_critical_hours: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            size_quota_bytes: None,
+            size_warning_ratio: default_size_warning_ratio(),
+            orphan_warning_ratio: default_orphan_warning_ratio(),
+            orphan_critical_ratio: default_orphan_critical_ratio(),
+            error_warning_rate: default_error_warning_rate(),
+            error_critical_rate: default_error_critical_rate(),
+            backup_warning_hours: default_backup_warning_hours(),
+            backup_critical_hours: default_backup_critical_hours(),
+        }
+    }
+}
+
+impl HealthConfig {
+    fn validate(&self) -> Result<(), String> {
+        let ratios = [
+            ("size_warning_ratio", self.size_warning_ratio),
+            ("orphan_warning_ratio", self.orphan_warning_ratio),
+            ("orphan_critical_ratio", self.orphan_critical_ratio),
+            ("error_warning_rate", self.error_warning_rate),
+            ("error_critical_rate", self.error_critical_rate),
+        ];
+        if let Some((name, _)) = ratios.iter().find(|(_, ratio)| !(0.0..=1.0).contains(ratio)) {
+            return Err(format!("{} must be between 0 and 1", name));
+        }
+        if self.size_quota_bytes == Some(0) {
+            return Err("size_quota_bytes must be at least 1".to_string());
+        }
+        if self.orphan_warning_ratio > self.orphan_critical_ratio
+            || self.error_warning_rate > self.error_critical_rate
+            || self.backup_warning_hours > self.backup_critical_hours
+        {
+            return Err("Warning thresholds cannot be above critical ones".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Backup {
+    pub taken_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Health {
+    #[serde(default)]
+    pub config: HealthConfig,
+    /// Backups reported by backup jobs, keyed by name
+    #[serde(default)]
+    backups: BTreeMap<String, Backup>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BackupReport {
+    name: String,
+    /// When the backup was taken, as epoch milliseconds or ISO 8601; now
+    /// when omitted
+    #[serde(default)]
+    taken_at: Option<String>,
+    /// Set once the backup has been restored somewhere and checked
+    #[serde(default)]
+    verified: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Grade {
+    Green,
+    Yellow,
+    Red,
+}
+
+#[derive(Serialize)]
+struct Reason {
+    signal: &'static str,
+    grade: Grade,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct Signals {
+    stored_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_quota_bytes: Option<u64>,
+    orphan_bytes: u64,
+    /// Failed share of the requests in the current error budget windows
+    error_rate: f64,
+    requests: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    oldest_unverified_backup_at: Option<u64>,
+    backups: usize,
+}
+
+#[derive(Serialize)]
+struct HealthReport {
+    grade: Grade,
+    reasons: Vec<Reason>,
+    signals: Signals,
+    checked_at: u64,
+}
+
+#[derive(Serialize)]
+struct BackupList<'a> {
+    backups: &'a BTreeMap<String, Backup>,
+}
+
+fn grade(value: f64, warning: f64, critical: f64) -> Grade {
+    if value >= critical {
+        Grade::Red
+    } else if value >= warning {
+        Grade::Yellow
+    } else {
+        Grade::Green
+    }
+}
+
+fn percent(ratio: f64) -> String {
+    format!("{:.1}%", ratio * 100.0)
+}
+
+/// Bytes of content some label points at, counting each ref once.
+fn labelled_bytes(store_id: &str) -> Result<u64, String> {
+    let mut refs = BTreeSet::new();
+    let mut bytes = 0;
+    for label in store::list_labels(store_id)? {
+        if let Some(content_ref) = store::get_by_label(store_id, &label)? {
+            if refs.insert(content_ref.hash.clone()) {
+                bytes += store::get(store_id, &content_ref)?.len() as u64;
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// The warning to attach to a write that left the store past the warning
+/// share of its size quota. Only the viewer's own store has a quota.
+pub fn size_warning(state: &StoreViewerState) -> Option<String> {
+    let config = &state.health.config;
+    let quota = config.size_quota_bytes?;
+    if !stores::is_home(state) {
+        return None;
+    }
+    let stored = store::calculate_total_size(&state.store_id).ok()?;
+    let ratio = stored as f64 / quota as f64;
+    (ratio >= config.size_warning_ratio).then(|| {
+        format!("The store holds {} bytes, {} of its {} byte quota", stored, percent(ratio), quota)
+    })
+}
+
+/// `GET /api/health`: `200` when green or yellow, `503` when red.
+pub fn handle_health(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Grading store health");
+
+    let config = &state.health.config;
+    let now = state.clock_ms;
+    let mut reasons = Vec::new();
+
+    let stored_bytes = store::calculate_total_size(&state.store_id)?;
+    if let Some(quota) = config.size_quota_bytes {
+        let ratio = stored_bytes as f64 / quota as f64;
+        let grade = grade(ratio, config.size_warning_ratio, 1.0);
+        if grade != Grade::Green {
+            reasons.push(Reason {
+                signal: "size",
+                grade,
+                message: format!("{} bytes stored, {} of the {} byte quota", stored_bytes, percent(ratio), quota),
+            });
+        }
+    }
+
+    let orphan_bytes = stored_bytes.saturating_sub(labelled_bytes(&state.store_id)?);
+    if stored_bytes > 0 {
+        let ratio = orphan_bytes as f64 / stored_bytes as f64;
+        let grade = grade(ratio, config.orphan_warning_ratio, config.orphan_critical_ratio);
+        if grade != Grade::Green {
+            reasons.push(Reason {
+                signal: "orphans",
+                grade,
+                message: format!("{} bytes ({}) are held by content no label points at", orphan_bytes, percent(ratio)),
+            });
+        }
+    }
+
+    // A handful of requests says nothing about a rate; the error budget's
+    // own minimum decides what counts as enough
+    let (requests, errors) = errorbudget::current_totals(&state.errorbudget, now);
+    let error_rate = if requests == 0 { 0.0 } else { errors as f64 / requests as f64 };
+    if requests >= state.errorbudget.config.min_requests {
+        let grade = grade(error_rate, config.error_warning_rate, config.error_critical_rate);
+        if grade != Grade::Green {
+            reasons.push(Reason {
+                signal: "errors",
+                grade,
+                message: format!("{} of {} recent requests failed ({})", errors, requests, percent(error_rate)),
+            });
+        }
+    }
+
+    let oldest_unverified = state
+        .health
+        .backups
+        .iter()
+        .filter(|(_, backup)| backup.verified_at.is_none())
+        .min_by_key(|(_, backup)| backup.taken_at);
+    if let Some((name, backup)) = oldest_unverified {
+        let hours = now.saturating_sub(backup.taken_at) / HOUR_MS;
+        let grade = grade(hours as f64, config.backup_warning_hours as f64, config.backup_critical_hours as f64);
+        if grade != Grade::Green {
+            reasons.push(Reason {
+                signal: "backups",
+                grade,
+                message: format!("Backup {} has gone unverified for {} hours", name, hours),
+            });
+        }
+    }
+
+    let report = HealthReport {
+        grade: reasons.iter().map(|reason| reason.grade).fold(Grade::Green, |worst, grade| {
+            if grade > worst { grade } else { worst }
+        }),
+        reasons,
+        signals: Signals {
+            stored_bytes,
+            size_quota_bytes: config.size_quota_bytes,
+            orphan_bytes,
+            error_rate,
+            requests,
+            oldest_unverified_backup_at: oldest_unverified.map(|(_, backup)| backup.taken_at),
+            backups: state.health.backups.len(),
+        },
+        checked_at: now,
+    };
+
+    let status = if report.grade == Grade::Red { 503 } else { 200 };
+    let body = serde_json::to_vec(&report)
+        .map_err(|e| format!("Failed to serialize health report: {}", e))?;
+
+    Ok(json_response(status, body))
+}
+
+/// `GET /api/admin/health`
+pub fn handle_get_config(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting health thresholds");
+
+    let body = serde_json::to_vec(&state.health.config)
+        .map_err(|e| format!("Failed to serialize health thresholds: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/admin/health`
+pub fn handle_put_config(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving health thresholds");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let config: HealthConfig = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err(e) = config.validate() {
+        return Ok(error_response(400, &e));
+    }
+
+    state.health.config = config;
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+/// `GET /api/admin/health/backups`
+pub fn handle_list_backups(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing reported backups");
+
+    let body = serde_json::to_vec(&BackupList { backups: &state.health.backups })
+        .map_err(|e| format!("Failed to serialize backups: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/admin/health/backups` with `{ "name", "taken_at"?, "verified"? }`
+pub fn handle_report_backup(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Recording backup");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let report: BackupReport = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if report.name.trim().is_empty() {
+        return Ok(error_response(400, "A backup needs a name"));
+    }
+    let taken_at = match report.taken_at.as_deref().map(clock::parse_time) {
+        None => None,
+        Some(Some(taken_at)) => Some(taken_at),
+        Some(None) => return Ok(error_response(400, "taken_at must be epoch milliseconds or an ISO 8601 time")),
+    };
+
+    let now = state.clock_ms;
+    let backups = &mut state.health.backups;
+    let backup = backups.entry(report.name.clone()).or_insert(Backup { taken_at: now, verified_at: None });
+    if let Some(taken_at) = taken_at {
+        backup.taken_at = taken_at;
+    }
+    if report.verified {
+        backup.verified_at.get_or_insert(now);
+    }
+    while backups.len() > MAX_BACKUPS {
+        let oldest = backups
+            .iter()
+            .min_by_key(|(_, backup)| backup.taken_at)
+            .map(|(name, _)| name.clone());
+        match oldest {
+            Some(name) => backups.remove(&name),
+            None => break,
+        };
+    }
+    log(&format!("Recorded backup {}{}", report.name, if report.verified { " (verified)" } else { "" }));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,112 @@
+//! Hex dumps of label content.
+//!
+//! `GET /api/labels/{name}/hex?offset=&length=` formats a byte range of a
+//! label the way `xxd` does: sixteen bytes a line, as hex pairs and as ASCII
+//! with unprintable bytes shown as `.`. Binary labels are otherwise only
+//! available as base64, which says little about what they hold.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, json_response, query_param, StoreViewerState};
+use serde::Serialize;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Bytes dumped when the request does not say.
+const DEFAULT_LENGTH: usize = 1024;
+
+/// Most bytes one request can dump.
+const MAX_LENGTH: usize = 64 * 1024;
+
+#[derive(Serialize)]
+struct HexLine {
+    /// Offset of the line's first byte in the label
+    offset: usize,
+    /// Hex pairs, with a wider gap after the eighth
+    hex: String,
+    ascii: String,
+}
+
+#[derive(Serialize)]
+struct HexDump<'a> {
+    label: &'a str,
+    size_bytes: usize,
+    offset: usize,
+    length: usize,
+    /// Where the next range starts, if the label goes on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<usize>,
+    lines: Vec<HexLine>,
+}
+
+fn format_line(offset: usize, bytes: &[u8]) -> HexLine {
+    let mut hex = String::with_capacity(BYTES_PER_LINE * 3 + 1);
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            hex.push(' ');
+        }
+        if i == BYTES_PER_LINE / 2 {
+            hex.push(' ');
+        }
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    let ascii = bytes
+        .iter()
+        .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+        .collect();
+    HexLine { offset, hex, ascii }
+}
+
+fn parse_param(req: &HttpRequest, name: &str, default: usize) -> Result<usize, String> {
+    match query_param(&req.uri, name) {
+        None => Ok(default),
+        Some(value) => value.parse().map_err(|_| format!("Invalid {}: {}", name, value)),
+    }
+}
+
+/// `GET /api/labels/{name}/hex?offset=&length=`
+pub fn handle_hex(state: &StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Dumping label as hex: {}", label_name));
+
+    let (offset, length) = match (parse_param(req, "offset", 0), parse_param(req, "length", DEFAULT_LENGTH)) {
+        (Ok(offset), Ok(length)) => (offset, length),
+        (Err(e), _) | (_, Err(e)) => return Ok(error_response(400, &e)),
+    };
+    if length == 0 || length > MAX_LENGTH {
+        return Ok(error_response(400, &format!("length must be between 1 and {}", MAX_LENGTH)));
+    }
+
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    if offset > content.len() {
+        return Ok(error_response(
+            400,
+            &format!("offset {} is past the end of the label ({} bytes)", offset, content.len()),
+        ));
+    }
+    let end = offset.saturating_add(length).min(content.len());
+    let lines = content[offset..end]
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(i, bytes)| format_line(offset + i * BYTES_PER_LINE, bytes))
+        .collect();
+
+    let dump = HexDump {
+        label: label_name,
+        size_bytes: content.len(),
+        offset,
+        length: end - offset,
+        next_offset: (end < content.len()).then_some(end),
+        lines,
+    };
+
+    let body = serde_json::to_vec(&dump)
+        .map_err(|e| format!("Failed to serialize hex dump: {}", e))?;
+
+    Ok(json_response(200, body))
+}
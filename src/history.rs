@@ -0,0 +1,386 @@
+//! Version history of labels.
+//!
+//! Writing a label re-points it at new content and the old ref is no longer
+//! reachable by name. Every change to a label is therefore recorded in a
+//! history label of its own, `__system/history/{name}`, as JSON lines of the
+//! ref the label was left at and when. `GET /api/labels/{name}/history` lists
+//! the versions, newest first, and `POST /api/labels/{name}/restore` writes
+//! one of them back. The store keeps the content of old refs, so a history
+//! entry is all a restore needs. History stays with the name: it outlives
+//! deletion and is not carried along by a rename.
+//!
+//! The retention policy governing a label limits its history: `keep_versions`
+//! caps the versions kept and `max_history_days` drops versions older than
+//! that, which in time removes the history of a deleted label altogether.
+//! Limits apply whenever a version is recorded, to every history in the
+//! hourly retention pass, and on demand with `POST /api/history/prune`, which
+//! can also drop the history of every deleted label. `GET /api/history`
+//! reports what history costs: its own index labels and the old content only
+//! it still points at.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::eventlog::Op;
+use crate::{dryrun, error_response, json_response, query_param, validate_write, write_label, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Versions kept per label; older ones are dropped. A retention policy's
+/// `keep_versions` can lower it.
+const MAX_VERSIONS: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Version {
+    op: Op,
+    /// Ref the label was left at; absent for deletions
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    content_ref: Option<String>,
+    at: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RestoreRequest {
+    #[serde(rename = "ref")]
+    content_ref: String,
+}
+
+#[derive(Serialize)]
+struct HistoryResponse<'a> {
+    label: &'a str,
+    /// Ref the label points at now; absent while it is deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current_ref: Option<String>,
+    /// Newest first
+    versions: Vec<Version>,
+}
+
+#[derive(Serialize)]
+struct RestoreResponse<'a> {
+    label: &'a str,
+    #[serde(rename = "ref")]
+    content_ref: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_ref: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct HistoryUsage {
+    /// Labels with a history, deleted ones included
+    pub labels: usize,
+    /// Of those, labels that no longer exist
+    pub deleted_labels: usize,
+    pub versions: usize,
+    /// Size of the history labels themselves
+    pub index_bytes: u64,
+    /// Size of the content no label points at any more that history keeps
+    pub retained_bytes: u64,
+}
+
+#[derive(Serialize, Default)]
+struct PruneResponse {
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    dry_run: bool,
+    /// Labels whose history lost versions
+    pruned: usize,
+    versions_removed: usize,
+    /// Labels whose history is gone entirely
+    histories_removed: Vec<String>,
+}
+
+fn history_label(label: &str) -> String {
+    format!("{}history/{}", SYSTEM_LABEL_PREFIX, label)
+}
+
+/// Labels with a recorded history under `prefix`.
+fn labels_with_history(store_id: &str, prefix: &str) -> Result<Vec<String>, String> {
+    let history_prefix = history_label("");
+    Ok(store::list_labels(store_id)?
+        .into_iter()
+        .filter_map(|label| label.strip_prefix(&history_prefix).map(str::to_string))
+        .filter(|label| label.starts_with(prefix))
+        .collect())
+}
+
+/// The versions `label` keeps under its retention policy, and the time
+/// before which versions are dropped.
+fn limits(state: &StoreViewerState, label: &str) -> (usize, u64) {
+    let policy = state.retention.policy_for(label);
+    let keep = policy
+        .and_then(|policy| policy.keep_versions)
+        .map_or(MAX_VERSIONS, |keep| (keep as usize).min(MAX_VERSIONS));
+    let cutoff = policy
+        .and_then(|policy| policy.max_history_days)
+        .map_or(0, |days| state.clock_ms.saturating_sub(u64::from(days) * DAY_MS));
+    (keep, cutoff)
+}
+
+/// Drop the versions outside the limits; returns how many were dropped.
+fn apply_limits(versions: &mut Vec<Version>, (keep, cutoff): (usize, u64)) -> usize {
+    let before = versions.len();
+    versions.retain(|version| version.at >= cutoff);
+    let excess = versions.len().saturating_sub(keep);
+    versions.drain(..excess);
+    before - versions.len()
+}
+
+/// Recorded versions of `label`, oldest first.
+fn read(store_id: &str, label: &str) -> Result<Vec<Version>, String> {
+    let content_ref = match store::get_by_label(store_id, &history_label(label))? {
+        Some(content_ref) => content_ref,
+        None => return Ok(Vec::new()),
+    };
+    let content = store::get(store_id, &content_ref)?;
+    String::from_utf8_lossy(&content)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| format!("Corrupt history of {}: {}", label, e)))
+        .collect()
+}
+
+/// Replace the history of `label`, removing it once no versions are left.
+fn write(store_id: &str, label: &str, versions: &[Version]) -> Result<(), String> {
+    if versions.is_empty() {
+        return store::remove_label(store_id, &history_label(label));
+    }
+    let mut content = Vec::new();
+    for version in versions {
+        serde_json::to_writer(&mut content, version).map_err(|e| format!("Failed to serialize version: {}", e))?;
+        content.push(b'\n');
+    }
+    store::store_at_label(store_id, &history_label(label), &content)?;
+    Ok(())
+}
+
+fn append(store_id: &str, label: &str, previous: Option<&str>, version: Version, limits: (usize, u64)) -> Result<(), String> {
+    let mut versions = read(store_id, label)?;
+    // A label written before its history was kept starts it with the ref it
+    // held, so the change can be restored away like any other
+    if let (true, Some(previous)) = (versions.is_empty(), previous) {
+        versions.push(Version { op: Op::Write, content_ref: Some(previous.to_string()), at: version.at });
+    }
+    versions.push(version);
+    apply_limits(&mut versions, limits);
+    write(store_id, label, &versions)
+}
+
+/// Refs `label` was written with, newest first.
+pub fn refs(store_id: &str, label: &str) -> Result<Vec<String>, String> {
+    Ok(read(store_id, label)?
+        .into_iter()
+        .rev()
+        .filter_map(|version| version.content_ref)
+        .collect())
+}
+
+/// Add a change of `label`, which pointed at `previous` before, to its
+/// history. The viewer's own bookkeeping has none.
+pub fn record(state: &mut StoreViewerState, op: Op, label: &str, previous: Option<&str>, content_ref: Option<&ContentRef>) {
+    if label.starts_with(SYSTEM_LABEL_PREFIX) {
+        return;
+    }
+    let version = Version { op, content_ref: content_ref.map(|r| r.hash.clone()), at: state.clock_ms };
+    if let Err(e) = append(&state.store_id, label, previous, version, limits(state, label)) {
+        log(&format!("Error recording history of {}: {}", label, e));
+    }
+}
+
+/// Apply the limits to every history under `prefix`, and with `deleted` drop
+/// the histories of labels that no longer exist.
+fn prune(state: &StoreViewerState, prefix: &str, deleted: bool, dry_run: bool) -> Result<PruneResponse, String> {
+    let mut response = PruneResponse { dry_run, ..PruneResponse::default() };
+    for label in labels_with_history(&state.store_id, prefix)? {
+        let mut versions = read(&state.store_id, &label)?;
+        let removed = if deleted && store::get_by_label(&state.store_id, &label)?.is_none() {
+            std::mem::take(&mut versions).len()
+        } else {
+            apply_limits(&mut versions, limits(state, &label))
+        };
+        if removed == 0 {
+            continue;
+        }
+        response.pruned += 1;
+        response.versions_removed += removed;
+        if versions.is_empty() {
+            response.histories_removed.push(label.clone());
+        }
+        if !dry_run {
+            write(&state.store_id, &label, &versions)?;
+        }
+    }
+    Ok(response)
+}
+
+/// Apply the limits to every history; called by the retention pass.
+pub fn prune_all(state: &StoreViewerState) -> Result<usize, String> {
+    Ok(prune(state, "", false, false)?.versions_removed)
+}
+
+/// What history takes up in the store.
+pub fn usage(state: &StoreViewerState) -> Result<HistoryUsage, String> {
+    let mut usage = HistoryUsage::default();
+    let mut current = BTreeSet::new();
+    for label in store::list_labels(&state.store_id)? {
+        if label.starts_with(SYSTEM_LABEL_PREFIX) {
+            continue;
+        }
+        if let Some(content_ref) = store::get_by_label(&state.store_id, &label)? {
+            current.insert(content_ref.hash);
+        }
+    }
+
+    // Content only history points at, counted once however often it recurs
+    let mut retained = BTreeMap::new();
+    for label in labels_with_history(&state.store_id, "")? {
+        if let Some(content_ref) = store::get_by_label(&state.store_id, &history_label(&label))? {
+            usage.index_bytes += store::get(&state.store_id, &content_ref)?.len() as u64;
+        }
+        let versions = read(&state.store_id, &label)?;
+        usage.labels += 1;
+        usage.versions += versions.len();
+        if store::get_by_label(&state.store_id, &label)?.is_none() {
+            usage.deleted_labels += 1;
+        }
+        for hash in versions.into_iter().filter_map(|version| version.content_ref) {
+            if current.contains(&hash) || retained.contains_key(&hash) {
+                continue;
+            }
+            // Content the store no longer holds takes no space
+            let size = store::get(&state.store_id, &ContentRef { hash: hash.clone() }).map_or(0, |content| content.len() as u64);
+            retained.insert(hash, size);
+        }
+    }
+    usage.retained_bytes = retained.values().sum();
+    Ok(usage)
+}
+
+/// `GET /api/history`
+pub fn handle_usage(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Measuring label history");
+
+    let body = serde_json::to_vec(&usage(state)?)
+        .map_err(|e| format!("Failed to serialize history usage: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/history/prune?prefix=&deleted=`
+pub fn handle_prune(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Pruning label history");
+
+    let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+    let deleted = query_param(&req.uri, "deleted").as_deref() == Some("true");
+    let response = prune(state, &prefix, deleted, dryrun::is_dry_run(req))?;
+
+    log(&format!(
+        "Pruned {} versions from {} histories, {} removed",
+        response.versions_removed,
+        response.pruned,
+        response.histories_removed.len()
+    ));
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize prune result: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `GET /api/labels/{name}/history`
+pub fn handle_history(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Getting history of label: {}", label_name));
+
+    let current_ref = store::get_by_label(&state.store_id, label_name)?.map(|r| r.hash);
+    let mut versions = read(&state.store_id, label_name)?;
+    if current_ref.is_none() && versions.is_empty() {
+        return Ok(error_response(404, &format!("Label not found: {}", label_name)));
+    }
+    versions.reverse();
+
+    let response = HistoryResponse { label: label_name, current_ref, versions };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize history: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/labels/{name}/restore` with `{ "ref" }` of an earlier version
+pub fn handle_restore(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Restoring a version of label: {}", label_name));
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let restore: RestoreRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    let known = read(&state.store_id, label_name)?
+        .iter()
+        .any(|version| version.content_ref.as_deref() == Some(restore.content_ref.as_str()));
+    if !known {
+        return Ok(error_response(404, &format!("{} is not a version of {}", restore.content_ref, label_name)));
+    }
+    let content_ref = ContentRef { hash: restore.content_ref };
+    let content = match store::get(&state.store_id, &content_ref) {
+        Ok(content) => content,
+        Err(e) => return Ok(error_response(410, &format!("Content of {} is gone: {}", content_ref.hash, e))),
+    };
+    if let Err((status, e)) = validate_write(state, label_name, &content) {
+        return Ok(error_response(status, &e));
+    }
+
+    if dryrun::is_dry_run(req) {
+        let change = dryrun::plan_write(&state.store_id, label_name, &content)?;
+        return dryrun::dry_run_response(&[change]);
+    }
+
+    let previous_ref = store::get_by_label(&state.store_id, label_name)?.map(|r| r.hash);
+    let content_ref = write_label(state, "restore", label_name, &content)?;
+
+    log(&format!("Restored {} to {}", label_name, content_ref.hash));
+
+    let response = RestoreResponse { label: label_name, content_ref: content_ref.hash, previous_ref };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize restore: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(times: &[u64]) -> Vec<Version> {
+        times
+            .iter()
+            .map(|&at| Version { op: Op::Write, content_ref: Some(format!("ref{}", at)), at })
+            .collect()
+    }
+
+    fn times(versions: &[Version]) -> Vec<u64> {
+        versions.iter().map(|version| version.at).collect()
+    }
+
+    #[test]
+    fn limits_keep_the_newest_versions() {
+        let mut kept = versions(&[1, 2, 3, 4, 5]);
+        assert_eq!(apply_limits(&mut kept, (2, 0)), 3);
+        assert_eq!(times(&kept), [4, 5]);
+    }
+
+    #[test]
+    fn limits_drop_versions_before_the_cutoff() {
+        let mut kept = versions(&[1, 2, 3, 4, 5]);
+        assert_eq!(apply_limits(&mut kept, (MAX_VERSIONS, 3)), 2);
+        assert_eq!(times(&kept), [3, 4, 5]);
+        // Both limits at once
+        assert_eq!(apply_limits(&mut kept, (1, 4)), 2);
+        assert_eq!(times(&kept), [5]);
+    }
+
+    #[test]
+    fn history_labels_sit_under_the_system_prefix() {
+        assert_eq!(history_label("docs/a"), "__system/history/docs/a");
+    }
+}
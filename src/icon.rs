@@ -0,0 +1,71 @@
+//! File-type icons for listings.
+//!
+//! Detailed listings tag every label with the category of its content, so a
+//! client can draw a type icon next to each name without fetching the
+//! content itself. The category comes from the content's magic bytes where
+//! there are any and from its text otherwise.
+
+use crate::{archive, wasm};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Icon {
+    Json,
+    Image,
+    Archive,
+    Wasm,
+    Text,
+    Binary,
+}
+
+/// Signatures of the common image formats.
+const IMAGE_SIGNATURES: &[&[u8]] = &[
+    b"\x89PNG\r\n\x1a\n",
+    b"\xff\xd8\xff",
+    b"GIF87a",
+    b"GIF89a",
+    b"BM",
+    b"\x00\x00\x01\x00",
+];
+
+fn is_image(content: &[u8], text: Option<&str>) -> bool {
+    match text {
+        // Short signatures like `BM` begin plenty of text too; SVG is the text image
+        Some(text) => {
+            let start = text.trim_start();
+            start.starts_with("<svg") || (start.starts_with("<?xml") && text.contains("<svg"))
+        }
+        None => {
+            let webp = content.len() >= 12 && content.starts_with(b"RIFF") && &content[8..12] == b"WEBP";
+            webp || IMAGE_SIGNATURES.iter().any(|signature| content.starts_with(signature))
+        }
+    }
+}
+
+/// The icon for `content`, whose text is `text` when it is text at all.
+pub fn for_content(label: &str, content: &[u8], text: Option<&str>) -> Icon {
+    if wasm::detect(content).is_some() {
+        return Icon::Wasm;
+    }
+    if archive::detect(content).is_some() {
+        return Icon::Archive;
+    }
+    if is_image(content, text) {
+        return Icon::Image;
+    }
+    match text {
+        Some(text) => {
+            let trimmed = text.trim_start();
+            let looks_like_json = label.to_ascii_lowercase().ends_with(".json")
+                || trimmed.starts_with('{')
+                || trimmed.starts_with('[');
+            if looks_like_json && serde_json::from_str::<serde_json::Value>(text).is_ok() {
+                Icon::Json
+            } else {
+                Icon::Text
+            }
+        }
+        None => Icon::Binary,
+    }
+}
@@ -0,0 +1,371 @@
+//! Importing labels into the viewer's store.
+//!
+//! `POST /api/import/store` copies labels from another store the actor can
+//! access, optionally only those under a prefix and optionally renamed under
+//! a different one, so content can be migrated between stores without leaving
+//! the host. Every copy is validated before the first write, and labels that
+//! already hold different content are left alone unless `overwrite` is set.
+//! A dry run previews the whole import, conflicts and rejections included.
+//!
+//! `POST /api/import` takes the labels themselves instead: a JSON object
+//! mapping names to content, for seeding test stores or carrying labels
+//! between environments. Its labels are always written, replacing what is
+//! there, and the response tells created labels from overwritten ones.
+//!
+//! With `?stage=true` either import lands in a staging set instead of the
+//! labels it names, to be reviewed and committed or discarded (see
+//! `staging`).
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::dryrun::{self, ChangeAction, PlannedChange};
+use crate::{duplicates, staging};
+use crate::journal::{self, Step};
+use crate::{decode_content, error_response, json_response, naming, validate_write, write_journaled, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Deserialize)]
+struct StoreImportRequest {
+    store_id: String,
+    /// Only copy source labels under this prefix
+    #[serde(default)]
+    prefix: Option<String>,
+    /// Replace `prefix` with this one in the imported names
+    #[serde(default)]
+    target_prefix: Option<String>,
+    /// Replace labels that exist with different content
+    #[serde(default)]
+    overwrite: bool,
+}
+
+/// Content of one label in an imported document: text, or an object that
+/// can say the text is base64.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DocumentEntry {
+    Text(String),
+    Encoded {
+        content: String,
+        #[serde(default)]
+        encoding: Option<String>,
+    },
+}
+
+#[derive(Serialize, Default)]
+struct DocumentImportResponse {
+    created: Vec<String>,
+    overwritten: Vec<String>,
+    /// Labels that already held the imported content
+    unchanged: Vec<String>,
+    /// Written labels whose content other labels already held, with those labels
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    deduplicated: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Serialize)]
+struct ImportedLabel {
+    source: String,
+    label: String,
+    action: ChangeAction,
+}
+
+#[derive(Serialize)]
+struct StoreImportResponse {
+    store_id: String,
+    imported: Vec<ImportedLabel>,
+    /// Labels that exist here with different content and were not replaced
+    skipped: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct RejectedLabel {
+    label: String,
+    error: String,
+}
+
+#[derive(Serialize, Default)]
+struct ImportSummary {
+    create: usize,
+    overwrite: usize,
+    unchanged: usize,
+    skipped: usize,
+    rejected: usize,
+}
+
+/// Dry-run answer: what the import would do and what stands in its way.
+#[derive(Serialize)]
+struct ImportPreview {
+    dry_run: bool,
+    /// Store imported from, for store imports
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store_id: Option<String>,
+    summary: ImportSummary,
+    changes: Vec<PlannedChange>,
+    /// Labels holding other content that would be kept without `overwrite`
+    skipped: Vec<PlannedChange>,
+    /// Labels that fail validation; a real import of them answers `422`
+    rejected: Vec<RejectedLabel>,
+}
+
+impl ImportPreview {
+    fn new(store_id: Option<String>, changes: Vec<PlannedChange>, skipped: Vec<PlannedChange>, rejected: Vec<RejectedLabel>) -> Self {
+        let mut summary = ImportSummary {
+            skipped: skipped.len(),
+            rejected: rejected.len(),
+            ..ImportSummary::default()
+        };
+        for change in &changes {
+            match change.action {
+                ChangeAction::Create => summary.create += 1,
+                ChangeAction::Overwrite => summary.overwrite += 1,
+                _ => summary.unchanged += 1,
+            }
+        }
+        ImportPreview { dry_run: true, store_id, summary, changes, skipped, rejected }
+    }
+}
+
+/// Why an import may not make `change` with `content`: the naming rules for
+/// a label it creates, and the checks of every write.
+pub fn rejections(state: &StoreViewerState, change: &PlannedChange, content: &[u8]) -> Vec<String> {
+    let mut errors = Vec::new();
+    if change.action == ChangeAction::Create {
+        if let Err(error) = naming::check_name(&state.naming, &change.label) {
+            errors.push(error);
+        }
+    }
+    if change.action != ChangeAction::Unchanged {
+        if let Err((_, error)) = validate_write(state, &change.label, content) {
+            errors.push(error);
+        }
+    }
+    errors
+}
+
+/// `POST /api/import/store`
+pub fn handle_import_store(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Importing labels from another store");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let import_req: StoreImportRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if import_req.store_id.is_empty() {
+        return Ok(error_response(400, "store_id is required"));
+    }
+    if import_req.store_id == state.store_id {
+        return Ok(error_response(400, "Cannot import a store into itself"));
+    }
+    if import_req.target_prefix.is_some() && import_req.prefix.is_none() {
+        return Ok(error_response(400, "target_prefix requires prefix"));
+    }
+    let stage = staging::is_staged(req);
+
+    let source_labels = match store::list_labels(&import_req.store_id) {
+        Ok(labels) => labels,
+        Err(e) => return Ok(error_response(404, &format!("Store {} is not accessible: {}", import_req.store_id, e))),
+    };
+
+    let prefix = import_req.prefix.as_deref().unwrap_or("");
+    let mut copies = Vec::new();
+    let mut skipped = Vec::new();
+    let mut rejected = Vec::new();
+    for source in source_labels {
+        if source.starts_with(SYSTEM_LABEL_PREFIX) {
+            continue;
+        }
+        let relative = match source.strip_prefix(prefix) {
+            Some(relative) => relative,
+            None => continue,
+        };
+        let label = match &import_req.target_prefix {
+            Some(target_prefix) => format!("{}{}", target_prefix, relative),
+            None => source.clone(),
+        };
+        let content_ref = match store::get_by_label(&import_req.store_id, &source)? {
+            Some(content_ref) => content_ref,
+            None => continue,
+        };
+        let content = store::get(&import_req.store_id, &content_ref)?;
+
+        let mut plan = dryrun::plan_write(&state.store_id, &label, &content)?;
+        // Refs are content hashes, so the source's ref is the one the copy gets
+        plan.content_ref = Some(content_ref.hash);
+        // Existing labels with other content are kept unless asked otherwise
+        if plan.action == ChangeAction::Overwrite && !import_req.overwrite {
+            skipped.push(plan);
+            continue;
+        }
+        // Staged labels are validated when the staging set is reviewed
+        if !stage {
+            for error in rejections(state, &plan, &content) {
+                rejected.push(RejectedLabel { label: label.clone(), error });
+            }
+        }
+        copies.push((source, content, plan));
+    }
+    if copies.is_empty() && skipped.is_empty() {
+        return Ok(error_response(404, &format!("No labels to import from store {}", import_req.store_id)));
+    }
+
+    if dryrun::is_dry_run(req) {
+        let changes: Vec<_> = copies.into_iter().map(|(_, _, plan)| plan).collect();
+        let preview = ImportPreview::new(Some(import_req.store_id), changes, skipped, rejected);
+        let body = serde_json::to_vec(&preview)
+            .map_err(|e| format!("Failed to serialize dry run: {}", e))?;
+        return Ok(json_response(200, body));
+    }
+    if !rejected.is_empty() {
+        let errors: Vec<&str> = rejected.iter().map(|r| r.error.as_str()).collect();
+        return Ok(error_response(422, &format!("Import rejected: {}", errors.join("; "))));
+    }
+    if stage {
+        let source = format!("store:{}", import_req.store_id);
+        let labels = copies.into_iter().map(|(_, content, plan)| (plan.label, content)).collect();
+        return staging::stage(state, source, labels);
+    }
+
+    let mut steps = Vec::new();
+    for (_, content, plan) in &copies {
+        if plan.action != ChangeAction::Unchanged {
+            steps.push(Step::write(&state.store_id, &plan.label, content)?);
+        }
+    }
+    let journal_id = journal::begin(state, "import_store", steps)?;
+
+    let mut imported = Vec::with_capacity(copies.len());
+    for (source, content, plan) in copies {
+        if plan.action != ChangeAction::Unchanged {
+            write_journaled(state, &plan.label, &content)?;
+        }
+        imported.push(ImportedLabel { source, label: plan.label, action: plan.action });
+    }
+    journal::complete(state, journal_id)?;
+
+    log(&format!(
+        "Imported {} labels from store {} ({} skipped)",
+        imported.len(),
+        import_req.store_id,
+        skipped.len()
+    ));
+
+    let skipped = skipped.into_iter().map(|plan| plan.label).collect();
+    let response = StoreImportResponse { store_id: import_req.store_id, imported, skipped };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/import` with `{ "<name>": "<content>" | { "content", "encoding"? } }`
+pub fn handle_import_document(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Importing labels from a document");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let document: BTreeMap<String, DocumentEntry> = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if document.is_empty() {
+        return Ok(error_response(400, "The document holds no labels"));
+    }
+    let stage = staging::is_staged(req);
+
+    let mut writes = Vec::with_capacity(document.len());
+    let mut rejected = Vec::new();
+    for (label, entry) in document {
+        if label.is_empty() {
+            rejected.push(RejectedLabel { label, error: "Label names cannot be empty".to_string() });
+            continue;
+        }
+        let decoded = match entry {
+            DocumentEntry::Text(content) => Ok(content.into_bytes()),
+            DocumentEntry::Encoded { content, encoding } => decode_content(content, encoding.as_deref()),
+        };
+        let content = match decoded {
+            Ok(content) => content,
+            Err(e) => {
+                rejected.push(RejectedLabel { label: label.clone(), error: format!("{}: {}", label, e) });
+                continue;
+            }
+        };
+
+        let plan = dryrun::plan_write(&state.store_id, &label, &content)?;
+        if !stage {
+            for error in rejections(state, &plan, &content) {
+                rejected.push(RejectedLabel { label: label.clone(), error });
+            }
+        }
+        writes.push((content, plan));
+    }
+
+    if dryrun::is_dry_run(req) {
+        let changes: Vec<_> = writes.into_iter().map(|(_, plan)| plan).collect();
+        let preview = ImportPreview::new(None, changes, Vec::new(), rejected);
+        let body = serde_json::to_vec(&preview)
+            .map_err(|e| format!("Failed to serialize dry run: {}", e))?;
+        return Ok(json_response(200, body));
+    }
+    if !rejected.is_empty() {
+        let errors: Vec<&str> = rejected.iter().map(|r| r.error.as_str()).collect();
+        return Ok(error_response(422, &format!("Import rejected: {}", errors.join("; "))));
+    }
+    if stage {
+        let labels = writes.into_iter().map(|(content, plan)| (plan.label, content)).collect();
+        return staging::stage(state, "document".to_string(), labels);
+    }
+
+    let mut steps = Vec::new();
+    for (content, plan) in &writes {
+        if plan.action != ChangeAction::Unchanged {
+            steps.push(Step::write(&state.store_id, &plan.label, content)?);
+        }
+    }
+    let journal_id = journal::begin(state, "import", steps)?;
+
+    let mut response = DocumentImportResponse::default();
+    let mut written = Vec::new();
+    for (content, plan) in writes {
+        if plan.action == ChangeAction::Unchanged {
+            response.unchanged.push(plan.label);
+            continue;
+        }
+        let content_ref = write_journaled(state, &plan.label, &content)?;
+        let created = plan.action == ChangeAction::Create;
+        written.push((plan.label.clone(), content_ref.hash));
+        if created {
+            response.created.push(plan.label);
+        } else {
+            response.overwritten.push(plan.label);
+        }
+    }
+    journal::complete(state, journal_id)?;
+
+    // One pass over the store covers every written label
+    match duplicates::labels_by_ref(&state.store_id) {
+        Ok(by_ref) => {
+            for (label, hash) in written {
+                let report = duplicates::report_from(&by_ref, Some(&label), &hash);
+                if report.deduplicated {
+                    response.deduplicated.insert(label, report.duplicate_of);
+                }
+            }
+        }
+        Err(e) => log(&format!("Error checking for duplicate content: {}", e)),
+    }
+
+    log(&format!(
+        "Imported document: {} created, {} overwritten, {} unchanged",
+        response.created.len(),
+        response.overwritten.len(),
+        response.unchanged.len()
+    ));
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
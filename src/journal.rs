@@ -0,0 +1,205 @@
+//! Write-ahead journal for label mutations.
+//!
+//! Before a request changes any label, the steps it is about to take are
+//! appended to a journal kept in the `__system/journal` label: for writes the
+//! new content is stored first, so the journal holds its ref. The entry is
+//! removed once every step has been applied. An entry still present when the
+//! actor starts belongs to an operation that was interrupted; the recovery
+//! pass in `init` compares each step with the store to report which steps of
+//! it took effect, then clears the journal.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::{json_response, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+
+pub fn journal_label() -> String {
+    format!("{}journal", SYSTEM_LABEL_PREFIX)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StepAction {
+    /// Point the label at `content_ref` (creating or overwriting it)
+    Write,
+    /// Remove the label
+    Delete,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Step {
+    pub label: String,
+    pub action: StepAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_ref: Option<String>,
+}
+
+impl Step {
+    /// Store `content` ahead of the write and describe the write.
+    pub fn write(store_id: &str, label: &str, content: &[u8]) -> Result<Self, String> {
+        let content_ref = store::store(store_id, content)?;
+        Ok(Self::point(label, &content_ref))
+    }
+
+    /// Describe re-pointing `label` at content that is already stored.
+    pub fn point(label: &str, content_ref: &ContentRef) -> Self {
+        Step {
+            label: label.to_string(),
+            action: StepAction::Write,
+            content_ref: Some(content_ref.hash.clone()),
+        }
+    }
+
+    pub fn delete(label: &str) -> Self {
+        Step {
+            label: label.to_string(),
+            action: StepAction::Delete,
+            content_ref: None,
+        }
+    }
+
+    /// Whether the store already reflects this step.
+    fn is_applied(&self, store_id: &str) -> Result<bool, String> {
+        let current = store::get_by_label(store_id, &self.label)?;
+        Ok(match self.action {
+            StepAction::Write => current.map(|r| r.hash) == self.content_ref,
+            StepAction::Delete => current.is_none(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub id: u64,
+    /// What the request was doing, e.g. `update` or `promote`
+    pub operation: String,
+    pub started_at: u64,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Journal {
+    next_id: u64,
+    entries: Vec<JournalEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryStatus {
+    /// Every step took effect; only the completion mark was lost
+    Applied,
+    /// Some steps took effect and some did not
+    Partial,
+    NotApplied,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecoveredOperation {
+    pub entry: JournalEntry,
+    pub status: RecoveryStatus,
+    /// Labels whose step took effect
+    pub applied: Vec<String>,
+    /// Labels whose step did not
+    pub pending: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct JournalResponse<'a> {
+    label: String,
+    in_progress: Vec<JournalEntry>,
+    recovered: &'a [RecoveredOperation],
+}
+
+fn load(store_id: &str) -> Result<Journal, String> {
+    match store::get_by_label(store_id, &journal_label())? {
+        Some(content_ref) => serde_json::from_slice(&store::get(store_id, &content_ref)?)
+            .map_err(|e| format!("Corrupt journal: {}", e)),
+        None => Ok(Journal::default()),
+    }
+}
+
+fn save(store_id: &str, journal: &Journal) -> Result<(), String> {
+    let bytes = serde_json::to_vec(journal)
+        .map_err(|e| format!("Failed to serialize journal: {}", e))?;
+    store::store_at_label(store_id, &journal_label(), &bytes)?;
+    Ok(())
+}
+
+/// Record the intent to apply `steps`; returns the entry id to pass to
+/// `complete` once they are applied.
+pub fn begin(state: &StoreViewerState, operation: &str, steps: Vec<Step>) -> Result<u64, String> {
+    let mut journal = load(&state.store_id)?;
+    let id = journal.next_id;
+    journal.next_id += 1;
+    journal.entries.push(JournalEntry {
+        id,
+        operation: operation.to_string(),
+        started_at: state.clock_ms,
+        steps,
+    });
+    save(&state.store_id, &journal)?;
+    Ok(id)
+}
+
+/// Mark a journaled operation as fully applied.
+pub fn complete(state: &StoreViewerState, id: u64) -> Result<(), String> {
+    let mut journal = load(&state.store_id)?;
+    journal.entries.retain(|entry| entry.id != id);
+    save(&state.store_id, &journal)
+}
+
+/// Inspect operations left in the journal by an interrupted run, then clear
+/// them. Called once from `init`.
+pub fn recover(store_id: &str) -> Result<Vec<RecoveredOperation>, String> {
+    let mut journal = load(store_id)?;
+    if journal.entries.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut recovered = Vec::new();
+    for entry in std::mem::take(&mut journal.entries) {
+        let mut applied = Vec::new();
+        let mut pending = Vec::new();
+        for step in &entry.steps {
+            if step.is_applied(store_id)? {
+                applied.push(step.label.clone());
+            } else {
+                pending.push(step.label.clone());
+            }
+        }
+        let status = match (applied.is_empty(), pending.is_empty()) {
+            (_, true) => RecoveryStatus::Applied,
+            (true, false) => RecoveryStatus::NotApplied,
+            (false, false) => RecoveryStatus::Partial,
+        };
+        log(&format!(
+            "Recovered interrupted {} operation {}: {} of {} steps applied",
+            entry.operation,
+            entry.id,
+            applied.len(),
+            entry.steps.len()
+        ));
+        recovered.push(RecoveredOperation { entry, status, applied, pending });
+    }
+
+    save(store_id, &journal)?;
+    Ok(recovered)
+}
+
+/// `GET /api/journal`: operations not yet completed and what the recovery
+/// pass at startup found.
+pub fn handle_journal(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting journal");
+
+    let response = JournalResponse {
+        label: journal_label(),
+        in_progress: load(&state.store_id)?.entries,
+        recovered: &state.recovered,
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize journal: {}", e))?;
+
+    Ok(json_response(200, body))
+}
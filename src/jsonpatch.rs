@@ -0,0 +1,179 @@
+//! JSON Patch responses for polling clients.
+//!
+//! `GET /api/labels/{name}?diff_since={ref}` answers with an RFC 6902 patch
+//! turning the JSON a client already holds (identified by its content ref)
+//! into the label's current JSON, so a client polling a large document only
+//! downloads what changed. Arrays are compared position by position; an
+//! insertion near the front shows up as a run of replacements rather than a
+//! single `add`, which is still a valid patch.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::{error_response, json_response, StoreViewerState};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+}
+
+#[derive(Serialize)]
+struct PatchResponse {
+    name: String,
+    /// Ref the patch applies to
+    base_ref: String,
+    /// Ref of the content the patch produces
+    content_ref: String,
+    patch: Vec<PatchOp>,
+}
+
+/// Escape a key for use as a JSON Pointer segment.
+fn escape_segment(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Operations turning `old` into `new`, with paths below `path`.
+pub fn diff(old: &Value, new: &Value, path: &str, ops: &mut Vec<PatchOp>) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let child = format!("{}/{}", path, escape_segment(key));
+                match new_map.get(key) {
+                    Some(new_value) => diff(old_value, new_value, &child, ops),
+                    None => ops.push(PatchOp::Remove { path: child }),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    ops.push(PatchOp::Add {
+                        path: format!("{}/{}", path, escape_segment(key)),
+                        value: new_value.clone(),
+                    });
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            let common = old_items.len().min(new_items.len());
+            for i in 0..common {
+                diff(&old_items[i], &new_items[i], &format!("{}/{}", path, i), ops);
+            }
+            // Remove from the end so earlier indices stay valid
+            for i in (common..old_items.len()).rev() {
+                ops.push(PatchOp::Remove { path: format!("{}/{}", path, i) });
+            }
+            for item in &new_items[common..] {
+                ops.push(PatchOp::Add { path: format!("{}/-", path), value: item.clone() });
+            }
+        }
+        _ if old == new => {}
+        _ => ops.push(PatchOp::Replace { path: path.to_string(), value: new.clone() }),
+    }
+}
+
+fn parse_json(content: &[u8]) -> Option<Value> {
+    serde_json::from_slice(content).ok()
+}
+
+/// `GET /api/labels/{name}?diff_since={ref}`
+pub fn handle_diff_since(state: &mut StoreViewerState, label_name: &str, base_hash: String) -> Result<HttpResponse, String> {
+    log(&format!("JSON patch for label {} since {}", label_name, base_hash));
+
+    let current_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let base_ref = ContentRef { hash: base_hash };
+    if !store::exists(&state.store_id, &base_ref)? {
+        return Ok(error_response(404, &format!("Content not found: {}", base_ref.hash)));
+    }
+
+    let mut patch = Vec::new();
+    if base_ref.hash != current_ref.hash {
+        let current = match parse_json(&store::get(&state.store_id, &current_ref)?) {
+            Some(current) => current,
+            None => return Ok(error_response(415, &format!("Label {} is not JSON", label_name))),
+        };
+        let base = match parse_json(&store::get(&state.store_id, &base_ref)?) {
+            Some(base) => base,
+            None => return Ok(error_response(415, &format!("Content {} is not JSON", base_ref.hash))),
+        };
+        diff(&base, &current, "", &mut patch);
+    }
+
+    state.stats.record_read(label_name, state.clock_ms);
+
+    let response = PatchResponse {
+        name: label_name.to_string(),
+        base_ref: base_ref.hash,
+        content_ref: current_ref.hash,
+        patch,
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize patch: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn patch(old: Value, new: Value) -> Vec<PatchOp> {
+        let mut ops = Vec::new();
+        diff(&old, &new, "", &mut ops);
+        ops
+    }
+
+    #[test]
+    fn equal_documents_need_no_operations() {
+        assert_eq!(patch(json!({"a": [1, {"b": null}]}), json!({"a": [1, {"b": null}]})), vec![]);
+    }
+
+    #[test]
+    fn object_members_are_added_removed_and_replaced() {
+        assert_eq!(
+            patch(json!({"keep": 1, "gone": 2, "change": 3}), json!({"keep": 1, "change": 4, "new": 5})),
+            vec![
+                PatchOp::Remove { path: "/gone".to_string() },
+                PatchOp::Replace { path: "/change".to_string(), value: json!(4) },
+                PatchOp::Add { path: "/new".to_string(), value: json!(5) },
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_shrink_from_the_end_and_grow_by_appending() {
+        assert_eq!(
+            patch(json!([1, 2, 3, 4]), json!([1, 9])),
+            vec![
+                PatchOp::Replace { path: "/1".to_string(), value: json!(9) },
+                PatchOp::Remove { path: "/3".to_string() },
+                PatchOp::Remove { path: "/2".to_string() },
+            ]
+        );
+        assert_eq!(patch(json!([1]), json!([1, 2])), vec![PatchOp::Add { path: "/-".to_string(), value: json!(2) }]);
+    }
+
+    #[test]
+    fn keys_are_escaped_as_pointer_segments() {
+        assert_eq!(
+            patch(json!({"a/b": {"c~d": 1}}), json!({"a/b": {"c~d": 2}})),
+            vec![PatchOp::Replace { path: "/a~1b/c~0d".to_string(), value: json!(2) }]
+        );
+    }
+
+    #[test]
+    fn a_changed_type_replaces_the_whole_value() {
+        assert_eq!(
+            patch(json!({"a": 1}), json!([1])),
+            vec![PatchOp::Replace { path: String::new(), value: json!([1]) }]
+        );
+    }
+}
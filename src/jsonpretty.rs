@@ -0,0 +1,121 @@
+//! Validating and pretty-printing JSON labels.
+//!
+//! `GET /api/labels/{name}/json` answers with the label's JSON indented two
+//! spaces a level, or with `422` and the line and column of the first syntax
+//! error, so a client can tell valid JSON from text that merely looks like it.
+//! The document is reindented as written rather than reserialized, so keys
+//! keep their order and numbers their exact digits.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, json_response, labelmeta, StoreViewerState};
+use serde::Serialize;
+use serde_json::error::Category;
+
+const INDENT: &str = "  ";
+
+#[derive(Serialize)]
+struct ParseError {
+    error: String,
+    /// `syntax`, `eof` (the document ends early) or `data`
+    category: &'static str,
+    line: usize,
+    column: usize,
+}
+
+fn newline(out: &mut String, depth: usize) {
+    out.push('\n');
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+}
+
+/// Reindent a document already known to be valid JSON.
+fn reindent(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut depth = 0;
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' | '[' => {
+                out.push(c);
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                // Empty containers stay on one line
+                if let Some(close) = chars.next_if(|&next| next == '}' || next == ']') {
+                    out.push(close);
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            ':' => out.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    out.push('\n');
+    out
+}
+
+/// `GET /api/labels/{name}/json`
+pub fn handle_json(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Pretty-printing JSON label: {}", label_name));
+
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    let text = match labelmeta::decode_text(state, label_name, &content) {
+        Some(text) => text,
+        None => return Ok(error_response(415, &format!("Label {} is not JSON: binary content", label_name))),
+    };
+
+    if let Err(e) = serde_json::from_str::<serde::de::IgnoredAny>(&text) {
+        let category = match e.classify() {
+            Category::Eof => "eof",
+            Category::Data => "data",
+            Category::Syntax | Category::Io => "syntax",
+        };
+        let error = ParseError {
+            error: format!("Label {} is not valid JSON: {}", label_name, e),
+            category,
+            line: e.line(),
+            column: e.column(),
+        };
+        let body = serde_json::to_vec(&error)
+            .map_err(|e| format!("Failed to serialize parse error: {}", e))?;
+        return Ok(json_response(422, body));
+    }
+
+    Ok(json_response(200, reindent(&text).into_bytes()))
+}
@@ -0,0 +1,294 @@
+//! Language, text encoding and response headers recorded for labels.
+//!
+//! The store keeps bytes only, so the language of a label's text and the
+//! encoding it was written in are kept in the actor state. The encoding is
+//! used to transcode content to UTF-8 for previews; when none is recorded it
+//! is detected from the content. Custom headers (`Cache-Control`, a
+//! `Content-Disposition` filename, ...) are sent whenever the label is served
+//! as-is, from `/raw` or a public share, so the store can host assets.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::charset::{self, Charset};
+use crate::dryrun;
+use crate::editor::{self, EditorHints};
+use crate::{error_response, header, is_text_content, json_response, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// Custom headers one label may carry.
+const MAX_HEADERS: usize = 20;
+
+/// Longest value of a custom header.
+const MAX_HEADER_VALUE_BYTES: usize = 1024;
+
+/// Headers the server sets itself: framing, caching validators, and the
+/// protections that keep served content from running on the viewer's origin.
+const RESERVED_HEADERS: &[&str] = &[
+    "Content-Length",
+    "Content-Range",
+    "Content-Encoding",
+    "Transfer-Encoding",
+    "Connection",
+    "Accept-Ranges",
+    "ETag",
+    "Set-Cookie",
+    "Content-Security-Policy",
+    "X-Content-Type-Options",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LabelMeta {
+    /// Language tag as sent in `Content-Language`, e.g. `en` or `de-CH`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Encoding of the stored bytes; detected when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charset: Option<Charset>,
+    /// Lint problems found in the content at its last write
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lint_warnings: Vec<String>,
+    /// Extra response headers for serving the label as-is
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl LabelMeta {
+    pub fn is_empty(&self) -> bool {
+        self.language.is_none() && self.charset.is_none() && self.lint_warnings.is_empty() && self.headers.is_empty()
+    }
+}
+
+/// Body of `PUT /api/labels/{name}/meta`. A missing field is left unchanged;
+/// `null` clears it.
+#[derive(Deserialize)]
+struct MetaUpdate {
+    #[serde(default, deserialize_with = "present")]
+    language: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    charset: Option<Option<String>>,
+    /// Replaces all custom headers; `{}` or `null` removes them
+    #[serde(default, deserialize_with = "present")]
+    headers: Option<Option<BTreeMap<String, String>>>,
+}
+
+/// Distinguish a field set to `null` from an absent one.
+pub fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// What `PUT .../meta?dry_run=true` would save.
+#[derive(Serialize)]
+struct MetaPreview<'a> {
+    dry_run: bool,
+    name: &'a str,
+    #[serde(flatten)]
+    meta: &'a LabelMeta,
+}
+
+#[derive(Serialize)]
+struct MetaResponse<'a> {
+    name: &'a str,
+    language: Option<&'a str>,
+    charset: Option<Charset>,
+    /// Encoding the content appears to be in
+    detected_charset: Option<Charset>,
+    editor: EditorHints,
+    lint_warnings: &'a [String],
+    headers: Option<&'a BTreeMap<String, String>>,
+}
+
+pub fn meta_for<'a>(state: &'a StoreViewerState, label: &str) -> Option<&'a LabelMeta> {
+    state.label_meta.get(label)
+}
+
+/// Check a language tag: alphanumeric subtags of up to 8 characters joined
+/// by hyphens.
+pub fn validate_language(tag: &str) -> Result<(), String> {
+    let valid = !tag.is_empty()
+        && tag
+            .split('-')
+            .all(|sub| !sub.is_empty() && sub.len() <= 8 && sub.chars().all(|c| c.is_ascii_alphanumeric()));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid language tag: {}", tag))
+    }
+}
+
+/// Check custom headers: token names other than the reserved ones, and
+/// values of visible ASCII and spaces.
+pub fn validate_headers(headers: &BTreeMap<String, String>) -> Result<(), String> {
+    if headers.len() > MAX_HEADERS {
+        return Err(format!("A label can have at most {} custom headers", MAX_HEADERS));
+    }
+    for (name, value) in headers {
+        let token = !name.is_empty()
+            && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+        if !token {
+            return Err(format!("Invalid header name: {}", name));
+        }
+        if RESERVED_HEADERS.iter().any(|reserved| reserved.eq_ignore_ascii_case(name)) {
+            return Err(format!("Header {} is set by the server and cannot be overridden", name));
+        }
+        if value.len() > MAX_HEADER_VALUE_BYTES || !value.bytes().all(|b| b == b'\t' || (b' '..=b'~').contains(&b)) {
+            return Err(format!("Invalid value for header {}", name));
+        }
+    }
+    Ok(())
+}
+
+/// Check metadata recorded other than through `PUT .../meta`, such as an
+/// imported index, as that endpoint would have.
+pub fn validate_meta(meta: &LabelMeta) -> Result<(), String> {
+    if let Some(tag) = &meta.language {
+        validate_language(tag)?;
+    }
+    validate_headers(&meta.headers)
+}
+
+/// Add a label's custom headers to a response serving it as-is, replacing
+/// any the response already has under the same name.
+pub fn apply_headers(state: &StoreViewerState, label: &str, headers: &mut Vec<(String, String)>) {
+    let custom = match meta_for(state, label) {
+        Some(meta) if !meta.headers.is_empty() => &meta.headers,
+        _ => return,
+    };
+    headers.retain(|(name, _)| !custom.keys().any(|custom| custom.eq_ignore_ascii_case(name)));
+    headers.extend(custom.iter().map(|(name, value)| (name.clone(), value.clone())));
+}
+
+/// The language given for a write: the `language` body field, else the
+/// `Content-Language` header.
+pub fn request_language(req: &HttpRequest, field: Option<String>) -> Result<Option<String>, String> {
+    let language = field.or_else(|| header(req, "content-language").map(str::to_string));
+    if let Some(tag) = &language {
+        validate_language(tag)?;
+    }
+    Ok(language)
+}
+
+/// Record metadata for a write through the API. Content sent as JSON is
+/// UTF-8, so any recorded encoding no longer applies; the language is kept
+/// unless a new one is given.
+pub fn record_write(state: &mut StoreViewerState, label: &str, language: Option<String>) {
+    let meta = state.label_meta.entry(label.to_string()).or_default();
+    meta.charset = None;
+    if language.is_some() {
+        meta.language = language;
+    }
+    if meta.is_empty() {
+        state.label_meta.remove(label);
+    }
+}
+
+/// The encoding a label's content is in: the recorded one, else detected.
+pub fn charset_of(state: &StoreViewerState, label: &str, content: &[u8]) -> Option<Charset> {
+    meta_for(state, label)
+        .and_then(|meta| meta.charset)
+        .or_else(|| charset::detect(content))
+}
+
+/// The content of a label as UTF-8 text, transcoded from its recorded or
+/// detected encoding. `None` for binary content.
+pub fn decode_text<'a>(state: &StoreViewerState, label: &str, content: &'a [u8]) -> Option<Cow<'a, str>> {
+    let charset = charset_of(state, label, content)?;
+    let text = charset.decode(content)?;
+    match charset {
+        // Valid UTF-8 can still be mostly control characters
+        Charset::Utf8 if !is_text_content(text.as_bytes()) => None,
+        _ => Some(text),
+    }
+}
+
+/// `GET /api/labels/{name}/meta`
+pub fn handle_get_meta(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Getting metadata of label: {}", label_name));
+
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    let meta = meta_for(state, label_name);
+    let response = MetaResponse {
+        name: label_name,
+        language: meta.and_then(|m| m.language.as_deref()),
+        charset: meta.and_then(|m| m.charset),
+        detected_charset: charset::detect(&content),
+        editor: editor::hints_for(&state.editor, label_name, decode_text(state, label_name, &content).is_some()),
+        lint_warnings: meta.map_or(&[], |m| m.lint_warnings.as_slice()),
+        headers: meta.map(|m| &m.headers).filter(|headers| !headers.is_empty()),
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize label metadata: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `PUT /api/labels/{name}/meta` with `{"language": "fr", "charset": "windows-1252", "headers": {...}}`
+pub fn handle_put_meta(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Setting metadata of label: {}", label_name));
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let update: MetaUpdate = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if store::get_by_label(&state.store_id, label_name)?.is_none() {
+        return Ok(error_response(404, &format!("Label not found: {}", label_name)));
+    }
+
+    let mut meta = meta_for(state, label_name).cloned().unwrap_or_default();
+    if let Some(language) = update.language {
+        if let Some(tag) = &language {
+            if let Err(e) = validate_language(tag) {
+                return Ok(error_response(400, &e));
+            }
+        }
+        meta.language = language;
+    }
+    if let Some(name) = update.charset {
+        meta.charset = match name {
+            Some(name) => match Charset::parse(&name) {
+                Some(charset) => Some(charset),
+                None => return Ok(error_response(400, &format!("Unsupported charset: {}", name))),
+            },
+            None => None,
+        };
+    }
+
+    if let Some(headers) = update.headers {
+        let headers = headers.unwrap_or_default();
+        if let Err(e) = validate_headers(&headers) {
+            return Ok(error_response(400, &e));
+        }
+        meta.headers = headers;
+    }
+
+    if dryrun::is_dry_run(req) {
+        let preview = MetaPreview { dry_run: true, name: label_name, meta: &meta };
+        let body = serde_json::to_vec(&preview)
+            .map_err(|e| format!("Failed to serialize label metadata: {}", e))?;
+        return Ok(json_response(200, body));
+    }
+
+    if meta.is_empty() {
+        state.label_meta.remove(label_name);
+    } else {
+        state.label_meta.insert(label_name.to_string(), meta);
+    }
+
+    log(&format!("Updated metadata of label: {}", label_name));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
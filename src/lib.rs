@@ -1,17 +1,115 @@
+mod append;
+mod archival;
+mod archive;
+mod audit;
+mod auth;
 #[allow(warnings)]
 mod bindings;
-
+mod bindiff;
+mod bulk;
+mod channels;
+mod charset;
+mod cli;
+mod clock;
+mod compare;
+mod compose;
+mod config;
+mod content;
+mod cors;
+mod diff;
+mod discovery;
+mod dryrun;
+mod duplicates;
+mod editor;
+mod errorbudget;
+mod etag;
+mod eventlog;
+mod events;
+mod expiry;
+mod export;
+mod field;
+mod fields;
+mod frontmatter;
+mod glob;
+mod health;
+mod hexdump;
+mod history;
+mod icon;
+mod import;
+mod journal;
+mod jsonpatch;
+mod jsonpretty;
+mod labelmeta;
+mod lint;
+mod logs;
+mod maintenance;
+mod manifest;
+mod merge;
+mod metadata;
+mod mime;
+mod mirror;
+mod naming;
+mod notify;
+mod openapi;
+mod outline;
+mod predicates;
+mod promotion;
+mod quotas;
+mod range;
+mod references;
+mod rename;
+mod retention;
+mod routes;
+mod scanning;
+mod scheduler;
+mod schemas;
+mod search;
+mod share;
+mod sniff;
+mod staging;
+mod startup;
+mod stats;
+mod stores;
+mod sync;
+mod tasks;
+mod template;
+mod timeline;
+mod trash;
+mod undo;
+mod views;
+mod wasm;
+
+use archival::ArchivedLabel;
 use bindings::exports::theater::simple::actor::Guest;
 use bindings::exports::theater::simple::http_handlers::Guest as HttpHandlersGuest;
 use bindings::theater::simple::http_framework::{
-    add_route, create_server, register_handler, start_server, HttpRequest, HttpResponse,
-    ServerConfig,
+    add_middleware, add_route, create_server, enable_websocket, register_handler, start_server, HttpRequest,
+    HttpResponse,
 };
 use bindings::theater::simple::http_types::MiddlewareResult;
 use bindings::theater::simple::runtime::log;
 use bindings::theater::simple::store;
 use bindings::theater::simple::websocket_types::WebsocketMessage;
+use editor::EditorRule;
+use events::Subscribers;
+use expiry::ExpiryWatch;
+use labelmeta::LabelMeta;
+use lint::LintRule;
+use naming::NamingRule;
+use notify::NotifyRule;
+use promotion::Promotions;
+use quotas::Quotas;
 use serde::{Deserialize, Serialize};
+use retention::Retention;
+use scanning::ScanConfig;
+use scheduler::Schedule;
+use schemas::{LabelFormat, SchemaRule};
+use share::Shares;
+use stats::{AccessStats, ListSort};
+use std::collections::BTreeMap;
+use timeline::UsageTimeline;
+use trash::Trash;
+use views::LabelQuery;
 
 // ============================================================================
 // State and Type Definitions
@@ -21,25 +119,307 @@ use serde::{Deserialize, Serialize};
 struct StoreViewerState {
     store_id: String,
     server_id: u64,
+    /// Port, address and TLS the server was started with
+    #[serde(default)]
+    server: config::ServerSettings,
+    /// Latest wall-clock time observed from a client, in epoch milliseconds
+    #[serde(default)]
+    clock_ms: u64,
+    #[serde(default)]
+    stats: AccessStats,
+    /// Saved label queries, keyed by view name
+    #[serde(default)]
+    views: BTreeMap<String, LabelQuery>,
+    /// Expected content formats, by label prefix
+    #[serde(default)]
+    schemas: Vec<SchemaRule>,
+    /// Naming conventions for new labels, by label prefix
+    #[serde(default)]
+    naming: Vec<NamingRule>,
+    /// Topics label changes are announced on, by label prefix
+    #[serde(default)]
+    notify: Vec<NotifyRule>,
+    /// How clients should present labels, by label prefix
+    #[serde(default)]
+    editor: Vec<EditorRule>,
+    /// How malformed JSON, YAML and TOML is treated, by label prefix
+    #[serde(default)]
+    lint: Vec<LintRule>,
+    /// Writes registered to happen at a later time
+    #[serde(default)]
+    schedule: Schedule,
+    #[serde(default)]
+    retention: Retention,
+    /// Record of labels promoted between prefixes
+    #[serde(default)]
+    promotions: Promotions,
+    /// Imports held for review before they are committed
+    #[serde(default)]
+    staging: staging::Staging,
+    /// Connected WebSocket clients
+    #[serde(default)]
+    subscribers: Subscribers,
+    #[serde(default)]
+    expiry: ExpiryWatch,
+    /// Interrupted operations found in the journal at startup
+    #[serde(default)]
+    recovered: Vec<journal::RecoveredOperation>,
+    /// Language and encoding recorded for labels
+    #[serde(default)]
+    label_meta: BTreeMap<String, LabelMeta>,
+    #[serde(default)]
+    timeline: UsageTimeline,
+    /// Prefixes readable by anyone under `/public/`
+    #[serde(default)]
+    shares: Shares,
+    /// Daily quotas and usage per API key
+    #[serde(default)]
+    quotas: Quotas,
+    /// Size limit and forbidden patterns checked on every write
+    #[serde(default)]
+    scanning: ScanConfig,
+    /// Labels moved under `archive/`, keyed by their archive name
+    #[serde(default)]
+    archived: BTreeMap<String, ArchivedLabel>,
+    /// Deleted labels that can still be restored
+    #[serde(default)]
+    trash: Trash,
+    /// Outcome of the self-test run at startup
+    #[serde(default)]
+    startup: startup::StartupReport,
+    /// Failure rates per API route and the alerts they raised
+    #[serde(default)]
+    errorbudget: errorbudget::ErrorBudget,
+    /// Recent changes per session, for `POST /api/undo`
+    #[serde(default)]
+    undo: undo::UndoHistory,
+    /// Origins allowed to call the API from the browser
+    #[serde(default)]
+    cors: cors::CorsConfig,
+    /// Token API requests must present
+    #[serde(default)]
+    auth: auth::Auth,
+    /// Stores besides `store_id` that requests can select
+    #[serde(default)]
+    stores: stores::Stores,
+    /// Append-only record of label changes
+    #[serde(default)]
+    event_log: eventlog::EventLog,
+    /// Record of every label change and the request that made it
+    #[serde(default)]
+    audit: audit::AuditLog,
+    /// Window during which writes are refused
+    #[serde(default)]
+    maintenance: maintenance::Maintenance,
+    /// Thresholds for the health grade and the backups reported to it
+    #[serde(default)]
+    health: health::Health,
+    /// Secondary store changes are written through to
+    #[serde(default)]
+    mirror: mirror::Mirror,
+    /// Release channels pinned per label, by channel name
+    #[serde(default)]
+    channels: BTreeMap<String, BTreeMap<String, channels::ChannelPin>>,
+    /// Default order, detail level and hidden prefixes of the label listing
+    #[serde(default)]
+    listing: views::ListingDefaults,
+}
+
+impl StoreViewerState {
+    /// Advance the actor's notion of the current time from a request.
+    fn observe_clock(&mut self, req: &HttpRequest) {
+        if let Some(ms) = clock::observed_time_ms(req) {
+            self.clock_ms = clock::advance(self.clock_ms, ms);
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 struct CreateLabelRequest {
     name: String,
     content: String,
+    /// `base64` when `content` encodes binary data; plain text otherwise
+    #[serde(default)]
+    encoding: Option<String>,
+    /// Language of the content; the `Content-Language` header also sets it
+    #[serde(default)]
+    language: Option<String>,
+}
+
+/// Body of a successful create or update.
+#[derive(Serialize)]
+struct WriteLabelResponse<'a> {
+    success: bool,
+    /// Lint problems found in content that was stored anyway
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    warnings: &'a [String],
+    /// Set when the store is nearing its size quota
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_warning: Option<String>,
+    #[serde(flatten)]
+    dedup: duplicates::DedupReport,
 }
 
 #[derive(Serialize, Deserialize)]
 struct UpdateLabelRequest {
     content: String,
+    #[serde(default)]
+    encoding: Option<String>,
+    /// Predicate on the current JSON content that must hold for the update
+    #[serde(default)]
+    condition: Option<String>,
+    #[serde(default)]
+    language: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct LabelContentResponse {
     name: String,
+    /// Content ref (hash) of the version returned
+    content_ref: String,
     content: String,
     is_text: bool,
     size_bytes: usize,
+    /// Format required by the schema registry, if a rule covers this label
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected_format: Option<LabelFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_prefix: Option<String>,
+    /// Fields declared in a YAML front-matter block at the top of the content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    front_matter: Option<serde_json::Value>,
+    /// Set when the content is a WebAssembly module or component
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wasm: Option<wasm::WasmKind>,
+    /// Set when the content is a Theater actor manifest
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    theater_manifest: bool,
+    /// Set when the content is a zip or tar archive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archive: Option<archive::ArchiveFormat>,
+    /// Set when the content is a line-oriented log
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_format: Option<logs::LogFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    /// Encoding the stored bytes were transcoded from, when not UTF-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    charset: Option<charset::Charset>,
+    /// How the content should be presented, from the editor rules
+    #[serde(default)]
+    editor: editor::EditorHints,
+    /// Format sniffed from the content and the label's extension
+    detected_format: sniff::Format,
+    /// MIME type the content is served with by `/raw`, without parameters
+    mime_type: &'static str,
+    /// When a retention policy deletes the label unless it is read again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    /// Channel the version was read from, with `?channel=`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel: Option<String>,
+}
+
+/// Fields of `LabelContentResponse` that `?fields=` can select.
+const LABEL_FIELDS: &[&str] = &[
+    "name", "content_ref", "content", "is_text", "size_bytes", "expected_format", "schema_prefix",
+    "front_matter", "wasm", "theater_manifest", "archive", "log_format", "language", "charset", "editor",
+    "detected_format", "mime_type", "expires_at", "channel",
+];
+
+#[derive(Serialize)]
+struct LabelListEntry {
+    name: String,
+    /// Present with `?front_matter=true`; `null` for labels without any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    front_matter: Option<Option<serde_json::Value>>,
+    /// When a retention policy will delete the label (with `?expiry=true`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    /// Size, ref and kind of the content (with `?details=true`)
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    details: Option<LabelDetails>,
+}
+
+/// Fields of `LabelListEntry` that `?fields=` can select.
+const LIST_FIELDS: &[&str] = &["name", "front_matter", "expires_at", "size_bytes", "content_ref", "is_text", "icon"];
+
+/// Listing fields that come from `LabelDetails`.
+const DETAIL_FIELDS: &[&str] = &["size_bytes", "content_ref", "is_text", "icon"];
+
+#[derive(Serialize)]
+struct LabelDetails {
+    size_bytes: usize,
+    content_ref: String,
+    is_text: bool,
+    /// File-type category to draw an icon for
+    icon: icon::Icon,
+}
+
+/// Body of `GET /api/labels` unless `?legacy=true` asks for the bare array.
+#[derive(Serialize)]
+struct LabelListResponse<T> {
+    items: Vec<T>,
+    /// Labels matching the request, including any not in `items`
+    total: usize,
+    offset: usize,
+    /// Most items a page holds; `null` when every match is returned
+    limit: Option<usize>,
+    /// Offset of the following page, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<usize>,
+    /// Order the items are in, from `?sort=` or the default view
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sort: Option<&'static str>,
+    generated_at: u64,
+}
+
+/// Window of a listing selected by `?offset=` and `?limit=`.
+struct Page {
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl Page {
+    /// Read the paging parameters; the error names the one that is invalid.
+    fn from_request(req: &HttpRequest) -> Result<Self, String> {
+        let number = |name: &str| match query_param(&req.uri, name) {
+            None => Ok(None),
+            Some(value) => value
+                .parse::<usize>()
+                .map(Some)
+                .map_err(|_| format!("Invalid {}: {}", name, value)),
+        };
+        let page = Page {
+            offset: number("offset")?.unwrap_or(0),
+            limit: number("limit")?,
+        };
+        if page.limit == Some(0) {
+            return Err("limit must be at least 1".to_string());
+        }
+        Ok(page)
+    }
+
+    fn apply<T>(&self, items: Vec<T>) -> Vec<T> {
+        let items = items.into_iter().skip(self.offset);
+        match self.limit {
+            Some(limit) => items.take(limit).collect(),
+            None => items.collect(),
+        }
+    }
+
+    fn next_offset(&self, total: usize) -> Option<usize> {
+        let end = self.offset.saturating_add(self.limit?);
+        (end < total).then_some(end)
+    }
+}
+
+#[derive(Serialize)]
+struct DeleteLabelResponse {
+    success: bool,
+    /// When the label stops being restorable; absent when deleted immediately
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purge_at: Option<u64>,
 }
 
 // ============================================================================
@@ -75,9 +455,196 @@ fn json_response(status: u16, body: Vec<u8>) -> HttpResponse {
     }
 }
 
+/// Value of a request header, matched case-insensitively.
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.trim())
+}
+
 fn error_response(status: u16, message: &str) -> HttpResponse {
-    let error_json = format!(r#"{{"error":"{}"}}"#, message);
-    json_response(status, error_json.into_bytes())
+    let error_json = serde_json::json!({ "error": message });
+    json_response(status, error_json.to_string().into_bytes())
+}
+
+/// Run every check a label write must pass before it reaches the store.
+/// On rejection, returns the HTTP status and message to report.
+fn validate_write(state: &StoreViewerState, label: &str, content: &[u8]) -> Result<(), (u16, String)> {
+    if label.starts_with(SYSTEM_LABEL_PREFIX) {
+        return Err((403, format!("Labels under {} are reserved", SYSTEM_LABEL_PREFIX)));
+    }
+    retention::check_write(&state.retention, label, content).map_err(|e| (413, e))?;
+    schemas::check_write(&state.schemas, label, content).map_err(|e| (422, e))?;
+    lint::check_write(&state.lint, label, content).map_err(|e| (422, e))?;
+    scanning::check_write(&state.scanning, label, content)?;
+    Ok(())
+}
+
+/// Labels under this prefix hold the viewer's own bookkeeping. They are hidden
+/// from listings and cannot be written through the API.
+const SYSTEM_LABEL_PREFIX: &str = "__system/";
+
+/// Write a label through the journal, count the write in the stats and tell
+/// WebSocket clients about the change.
+fn write_label(state: &mut StoreViewerState, operation: &str, label: &str, content: &[u8]) -> Result<store::ContentRef, String> {
+    let step = journal::Step::write(&state.store_id, label, content)?;
+    let id = journal::begin(state, operation, vec![step])?;
+    let content_ref = write_journaled(state, label, content)?;
+    journal::complete(state, id)?;
+    Ok(content_ref)
+}
+
+/// Write a label as one step of an operation the caller has already begun in
+/// the journal, recording it like `write_label` does. Batches use this so the
+/// whole batch is a single journal entry.
+fn write_journaled(state: &mut StoreViewerState, label: &str, content: &[u8]) -> Result<store::ContentRef, String> {
+    let previous = store::get_by_label(&state.store_id, label)?;
+    let content_ref = store::store_at_label(&state.store_id, label, content)?;
+    state.stats.record_write(label, state.clock_ms);
+    lint::record_write(state, label, content);
+    undo::record(state, label, previous.as_ref(), Some(&content_ref));
+    if previous.as_ref().map(|r| &r.hash) != Some(&content_ref.hash) {
+        events::label_written(state, label, previous.as_ref().map(|r| r.hash.as_str()), &content_ref);
+    }
+    Ok(content_ref)
+}
+
+/// Bytes of request `content` sent with the given `encoding`.
+fn decode_content(content: String, encoding: Option<&str>) -> Result<Vec<u8>, String> {
+    match encoding {
+        None | Some("utf-8") => Ok(content.into_bytes()),
+        Some("base64") => {
+            use base64::{engine::general_purpose::STANDARD, Engine};
+            STANDARD
+                .decode(content.trim())
+                .map_err(|e| format!("Invalid base64 content: {}", e))
+        }
+        Some(other) => Err(format!("Unknown encoding: {} (expected base64 or utf-8)", other)),
+    }
+}
+
+/// Remove a label through the journal and forget everything recorded about it.
+fn delete_label(state: &mut StoreViewerState, operation: &str, label: &str) -> Result<(), String> {
+    let previous = store::get_by_label(&state.store_id, label)?;
+    let id = journal::begin(state, operation, vec![journal::Step::delete(label)])?;
+    store::remove_label(&state.store_id, label)?;
+    journal::complete(state, id)?;
+    state.stats.remove(label);
+    state.label_meta.remove(label);
+    state.archived.remove(label);
+    undo::record(state, label, previous.as_ref(), None);
+    events::label_deleted(state, label, previous.as_ref().map(|r| r.hash.as_str()));
+    Ok(())
+}
+
+/// Decode `%XX` escapes (and `+` in query strings) into a UTF-8 string.
+fn percent_decode(input: &str, plus_as_space: bool) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi << 4 | lo);
+                    i += 3;
+                }
+                _ => {
+                    out.push(b'%');
+                    i += 1;
+                }
+            },
+            b'+' if plus_as_space => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+/// All (decoded) query string parameters of a request URI, in order.
+fn query_params(uri: &str) -> Vec<(String, String)> {
+    let query = match uri.split_once('?') {
+        Some((_, query)) => query,
+        None => return Vec::new(),
+    };
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(k, true), percent_decode(v, true))
+        })
+        .collect()
+}
+
+/// Look up a (decoded) query string parameter from a request URI.
+fn query_param(uri: &str, key: &str) -> Option<String> {
+    query_params(uri)
+        .into_iter()
+        .find_map(|(k, v)| (k == key).then_some(v))
+}
+
+/// Pick the per-prefix rule governing `label`: the one with the longest
+/// matching prefix.
+fn longest_prefix_match<'a, T>(rules: &'a [T], label: &str, prefix: impl Fn(&T) -> &str) -> Option<&'a T> {
+    rules
+        .iter()
+        .filter(|rule| label.starts_with(prefix(rule)))
+        .max_by_key(|rule| prefix(rule).len())
+}
+
+/// Sub-resources that can follow a label name, e.g. `/api/labels/{name}/stats`.
+const LABEL_ACTIONS: &[&str] = &["stats", "bindiff", "hex", "dependencies", "dependents", "wasm-info", "entries", "outline", "json", "resolved", "loglines", "meta", "merge", "compose", "manifest-info", "undelete", "rename", "raw", "channels", "history", "restore", "field", "append", "search"];
+
+/// Split the part of a path after `/api/labels/` into a decoded label name and
+/// an optional sub-resource action.
+fn parse_label_path(rest: &str) -> (String, Option<&'static str>) {
+    for action in LABEL_ACTIONS {
+        if let Some(name) = rest
+            .strip_suffix(action)
+            .and_then(|r| r.strip_suffix('/'))
+            .filter(|name| !name.is_empty())
+        {
+            return (percent_decode(name, false), Some(action));
+        }
+    }
+    (percent_decode(rest, false), None)
+}
+
+/// Like `parse_label_path`, but a name ending in an action is the label
+/// itself when that label exists and the one before the action does not, so
+/// labels such as `reports/stats` stay reachable.
+fn resolve_label_path(state: &StoreViewerState, rest: &str) -> (String, Option<&'static str>) {
+    let (name, action) = parse_label_path(rest);
+    if action.is_some() {
+        let exists = |label: &str| matches!(store::get_by_label(&state.store_id, label), Ok(Some(_)));
+        let full = percent_decode(rest, false);
+        if exists(&full) && !exists(&name) {
+            return (full, None);
+        }
+    }
+    (name, action)
+}
+
+/// Split `{name}/entries/{path}` into a decoded label name and archive member
+/// path. Label names containing `/entries/` must be percent-encoded.
+fn parse_entry_path(rest: &str) -> Option<(String, String)> {
+    let (name, path) = rest.split_once("/entries/")?;
+    if name.is_empty() || path.is_empty() {
+        return None;
+    }
+    Some((percent_decode(name, false), percent_decode(path, false)))
 }
 
 // ============================================================================
@@ -113,58 +680,323 @@ fn serve_app_js() -> HttpResponse {
     }
 }
 
+/// Whether the request comes from a browser navigating to a page, which lists
+/// `text/html` in `Accept`; API clients and the UI's `fetch` calls do not.
+fn accepts_html(req: &HttpRequest) -> bool {
+    header(req, "accept").is_some_and(|accept| {
+        accept
+            .split(',')
+            .any(|range| range.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("text/html"))
+    })
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn status_title(status: u16) -> &'static str {
+    match status {
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        412 => "Precondition Failed",
+        413 => "Content Too Large",
+        415 => "Unsupported Media Type",
+        422 => "Unprocessable Content",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Error",
+    }
+}
+
+fn serve_error_page(status: u16, message: &str) -> HttpResponse {
+    let html = include_str!("../assets/error.html")
+        .replace("{{status}}", &status.to_string())
+        .replace("{{title}}", status_title(status))
+        .replace("{{message}}", &escape_html(message));
+    HttpResponse {
+        status,
+        headers: vec![("Content-Type".to_string(), "text/html".to_string())],
+        body: Some(html.into_bytes()),
+    }
+}
+
+/// Replace an error response with the styled error page when a browser asked
+/// for HTML. The message is taken from the JSON `error` field or the plain
+/// text body.
+fn error_page_for_browser(req: &HttpRequest, response: HttpResponse) -> HttpResponse {
+    if response.status < 400 || !accepts_html(req) {
+        return response;
+    }
+    let body = response.body.as_deref().unwrap_or_default();
+    let message = serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| value.get("error")?.as_str().map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned());
+    serve_error_page(response.status, &message)
+}
+
 // ============================================================================
 // API Handlers
 // ============================================================================
 
-fn handle_list_labels(state: &StoreViewerState) -> Result<HttpResponse, String> {
+fn handle_list_labels(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
     log("Listing all labels");
 
-    let labels = store::list_labels(&state.store_id)?;
+    let sort = match query_param(&req.uri, "sort") {
+        Some(value) => match ListSort::parse(&value) {
+            Some(sort) => Some(sort),
+            None => return Ok(error_response(400, &format!("Unknown sort: {}", value))),
+        },
+        None => state.listing.sort(),
+    };
+
+    // Selected fields are computed whether or not their flags are given
+    let fields = match fields::from_request(req, LIST_FIELDS) {
+        Ok(fields) => fields,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    let wants = |name: &str| fields.as_ref().is_some_and(|fields| fields.includes(name));
+
+    let params = query_params(&req.uri);
+    let flag = |name: &str| params.iter().any(|(k, v)| k == name && v == "true");
+    let include_front_matter = flag("front_matter") || wants("front_matter");
+    let include_expiry = flag("expiry") || wants("expires_at");
+    let include_details = match query_param(&req.uri, "details") {
+        Some(value) => value == "true",
+        // The fields asked for override the default view's details
+        None => fields.is_none() && state.listing.details,
+    } || DETAIL_FIELDS.iter().any(|name| wants(name));
+    let fm_filters: Vec<(&str, &str)> = params
+        .iter()
+        .filter_map(|(k, v)| Some((k.strip_prefix(frontmatter::FILTER_PREFIX)?, v.as_str())))
+        .collect();
+
+    let glob = match query_param(&req.uri, "glob") {
+        Some(pattern) => match glob::compile(&pattern) {
+            Ok(glob) => Some(glob),
+            Err(e) => return Ok(error_response(400, &e)),
+        },
+        None => None,
+    };
+
+    let mut labels = store::list_labels(&state.store_id)?;
+    let prefix = query_param(&req.uri, "prefix");
+    if let Some(prefix) = &prefix {
+        labels.retain(|label| label.starts_with(prefix.as_str()));
+    }
+    if !flag("hidden") {
+        labels.retain(|label| !state.listing.hides(label, prefix.as_deref()));
+    }
+    if let Some(glob) = &glob {
+        labels.retain(|label| glob.is_match(label));
+    }
+    if !flag("system") {
+        labels.retain(|label| !label.starts_with(SYSTEM_LABEL_PREFIX));
+    }
+    if !flag("archived") {
+        labels.retain(|label| !archival::is_archived(state, label));
+    }
+    if let Some(sort) = sort {
+        stats::sort_labels(&mut labels, sort, &state.stats);
+    }
+
+    let page = match Page::from_request(req) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    let legacy = flag("legacy");
+
+    // Without front-matter filters every label is listed, so only the page
+    // needs to be read
+    let mut total = labels.len();
+    if fm_filters.is_empty() {
+        labels = page.apply(labels);
+    }
+    let plain = !include_front_matter && !include_expiry && !include_details && fields.is_none();
+    if plain && fm_filters.is_empty() {
+        return label_list_response(state, labels, total, &page, sort, legacy);
+    }
+
+    let mut entries = Vec::new();
+    for name in labels {
+        // Front matter and details come from the content, so each label has
+        // to be read
+        let content = if include_front_matter || include_details || !fm_filters.is_empty() {
+            match store::get_by_label(&state.store_id, &name)? {
+                Some(content_ref) => {
+                    let content = store::get(&state.store_id, &content_ref)?;
+                    Some((content_ref, content))
+                }
+                None => None,
+            }
+        } else {
+            None
+        };
+        let front_matter = content.as_ref().and_then(|(_, content)| frontmatter::extract(content));
+        let matches = fm_filters.iter().all(|(field, expected)| {
+            front_matter
+                .as_ref()
+                .is_some_and(|fm| frontmatter::field_matches(fm, field, expected))
+        });
+        if matches {
+            let details = match content {
+                Some((content_ref, content)) if include_details => {
+                    let text = labelmeta::decode_text(state, &name, &content);
+                    Some(LabelDetails {
+                        size_bytes: content.len(),
+                        content_ref: content_ref.hash,
+                        is_text: text.is_some(),
+                        icon: icon::for_content(&name, &content, text.as_deref()),
+                    })
+                }
+                _ => None,
+            };
+            entries.push(LabelListEntry {
+                expires_at: if include_expiry { retention::expires_at(state, &name) } else { None },
+                front_matter: include_front_matter.then_some(front_matter),
+                details,
+                name,
+            });
+        }
+    }
+
+    if !fm_filters.is_empty() {
+        total = entries.len();
+        entries = page.apply(entries);
+    }
+
+    if let Some(fields) = &fields {
+        label_list_response(state, fields.select_all(entries)?, total, &page, sort, legacy)
+    } else if !plain {
+        label_list_response(state, entries, total, &page, sort, legacy)
+    } else {
+        label_list_response(state, entries.into_iter().map(|e| e.name).collect(), total, &page, sort, legacy)
+    }
+}
 
-    let body = serde_json::to_vec(&labels)
-        .map_err(|e| format!("Failed to serialize labels: {}", e))?;
+/// Wrap listed items in the response envelope, or send them as a bare array
+/// for clients written before the envelope existed.
+fn label_list_response<T: Serialize>(
+    state: &StoreViewerState,
+    items: Vec<T>,
+    total: usize,
+    page: &Page,
+    sort: Option<ListSort>,
+    legacy: bool,
+) -> Result<HttpResponse, String> {
+    let body = if legacy {
+        serde_json::to_vec(&items)
+    } else {
+        serde_json::to_vec(&LabelListResponse {
+            items,
+            total,
+            offset: page.offset,
+            limit: page.limit,
+            next_offset: page.next_offset(total),
+            sort: sort.map(ListSort::name),
+            generated_at: state.clock_ms,
+        })
+    }
+    .map_err(|e| format!("Failed to serialize labels: {}", e))?;
 
     Ok(json_response(200, body))
 }
 
-fn handle_get_label(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+fn handle_get_label(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
     log(&format!("Getting label: {}", label_name));
 
-    // Get the content reference for this label
-    let content_ref = store::get_by_label(&state.store_id, label_name)?
-        .ok_or_else(|| format!("Label not found: {}", label_name))?;
+    let fields = match fields::from_request(req, LABEL_FIELDS) {
+        Ok(fields) => fields,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+
+    // Get the content reference for this label, or for the channel asked for
+    let content_ref = match channels::resolve(state, label_name, req) {
+        Ok(content_ref) => content_ref,
+        Err((500, e)) => return Err(e),
+        Err((status, e)) => return Ok(error_response(status, &e)),
+    };
+
+    // A client holding the current ref doesn't need the content again
+    let etag = etag::for_ref(&content_ref.hash);
+    if etag::none_match(req, &etag) {
+        state.stats.record_read(label_name, state.clock_ms);
+        let mut response = etag::not_modified(etag);
+        response.headers.extend(retention::expires_at(state, label_name).map(expiry::header));
+        return Ok(response);
+    }
 
     // Retrieve the actual content
     let content_bytes = store::get(&state.store_id, &content_ref)?;
 
-    // Determine if it's text or binary
-    let is_text = is_text_content(&content_bytes);
+    // Transcode text to UTF-8; binary content is sent as base64
+    let text = labelmeta::decode_text(state, label_name, &content_bytes);
+    let is_text = text.is_some();
+    let transcoded_from = labelmeta::charset_of(state, label_name, &content_bytes)
+        .filter(|charset| is_text && *charset != charset::Charset::Utf8);
+    let language = labelmeta::meta_for(state, label_name).and_then(|m| m.language.clone());
 
-    // Convert to appropriate string format
-    let content_str = if is_text {
-        String::from_utf8(content_bytes.clone())
-            .unwrap_or_else(|_| "[Encoding error]".to_string())
-    } else {
-        // Encode binary content as base64
-        use base64::{engine::general_purpose::STANDARD, Engine};
-        STANDARD.encode(&content_bytes)
-    };
+    state.stats.record_read(label_name, state.clock_ms);
+    // Read after the access is recorded, which pushes an idle deadline back
+    let expires_at = retention::expires_at(state, label_name);
+
+    let rule = schemas::rule_for(&state.schemas, label_name);
+    let detected_format = sniff::detect(label_name, &content_bytes, text.as_deref());
 
     let response_data = LabelContentResponse {
         name: label_name.to_string(),
-        content: content_str,
+        content_ref: content_ref.hash,
+        content: match &text {
+            Some(text) => text.to_string(),
+            None => {
+                use base64::{engine::general_purpose::STANDARD, Engine};
+                STANDARD.encode(&content_bytes)
+            }
+        },
         is_text,
         size_bytes: content_bytes.len(),
+        expected_format: rule.map(|r| r.format),
+        schema_prefix: rule.map(|r| r.prefix.clone()),
+        front_matter: text.as_deref().and_then(|t| frontmatter::extract(t.as_bytes())),
+        wasm: wasm::detect(&content_bytes),
+        theater_manifest: text.as_deref().is_some_and(manifest::detect),
+        archive: archive::detect(&content_bytes),
+        log_format: text.as_deref().and_then(logs::detect),
+        language,
+        charset: transcoded_from,
+        editor: editor::hints_for(&state.editor, label_name, is_text),
+        detected_format,
+        mime_type: mime::essence(detected_format),
+        expires_at,
+        channel: query_param(&req.uri, "channel"),
     };
 
-    let body = serde_json::to_vec(&response_data)
-        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+    let body = match &fields {
+        Some(fields) => serde_json::to_value(&response_data).and_then(|value| serde_json::to_vec(&fields.select(value))),
+        None => serde_json::to_vec(&response_data),
+    }
+    .map_err(|e| format!("Failed to serialize response: {}", e))?;
 
-    Ok(json_response(200, body))
+    let mut response = json_response(200, body);
+    response.headers.push(("ETag".to_string(), etag));
+    response.headers.extend(expires_at.map(expiry::header));
+    Ok(response)
 }
 
-fn handle_create_label(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+fn handle_create_label(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
     log("Creating new label");
 
     let body = req.body.as_ref().ok_or("Request body is required")?;
@@ -177,18 +1009,46 @@ fn handle_create_label(state: &StoreViewerState, req: &HttpRequest) -> Result<Ht
         return Ok(error_response(400, "Label name cannot be empty"));
     }
 
+    if let Err(e) = naming::check_name(&state.naming, &create_req.name) {
+        return Ok(error_response(422, &e));
+    }
+
+    let language = match labelmeta::request_language(req, create_req.language) {
+        Ok(language) => language,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+
     // Store the content at the label
-    let content_bytes = create_req.content.into_bytes();
-    store::store_at_label(&state.store_id, &create_req.name, &content_bytes)?;
+    let content_bytes = match decode_content(create_req.content, create_req.encoding.as_deref()) {
+        Ok(content_bytes) => content_bytes,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    if let Err((status, e)) = validate_write(state, &create_req.name, &content_bytes) {
+        return Ok(error_response(status, &e));
+    }
+
+    if dryrun::is_dry_run(req) {
+        let change = dryrun::plan_write(&state.store_id, &create_req.name, &content_bytes)?;
+        return dryrun::dry_run_response(&[change]);
+    }
+    let content_ref = write_label(state, "create", &create_req.name, &content_bytes)?;
+    labelmeta::record_write(state, &create_req.name, language);
 
     log(&format!("Created label: {}", create_req.name));
 
-    let success_json = r#"{"success":true}"#;
-    Ok(json_response(200, success_json.as_bytes().to_vec()))
+    let response = WriteLabelResponse {
+        success: true,
+        warnings: lint::warnings_for(state, &create_req.name),
+        size_warning: health::size_warning(state),
+        dedup: duplicates::report(&state.store_id, Some(&create_req.name), &content_ref.hash),
+    };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+    Ok(json_response(200, body))
 }
 
 fn handle_update_label(
-    state: &StoreViewerState,
+    state: &mut StoreViewerState,
     label_name: &str,
     req: &HttpRequest,
 ) -> Result<HttpResponse, String> {
@@ -199,182 +1059,1471 @@ fn handle_update_label(
     let update_req: UpdateLabelRequest = serde_json::from_slice(body)
         .map_err(|e| format!("Invalid JSON: {}", e))?;
 
-    // Store the updated content at the label (overwrites)
-    let content_bytes = update_req.content.into_bytes();
-    store::store_at_label(&state.store_id, label_name, &content_bytes)?;
+    let language = match labelmeta::request_language(req, update_req.language) {
+        Ok(language) => language,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
 
-    log(&format!("Updated label: {}", label_name));
+    // `If-Match` names the version the client read: refuse to replace any other
+    if let Some(expected) = etag::if_match(req) {
+        let current_ref = store::get_by_label(&state.store_id, label_name)?;
+        let current_etag = current_ref.as_ref().map(|r| etag::for_ref(&r.hash));
+        if !etag::match_holds(expected, current_etag.as_deref()) {
+            log(&format!("Refused to overwrite {}: If-Match {} does not hold", label_name, expected));
+            let message = format!(
+                "Label {} changed since it was read; merge your edit with POST /api/labels/{}/merge and save again",
+                label_name, label_name
+            );
+            return Ok(etag::precondition_failed(&message, current_ref.as_ref().map(|r| r.hash.as_str())));
+        }
+    }
 
-    let success_json = r#"{"success":true}"#;
-    Ok(json_response(200, success_json.as_bytes().to_vec()))
-}
+    // `If-None-Match: *` asks for creation only: never replace an existing label
+    if header(req, "if-none-match") == Some("*") {
+        if store::get_by_label(&state.store_id, label_name)?.is_some() {
+            log(&format!("Refused to overwrite {}: If-None-Match: *", label_name));
+            return Ok(error_response(412, &format!("Label already exists: {}", label_name)));
+        }
+        if let Err(e) = naming::check_name(&state.naming, label_name) {
+            return Ok(error_response(422, &e));
+        }
+    }
 
-// ============================================================================
-// Actor Implementation
-// ============================================================================
+    if let Some(source) = &update_req.condition {
+        let condition = match predicates::Condition::parse(source) {
+            Ok(condition) => condition,
+            Err(e) => return Ok(error_response(400, &e)),
+        };
+        let current_ref = match store::get_by_label(&state.store_id, label_name)? {
+            Some(current_ref) => current_ref,
+            None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+        };
+        let current: serde_json::Value = match serde_json::from_slice(&store::get(&state.store_id, &current_ref)?) {
+            Ok(value) => value,
+            Err(_) => return Ok(error_response(412, "Condition not met: current content is not JSON")),
+        };
+        if let Err(e) = condition.evaluate(&current) {
+            log(&format!("Rejected update of {}: {}", label_name, e));
+            return Ok(error_response(412, &e));
+        }
+        log(&format!("Condition holds for {}: {}", label_name, condition.source()));
+    }
 
-struct Component;
+    // Store the updated content at the label (overwrites)
+    let content_bytes = match decode_content(update_req.content, update_req.encoding.as_deref()) {
+        Ok(content_bytes) => content_bytes,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    if let Err((status, e)) = validate_write(state, label_name, &content_bytes) {
+        return Ok(error_response(status, &e));
+    }
 
-impl Guest for Component {
-    fn init(
-        _state: Option<Vec<u8>>,
-        params: (String,),
-    ) -> Result<(Option<Vec<u8>>,), String> {
-        log("Initializing store-viewer actor");
-        let (actor_id,) = params;
-        log(&format!("Actor ID: {}", actor_id));
+    if dryrun::is_dry_run(req) {
+        let change = dryrun::plan_write(&state.store_id, label_name, &content_bytes)?;
+        return dryrun::dry_run_response(&[change]);
+    }
+    let content_ref = write_label(state, "update", label_name, &content_bytes)?;
+    labelmeta::record_write(state, label_name, language);
 
-        // Create store
-        let store_id = "store-viewer".to_string();
+    log(&format!("Updated label: {}", label_name));
 
-        // Create HTTP server on port 8080
-        let config = ServerConfig {
-            port: Some(8080),
-            host: Some("0.0.0.0".to_string()),
-            tls_config: None,
-        };
+    let response = WriteLabelResponse {
+        success: true,
+        warnings: lint::warnings_for(state, label_name),
+        size_warning: health::size_warning(state),
+        dedup: duplicates::report(&state.store_id, Some(label_name), &content_ref.hash),
+    };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+    let mut response = json_response(200, body);
+    response.headers.push(("ETag".to_string(), etag::for_ref(&content_ref.hash)));
+    Ok(response)
+}
 
-        let server_id = create_server(&config)?;
-        log(&format!("Created HTTP server with ID: {}", server_id));
+fn handle_delete_label(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Deleting label: {}", label_name));
 
-        // Register handler
-        let handler_id = register_handler("handle_request")?;
-        log(&format!("Registered handler with ID: {}", handler_id));
+    if label_name.starts_with(SYSTEM_LABEL_PREFIX) {
+        return Ok(error_response(403, &format!("Labels under {} are reserved", SYSTEM_LABEL_PREFIX)));
+    }
 
-        // Register static asset routes
-        add_route(server_id, "/", "GET", handler_id)?;
-        add_route(server_id, "/app.css", "GET", handler_id)?;
-        add_route(server_id, "/app.js", "GET", handler_id)?;
+    let current_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(current_ref) => current_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
 
-        // Register API routes
-        add_route(server_id, "/api/labels", "GET", handler_id)?;
-        add_route(server_id, "/api/labels", "POST", handler_id)?;
-        add_route(server_id, "/api/labels/{*name}", "GET", handler_id)?;
-        add_route(server_id, "/api/labels/{*name}", "PUT", handler_id)?;
+    if !references::is_forced(req) {
+        let dependents = references::dependents_of(&state.store_id, label_name)?;
+        if !dependents.is_empty() {
+            return Ok(error_response(409, &references::still_referenced(label_name, &dependents)));
+        }
+    }
 
-        log("All routes registered");
+    if dryrun::is_dry_run(req) {
+        let change = dryrun::plan_delete(&state.store_id, label_name, &current_ref)?;
+        return dryrun::dry_run_response(&[change]);
+    }
 
-        // Start the server
-        start_server(server_id)?;
-        log("HTTP server started on port 8080");
+    let purge_at = trash::delete(state, "delete", label_name, &current_ref)?;
 
-        // Create and serialize state
-        let state = StoreViewerState { store_id, server_id };
-        let state_bytes = serde_json::to_vec(&state)
-            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+    log(&format!("Deleted label: {}", label_name));
 
-        log("Store viewer initialization complete");
+    let body = serde_json::to_vec(&DeleteLabelResponse { success: true, purge_at })
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+    Ok(json_response(200, body))
+}
 
-        Ok((Some(state_bytes),))
+// ============================================================================
+// Request Routing
+// ============================================================================
+
+/// Route a request, against another store if it selects one.
+fn route_for_store(viewer_state: &mut StoreViewerState, req: &HttpRequest, method: &str, path: &str) -> HttpResponse {
+    match stores::select(viewer_state, req, path) {
+        Ok(None) => route_request(viewer_state, req, method, path),
+        Ok(Some(routed)) => {
+            let response = route_request(viewer_state, req, method, &routed);
+            stores::restore(viewer_state);
+            response
+        }
+        Err(rejection) => rejection,
     }
 }
 
-impl HttpHandlersGuest for Component {
-    fn handle_request(
-        state: Option<Vec<u8>>,
-        params: (u64, HttpRequest),
-    ) -> Result<(Option<Vec<u8>>, (HttpResponse,)), String> {
-        // Deserialize state
-        let state_bytes = state.ok_or("State not found")?;
-        let viewer_state: StoreViewerState = serde_json::from_slice(&state_bytes)
-            .map_err(|e| format!("Failed to deserialize state: {}", e))?;
+/// Dispatch a request to its handler, mapping handler failures to error
+/// responses.
+fn route_request(viewer_state: &mut StoreViewerState, req: &HttpRequest, method: &str, path: &str) -> HttpResponse {
+    match (method, path) {
+        // Static assets
+        ("GET", "/") => serve_index_html(),
+        ("GET", "/app.css") => serve_app_css(),
+        ("GET", "/app.js") => serve_app_js(),
+
+        // API routes
+        ("GET", "/api") => match discovery::handle_root(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error describing the API: {}", e));
+                error_response(500, &e)
+            }
+        },
 
-        let (_server_id, req) = params;
+        ("GET", "/api/openapi.json") => match openapi::handle_openapi(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error generating OpenAPI document: {}", e));
+                error_response(500, &e)
+            }
+        },
 
-        // Get path without query string
-        let path = req.uri.split('?').next().unwrap_or("/");
-        let method = req.method.as_str();
+        ("GET", "/api/docs") => match discovery::handle_docs() {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error rendering API explorer: {}", e));
+                error_response(500, &e)
+            }
+        },
 
-        log(&format!("Request: {} {}", method, path));
+        ("GET", "/api/labels") => match handle_list_labels(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing labels: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/labels") => match handle_create_label(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error creating label: {}", e));
+                error_response(500, &e)
+            }
+        },
 
-        // Route the request
-        let response = match (method, path) {
-            // Static assets
-            ("GET", "/") => serve_index_html(),
-            ("GET", "/app.css") => serve_app_css(),
-            ("GET", "/app.js") => serve_app_js(),
+        ("POST", "/api/labels/bulk-delete") => match bulk::handle_bulk_delete(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error deleting labels in bulk: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("POST", "/api/labels/meta/bulk") => match bulk::handle_bulk_meta(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error editing label metadata in bulk: {}", e));
+                error_response(400, &e)
+            }
+        },
 
-            // API routes
-            ("GET", "/api/labels") => match handle_list_labels(&viewer_state) {
+        ("POST", "/api/labels/head") => match sync::handle_bulk_head(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error comparing label refs: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("POST", p) if p.starts_with("/api/labels/") => {
+            let (label_name, action) = resolve_label_path(viewer_state, p.strip_prefix("/api/labels/").unwrap());
+            let result = match action {
+                Some("merge") => merge::handle_merge(viewer_state, &label_name, req),
+                Some("compose") => compose::handle_compose(viewer_state, &label_name, req),
+                Some("undelete") => trash::handle_undelete(viewer_state, &label_name, req),
+                Some("rename") => rename::handle_rename(viewer_state, &label_name, req),
+                Some("channels") => channels::handle_promote(viewer_state, &label_name, req),
+                Some("restore") => history::handle_restore(viewer_state, &label_name, req),
+                Some("append") => append::handle_append(viewer_state, &label_name, req),
+                _ => Ok(error_response(404, &format!("Unknown label action: {}", p))),
+            };
+            match result {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error handling label action: {}", e));
+                    error_response(400, &e)
+                }
+            }
+        },
+
+        ("GET", p) if p.starts_with("/api/labels/") => {
+            let rest = p.strip_prefix("/api/labels/").unwrap();
+            let result = if let Some((label_name, path)) = parse_entry_path(rest) {
+                archive::handle_get_entry(viewer_state, &label_name, &path)
+            } else {
+                let (label_name, action) = resolve_label_path(viewer_state, rest);
+                match action {
+                    Some("stats") => stats::handle_label_stats(viewer_state, &label_name),
+                    Some("bindiff") => bindiff::handle_label_bindiff(viewer_state, &label_name, req),
+                    Some("hex") => hexdump::handle_hex(viewer_state, &label_name, req),
+                    Some("dependencies") => references::handle_dependencies(viewer_state, &label_name),
+                    Some("dependents") => references::handle_dependents(viewer_state, &label_name),
+                    Some("wasm-info") => wasm::handle_wasm_info(viewer_state, &label_name),
+                    Some("manifest-info") => manifest::handle_manifest_info(viewer_state, &label_name),
+                    Some("entries") => archive::handle_list_entries(viewer_state, &label_name),
+                    Some("outline") => outline::handle_outline(viewer_state, &label_name, req),
+                    Some("json") => jsonpretty::handle_json(viewer_state, &label_name),
+                    Some("raw") => mime::handle_raw(viewer_state, &label_name, req),
+                    Some("resolved") => template::handle_resolved(viewer_state, &label_name),
+                    Some("loglines") => logs::handle_loglines(viewer_state, &label_name, req),
+                    Some("search") => search::handle_search(viewer_state, &label_name, req),
+                    Some("meta") => labelmeta::handle_get_meta(viewer_state, &label_name),
+                    Some("channels") => channels::handle_list(viewer_state, &label_name),
+                    Some("history") => history::handle_history(viewer_state, &label_name),
+                    _ => match query_param(&req.uri, "diff_since") {
+                        Some(base_ref) => jsonpatch::handle_diff_since(viewer_state, &label_name, base_ref),
+                        None => handle_get_label(viewer_state, &label_name, req),
+                    },
+                }
+            };
+            match result {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error getting label: {}", e));
+                    error_response(404, &e)
+                }
+            }
+        },
+
+        ("HEAD", p) if p.starts_with("/api/labels/") => {
+            let (label_name, action) = resolve_label_path(viewer_state, p.strip_prefix("/api/labels/").unwrap());
+            let result = match action {
+                None | Some("raw") => mime::handle_head(viewer_state, &label_name, req),
+                _ => Ok(error_response(404, &format!("HEAD is not supported at {}", p))),
+            };
+            match result {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error checking label: {}", e));
+                    error_response(404, &e)
+                }
+            }
+        },
+
+        ("PUT", p) if p.starts_with("/api/labels/") => {
+            let (label_name, action) = resolve_label_path(viewer_state, p.strip_prefix("/api/labels/").unwrap());
+            let result = match action {
+                Some("meta") => labelmeta::handle_put_meta(viewer_state, &label_name, req),
+                Some("field") => field::handle_put_field(viewer_state, &label_name, req),
+                _ => handle_update_label(viewer_state, &label_name, req),
+            };
+            match result {
                 Ok(resp) => resp,
                 Err(e) => {
-                    log(&format!("Error listing labels: {}", e));
+                    log(&format!("Error updating label: {}", e));
                     error_response(500, &e)
                 }
-            },
+            }
+        },
 
-            ("POST", "/api/labels") => match handle_create_label(&viewer_state, &req) {
+        ("DELETE", p) if p.starts_with("/api/labels/") => {
+            let label_name = percent_decode(p.strip_prefix("/api/labels/").unwrap(), false);
+            match handle_delete_label(viewer_state, &label_name, req) {
                 Ok(resp) => resp,
                 Err(e) => {
-                    log(&format!("Error creating label: {}", e));
+                    log(&format!("Error deleting label: {}", e));
                     error_response(500, &e)
                 }
-            },
+            }
+        },
 
-            ("GET", p) if p.starts_with("/api/labels/") => {
-                let label_name = p.strip_prefix("/api/labels/").unwrap();
-                match handle_get_label(&viewer_state, label_name) {
-                    Ok(resp) => resp,
-                    Err(e) => {
-                        log(&format!("Error getting label: {}", e));
-                        error_response(404, &e)
-                    }
-                }
-            },
+        ("OPTIONS", p) if p.starts_with("/api/") => match discovery::handle_options(p) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error describing API path: {}", e));
+                error_response(500, &e)
+            }
+        },
 
-            ("PUT", p) if p.starts_with("/api/labels/") => {
-                let label_name = p.strip_prefix("/api/labels/").unwrap();
-                match handle_update_label(&viewer_state, label_name, &req) {
-                    Ok(resp) => resp,
-                    Err(e) => {
-                        log(&format!("Error updating label: {}", e));
-                        error_response(500, &e)
-                    }
-                }
-            },
+        ("GET", "/api/stores") => match stores::handle_list_stores(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing stores: {}", e));
+                error_response(500, &e)
+            }
+        },
 
-            // 404 for everything else
-            _ => {
-                log(&format!("404 Not Found: {} {}", method, path));
-                HttpResponse {
-                    status: 404,
-                    headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
-                    body: Some(b"Not Found".to_vec()),
+        ("POST", "/api/stores") => match stores::handle_register_store(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error registering store: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", p) if p.starts_with(stores::STORES_PATH) => {
+            let store_id = percent_decode(p.strip_prefix(stores::STORES_PATH).unwrap(), false);
+            match stores::handle_unregister_store(viewer_state, &store_id) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error unregistering store: {}", e));
+                    error_response(500, &e)
                 }
             }
-        };
+        },
 
-        // Return state unchanged and the response
-        Ok((Some(state_bytes), (response,)))
-    }
+        ("GET", "/api/compare") => match compare::handle_compare(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error comparing stores: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/cli") => match cli::handle_cli(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error running command: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/trash") => match trash::handle_list_pending(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing deleted labels: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/undo") => match undo::handle_history(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting undo history: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/undo") => match undo::handle_undo(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error undoing last change: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/archive") => match archival::handle_list_archived(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing archived labels: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/archive") => match archival::handle_archive(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error archiving labels: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("POST", "/api/unarchive") => match archival::handle_unarchive(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error restoring archived labels: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/listing") => match views::handle_get_defaults(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting listing defaults: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/listing") => match views::handle_put_defaults(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving listing defaults: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/cors") => match cors::handle_get_config(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting CORS configuration: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/cors") => match cors::handle_put_config(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving CORS configuration: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/error-budget") => match errorbudget::handle_status(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting error budget: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/error-budget") => match errorbudget::handle_put_config(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving error budget configuration: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/health") => match health::handle_get_config(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting health thresholds: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/health") => match health::handle_put_config(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving health thresholds: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/health/backups") => match health::handle_list_backups(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing backups: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/health/backups") => match health::handle_report_backup(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error recording backup: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/maintenance") => match maintenance::handle_status(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting maintenance status: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/maintenance") => match maintenance::handle_configure(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error configuring maintenance: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/event-log") => match eventlog::handle_status(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting event log status: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/event-log") => match eventlog::handle_configure(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error configuring event log: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/mirror") => match mirror::handle_status(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting mirror status: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/mirror") => match mirror::handle_configure(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error configuring mirror: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/quotas") => match quotas::handle_list_quotas(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing quotas: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/quotas") => match quotas::handle_put_quota(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving quota: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", "/api/admin/quotas") => match quotas::handle_delete_quota(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error deleting quota: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/admin/routes") => match routes::handle_list_routes(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing routes: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/admin/scanning") => match scanning::handle_get_config(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting scan configuration: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/admin/scanning") => match scanning::handle_put_config(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving scan configuration: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("POST", "/api/admin/scanning/check") => match scanning::handle_check(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error scanning content: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/admin/startup") => match startup::handle_get_report(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting startup report: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/admin/usage") => match quotas::handle_usage_report(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error building usage report: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", p) if p.starts_with("/api/content/") => {
+            let hash = p.strip_prefix("/api/content/").unwrap();
+            match content::handle_get_content(viewer_state, hash, req) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error getting content: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("POST", "/api/content") => match content::handle_store_content(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error storing content: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/audit") => match audit::handle_audit(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error reading audit log: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/event-log") => match eventlog::handle_events(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error reading event log: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/event-log/replay") => match eventlog::handle_replay(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error replaying event log: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/health") => match health::handle_health(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error grading store health: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/diff") => match diff::handle_diff(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error diffing content: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/duplicates") => match duplicates::handle_list_duplicates(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error finding duplicates: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/duplicates/dedup") => match duplicates::handle_dedup(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error deduplicating content: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/editor") => match editor::handle_list_rules(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing editor rules: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/editor") => match editor::handle_put_rule(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving editor rule: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", "/api/editor") => {
+            let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+            match editor::handle_delete_rule(viewer_state, &prefix) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error deleting editor rule: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", "/api/lint") => match lint::handle_list_rules(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing lint rules: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/lint") => match lint::handle_put_rule(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving lint rule: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", "/api/lint") => {
+            let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+            match lint::handle_delete_rule(viewer_state, &prefix) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error deleting lint rule: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", "/api/export") => match export::handle_export(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error exporting labels: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/history") => match history::handle_usage(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error measuring history: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/history/prune") => match history::handle_prune(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error pruning history: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/import") => match import::handle_import_document(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error importing document: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("POST", "/api/import/store") => match import::handle_import_store(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error importing from store: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/journal") => match journal::handle_journal(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting journal: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/metadata/export") => match metadata::handle_export(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error exporting metadata: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/metadata/import") => match metadata::handle_import(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error importing metadata: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/naming") => match naming::handle_list_rules(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing naming rules: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/naming") => match naming::handle_put_rule(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving naming rule: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", "/api/naming") => {
+            let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+            match naming::handle_delete_rule(viewer_state, &prefix) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error deleting naming rule: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", "/api/notifications") => match notify::handle_list_rules(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing notification rules: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/notifications") => match notify::handle_put_rule(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving notification rule: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", "/api/notifications") => {
+            let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+            match notify::handle_delete_rule(viewer_state, &prefix) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error deleting notification rule: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("POST", "/api/promote") => match promotion::handle_promote(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error promoting labels: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", "/api/promotions") => match promotion::handle_list_promotions(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing promotions: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", p) if p.starts_with("/api/promotions/") => {
+            let id = p.strip_prefix("/api/promotions/").unwrap();
+            match promotion::handle_get_promotion(viewer_state, id) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error getting promotion: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", "/api/retention") => match retention::handle_list_policies(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing retention policies: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/retention") => match retention::handle_put_policy(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving retention policy: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", "/api/retention") => {
+            let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+            match retention::handle_delete_policy(viewer_state, &prefix) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error deleting retention policy: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", "/api/retention/report") => match retention::handle_report(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error building retention report: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/schedule") => match scheduler::handle_list_scheduled(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing scheduled writes: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/schedule") => match scheduler::handle_schedule_write(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error scheduling write: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", p) if p.starts_with("/api/schedule/") => {
+            let id = p.strip_prefix("/api/schedule/").unwrap();
+            match scheduler::handle_cancel_scheduled(viewer_state, id) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error cancelling scheduled write: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", "/api/schemas") => match schemas::handle_list_schemas(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing schemas: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/schemas") => match schemas::handle_register_schema(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error registering schema: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", "/api/schemas") => {
+            let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+            match schemas::handle_delete_schema(viewer_state, &prefix) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error deleting schema: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", "/api/shares") => match share::handle_list_shares(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing shares: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/shares") => match share::handle_put_share(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving share: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("DELETE", "/api/shares") => {
+            let prefix = query_param(&req.uri, "prefix").unwrap_or_default();
+            match share::handle_delete_share(viewer_state, &prefix) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error removing share: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", "/api/staging") => match staging::handle_list(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing staging sets: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", p) if p.starts_with("/api/staging/") && p.ends_with("/commit") => {
+            let id = p.strip_prefix("/api/staging/").unwrap().strip_suffix("/commit").unwrap();
+            match staging::handle_commit(viewer_state, id, req) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error committing staging set: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", p) if p.starts_with("/api/staging/") => {
+            let id = p.strip_prefix("/api/staging/").unwrap();
+            match staging::handle_review(viewer_state, id) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error reviewing staging set: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("DELETE", p) if p.starts_with("/api/staging/") => {
+            let id = p.strip_prefix("/api/staging/").unwrap();
+            match staging::handle_discard(viewer_state, id) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error discarding staging set: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", "/api/stats/timeline") => match timeline::handle_timeline(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error getting usage timeline: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("GET", "/api/views") => match views::handle_list_views(viewer_state) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error listing views: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        ("POST", "/api/views") => match views::handle_save_view(viewer_state, req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error saving view: {}", e));
+                error_response(400, &e)
+            }
+        },
+
+        ("GET", p) if p.starts_with("/api/views/") && p.ends_with("/run") => {
+            let view_name = percent_decode(
+                p.strip_prefix("/api/views/").unwrap().strip_suffix("/run").unwrap(),
+                false,
+            );
+            match views::handle_run_view(viewer_state, &view_name) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error running view: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("DELETE", p) if p.starts_with("/api/views/") => {
+            let view_name = percent_decode(p.strip_prefix("/api/views/").unwrap(), false);
+            match views::handle_delete_view(viewer_state, &view_name) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log(&format!("Error deleting view: {}", e));
+                    error_response(500, &e)
+                }
+            }
+        },
+
+        ("GET", p) if p.starts_with(share::PUBLIC_PATH) => match share::handle_public(viewer_state, req, p) {
+            Ok(resp) => resp,
+            Err(e) => {
+                log(&format!("Error serving public label: {}", e));
+                error_response(500, &e)
+            }
+        },
+
+        // 404 for everything else
+        _ => {
+            log(&format!("404 Not Found: {} {}", method, path));
+            if accepts_html(req) {
+                serve_error_page(404, &format!("There is nothing at {}", path))
+            } else {
+                HttpResponse {
+                    status: 404,
+                    headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+                    body: Some(b"Not Found".to_vec()),
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Actor Implementation
+// ============================================================================
+
+struct Component;
+
+impl Guest for Component {
+    fn init(
+        state: Option<Vec<u8>>,
+        params: (String,),
+    ) -> Result<(Option<Vec<u8>>,), String> {
+        log("Initializing store-viewer actor");
+        let (actor_id,) = params;
+        log(&format!("Actor ID: {}", actor_id));
+
+        // The manifest's initial state carries the configuration
+        let init_config = config::InitConfig::parse(state.as_deref())?;
+
+        let store_id = init_config.store_id();
+        log(&format!("Using store: {}", store_id));
+        let mut report = startup::StartupReport::new(&actor_id, &store_id);
+
+        // Report operations a previous run left half-applied
+        let recovered = match journal::recover(&store_id) {
+            Ok(recovered) => {
+                report.check("journal", Ok(()));
+                recovered
+            }
+            Err(e) => {
+                log(&format!("Error recovering journal: {}", e));
+                report.check("journal", Err(e));
+                Vec::new()
+            }
+        };
+        if !recovered.is_empty() {
+            log(&format!("Found {} interrupted operations in the journal", recovered.len()));
+        }
+
+        // Create HTTP server where configured
+        let server = init_config.server();
+        let server_id = create_server(&server.server_config())?;
+        log(&format!("Created HTTP server with ID: {}", server_id));
+
+        // Register handler
+        let handler_id = register_handler(routes::REQUEST_HANDLER)?;
+        log(&format!("Registered handler with ID: {}", handler_id));
+
+        for &(method, path) in routes::ROUTES {
+            add_route(server_id, path, method, handler_id)?;
+        }
+        log("All routes registered");
+
+        // Check the API token before requests reach the handler
+        if init_config.auth_token.is_some() {
+            let middleware_id = register_handler(routes::MIDDLEWARE_HANDLER)?;
+            add_middleware(server_id, auth::PROTECTED_PATH, middleware_id)?;
+            log("API requests require a bearer token");
+            if let Some(anonymous) = &init_config.anonymous_access {
+                log(&format!("Requests without it may read, {} per minute", anonymous.requests_per_minute));
+            }
+        }
+        report.capability("auth", init_config.auth_token.is_some());
+
+        // Push events to WebSocket clients
+        let [ws_connect, ws_message, ws_disconnect] = routes::WEBSOCKET_HANDLERS;
+        let ws_connect_id = register_handler(ws_connect)?;
+        let ws_message_id = register_handler(ws_message)?;
+        let ws_disconnect_id = register_handler(ws_disconnect)?;
+        match enable_websocket(server_id, events::WS_PATH, Some(ws_connect_id), ws_message_id, Some(ws_disconnect_id)) {
+            Ok(()) => {
+                log(&format!("WebSocket enabled at {}", events::WS_PATH));
+                report.capability("websocket", true);
+            }
+            Err(e) => {
+                log(&format!("WebSocket unavailable, events will not be pushed: {}", e));
+                report.capability("websocket", false);
+            }
+        }
+        report.capability("tls", server.tls.is_some());
+
+        // Start the server
+        start_server(server_id)?;
+        log(&format!("HTTP server started at {}://{}:{}", server.scheme(), server.host, server.port));
+
+        // Check that the store and the routes work before serving anyone
+        report.check("store", startup::probe_store(&store_id));
+        report.check("routes", startup::check_routes(routes::ROUTES));
+        report.routes = routes::ROUTES.len();
+        log(if report.ok { "Startup self-test passed" } else { "Startup self-test failed; see /api/admin/startup" });
+
+        let registered_stores = init_config
+            .stores
+            .iter()
+            .filter(|id| **id != store_id)
+            .map(|id| (id.clone(), stores::StoreEntry::default()))
+            .collect();
+
+        // Create and serialize state
+        let state = StoreViewerState {
+            store_id,
+            server_id,
+            server,
+            clock_ms: 0,
+            stats: AccessStats::default(),
+            views: BTreeMap::new(),
+            schemas: Vec::new(),
+            naming: Vec::new(),
+            notify: Vec::new(),
+            editor: Vec::new(),
+            lint: Vec::new(),
+            schedule: Schedule::default(),
+            retention: Retention::default(),
+            promotions: Promotions::default(),
+            staging: staging::Staging::default(),
+            subscribers: Subscribers::new(),
+            expiry: ExpiryWatch::default(),
+            recovered,
+            label_meta: BTreeMap::new(),
+            timeline: UsageTimeline::default(),
+            shares: Shares::default(),
+            quotas: Quotas::default(),
+            scanning: ScanConfig::default(),
+            archived: BTreeMap::new(),
+            trash: Trash {
+                grace_ms: init_config.deletion_grace_ms().unwrap_or(trash::DEFAULT_GRACE_MS),
+                ..Trash::default()
+            },
+            startup: report,
+            errorbudget: errorbudget::ErrorBudget::default(),
+            undo: undo::UndoHistory::default(),
+            cors: init_config.cors.clone().unwrap_or_default(),
+            auth: auth::Auth::new(init_config.auth_token.clone(), init_config.anonymous_access.clone()),
+            stores: stores::Stores::new(registered_stores),
+            event_log: eventlog::EventLog { enabled: init_config.event_log, events: 0 },
+            audit: audit::AuditLog::default(),
+            maintenance: maintenance::Maintenance::default(),
+            health: health::Health::default(),
+            mirror: mirror::Mirror::new(init_config.mirror.clone()),
+            channels: BTreeMap::new(),
+            listing: init_config.listing.clone().unwrap_or_default(),
+        };
+        let state_bytes = serde_json::to_vec(&state)
+            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+
+        log("Store viewer initialization complete");
+
+        Ok((Some(state_bytes),))
+    }
+}
+
+impl HttpHandlersGuest for Component {
+    fn handle_request(
+        state: Option<Vec<u8>>,
+        params: (u64, HttpRequest),
+    ) -> Result<(Option<Vec<u8>>, (HttpResponse,)), String> {
+        // Deserialize state
+        let state_bytes = state.ok_or("State not found")?;
+        let mut viewer_state: StoreViewerState = serde_json::from_slice(&state_bytes)
+            .map_err(|e| format!("Failed to deserialize state: {}", e))?;
+
+        let (_server_id, req) = params;
+
+        // Get path without query string
+        let path = req.uri.split('?').next().unwrap_or("/");
+        let method = req.method.as_str();
+
+        // Anonymous public readers are not trusted with the clock, nor are
+        // API clients without the token
+        if !path.starts_with(share::PUBLIC_PATH) && auth::is_authenticated(&viewer_state.auth, &req) {
+            viewer_state.observe_clock(&req);
+        }
+        tasks::run_due(&mut viewer_state);
+
+        log(&format!("Request: {} {}", method, path));
+
+        // Route the request, unless the client has used up its quota, and
+        // remember what it changed for undo and the audit log
+        undo::begin(&mut viewer_state, &req, method, path);
+        audit::begin(&mut viewer_state, &req, method, path);
+        let now_ms = viewer_state.clock_ms;
+        let response = if let Some(rejection) = auth::rejection(&mut viewer_state.auth, &req, now_ms) {
+            rejection
+        } else if method == "OPTIONS" && cors::is_preflight(&req) {
+            // Preflights are answered before quotas so they never use any up
+            cors::preflight(&viewer_state.cors, &req)
+        } else if let Some(rejection) = maintenance::rejection(&viewer_state, method, path) {
+            rejection
+        } else if quotas::is_metered(path) {
+            let key = quotas::client_key(&viewer_state, &req);
+            let mut response = match quotas::admit(&mut viewer_state, &req, &key) {
+                Some(rejection) => rejection,
+                None => route_for_store(&mut viewer_state, &req, method, path),
+            };
+            quotas::record_response(&mut viewer_state, &key, &mut response);
+            response
+        } else {
+            route_for_store(&mut viewer_state, &req, method, path)
+        };
+        undo::finish(&mut viewer_state);
+        audit::finish(&mut viewer_state);
+
+        errorbudget::record(&mut viewer_state, method, path, response.status);
+
+        // Browsers get a page to navigate from; API clients keep JSON errors
+        let mut response = error_page_for_browser(&req, response);
+        cors::apply(&viewer_state.cors, &req, &mut response);
+        // HEAD answers with the headers alone, errors included
+        if method == "HEAD" {
+            response.body = None;
+        }
+
+        // Deliver the events this request and the tasks before it produced
+        events::flush(&mut viewer_state);
+
+        let state_bytes = serde_json::to_vec(&viewer_state)
+            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+
+        Ok((Some(state_bytes), (response,)))
+    }
 
     fn handle_middleware(
-        _state: Option<Vec<u8>>,
-        _params: (u64, HttpRequest),
+        state: Option<Vec<u8>>,
+        params: (u64, HttpRequest),
     ) -> Result<(Option<Vec<u8>>, (MiddlewareResult,)), String> {
-        unreachable!("Middleware not used")
+        let state_bytes = state.ok_or("State not found")?;
+        let viewer_state: StoreViewerState = serde_json::from_slice(&state_bytes)
+            .map_err(|e| format!("Failed to deserialize state: {}", e))?;
+
+        let (_handler_id, req) = params;
+
+        // Every request goes on; handle_request refuses the unauthenticated
+        let result = MiddlewareResult {
+            proceed: true,
+            request: auth::mark(&viewer_state.auth, req),
+        };
+
+        Ok((Some(state_bytes), (result,)))
     }
 
     fn handle_websocket_connect(
-        _state: Option<Vec<u8>>,
-        _params: (u64, u64, String, Option<String>),
+        state: Option<Vec<u8>>,
+        params: (u64, u64, String, Option<String>),
     ) -> Result<(Option<Vec<u8>>,), String> {
-        unreachable!("WebSocket not used")
+        let state_bytes = state.ok_or("State not found")?;
+        let mut viewer_state: StoreViewerState = serde_json::from_slice(&state_bytes)
+            .map_err(|e| format!("Failed to deserialize state: {}", e))?;
+
+        let (_handler_id, connection_id, path, _protocol) = params;
+        if auth::admits_socket(&viewer_state.auth, &path) {
+            events::handle_connect(&mut viewer_state, connection_id);
+        } else {
+            events::refuse(&viewer_state, connection_id);
+        }
+
+        let state_bytes = serde_json::to_vec(&viewer_state)
+            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+        Ok((Some(state_bytes),))
     }
 
     fn handle_websocket_message(
-        _state: Option<Vec<u8>>,
-        _params: (u64, u64, WebsocketMessage),
+        state: Option<Vec<u8>>,
+        params: (u64, u64, WebsocketMessage),
     ) -> Result<(Option<Vec<u8>>, (Vec<WebsocketMessage>,)), String> {
-        unreachable!("WebSocket not used")
+        let state_bytes = state.ok_or("State not found")?;
+        let mut viewer_state: StoreViewerState = serde_json::from_slice(&state_bytes)
+            .map_err(|e| format!("Failed to deserialize state: {}", e))?;
+
+        let (_handler_id, connection_id, message) = params;
+        let replies = events::handle_message(&mut viewer_state, connection_id, &message).unwrap_or_else(|e| {
+            log(&format!("Error handling WebSocket message: {}", e));
+            Vec::new()
+        });
+
+        let state_bytes = serde_json::to_vec(&viewer_state)
+            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+        Ok((Some(state_bytes), (replies,)))
     }
 
     fn handle_websocket_disconnect(
-        _state: Option<Vec<u8>>,
-        _params: (u64, u64),
+        state: Option<Vec<u8>>,
+        params: (u64, u64),
     ) -> Result<(Option<Vec<u8>>,), String> {
-        unreachable!("WebSocket not used")
+        let state_bytes = state.ok_or("State not found")?;
+        let mut viewer_state: StoreViewerState = serde_json::from_slice(&state_bytes)
+            .map_err(|e| format!("Failed to deserialize state: {}", e))?;
+
+        let (_handler_id, connection_id) = params;
+        events::handle_disconnect(&mut viewer_state, connection_id);
+
+        let state_bytes = serde_json::to_vec(&viewer_state)
+            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+        Ok((Some(state_bytes),))
     }
 }
 
 bindings::export!(Component with_types_in bindings);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_decode_handles_escapes_and_plus() {
+        assert_eq!(percent_decode("a%2Fb", false), "a/b");
+        assert_eq!(percent_decode("caf%C3%A9", false), "café");
+        assert_eq!(percent_decode("a+b", false), "a+b");
+        assert_eq!(percent_decode("a+b", true), "a b");
+        assert_eq!(percent_decode("%41%4a%4A", false), "AJJ");
+    }
+
+    #[test]
+    fn percent_decode_keeps_malformed_escapes() {
+        assert_eq!(percent_decode("100%", false), "100%");
+        assert_eq!(percent_decode("%4", false), "%4");
+        assert_eq!(percent_decode("%zz", false), "%zz");
+        assert_eq!(percent_decode("%%41", false), "%A");
+    }
+
+    #[test]
+    fn query_params_are_decoded() {
+        let uri = "/api/labels?prefix=a%2Fb&q=x+y&flag&=empty";
+        assert_eq!(query_param(uri, "prefix").as_deref(), Some("a/b"));
+        assert_eq!(query_param(uri, "q").as_deref(), Some("x y"));
+        assert_eq!(query_param(uri, "flag").as_deref(), Some(""));
+        assert_eq!(query_param(uri, "missing"), None);
+        assert_eq!(query_param("/api/labels", "prefix"), None);
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let rules = ["", "docs/", "docs/api/", "logs/"];
+        let best = |label| longest_prefix_match(&rules, label, |rule| rule).copied();
+        assert_eq!(best("docs/api/v1"), Some("docs/api/"));
+        assert_eq!(best("docs/guide"), Some("docs/"));
+        assert_eq!(best("other"), Some(""));
+        assert_eq!(longest_prefix_match(&rules[1..], "other", |rule| rule), None);
+    }
+
+    #[test]
+    fn label_paths_split_off_actions() {
+        assert_eq!(parse_label_path("reports/q1/stats"), ("reports/q1".to_string(), Some("stats")));
+        assert_eq!(parse_label_path("a%2Fb/meta"), ("a/b".to_string(), Some("meta")));
+        assert_eq!(parse_label_path("reports/q1"), ("reports/q1".to_string(), None));
+        // An action on its own is a label name
+        assert_eq!(parse_label_path("stats"), ("stats".to_string(), None));
+        assert_eq!(parse_label_path("reports%2Fstats"), ("reports/stats".to_string(), None));
+    }
+
+    #[test]
+    fn entry_paths_split_at_entries() {
+        assert_eq!(
+            parse_entry_path("bundle.zip/entries/src/main.rs"),
+            Some(("bundle.zip".to_string(), "src/main.rs".to_string()))
+        );
+        assert_eq!(parse_entry_path("bundle.zip/entries"), None);
+        assert_eq!(parse_entry_path("bundle.zip/entries/"), None);
+    }
+}
@@ -0,0 +1,171 @@
+//! Linting of structured formats on write.
+//!
+//! Labels whose names end in `.json`, `.yaml`/`.yml` or `.toml` can be checked
+//! for well-formedness when they are written. Lint rules say, per prefix,
+//! whether a malformed document is rejected or only flagged; as with schemas,
+//! the rule with the longest matching prefix applies, and labels under no
+//! rule are not linted. A flagged label keeps its warnings in its metadata
+//! until the next write, and the write that caused them reports them too.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{error_response, json_response, longest_prefix_match, StoreViewerState};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintMode {
+    /// Store malformed content but flag it
+    Warn,
+    /// Refuse to store malformed content
+    Reject,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LintRule {
+    pub prefix: String,
+    pub mode: LintMode,
+}
+
+/// Formats that can be linted, recognized by extension.
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    fn of(label: &str) -> Option<Format> {
+        let (_, extension) = label.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+        }
+    }
+}
+
+pub fn rule_for<'a>(rules: &'a [LintRule], label: &str) -> Option<&'a LintRule> {
+    longest_prefix_match(rules, label, |rule| &rule.prefix)
+}
+
+/// 1-based line of a byte offset.
+fn line_of(content: &[u8], offset: usize) -> usize {
+    content[..offset.min(content.len())].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+/// What is wrong with `content` in the given format, if anything.
+fn problems(format: Format, content: &[u8]) -> Vec<String> {
+    let text = match std::str::from_utf8(content) {
+        Ok(text) => text,
+        Err(e) => return vec![format!("line {}: content is not UTF-8", line_of(content, e.valid_up_to()))],
+    };
+    let problem = match format {
+        Format::Json => serde_json::from_str::<serde_json::Value>(text)
+            .err()
+            .map(|e| e.to_string()),
+        Format::Yaml => serde_yaml::Deserializer::from_str(text)
+            .map(serde_yaml::Value::deserialize)
+            .find_map(Result::err)
+            .map(|e| e.to_string()),
+        Format::Toml => text.parse::<toml::Table>().err().map(|e| match e.span() {
+            Some(span) => format!("line {}: {}", line_of(content, span.start), e.message()),
+            None => e.message().to_string(),
+        }),
+    };
+    problem.into_iter().collect()
+}
+
+/// Lint content about to be written to `label`: the warnings to record, or
+/// an error when the governing rule rejects malformed content.
+pub fn check_write(rules: &[LintRule], label: &str, content: &[u8]) -> Result<Vec<String>, String> {
+    let (rule, format) = match (rule_for(rules, label), Format::of(label)) {
+        (Some(rule), Some(format)) => (rule, format),
+        _ => return Ok(Vec::new()),
+    };
+    let problems = problems(format, content);
+    if rule.mode == LintMode::Reject && !problems.is_empty() {
+        return Err(format!(
+            "Label {} must contain valid {} (lint rule for prefix '{}'): {}",
+            label,
+            format.name(),
+            rule.prefix,
+            problems.join("; ")
+        ));
+    }
+    Ok(problems
+        .into_iter()
+        .map(|problem| format!("Invalid {}: {}", format.name(), problem))
+        .collect())
+}
+
+/// Record the warnings for content just written to `label` in its metadata.
+pub fn record_write(state: &mut StoreViewerState, label: &str, content: &[u8]) {
+    // Rejections were already enforced before the write
+    let warnings = check_write(&state.lint, label, content).unwrap_or_default();
+    if !warnings.is_empty() {
+        log(&format!("Label {} was written with {} lint warning(s)", label, warnings.len()));
+        state.label_meta.entry(label.to_string()).or_default().lint_warnings = warnings;
+    } else if let Some(meta) = state.label_meta.get_mut(label) {
+        meta.lint_warnings.clear();
+        if meta.is_empty() {
+            state.label_meta.remove(label);
+        }
+    }
+}
+
+/// Warnings recorded for the last write to `label`.
+pub fn warnings_for<'a>(state: &'a StoreViewerState, label: &str) -> &'a [String] {
+    state.label_meta.get(label).map_or(&[], |meta| meta.lint_warnings.as_slice())
+}
+
+pub fn handle_list_rules(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing lint rules");
+
+    let body = serde_json::to_vec(&state.lint)
+        .map_err(|e| format!("Failed to serialize lint rules: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_put_rule(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving lint rule");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let rule: LintRule = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    log(&format!("Saved lint rule for prefix: {}", rule.prefix));
+
+    // Saving a prefix again replaces its previous rule
+    state.lint.retain(|existing| existing.prefix != rule.prefix);
+    state.lint.push(rule);
+    state.lint.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+pub fn handle_delete_rule(state: &mut StoreViewerState, prefix: &str) -> Result<HttpResponse, String> {
+    log(&format!("Deleting lint rule for prefix: {}", prefix));
+
+    let before = state.lint.len();
+    state.lint.retain(|rule| rule.prefix != prefix);
+    if state.lint.len() == before {
+        return Ok(error_response(404, &format!("No lint rule for prefix: {}", prefix)));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
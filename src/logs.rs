@@ -0,0 +1,291 @@
+//! Parsing and filtering of log labels.
+//!
+//! A text label is treated as a log when most of its lines are either JSON
+//! objects (JSON lines, as written by structured loggers) or start with a
+//! timestamp and/or a level (`2024-05-01T12:00:00Z ERROR message`,
+//! `[2024-05-01 12:00:00,123] [WARN] message`, `INFO: message`). Lines that
+//! match neither shape, such as stack traces, are continuation lines and are
+//! appended to the entry before them.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{clock, error_response, json_response, labelmeta, query_param, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Lines sampled to decide whether a label is a log.
+const DETECT_SAMPLE_LINES: usize = 50;
+
+const DEFAULT_LIMIT: usize = 1000;
+
+const LEVEL_KEYS: &[&str] = &["level", "severity", "lvl", "log.level"];
+const TIMESTAMP_KEYS: &[&str] = &["timestamp", "time", "ts", "@timestamp"];
+const MESSAGE_KEYS: &[&str] = &["message", "msg"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    JsonLines,
+    Text,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "trace" => Some(Level::Trace),
+            "debug" => Some(Level::Debug),
+            "info" | "information" | "notice" => Some(Level::Info),
+            "warn" | "warning" => Some(Level::Warn),
+            "error" | "err" => Some(Level::Error),
+            "fatal" | "critical" | "crit" | "panic" => Some(Level::Fatal),
+            _ => None,
+        }
+    }
+
+    /// Numeric levels as used by pino and bunyan.
+    fn from_number(value: u64) -> Option<Self> {
+        match value {
+            10 => Some(Level::Trace),
+            20 => Some(Level::Debug),
+            30 => Some(Level::Info),
+            40 => Some(Level::Warn),
+            50 => Some(Level::Error),
+            60 => Some(Level::Fatal),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct LogEntry {
+    /// 1-based line number the entry starts on
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<Level>,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+struct LogLinesResponse {
+    name: String,
+    format: LogFormat,
+    total_entries: usize,
+    matched: usize,
+    truncated: bool,
+    entries: Vec<LogEntry>,
+}
+
+fn first_field<'a>(object: &'a Map<String, Value>, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter().find_map(|key| object.get(*key))
+}
+
+/// Interpret a numeric timestamp as epoch seconds or milliseconds.
+fn numeric_timestamp(value: f64) -> Option<u64> {
+    if value < 0.0 {
+        None
+    } else if value < 1e11 {
+        Some((value * 1000.0) as u64)
+    } else {
+        Some(value as u64)
+    }
+}
+
+fn parse_json_line(line_no: usize, line: &str) -> Option<LogEntry> {
+    let object = match serde_json::from_str::<Value>(line).ok()? {
+        Value::Object(object) => object,
+        _ => return None,
+    };
+
+    // A JSON object without any log fields is data, not a log record
+    if [LEVEL_KEYS, TIMESTAMP_KEYS, MESSAGE_KEYS].iter().all(|keys| first_field(&object, keys).is_none()) {
+        return None;
+    }
+
+    let level = match first_field(&object, LEVEL_KEYS) {
+        Some(Value::String(s)) => Level::parse(s),
+        Some(Value::Number(n)) => n.as_u64().and_then(Level::from_number),
+        _ => None,
+    };
+    let timestamp = match first_field(&object, TIMESTAMP_KEYS) {
+        Some(Value::String(s)) => clock::parse_time(s),
+        Some(Value::Number(n)) => n.as_f64().and_then(numeric_timestamp),
+        _ => None,
+    };
+    let message = match first_field(&object, MESSAGE_KEYS) {
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+        None => line.to_string(),
+    };
+
+    Some(LogEntry { line: line_no, timestamp, level, message })
+}
+
+fn parse_text_line(line_no: usize, line: &str) -> Option<LogEntry> {
+    let mut rest = line.trim_start();
+
+    let bracketed = rest.strip_prefix('[');
+    let timestamp = match clock::parse_iso8601_prefix(bracketed.unwrap_or(rest)) {
+        Some((ms, len)) => {
+            let after = &bracketed.unwrap_or(rest)[len..];
+            rest = if bracketed.is_some() { after.strip_prefix(']')? } else { after };
+            Some(ms)
+        }
+        None => None,
+    };
+    rest = rest.trim_start();
+
+    let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let word = rest[..word_end].trim_matches(|c| matches!(c, '[' | ']' | '(' | ')' | ':'));
+    let level = Level::parse(word);
+    if level.is_some() {
+        rest = &rest[word_end..];
+    }
+
+    if timestamp.is_none() && level.is_none() {
+        return None;
+    }
+
+    let message = rest.trim_start_matches(|c: char| c == ':' || c.is_whitespace()).to_string();
+    Some(LogEntry { line: line_no, timestamp, level, message })
+}
+
+fn parse_line(line_no: usize, line: &str) -> Option<LogEntry> {
+    if line.trim_start().starts_with('{') {
+        if let Some(entry) = parse_json_line(line_no, line) {
+            return Some(entry);
+        }
+    }
+    parse_text_line(line_no, line)
+}
+
+/// Decide whether text content is a log, and of which kind.
+pub fn detect(text: &str) -> Option<LogFormat> {
+    let mut sampled = 0;
+    let mut json = 0;
+    let mut text_lines = 0;
+    for (i, line) in text.lines().filter(|l| !l.trim().is_empty()).take(DETECT_SAMPLE_LINES).enumerate() {
+        sampled += 1;
+        if line.trim_start().starts_with('{') && parse_json_line(i, line).is_some() {
+            json += 1;
+        } else if parse_text_line(i, line).is_some() {
+            text_lines += 1;
+        }
+    }
+
+    if sampled == 0 {
+        None
+    } else if json * 2 >= sampled {
+        Some(LogFormat::JsonLines)
+    } else if (json + text_lines) * 2 >= sampled {
+        Some(LogFormat::Text)
+    } else {
+        None
+    }
+}
+
+/// Split a log into entries, folding continuation lines into the entry
+/// before them.
+pub fn parse(text: &str) -> Vec<LogEntry> {
+    let mut entries: Vec<LogEntry> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(i + 1, line) {
+            Some(entry) => entries.push(entry),
+            None => match entries.last_mut() {
+                Some(previous) => {
+                    previous.message.push('\n');
+                    previous.message.push_str(line);
+                }
+                None => entries.push(LogEntry {
+                    line: i + 1,
+                    timestamp: None,
+                    level: None,
+                    message: line.to_string(),
+                }),
+            },
+        }
+    }
+    entries
+}
+
+/// `GET /api/labels/{name}/loglines?level=warn&since=<time>&limit=<n>`
+pub fn handle_loglines(state: &StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Parsing log label: {}", label_name));
+
+    let min_level = match query_param(&req.uri, "level") {
+        None => None,
+        Some(level) => match Level::parse(&level) {
+            Some(level) => Some(level),
+            None => return Ok(error_response(400, &format!("Unknown log level: {}", level))),
+        },
+    };
+    let since = match query_param(&req.uri, "since") {
+        None => None,
+        Some(since) => match clock::parse_time(&since) {
+            Some(ms) => Some(ms),
+            None => return Ok(error_response(400, &format!("Invalid since time: {}", since))),
+        },
+    };
+    let limit = match query_param(&req.uri, "limit") {
+        None => DEFAULT_LIMIT,
+        Some(limit) => match limit.parse::<usize>() {
+            Ok(limit) => limit,
+            Err(_) => return Ok(error_response(400, &format!("Invalid limit: {}", limit))),
+        },
+    };
+
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    let text = labelmeta::decode_text(state, label_name, &content);
+    let format = match text.as_deref().and_then(detect) {
+        Some(format) => format,
+        None => return Ok(error_response(415, &format!("Label {} is not a line-oriented log", label_name))),
+    };
+    let text = text.unwrap_or_default();
+
+    let entries = parse(&text);
+    let total_entries = entries.len();
+
+    // Entries without a level or timestamp cannot satisfy the matching filter
+    let matching: Vec<LogEntry> = entries
+        .into_iter()
+        .filter(|e| min_level.is_none_or(|min| e.level.is_some_and(|level| level >= min)))
+        .filter(|e| since.is_none_or(|since| e.timestamp.is_some_and(|ts| ts >= since)))
+        .collect();
+    let matched = matching.len();
+
+    let response = LogLinesResponse {
+        name: label_name.to_string(),
+        format,
+        total_entries,
+        matched,
+        truncated: matched > limit,
+        entries: matching.into_iter().take(limit).collect(),
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize log lines: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
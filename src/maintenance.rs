@@ -0,0 +1,175 @@
+//! Maintenance mode.
+//!
+//! `POST /api/admin/maintenance` puts the store into maintenance while an
+//! operator runs a garbage collection, a large import or a migration against
+//! it. Reads carry on as usual, but every API request that could change
+//! labels is answered with `503`, the reason and a `Retry-After`, and the
+//! scheduled writes, retention and trash purges wait. Admin endpoints stay
+//! available so maintenance can be ended. A window given an `until` ends by
+//! itself at that time.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::stores;
+use crate::{clock, error_response, json_response, parse_label_path, StoreViewerState};
+use serde::{Deserialize, Serialize};
+
+/// `Retry-After` sent when the window has no end time and none was given.
+const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+
+/// Reason given when none was.
+const DEFAULT_REASON: &str = "Store maintenance";
+
+/// POST endpoints that only read, and so stay open.
+const READ_ONLY_POSTS: &[&str] = &["/api/labels/head"];
+
+/// Label actions (`POST /api/labels/{name}/{action}`) that only read.
+const READ_ONLY_LABEL_ACTIONS: &[&str] = &["merge"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Window {
+    reason: String,
+    started_at: u64,
+    /// When the window ends by itself, if it was given an end
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    until: Option<u64>,
+    /// Seconds clients are told to wait when the window has no end
+    retry_after_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Maintenance {
+    #[serde(default)]
+    window: Option<Window>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaintenanceRequest {
+    enabled: bool,
+    #[serde(default)]
+    reason: Option<String>,
+    /// End of the window as epoch milliseconds or ISO 8601
+    #[serde(default)]
+    until: Option<String>,
+    #[serde(default)]
+    retry_after_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct StatusResponse<'a> {
+    active: bool,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    window: Option<&'a Window>,
+}
+
+/// The maintenance window in force at the current clock, if any.
+fn active(state: &StoreViewerState) -> Option<&Window> {
+    state
+        .maintenance
+        .window
+        .as_ref()
+        .filter(|window| window.until.is_none_or(|until| state.clock_ms < until))
+}
+
+/// Whether maintenance is in force, so background writes should wait.
+pub fn is_active(state: &StoreViewerState) -> bool {
+    active(state).is_some()
+}
+
+/// Whether a request could change labels, judged by the route it reaches.
+fn is_write(method: &str, path: &str) -> bool {
+    if !matches!(method, "POST" | "PUT" | "DELETE" | "PATCH")
+        || !path.starts_with("/api/")
+        || path.starts_with("/api/admin/")
+    {
+        return false;
+    }
+    // Another store's labels are routed like the viewer's own
+    let path = match path.strip_prefix(stores::STORES_PATH).and_then(|rest| rest.split_once('/')) {
+        Some((_, rest)) => format!("/api/{}", rest),
+        None => path.to_string(),
+    };
+    if method != "POST" {
+        return true;
+    }
+    if READ_ONLY_POSTS.contains(&path.as_str()) {
+        return false;
+    }
+    match path.strip_prefix("/api/labels/").map(parse_label_path) {
+        Some((_, Some(action))) => !READ_ONLY_LABEL_ACTIONS.contains(&action),
+        _ => true,
+    }
+}
+
+/// The `503` for a request that would write during maintenance.
+pub fn rejection(state: &StoreViewerState, method: &str, path: &str) -> Option<HttpResponse> {
+    let window = active(state)?;
+    if !is_write(method, path) {
+        return None;
+    }
+    let retry_after = match window.until {
+        Some(until) => (until - state.clock_ms).div_ceil(1000).max(1),
+        None => window.retry_after_secs,
+    };
+    log(&format!("Refused {} {} during maintenance", method, path));
+    let mut response = error_response(503, &format!("The store is in maintenance: {}", window.reason));
+    response.headers.push(("Retry-After".to_string(), retry_after.to_string()));
+    Some(response)
+}
+
+/// `GET /api/admin/maintenance`
+pub fn handle_status(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting maintenance status");
+
+    let window = active(state);
+    let body = serde_json::to_vec(&StatusResponse { active: window.is_some(), window })
+        .map_err(|e| format!("Failed to serialize maintenance status: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/admin/maintenance` with `{ "enabled", "reason"?, "until"?, "retry_after_secs"? }`
+pub fn handle_configure(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Configuring maintenance");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let config: MaintenanceRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if !config.enabled {
+        if state.maintenance.window.take().is_some() {
+            log("Maintenance ended");
+        }
+        let success_json = r#"{"success":true}"#;
+        return Ok(json_response(200, success_json.as_bytes().to_vec()));
+    }
+
+    let until = match config.until.as_deref().map(clock::parse_time) {
+        None => None,
+        Some(Some(until)) if until > state.clock_ms => Some(until),
+        Some(Some(_)) => return Ok(error_response(400, "until must be in the future")),
+        Some(None) => return Ok(error_response(400, "until must be epoch milliseconds or an ISO 8601 time")),
+    };
+    if config.retry_after_secs == Some(0) {
+        return Ok(error_response(400, "retry_after_secs must be at least 1"));
+    }
+    let reason = match config.reason.map(|reason| reason.trim().to_string()) {
+        Some(reason) if reason.len() > 200 => return Ok(error_response(400, "reason must be at most 200 bytes")),
+        Some(reason) if !reason.is_empty() => reason,
+        _ => DEFAULT_REASON.to_string(),
+    };
+
+    let window = Window {
+        reason,
+        // Changing a window in force keeps its start
+        started_at: active(state).map_or(state.clock_ms, |window| window.started_at),
+        until,
+        retry_after_secs: config.retry_after_secs.unwrap_or(DEFAULT_RETRY_AFTER_SECS),
+    };
+    log(&format!("Maintenance started: {}", window.reason));
+    state.maintenance.window = Some(window);
+
+    handle_status(state)
+}
@@ -0,0 +1,190 @@
+//! Inspection of Theater actor manifests.
+//!
+//! A manifest is a TOML document naming the actor, the component it runs,
+//! the handlers it is given and optionally its initial state. Labels holding
+//! one are recognised by their `name` and `component` keys, can be described
+//! in structured form, and are checked for the fields Theater needs when the
+//! schema registry marks their prefix as `manifest`.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, json_response, StoreViewerState};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Handler types Theater knows how to provide.
+const HANDLER_TYPES: &[&str] = &[
+    "runtime",
+    "message-server",
+    "http-client",
+    "http-framework",
+    "filesystem",
+    "store",
+    "supervisor",
+    "timing",
+    "process",
+    "environment",
+    "random",
+    "wasi-http",
+];
+
+#[derive(Serialize, Debug)]
+pub struct Handler {
+    #[serde(rename = "type")]
+    pub handler_type: String,
+    /// The handler's settings, everything besides its type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ManifestInfo {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Path or URL of the actor's WebAssembly component
+    pub component: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub save_chain: Option<bool>,
+    pub handlers: Vec<Handler>,
+    /// Initial state: a path to load it from, or the state inline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub init_state: Option<Value>,
+}
+
+fn to_json(value: toml::Value) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// Whether text looks like a Theater manifest.
+pub fn detect(text: &str) -> bool {
+    match text.parse::<toml::Table>() {
+        Ok(table) => table.get("name").is_some_and(toml::Value::is_str)
+            && table.get("component").is_some_and(toml::Value::is_str),
+        Err(_) => false,
+    }
+}
+
+/// Parse and check a manifest; returns every problem found.
+pub fn parse(text: &str) -> Result<ManifestInfo, Vec<String>> {
+    let mut table = text.parse::<toml::Table>().map_err(|e| vec![format!("invalid TOML: {}", e.message())])?;
+    let mut errors = Vec::new();
+
+    let mut string_field = |table: &mut toml::Table, key: &str, required: bool| match table.remove(key) {
+        Some(toml::Value::String(value)) if !value.is_empty() => Some(value),
+        Some(_) => {
+            errors.push(format!("{} must be a non-empty string", key));
+            None
+        }
+        None => {
+            if required {
+                errors.push(format!("missing required field {}", key));
+            }
+            None
+        }
+    };
+    let name = string_field(&mut table, "name", true);
+    let component = string_field(&mut table, "component", true);
+    let version = string_field(&mut table, "version", false);
+    let description = string_field(&mut table, "description", false);
+
+    let save_chain = match table.remove("save_chain") {
+        Some(toml::Value::Boolean(save_chain)) => Some(save_chain),
+        Some(_) => {
+            errors.push("save_chain must be a boolean".to_string());
+            None
+        }
+        None => None,
+    };
+
+    let init_state = match table.remove("init_state") {
+        Some(value @ (toml::Value::String(_) | toml::Value::Table(_))) => Some(to_json(value)),
+        Some(_) => {
+            errors.push("init_state must be a path or a table".to_string());
+            None
+        }
+        None => None,
+    };
+
+    let mut handlers = Vec::new();
+    match table.remove("handler") {
+        Some(toml::Value::Array(entries)) => {
+            for (i, entry) in entries.into_iter().enumerate() {
+                let mut entry = match entry {
+                    toml::Value::Table(entry) => entry,
+                    _ => {
+                        errors.push(format!("handler[{}] must be a table", i));
+                        continue;
+                    }
+                };
+                let handler_type = match entry.remove("type") {
+                    Some(toml::Value::String(handler_type)) => handler_type,
+                    _ => {
+                        errors.push(format!("handler[{}] is missing its type", i));
+                        continue;
+                    }
+                };
+                if !HANDLER_TYPES.contains(&handler_type.as_str()) {
+                    errors.push(format!("handler[{}] has unknown type {}", i, handler_type));
+                }
+                if handlers.iter().any(|h: &Handler| h.handler_type == handler_type) {
+                    errors.push(format!("handler {} is listed more than once", handler_type));
+                }
+                let config = match entry.remove("config") {
+                    Some(config) if entry.is_empty() => Some(to_json(config)),
+                    Some(config) => {
+                        entry.insert("config".to_string(), config);
+                        Some(to_json(toml::Value::Table(entry)))
+                    }
+                    None if entry.is_empty() => None,
+                    None => Some(to_json(toml::Value::Table(entry))),
+                };
+                handlers.push(Handler { handler_type, config });
+            }
+        }
+        Some(_) => errors.push("handler must be an array of tables ([[handler]])".to_string()),
+        None => {}
+    }
+
+    match (name, component) {
+        (Some(name), Some(component)) if errors.is_empty() => Ok(ManifestInfo {
+            name,
+            version,
+            description,
+            component,
+            save_chain,
+            handlers,
+            init_state,
+        }),
+        _ => Err(errors),
+    }
+}
+
+/// `GET /api/labels/{name}/manifest-info`
+pub fn handle_manifest_info(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Inspecting manifest label: {}", label_name));
+
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    let text = match std::str::from_utf8(&content) {
+        Ok(text) if detect(text) => text,
+        _ => return Ok(error_response(415, &format!("Label {} is not a Theater manifest", label_name))),
+    };
+
+    let info = match parse(text) {
+        Ok(info) => info,
+        Err(errors) => return Ok(error_response(422, &format!("Invalid manifest: {}", errors.join("; ")))),
+    };
+
+    let body = serde_json::to_vec(&info)
+        .map_err(|e| format!("Failed to serialize manifest info: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,378 @@
+//! Three-way merge of text labels.
+//!
+//! A client whose update lost a race holds three versions: the one it started
+//! editing from (the base), its own edit, and the label's current content.
+//! Merging line by line keeps every change that only one side made; where
+//! both sides changed the same lines differently the result carries conflict
+//! markers for the user to resolve. Nothing is written; the client submits the
+//! merged content with a regular update.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::{error_response, json_response, StoreViewerState};
+use serde::{Deserialize, Serialize};
+
+const CURRENT_MARKER: &str = "<<<<<<< current";
+const SEPARATOR_MARKER: &str = "=======";
+const CLIENT_MARKER: &str = ">>>>>>> yours";
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    /// Ref of the version the client's edit started from
+    #[serde(default)]
+    base_ref: Option<String>,
+    /// Or the base content itself
+    #[serde(default)]
+    base: Option<String>,
+    /// The client's edited content
+    content: String,
+}
+
+#[derive(Serialize)]
+struct MergeResponse {
+    name: String,
+    /// Ref of the current content the merge was made against; send it back as
+    /// the expected version when saving the result
+    current_ref: String,
+    /// Whether the merge completed without conflicts
+    clean: bool,
+    conflicts: usize,
+    content: String,
+}
+
+/// Most lines either side of a merge or diff may have. The search takes time
+/// proportional to the lines times the number of differences, so larger
+/// inputs are refused with 413 rather than compared.
+pub const MAX_LINES: usize = 20_000;
+
+/// Pairs of indices of equal items in `a` and `b` forming a longest common
+/// subsequence, in increasing order (Myers' algorithm, in linear space).
+pub fn common_lines<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    collect_common(a, b, 0, 0, &mut pairs);
+    pairs
+}
+
+/// Append the common subsequence of `a` and `b`, which start at `a_off` and
+/// `b_off` in the full sequences, to `pairs`.
+fn collect_common<T: PartialEq>(a: &[T], b: &[T], a_off: usize, b_off: usize, pairs: &mut Vec<(usize, usize)>) {
+    // Common prefix and suffix need no search
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    pairs.extend((0..prefix).map(|i| (a_off + i, b_off + i)));
+    if let Some((x, y)) = middle(a_mid, b_mid) {
+        let (a_mid_off, b_mid_off) = (a_off + prefix, b_off + prefix);
+        collect_common(&a_mid[..x], &b_mid[..y], a_mid_off, b_mid_off, pairs);
+        collect_common(&a_mid[x..], &b_mid[y..], a_mid_off + x, b_mid_off + y, pairs);
+    }
+    pairs.extend((0..suffix).map(|k| (a_off + a.len() - suffix + k, b_off + b.len() - suffix + k)));
+}
+
+/// Point on a shortest edit path from the start of `a` and `b` to their end
+/// where the searches from both ends meet, splitting the problem in two.
+/// `None` when the two have nothing in common.
+fn middle<T: PartialEq>(a: &[T], b: &[T]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    if n == 0 || m == 0 {
+        return None;
+    }
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let len = 2 * max_d as usize + 2;
+    // Furthest x reached on each diagonal, from the start and from the end
+    let mut forward = vec![-1isize; len];
+    let mut backward = vec![-1isize; len];
+    forward[offset as usize + 1] = 0;
+    backward[offset as usize + 1] = 0;
+    let delta = n - m;
+    // With an odd difference in length the paths meet on a forward step
+    let front = delta % 2 != 0;
+    // Diagonals that have run off the edge are not searched again
+    let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+
+    for d in 0..max_d {
+        let mut k1 = -d + k1_start;
+        while k1 <= d - k1_end {
+            let k1_idx = (offset + k1) as usize;
+            let mut x1 = if k1 == -d || (k1 != d && forward[k1_idx - 1] < forward[k1_idx + 1]) {
+                forward[k1_idx + 1]
+            } else {
+                forward[k1_idx - 1] + 1
+            };
+            let mut y1 = x1 - k1;
+            while x1 < n && y1 < m && a[x1 as usize] == b[y1 as usize] {
+                x1 += 1;
+                y1 += 1;
+            }
+            forward[k1_idx] = x1;
+            if x1 > n {
+                k1_end += 2;
+            } else if y1 > m {
+                k1_start += 2;
+            } else if front {
+                let k2_idx = offset + delta - k1;
+                if (0..len as isize).contains(&k2_idx) && backward[k2_idx as usize] != -1 && x1 >= n - backward[k2_idx as usize] {
+                    return Some((x1 as usize, y1 as usize));
+                }
+            }
+            k1 += 2;
+        }
+
+        let mut k2 = -d + k2_start;
+        while k2 <= d - k2_end {
+            let k2_idx = (offset + k2) as usize;
+            let mut x2 = if k2 == -d || (k2 != d && backward[k2_idx - 1] < backward[k2_idx + 1]) {
+                backward[k2_idx + 1]
+            } else {
+                backward[k2_idx - 1] + 1
+            };
+            let mut y2 = x2 - k2;
+            while x2 < n && y2 < m && a[(n - x2 - 1) as usize] == b[(m - y2 - 1) as usize] {
+                x2 += 1;
+                y2 += 1;
+            }
+            backward[k2_idx] = x2;
+            if x2 > n {
+                k2_end += 2;
+            } else if y2 > m {
+                k2_start += 2;
+            } else if !front {
+                let k1_idx = offset + delta - k2;
+                if (0..len as isize).contains(&k1_idx) && forward[k1_idx as usize] != -1 {
+                    let x1 = forward[k1_idx as usize];
+                    let y1 = offset + x1 - k1_idx;
+                    if x1 >= n - x2 {
+                        return Some((x1 as usize, y1 as usize));
+                    }
+                }
+            }
+            k2 += 2;
+        }
+    }
+    None
+}
+
+/// Merge line-based edits of `base` made in `current` and `client`. Returns
+/// the merged text and the number of conflicting regions.
+pub fn merge3(base: &str, current: &str, client: &str) -> (String, usize) {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let current: Vec<&str> = current.split_inclusive('\n').collect();
+    let client: Vec<&str> = client.split_inclusive('\n').collect();
+
+    // For each base line, the matching line in either version
+    let mut in_current = vec![None; base.len()];
+    for (i, j) in common_lines(&base, &current) {
+        in_current[i] = Some(j);
+    }
+    let mut in_client = vec![None; base.len()];
+    for (i, j) in common_lines(&base, &client) {
+        in_client[i] = Some(j);
+    }
+
+    let mut merged = String::new();
+    let mut conflicts = 0;
+    let (mut i, mut a, mut b) = (0, 0, 0);
+    loop {
+        // Next base line kept unchanged by both sides
+        let stable = (i..base.len()).find_map(|k| Some((k, in_current[k]?, in_client[k]?)));
+        let (end_i, end_a, end_b) = stable.unwrap_or((base.len(), current.len(), client.len()));
+
+        let (base_chunk, current_chunk, client_chunk) = (&base[i..end_i], &current[a..end_a], &client[b..end_b]);
+        if current_chunk == base_chunk || current_chunk == client_chunk {
+            merged.extend(client_chunk.iter().copied());
+        } else if client_chunk == base_chunk {
+            merged.extend(current_chunk.iter().copied());
+        } else {
+            conflicts += 1;
+            push_conflict(&mut merged, current_chunk, client_chunk);
+        }
+
+        match stable {
+            Some((k, j_current, j_client)) => {
+                merged.push_str(base[k]);
+                i = k + 1;
+                a = j_current + 1;
+                b = j_client + 1;
+            }
+            None => break,
+        }
+    }
+    (merged, conflicts)
+}
+
+fn push_conflict(out: &mut String, current: &[&str], client: &[&str]) {
+    out.push_str(CURRENT_MARKER);
+    out.push('\n');
+    push_lines(out, current);
+    out.push_str(SEPARATOR_MARKER);
+    out.push('\n');
+    push_lines(out, client);
+    out.push_str(CLIENT_MARKER);
+    out.push('\n');
+}
+
+/// Append lines, ending the last one so a marker can follow.
+fn push_lines(out: &mut String, lines: &[&str]) {
+    for line in lines {
+        out.push_str(line);
+    }
+    if lines.last().is_some_and(|line| !line.ends_with('\n')) {
+        out.push('\n');
+    }
+}
+
+/// `POST /api/labels/{name}/merge`
+pub fn handle_merge(state: &StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Merging edit into label: {}", label_name));
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let merge_req: MergeRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    let current_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(current_ref) => current_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let current = match String::from_utf8(store::get(&state.store_id, &current_ref)?) {
+        Ok(current) => current,
+        Err(_) => return Ok(error_response(415, &format!("Label {} is not UTF-8 text", label_name))),
+    };
+
+    let base = match (merge_req.base, merge_req.base_ref) {
+        (Some(base), None) => base,
+        (None, Some(hash)) => {
+            let content_ref = ContentRef { hash };
+            if !store::exists(&state.store_id, &content_ref)? {
+                return Ok(error_response(404, &format!("Base version not found: {}", content_ref.hash)));
+            }
+            match String::from_utf8(store::get(&state.store_id, &content_ref)?) {
+                Ok(base) => base,
+                Err(_) => return Ok(error_response(415, "Base version is not UTF-8 text")),
+            }
+        }
+        _ => return Ok(error_response(400, "Exactly one of base and base_ref is required")),
+    };
+
+    if [&base, &current, &merge_req.content].iter().any(|text| text.lines().count() > MAX_LINES) {
+        return Ok(error_response(413, &format!("Merges are limited to {} lines per version", MAX_LINES)));
+    }
+
+    let (content, conflicts) = merge3(&base, &current, &merge_req.content);
+
+    log(&format!("Merged edit into {} with {} conflicts", label_name, conflicts));
+
+    let response = MergeResponse {
+        name: label_name.to_string(),
+        current_ref: current_ref.hash,
+        clean: conflicts == 0,
+        conflicts,
+        content,
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize merge: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Length of a longest common subsequence, by dynamic programming.
+    fn lcs_len(a: &[u8], b: &[u8]) -> usize {
+        let mut row = vec![0; b.len() + 1];
+        for x in a {
+            let mut diagonal = 0;
+            for (j, y) in b.iter().enumerate() {
+                let above = row[j + 1];
+                row[j + 1] = if x == y { diagonal + 1 } else { above.max(row[j]) };
+                diagonal = above;
+            }
+        }
+        row[b.len()]
+    }
+
+    fn assert_common(a: &[u8], b: &[u8]) {
+        let pairs = common_lines(a, b);
+        assert_eq!(pairs.len(), lcs_len(a, b), "{:?} {:?}", a, b);
+        for window in pairs.windows(2) {
+            assert!(window[0].0 < window[1].0 && window[0].1 < window[1].1);
+        }
+        for &(i, j) in &pairs {
+            assert_eq!(a[i], b[j]);
+        }
+    }
+
+    #[test]
+    fn common_lines_are_a_longest_common_subsequence() {
+        let cases: [(&[u8], &[u8]); 8] = [
+            (b"", b""),
+            (b"a", b""),
+            (b"a", b"b"),
+            (b"a", b"a"),
+            (b"abcabba", b"cbabac"),
+            (b"xaxbxc", b"abc"),
+            (b"abcdefgh", b"abxdefyh"),
+            (b"aaaaab", b"baaaaa"),
+        ];
+        for (a, b) in cases {
+            assert_common(a, b);
+            assert_common(b, a);
+        }
+    }
+
+    #[test]
+    fn common_lines_match_exhaustive_small_inputs() {
+        // Every pair of strings over a two-letter alphabet up to length 5
+        let all: Vec<Vec<u8>> = (0..=5)
+            .flat_map(|len| (0..1u32 << len).map(move |bits| (0..len).map(|i| b'a' + (bits >> i & 1) as u8).collect()))
+            .collect();
+        for a in &all {
+            for b in &all {
+                assert_common(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn merge_keeps_changes_from_both_sides() {
+        let base = "one\ntwo\nthree\nfour\n";
+        let current = "ONE\ntwo\nthree\nfour\n";
+        let client = "one\ntwo\nthree\nFOUR\n";
+        assert_eq!(merge3(base, current, client), ("ONE\ntwo\nthree\nFOUR\n".to_string(), 0));
+    }
+
+    #[test]
+    fn merge_takes_identical_changes_once() {
+        let base = "a\nb\nc\n";
+        let both = "a\nB\nc\n";
+        assert_eq!(merge3(base, both, both), (both.to_string(), 0));
+    }
+
+    #[test]
+    fn merge_marks_conflicting_changes() {
+        let base = "a\nb\nc\n";
+        let current = "a\ncurrent\nc\n";
+        let client = "a\nclient\nc\n";
+        let (merged, conflicts) = merge3(base, current, client);
+        assert_eq!(conflicts, 1);
+        assert_eq!(merged, "a\n<<<<<<< current\ncurrent\n=======\nclient\n>>>>>>> yours\nc\n");
+    }
+
+    #[test]
+    fn conflict_markers_start_on_their_own_line() {
+        let (merged, conflicts) = merge3("a", "b", "c");
+        assert_eq!(conflicts, 1);
+        assert_eq!(merged, "<<<<<<< current\nb\n=======\nc\n>>>>>>> yours\n");
+    }
+}
@@ -0,0 +1,214 @@
+//! Export and import of the metadata index.
+//!
+//! Everything the viewer knows about labels beyond their content (access
+//! statistics, saved views, schema, naming, editor and lint rules, retention
+//! policies, language, encoding and headers, archived labels) can be exported as a single JSON document and
+//! imported into another viewer instance that shares the same store, or
+//! restored from a backup. Label content itself is never part of the index.
+
+use crate::archival::ArchivedLabel;
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::editor::{self, EditorRule};
+use crate::labelmeta::{self, LabelMeta};
+use crate::lint::LintRule;
+use crate::naming::{self, NamingRule};
+use crate::retention::{self, RetentionPolicy};
+use crate::schemas::{self, SchemaRule};
+use crate::stats::AccessStats;
+use crate::views::LabelQuery;
+use crate::{error_response, json_response, query_param, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Version of the export format.
+const INDEX_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct MetadataIndex {
+    pub version: u32,
+    /// Store the index was exported from
+    #[serde(default)]
+    pub store_id: String,
+    #[serde(default)]
+    pub exported_at: u64,
+    #[serde(default)]
+    pub stats: AccessStats,
+    #[serde(default)]
+    pub views: BTreeMap<String, LabelQuery>,
+    #[serde(default)]
+    pub schemas: Vec<SchemaRule>,
+    #[serde(default)]
+    pub naming_rules: Vec<NamingRule>,
+    #[serde(default)]
+    pub editor_rules: Vec<EditorRule>,
+    #[serde(default)]
+    pub lint_rules: Vec<LintRule>,
+    #[serde(default)]
+    pub retention_policies: Vec<RetentionPolicy>,
+    #[serde(default)]
+    pub label_meta: BTreeMap<String, LabelMeta>,
+    #[serde(default)]
+    pub archived: BTreeMap<String, ArchivedLabel>,
+}
+
+#[derive(Serialize)]
+struct ImportResponse {
+    mode: &'static str,
+    stats: usize,
+    views: usize,
+    schemas: usize,
+    naming_rules: usize,
+    editor_rules: usize,
+    lint_rules: usize,
+    retention_policies: usize,
+    label_meta: usize,
+    archived: usize,
+}
+
+pub fn handle_export(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Exporting metadata index");
+
+    let index = MetadataIndex {
+        version: INDEX_VERSION,
+        store_id: state.store_id.clone(),
+        exported_at: state.clock_ms,
+        stats: state.stats.clone(),
+        views: state.views.clone(),
+        schemas: state.schemas.clone(),
+        naming_rules: state.naming.clone(),
+        editor_rules: state.editor.clone(),
+        lint_rules: state.lint.clone(),
+        retention_policies: state.retention.policies.clone(),
+        label_meta: state.label_meta.clone(),
+        archived: state.archived.clone(),
+    };
+
+    let body = serde_json::to_vec(&index)
+        .map_err(|e| format!("Failed to serialize metadata index: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/metadata/import?mode=replace|merge`. `replace` (the default)
+/// swaps the whole index; `merge` keeps existing entries and lets imported
+/// ones win where both define the same label, view or prefix.
+pub fn handle_import(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Importing metadata index");
+
+    let merge = match query_param(&req.uri, "mode").as_deref() {
+        None | Some("replace") => false,
+        Some("merge") => true,
+        Some(other) => return Ok(error_response(400, &format!("Unknown import mode: {}", other))),
+    };
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let mut index: MetadataIndex = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid metadata index: {}", e))?;
+
+    if index.version != INDEX_VERSION {
+        return Ok(error_response(400, &format!("Unsupported metadata index version: {}", index.version)));
+    }
+
+    for query in index.views.values() {
+        if let Err(e) = query.validate() {
+            return Ok(error_response(400, &format!("Invalid view in index: {}", e)));
+        }
+    }
+
+    for rule in &index.naming_rules {
+        if let Err(e) = naming::validate_rule(rule) {
+            return Ok(error_response(400, &format!("Invalid naming rule in index: {}", e)));
+        }
+    }
+
+    for rule in &index.editor_rules {
+        if let Err(e) = editor::validate_rule(rule) {
+            return Ok(error_response(400, &format!("Invalid editor rule in index: {}", e)));
+        }
+    }
+
+    for rule in &index.schemas {
+        if let Err(e) = schemas::validate_rule(rule) {
+            return Ok(error_response(400, &format!("Invalid schema rule in index: {}", e)));
+        }
+    }
+
+    // Reserved headers would replace the ones that keep served labels inert
+    for (label, meta) in &index.label_meta {
+        if let Err(e) = labelmeta::validate_meta(meta) {
+            return Ok(error_response(400, &format!("Invalid metadata for {} in index: {}", label, e)));
+        }
+    }
+
+    // Idle time is counted from the import, as for a policy saved here
+    for policy in &mut index.retention_policies {
+        if let Err((status, e)) = retention::validate_policy(state, policy) {
+            return Ok(error_response(status, &format!("Invalid retention policy in index: {}", e)));
+        }
+        policy.created_at = state.clock_ms;
+    }
+
+    let response = ImportResponse {
+        mode: if merge { "merge" } else { "replace" },
+        stats: index.stats.len(),
+        views: index.views.len(),
+        schemas: index.schemas.len(),
+        naming_rules: index.naming_rules.len(),
+        editor_rules: index.editor_rules.len(),
+        lint_rules: index.lint_rules.len(),
+        retention_policies: index.retention_policies.len(),
+        label_meta: index.label_meta.len(),
+        archived: index.archived.len(),
+    };
+
+    if merge {
+        state.stats.merge(index.stats);
+        state.views.extend(index.views);
+        state.label_meta.extend(index.label_meta);
+        state.archived.extend(index.archived);
+        for rule in index.schemas {
+            state.schemas.retain(|existing| existing.prefix != rule.prefix);
+            state.schemas.push(rule);
+        }
+        for rule in index.naming_rules {
+            state.naming.retain(|existing| existing.prefix != rule.prefix);
+            state.naming.push(rule);
+        }
+        for rule in index.editor_rules {
+            state.editor.retain(|existing| existing.prefix != rule.prefix);
+            state.editor.push(rule);
+        }
+        for rule in index.lint_rules {
+            state.lint.retain(|existing| existing.prefix != rule.prefix);
+            state.lint.push(rule);
+        }
+        for policy in index.retention_policies {
+            state.retention.policies.retain(|existing| existing.prefix != policy.prefix);
+            state.retention.policies.push(policy);
+        }
+    } else {
+        state.stats = index.stats;
+        state.views = index.views;
+        state.label_meta = index.label_meta;
+        state.archived = index.archived;
+        state.schemas = index.schemas;
+        state.naming = index.naming_rules;
+        state.editor = index.editor_rules;
+        state.lint = index.lint_rules;
+        state.retention.policies = index.retention_policies;
+    }
+    state.schemas.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+    state.naming.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+    state.editor.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+    state.lint.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+    state.retention.policies.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+
+    log(&format!("Imported metadata index from store {}", index.store_id));
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,174 @@
+//! MIME types of label content.
+//!
+//! `GET /api/labels/{name}/raw` serves a label's bytes exactly as stored,
+//! with the MIME type of its detected format (see `sniff`) rather than as an
+//! opaque byte stream, so browsers and download tools know what they got.
+//! Text types name the charset the bytes are stored in, which is not
+//! necessarily UTF-8. The JSON API reports the same type as `mime_type`.
+//!
+//! `HEAD` on a label, or on its `/raw`, answers with the headers `/raw` would
+//! send and the `Content-Length` of the stored bytes but no body, so scripts
+//! can check that a label exists, how big it is and whether it changed
+//! without downloading it. It does not count as a read.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::charset::{self, Charset};
+use crate::sniff::{self, Format};
+use crate::{channels, error_response, etag, expiry, is_text_content, labelmeta, query_param, range, retention, StoreViewerState};
+
+/// MIME type of a format, without parameters.
+pub fn essence(format: Format) -> &'static str {
+    match format {
+        Format::Json => "application/json",
+        Format::Yaml => "application/yaml",
+        Format::Toml => "application/toml",
+        Format::Markdown => "text/markdown",
+        Format::Html => "text/html",
+        Format::Xml => "application/xml",
+        Format::Svg => "image/svg+xml",
+        Format::Csv => "text/csv",
+        Format::Text => "text/plain",
+        Format::Wasm => "application/wasm",
+        Format::Png => "image/png",
+        Format::Jpeg => "image/jpeg",
+        Format::Gif => "image/gif",
+        Format::Webp => "image/webp",
+        Format::Bmp => "image/bmp",
+        Format::Ico => "image/vnd.microsoft.icon",
+        Format::Pdf => "application/pdf",
+        Format::Mp3 => "audio/mpeg",
+        Format::Mp4 => "video/mp4",
+        Format::Ogg => "application/ogg",
+        Format::Woff => "font/woff",
+        Format::Woff2 => "font/woff2",
+        Format::Sqlite => "application/vnd.sqlite3",
+        Format::Zip => "application/zip",
+        Format::Tar => "application/x-tar",
+        Format::TarGz | Format::Gzip => "application/gzip",
+        Format::Binary => "application/octet-stream",
+    }
+}
+
+fn with_charset(format: Format, charset: Option<Charset>) -> String {
+    match charset {
+        Some(charset) if format.is_text() => format!("{}; charset={}", essence(format), charset.name()),
+        _ => essence(format).to_string(),
+    }
+}
+
+/// `Content-Type` of the stored bytes of `label`.
+pub fn content_type(state: &StoreViewerState, label: &str, content: &[u8]) -> String {
+    let text = labelmeta::decode_text(state, label, content);
+    let format = sniff::detect(label, content, text.as_deref());
+    let charset = text.as_ref().and_then(|_| labelmeta::charset_of(state, label, content));
+    with_charset(format, charset)
+}
+
+/// `Content-Type` of bytes that have a name but no label of their own, such
+/// as archive members.
+pub fn content_type_of(name: &str, content: &[u8]) -> String {
+    let charset = charset::detect(content);
+    let text = charset
+        .and_then(|charset| charset.decode(content))
+        .filter(|text| is_text_content(text.as_bytes()));
+    let format = sniff::detect(name, content, text.as_deref());
+    with_charset(format, charset.filter(|_| text.is_some()))
+}
+
+/// `Content-Disposition` that makes browsers save a label as a file named
+/// after the last segment of the label's name. Names that are not plain
+/// ASCII get an RFC 5987 `filename*` with an ASCII fallback.
+pub fn attachment(label: &str) -> String {
+    let name = label.rsplit('/').find(|segment| !segment.is_empty()).unwrap_or("download");
+    let fallback: String = name
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    if fallback == name {
+        return format!("attachment; filename=\"{}\"", name);
+    }
+    let encoded: String = name
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
+
+/// `GET /api/labels/{name}/raw`, whole or in part (see `range`); with
+/// `?download=true` as an attachment
+pub fn handle_raw(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Serving raw label: {}", label_name));
+
+    let content_ref = match channels::resolve(state, label_name, req) {
+        Ok(content_ref) => content_ref,
+        Err((500, e)) => return Err(e),
+        Err((status, e)) => return Ok(error_response(status, &e)),
+    };
+    let etag = etag::for_ref(&content_ref.hash);
+    state.stats.record_read(label_name, state.clock_ms);
+    let expires = retention::expires_at(state, label_name).map(expiry::header);
+    if etag::none_match(req, &etag) {
+        let mut response = etag::not_modified(etag);
+        response.headers.extend(expires);
+        return Ok(response);
+    }
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    let mut headers = raw_headers(state, label_name, &etag, &content);
+    headers.extend(expires);
+    labelmeta::apply_headers(state, label_name, &mut headers);
+    // Asked for explicitly, so it wins over a recorded Content-Disposition
+    if query_param(&req.uri, "download").as_deref() == Some("true") {
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case("Content-Disposition"));
+        headers.push(("Content-Disposition".to_string(), attachment(label_name)));
+    }
+
+    Ok(range::respond(req, &etag, headers, content))
+}
+
+/// Headers describing the stored bytes of a label, before its custom ones.
+fn raw_headers(state: &StoreViewerState, label_name: &str, etag: &str, content: &[u8]) -> Vec<(String, String)> {
+    let mut headers = vec![
+        ("Content-Type".to_string(), content_type(state, label_name, content)),
+        ("ETag".to_string(), etag.to_string()),
+        // Served from the viewer's origin, so nothing in it may run there
+        ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+        ("Content-Security-Policy".to_string(), "sandbox".to_string()),
+    ];
+    if let Some(language) = labelmeta::meta_for(state, label_name).and_then(|m| m.language.as_ref()) {
+        headers.push(("Content-Language".to_string(), language.clone()));
+    }
+    headers
+}
+
+/// `HEAD /api/labels/{name}` and `HEAD /api/labels/{name}/raw`
+pub fn handle_head(state: &StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Checking label: {}", label_name));
+
+    let content_ref = match channels::resolve(state, label_name, req) {
+        Ok(content_ref) => content_ref,
+        Err((500, e)) => return Err(e),
+        Err((status, e)) => return Ok(error_response(status, &e)),
+    };
+    let etag = etag::for_ref(&content_ref.hash);
+    let expires = retention::expires_at(state, label_name).map(expiry::header);
+    if etag::none_match(req, &etag) {
+        let mut response = etag::not_modified(etag);
+        response.headers.extend(expires);
+        return Ok(response);
+    }
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    let mut headers = raw_headers(state, label_name, &etag, &content);
+    headers.extend(expires);
+    labelmeta::apply_headers(state, label_name, &mut headers);
+    headers.push(("Content-Length".to_string(), content.len().to_string()));
+    headers.push(("Accept-Ranges".to_string(), "bytes".to_string()));
+
+    Ok(HttpResponse { status: 200, headers, body: None })
+}
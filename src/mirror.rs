@@ -0,0 +1,284 @@
+//! Write-through mirroring to a secondary store.
+//!
+//! With a mirror configured (`mirror` at init, or `POST /api/admin/mirror`),
+//! every change to a label of the viewer's own store is applied to the
+//! secondary store as it happens, giving a hot standby without a separate
+//! sync job. `prefixes` limits mirroring to the labels that matter. Mirroring
+//! is best effort: a change that cannot be applied is queued, one entry per
+//! label holding its latest change, and retried by the task runner. The lag
+//! is the age of the oldest change still queued.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::eventlog::Op;
+use crate::{error_response, json_response, stores, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Least time between two retries of the queue.
+const RETRY_INTERVAL_MS: u64 = 30 * 1000;
+
+/// Labels queued at most; changes beyond this are dropped and counted, and
+/// the mirror needs a full copy to catch up.
+const MAX_PENDING: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    /// Store every change is also applied to
+    pub store_id: String,
+    /// Only mirror labels under these prefixes; all labels when empty
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+}
+
+impl MirrorConfig {
+    pub fn validate(&self, home: &str) -> Result<(), String> {
+        if self.store_id.is_empty() {
+            return Err("mirror store_id cannot be empty".to_string());
+        }
+        if self.store_id == home {
+            return Err("A store cannot mirror to itself".to_string());
+        }
+        Ok(())
+    }
+
+    fn covers(&self, label: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|prefix| label.starts_with(prefix.as_str()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PendingChange {
+    op: Op,
+    /// Ref to copy for a write
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    content_ref: Option<String>,
+    /// When the oldest change not yet mirrored was made
+    since: u64,
+    attempts: u32,
+    last_error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Mirror {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<MirrorConfig>,
+    /// Changes still to apply, by label
+    #[serde(default)]
+    pending: BTreeMap<String, PendingChange>,
+    #[serde(default)]
+    mirrored: u64,
+    #[serde(default)]
+    last_mirrored_at: Option<u64>,
+    /// Changes dropped because the queue was full
+    #[serde(default)]
+    dropped: u64,
+    #[serde(default)]
+    next_retry_at: u64,
+}
+
+impl Mirror {
+    pub fn new(config: Option<MirrorConfig>) -> Self {
+        Mirror { config, ..Mirror::default() }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigRequest {
+    /// `null` turns mirroring off
+    store_id: Option<String>,
+    #[serde(default)]
+    prefixes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct PendingStatus<'a> {
+    label: &'a str,
+    #[serde(flatten)]
+    change: &'a PendingChange,
+}
+
+#[derive(Serialize)]
+struct MirrorStatus<'a> {
+    enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store_id: Option<&'a str>,
+    prefixes: &'a [String],
+    mirrored: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_mirrored_at: Option<u64>,
+    /// Milliseconds since the oldest change still queued; 0 when caught up
+    lag_ms: u64,
+    dropped: u64,
+    pending: Vec<PendingStatus<'a>>,
+}
+
+/// Apply one change to the mirror store.
+fn apply(home: &str, mirror: &str, label: &str, op: Op, content_ref: Option<&str>) -> Result<(), String> {
+    match (op, content_ref) {
+        (Op::Write, Some(hash)) => {
+            let content = store::get(home, &ContentRef { hash: hash.to_string() })?;
+            store::store_at_label(mirror, label, &content)?;
+        }
+        _ => {
+            if store::get_by_label(mirror, label)?.is_some() {
+                store::remove_label(mirror, label)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn queue(state: &mut StoreViewerState, label: &str, op: Op, content_ref: Option<String>, error: String) {
+    let now = state.clock_ms;
+    let mirror = &mut state.mirror;
+    let full = mirror.pending.len() >= MAX_PENDING;
+    match mirror.pending.get_mut(label) {
+        Some(pending) => {
+            pending.op = op;
+            pending.content_ref = content_ref;
+            pending.last_error = error;
+        }
+        None if full => {
+            mirror.dropped += 1;
+        }
+        None => {
+            mirror.pending.insert(label.to_string(), PendingChange {
+                op,
+                content_ref,
+                since: now,
+                attempts: 1,
+                last_error: error,
+            });
+        }
+    }
+}
+
+/// Mirror a change to `label`, or queue it when the mirror cannot take it.
+/// Changes to other stores and to the viewer's own bookkeeping are not
+/// mirrored.
+pub fn record(state: &mut StoreViewerState, op: Op, label: &str, content_ref: Option<&ContentRef>) {
+    let target = match &state.mirror.config {
+        Some(config) if config.covers(label) => config.store_id.clone(),
+        _ => return,
+    };
+    if !stores::is_home(state) || label.starts_with(SYSTEM_LABEL_PREFIX) {
+        return;
+    }
+    let content_ref = content_ref.map(|r| r.hash.clone());
+    match apply(&state.store_id, &target, label, op, content_ref.as_deref()) {
+        Ok(()) => {
+            state.mirror.pending.remove(label);
+            state.mirror.mirrored += 1;
+            state.mirror.last_mirrored_at = Some(state.clock_ms);
+        }
+        Err(e) => {
+            log(&format!("Error mirroring {} to {}: {}", label, target, e));
+            queue(state, label, op, content_ref, e);
+        }
+    }
+}
+
+/// Retry queued changes, at most once per `RETRY_INTERVAL_MS`; called by the
+/// task runner. Returns how many were applied.
+pub fn run_due(state: &mut StoreViewerState) -> usize {
+    let target = match &state.mirror.config {
+        Some(config) => config.store_id.clone(),
+        None => return 0,
+    };
+    if state.mirror.pending.is_empty() || state.clock_ms < state.mirror.next_retry_at {
+        return 0;
+    }
+    state.mirror.next_retry_at = state.clock_ms + RETRY_INTERVAL_MS;
+
+    let mut applied = 0;
+    let mut pending = std::mem::take(&mut state.mirror.pending);
+    pending.retain(|label, change| {
+        match apply(&state.store_id, &target, label, change.op, change.content_ref.as_deref()) {
+            Ok(()) => {
+                applied += 1;
+                false
+            }
+            Err(e) => {
+                change.attempts += 1;
+                change.last_error = e;
+                true
+            }
+        }
+    });
+    state.mirror.pending = pending;
+    if applied > 0 {
+        state.mirror.mirrored += applied as u64;
+        state.mirror.last_mirrored_at = Some(state.clock_ms);
+    }
+    applied
+}
+
+/// `GET /api/admin/mirror`
+pub fn handle_status(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting mirror status");
+
+    let mirror = &state.mirror;
+    let oldest = mirror.pending.values().map(|pending| pending.since).min();
+    let status = MirrorStatus {
+        enabled: mirror.config.is_some(),
+        store_id: mirror.config.as_ref().map(|config| config.store_id.as_str()),
+        prefixes: mirror.config.as_ref().map_or(&[], |config| config.prefixes.as_slice()),
+        mirrored: mirror.mirrored,
+        last_mirrored_at: mirror.last_mirrored_at,
+        lag_ms: oldest.map_or(0, |since| state.clock_ms.saturating_sub(since)),
+        dropped: mirror.dropped,
+        pending: mirror
+            .pending
+            .iter()
+            .map(|(label, change)| PendingStatus { label, change })
+            .collect(),
+    };
+
+    let body = serde_json::to_vec(&status)
+        .map_err(|e| format!("Failed to serialize mirror status: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/admin/mirror` with `{ "store_id", "prefixes"? }`
+pub fn handle_configure(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Configuring mirror");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let request: ConfigRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    let config = match request.store_id {
+        Some(store_id) => {
+            let config = MirrorConfig { store_id, prefixes: request.prefixes };
+            if let Err(e) = config.validate(&state.store_id) {
+                return Ok(error_response(400, &e));
+            }
+            if let Err(e) = store::list_labels(&config.store_id) {
+                return Ok(error_response(400, &format!("Cannot open store {}: {}", config.store_id, e)));
+            }
+            Some(config)
+        }
+        None => None,
+    };
+
+    // Queued changes belong to the previous mirror
+    let changed = state.mirror.config.as_ref().map(|c| &c.store_id) != config.as_ref().map(|c| &c.store_id);
+    match &config {
+        Some(config) => log(&format!("Mirroring to store {}", config.store_id)),
+        None => log("Mirroring disabled"),
+    }
+    if changed {
+        state.mirror = Mirror::new(config);
+    } else {
+        state.mirror.config = config;
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,144 @@
+//! Label naming conventions.
+//!
+//! A naming rule constrains the names of new labels under a prefix: a regular
+//! expression the whole name must match, the file extensions it may end in,
+//! and how many `/`-separated segments it may have below the prefix. As with
+//! schemas, the rule with the longest matching prefix applies. Rules are
+//! checked when a label is created or renamed; existing labels keep their
+//! names.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{error_response, json_response, longest_prefix_match, StoreViewerState};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NamingRule {
+    pub prefix: String,
+    /// Regular expression the whole label name must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Allowed endings of the name, e.g. `.json`; any when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<String>,
+    /// Most `/`-separated segments allowed after the prefix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    /// Explanation shown along with violations, e.g. the convention's intent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl NamingRule {
+    fn regex(&self) -> Result<Option<Regex>, String> {
+        self.pattern
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(&format!("^(?:{})$", pattern))
+                    .map_err(|e| format!("Invalid pattern {}: {}", pattern, e))
+            })
+            .transpose()
+    }
+}
+
+pub fn rule_for<'a>(rules: &'a [NamingRule], label: &str) -> Option<&'a NamingRule> {
+    longest_prefix_match(rules, label, |rule| &rule.prefix)
+}
+
+/// Check the name of a label about to be created; returns every convention
+/// the name breaks.
+pub fn check_name(rules: &[NamingRule], label: &str) -> Result<(), String> {
+    let rule = match rule_for(rules, label) {
+        Some(rule) => rule,
+        None => return Ok(()),
+    };
+
+    let mut problems = Vec::new();
+    if let Some(regex) = rule.regex()? {
+        if !regex.is_match(label) {
+            problems.push(format!("must match the pattern {}", rule.pattern.as_deref().unwrap_or_default()));
+        }
+    }
+    if !rule.extensions.is_empty() && !rule.extensions.iter().any(|ext| label.ends_with(ext.as_str())) {
+        problems.push(format!("must end in one of {}", rule.extensions.join(", ")));
+    }
+    if let Some(max_depth) = rule.max_depth {
+        let depth = label[rule.prefix.len()..].split('/').filter(|s| !s.is_empty()).count();
+        if depth > max_depth {
+            problems.push(format!(
+                "may have at most {} path segments after '{}' (has {})",
+                max_depth, rule.prefix, depth
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let mut message = format!(
+        "Label name {} breaks the naming rule for prefix '{}': it {}",
+        label,
+        rule.prefix,
+        problems.join("; it ")
+    );
+    if let Some(description) = &rule.description {
+        message.push_str(&format!(" ({})", description));
+    }
+    Err(message)
+}
+
+pub fn validate_rule(rule: &NamingRule) -> Result<(), String> {
+    if rule.pattern.is_none() && rule.extensions.is_empty() && rule.max_depth.is_none() {
+        return Err("A naming rule needs a pattern, extensions or max_depth".to_string());
+    }
+    if rule.extensions.iter().any(String::is_empty) {
+        return Err("Extensions cannot be empty".to_string());
+    }
+    rule.regex().map(|_| ())
+}
+
+pub fn handle_list_rules(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing naming rules");
+
+    let body = serde_json::to_vec(&state.naming)
+        .map_err(|e| format!("Failed to serialize naming rules: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_put_rule(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving naming rule");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let rule: NamingRule = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err(e) = validate_rule(&rule) {
+        return Ok(error_response(400, &e));
+    }
+
+    log(&format!("Saved naming rule for prefix: {}", rule.prefix));
+
+    // Saving a prefix again replaces its previous rule
+    state.naming.retain(|existing| existing.prefix != rule.prefix);
+    state.naming.push(rule);
+    state.naming.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+pub fn handle_delete_rule(state: &mut StoreViewerState, prefix: &str) -> Result<HttpResponse, String> {
+    log(&format!("Deleting naming rule for prefix: {}", prefix));
+
+    let before = state.naming.len();
+    state.naming.retain(|rule| rule.prefix != prefix);
+    if state.naming.len() == before {
+        return Ok(error_response(404, &format!("No naming rule for prefix: {}", prefix)));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,105 @@
+//! Routing of change notifications.
+//!
+//! Label changes are pushed to the clients of the event WebSocket (see
+//! `events`). A routing rule sends the changes under a prefix to a topic, so
+//! a deployment dashboard can hear about `configs/prod/` without the churn of
+//! `scratch/`: a client sends `{ "type": "subscribe", "topics": [...] }` and
+//! from then on receives only the changes routed to those topics. As with
+//! naming rules, the rule with the longest matching prefix applies, and is
+//! looked up for every change; changes no rule matches go to the `default`
+//! topic. Clients that never subscribe receive every change, as before.
+//!
+//! The actor has no outbound HTTP and cannot message other actors, so a topic
+//! is something WebSocket clients subscribe to rather than a webhook URL.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{error_response, json_response, longest_prefix_match, StoreViewerState};
+use serde::{Deserialize, Serialize};
+
+/// Topic of the changes no rule routes.
+pub const DEFAULT_TOPIC: &str = "default";
+
+/// Longest topic name allowed.
+const MAX_TOPIC_LEN: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NotifyRule {
+    pub prefix: String,
+    /// Topic the changes under the prefix are sent to
+    pub topic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Topic the rules route a change of `label` to, if one does.
+pub fn topic_for<'a>(rules: &'a [NotifyRule], label: &str) -> Option<&'a str> {
+    longest_prefix_match(rules, label, |rule| &rule.prefix).map(|rule| rule.topic.as_str())
+}
+
+/// Check a topic name given by a rule or a subscribing client.
+pub fn validate_topic(topic: &str) -> Result<(), String> {
+    if topic.is_empty() || topic.len() > MAX_TOPIC_LEN {
+        return Err(format!("A topic must be 1 to {} bytes", MAX_TOPIC_LEN));
+    }
+    if !topic.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.')) {
+        return Err(format!("Invalid topic {}: use letters, digits, -, _ and .", topic));
+    }
+    Ok(())
+}
+
+fn validate_rule(rule: &NotifyRule) -> Result<(), String> {
+    if rule.prefix.is_empty() {
+        return Err(format!("A rule needs a prefix; unrouted changes already go to {}", DEFAULT_TOPIC));
+    }
+    validate_topic(&rule.topic)
+}
+
+/// `GET /api/notifications`
+pub fn handle_list_rules(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing notification rules");
+
+    let body = serde_json::to_vec(&state.notify)
+        .map_err(|e| format!("Failed to serialize notification rules: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/notifications` with `{ "prefix", "topic", "description"? }`
+pub fn handle_put_rule(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving notification rule");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let rule: NotifyRule = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err(e) = validate_rule(&rule) {
+        return Ok(error_response(400, &e));
+    }
+
+    log(&format!("Routing changes under {} to {}", rule.prefix, rule.topic));
+
+    // Saving a prefix again replaces its previous rule
+    state.notify.retain(|existing| existing.prefix != rule.prefix);
+    state.notify.push(rule);
+    state.notify.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+/// `DELETE /api/notifications?prefix=`
+pub fn handle_delete_rule(state: &mut StoreViewerState, prefix: &str) -> Result<HttpResponse, String> {
+    log(&format!("Deleting notification rule for prefix: {}", prefix));
+
+    let before = state.notify.len();
+    state.notify.retain(|rule| rule.prefix != prefix);
+    if state.notify.len() == before {
+        return Ok(error_response(404, &format!("No notification rule for prefix: {}", prefix)));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,129 @@
+//! OpenAPI description of the HTTP API.
+//!
+//! `GET /api/openapi.json` is an OpenAPI 3 document built from the endpoint
+//! table in `discovery`, the same one `OPTIONS`, `GET /api` and the API
+//! explorer read and `startup` checks against the registered routes, so it
+//! cannot fall behind what the viewer serves. Clients can be generated from
+//! it and it loads in standard tooling.
+//!
+//! The table has no response schemas, so each operation documents its
+//! success without one and its failures as `{ "error" }`. A placeholder
+//! such as `{name}` may span several `/`-separated segments, which OpenAPI
+//! cannot express: such parameters say that `/` must be sent encoded as
+//! `%2F`.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::discovery::{self, Endpoint, ENDPOINTS};
+use crate::{auth, json_response, StoreViewerState};
+use serde_json::{json, Map, Value};
+
+const OPENAPI_VERSION: &str = "3.0.3";
+
+/// Name of the bearer token scheme in `components`.
+const TOKEN_SCHEME: &str = "token";
+
+/// Placeholders in a path pattern, in order.
+fn path_params(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{').and_then(|segment| segment.strip_suffix('}')))
+}
+
+fn parameters(endpoint: &Endpoint) -> Vec<Value> {
+    let in_path = path_params(endpoint.path).map(|name| {
+        json!({
+            "name": name,
+            "in": "path",
+            "required": true,
+            "description": "May contain `/`, sent as `%2F`",
+            "schema": { "type": "string" },
+        })
+    });
+    let in_query = endpoint.params.iter().map(|&(name, description)| {
+        json!({
+            "name": name,
+            "in": "query",
+            "description": description,
+            "schema": { "type": "string" },
+        })
+    });
+    in_path.chain(in_query).collect()
+}
+
+fn operation(state: &StoreViewerState, endpoint: &Endpoint) -> Value {
+    let mut operation = Map::new();
+    operation.insert("summary".to_string(), json!(endpoint.summary));
+    let parameters = parameters(endpoint);
+    if !parameters.is_empty() {
+        operation.insert("parameters".to_string(), Value::Array(parameters));
+    }
+    let accepts = discovery::accepts(endpoint);
+    if !accepts.is_empty() {
+        let content: Map<String, Value> = accepts
+            .iter()
+            .map(|content_type| (content_type.to_string(), json!({ "schema": {} })))
+            .collect();
+        operation.insert("requestBody".to_string(), json!({ "content": content }));
+    }
+    operation.insert(
+        "responses".to_string(),
+        json!({
+            "200": { "description": "Success" },
+            "default": {
+                "description": "Failure",
+                "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } },
+            },
+        }),
+    );
+    if state.auth.token.is_some() && auth::OPEN_PATHS.contains(&endpoint.path) {
+        operation.insert("security".to_string(), json!([]));
+    }
+    Value::Object(operation)
+}
+
+/// The OpenAPI document for every endpoint in the table.
+fn document(state: &StoreViewerState) -> Value {
+    let mut paths = Map::new();
+    for endpoint in ENDPOINTS {
+        let item = paths
+            .entry(endpoint.path.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(item) = item {
+            item.insert(endpoint.method.to_lowercase(), operation(state, endpoint));
+        }
+    }
+
+    let mut document = json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": "Store Viewer API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": {
+                "Error": {
+                    "type": "object",
+                    "properties": { "error": { "type": "string" } },
+                    "required": ["error"],
+                },
+            },
+        },
+    });
+    // Instances without a token accept every request as it is
+    if state.auth.token.is_some() {
+        document["components"]["securitySchemes"] = json!({ TOKEN_SCHEME: { "type": "http", "scheme": "bearer" } });
+        document["security"] = json!([{ TOKEN_SCHEME: [] }]);
+    }
+    document
+}
+
+/// `GET /api/openapi.json`
+pub fn handle_openapi(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Generating OpenAPI document");
+
+    let body = serde_json::to_vec(&document(state))
+        .map_err(|e| format!("Failed to serialize OpenAPI document: {}", e))?;
+
+    Ok(json_response(200, body))
+}
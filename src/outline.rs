@@ -0,0 +1,157 @@
+//! Structure outline of JSON labels.
+//!
+//! Large JSON documents are expensive to ship and render whole. The outline
+//! describes the key tree down to a requested depth, giving each value's type
+//! and size but not the value itself, and every node carries the path that can
+//! be passed back as `?path=` to expand it further.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, json_response, labelmeta, predicates, query_param, StoreViewerState};
+use serde::Serialize;
+use serde_json::Value;
+
+const DEFAULT_DEPTH: usize = 2;
+
+/// Children listed per object or array; the rest are only counted.
+const MAX_CHILDREN: usize = 200;
+
+#[derive(Serialize)]
+pub struct OutlineNode {
+    /// Key or array index within the parent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub path: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// Number of keys, items or characters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<usize>,
+    /// Present when the node is within the requested depth
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<OutlineNode>>,
+    /// Set when only the first `MAX_CHILDREN` children are listed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+#[derive(Serialize)]
+struct OutlineResponse {
+    name: String,
+    size_bytes: usize,
+    depth: usize,
+    outline: OutlineNode,
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Object(_) => "object",
+        Value::Array(_) => "array",
+        Value::String(_) => "string",
+        Value::Number(_) => "number",
+        Value::Bool(_) => "boolean",
+        Value::Null => "null",
+    }
+}
+
+/// Append a `.key` or `[index]` segment to a path.
+fn child_path(parent: &str, segment: &str) -> String {
+    let parent = if parent == "." { "" } else { parent };
+    format!("{}{}", parent, segment)
+}
+
+/// Outline `value`, expanding `depth` levels of children.
+pub fn outline(value: &Value, key: Option<String>, path: String, depth: usize) -> OutlineNode {
+    let length = match value {
+        Value::Object(map) => Some(map.len()),
+        Value::Array(items) => Some(items.len()),
+        Value::String(s) => Some(s.chars().count()),
+        _ => None,
+    };
+
+    let mut truncated = false;
+    let children = if depth == 0 {
+        None
+    } else {
+        match value {
+            Value::Object(map) => {
+                truncated = map.len() > MAX_CHILDREN;
+                Some(
+                    map.iter()
+                        .take(MAX_CHILDREN)
+                        .map(|(k, v)| outline(v, Some(k.clone()), child_path(&path, &format!(".{}", k)), depth - 1))
+                        .collect(),
+                )
+            }
+            Value::Array(items) => {
+                truncated = items.len() > MAX_CHILDREN;
+                Some(
+                    items
+                        .iter()
+                        .take(MAX_CHILDREN)
+                        .enumerate()
+                        .map(|(i, v)| outline(v, Some(i.to_string()), child_path(&path, &format!("[{}]", i)), depth - 1))
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    };
+
+    OutlineNode {
+        key,
+        path,
+        kind: type_name(value),
+        length,
+        children,
+        truncated,
+    }
+}
+
+/// `GET /api/labels/{name}/outline?depth=2&path=.items`
+pub fn handle_outline(state: &StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Outlining label: {}", label_name));
+
+    let depth = match query_param(&req.uri, "depth") {
+        None => DEFAULT_DEPTH,
+        Some(depth) => match depth.parse::<usize>() {
+            Ok(depth) => depth,
+            Err(_) => return Ok(error_response(400, &format!("Invalid depth: {}", depth))),
+        },
+    };
+    let path = query_param(&req.uri, "path").unwrap_or_else(|| ".".to_string());
+
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    let text = match labelmeta::decode_text(state, label_name, &content) {
+        Some(text) => text,
+        None => return Ok(error_response(415, &format!("Label {} is not JSON: binary content", label_name))),
+    };
+    let document: Value = match serde_json::from_str(&text) {
+        Ok(document) => document,
+        Err(e) => return Ok(error_response(415, &format!("Label {} is not JSON: {}", label_name, e))),
+    };
+
+    let value = match predicates::resolve(&document, &path) {
+        Ok(Some(value)) => value,
+        Ok(None) => return Ok(error_response(404, &format!("Nothing at path {} in {}", path, label_name))),
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+
+    let response = OutlineResponse {
+        name: label_name.to_string(),
+        size_bytes: content.len(),
+        depth,
+        outline: outline(value, None, path, depth),
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize outline: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,244 @@
+//! Content predicates for conditional writes.
+//!
+//! A write may carry a condition on the label's current JSON content, e.g.
+//! `.version == 3 && .meta.owner exists`, which must hold for the write to be
+//! applied. This lets automation update structured labels safely without a
+//! read-modify-write race on the whole document.
+//!
+//! Grammar:
+//!
+//! ```text
+//! condition := clause ( "&&" clause )*
+//! clause    := path op json-value | path "exists" | path "missing"
+//! path      := "." | ( "." key | "[" index "]" )+
+//! op        := "==" | "!=" | "<" | "<=" | ">" | ">="
+//! ```
+
+use serde_json::Value;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Clause {
+    Compare(Vec<Segment>, Op, Value),
+    Exists(Vec<Segment>),
+    Missing(Vec<Segment>),
+}
+
+/// A parsed condition: every clause must hold.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    source: String,
+    clauses: Vec<Clause>,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let clauses = split_clauses(source)
+            .into_iter()
+            .map(|clause| parse_clause(clause.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        if clauses.is_empty() {
+            return Err("Condition is empty".to_string());
+        }
+        Ok(Condition { source: source.trim().to_string(), clauses })
+    }
+
+    /// Check the condition against a document; on failure, explain which
+    /// clause did not hold.
+    pub fn evaluate(&self, document: &Value) -> Result<(), String> {
+        for clause in &self.clauses {
+            let holds = match clause {
+                Clause::Exists(path) => lookup(document, path).is_some(),
+                Clause::Missing(path) => lookup(document, path).is_none(),
+                Clause::Compare(path, op, expected) => match lookup(document, path) {
+                    Some(actual) => compare(actual, *op, expected),
+                    None => false,
+                },
+            };
+            if !holds {
+                return Err(format!("Condition not met: {}", describe(clause)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+/// Split on `&&` that appear outside of string literals.
+fn split_clauses(source: &str) -> Vec<&str> {
+    let mut clauses = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    let bytes = source.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+            }
+        } else if b == b'"' {
+            in_string = true;
+        } else if b == b'&' && bytes.get(i + 1) == Some(&b'&') {
+            clauses.push(&source[start..i]);
+            start = i + 2;
+            i += 1;
+        }
+        i += 1;
+    }
+    if !source[start..].trim().is_empty() || !clauses.is_empty() {
+        clauses.push(&source[start..]);
+    }
+    clauses
+}
+
+fn parse_clause(clause: &str) -> Result<Clause, String> {
+    let path_end = clause
+        .find(|c: char| c.is_whitespace() || "=!<>".contains(c))
+        .ok_or_else(|| format!("Incomplete clause: {}", clause))?;
+    let path = parse_path(&clause[..path_end])?;
+    let rest = clause[path_end..].trim_start();
+
+    if rest == "exists" {
+        return Ok(Clause::Exists(path));
+    }
+    if rest == "missing" {
+        return Ok(Clause::Missing(path));
+    }
+
+    let (op, value_str) = [
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ]
+    .iter()
+    .find_map(|(token, op)| rest.strip_prefix(token).map(|v| (*op, v)))
+    .ok_or_else(|| format!("Unknown operator in clause: {}", clause))?;
+
+    let value: Value = serde_json::from_str(value_str.trim())
+        .map_err(|_| format!("Expected a JSON value in clause: {}", clause))?;
+
+    Ok(Clause::Compare(path, op, value))
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    if !path.starts_with('.') && !path.starts_with('[') {
+        return Err(format!("Path must start with '.': {}", path));
+    }
+    if path == "." {
+        return Ok(Vec::new());
+    }
+
+    let mut segments = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let (index, tail) = after
+                .split_once(']')
+                .ok_or_else(|| format!("Unclosed '[' in path: {}", path))?;
+            let index = index
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid array index in path: {}", path))?;
+            segments.push(Segment::Index(index));
+            rest = tail;
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(format!("Empty key in path: {}", path));
+            }
+            segments.push(Segment::Key(after[..end].to_string()));
+            rest = &after[end..];
+        } else {
+            return Err(format!("Invalid path: {}", path));
+        }
+    }
+    Ok(segments)
+}
+
+/// Resolve a path in the predicate syntax (e.g. `.items[0].name`) against a
+/// document; `Ok(None)` when nothing is at that path.
+pub fn resolve<'a>(document: &'a Value, path: &str) -> Result<Option<&'a Value>, String> {
+    Ok(lookup(document, &parse_path(path)?))
+}
+
+fn lookup<'a>(document: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter().try_fold(document, |value, segment| match segment {
+        Segment::Key(key) => value.get(key),
+        Segment::Index(index) => value.get(*index),
+    })
+}
+
+fn compare(actual: &Value, op: Op, expected: &Value) -> bool {
+    let ordering = match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+
+    match op {
+        Op::Eq => ordering.map_or(actual == expected, |o| o == Ordering::Equal),
+        Op::Ne => ordering.map_or(actual != expected, |o| o != Ordering::Equal),
+        Op::Lt => ordering == Some(Ordering::Less),
+        Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        Op::Gt => ordering == Some(Ordering::Greater),
+        Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+    }
+}
+
+fn describe(clause: &Clause) -> String {
+    let path = |segments: &[Segment]| {
+        if segments.is_empty() {
+            return ".".to_string();
+        }
+        segments
+            .iter()
+            .map(|s| match s {
+                Segment::Key(k) => format!(".{}", k),
+                Segment::Index(i) => format!("[{}]", i),
+            })
+            .collect::<String>()
+    };
+
+    match clause {
+        Clause::Exists(p) => format!("{} exists", path(p)),
+        Clause::Missing(p) => format!("{} missing", path(p)),
+        Clause::Compare(p, op, value) => {
+            let op = match op {
+                Op::Eq => "==",
+                Op::Ne => "!=",
+                Op::Lt => "<",
+                Op::Le => "<=",
+                Op::Gt => ">",
+                Op::Ge => ">=",
+            };
+            format!("{} {} {}", path(p), op, value)
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Promotion of labels between environment prefixes.
+//!
+//! A promotion copies labels from one prefix to another (`staging/` to
+//! `prod/`), keeping the part of the name after the prefix. Every target write
+//! is validated before anything is written and the writes share one journal
+//! entry, so a promotion is applied either completely or not at all, and a
+//! record of what changed in each target label is kept for later review.
+
+use crate::bindiff::{self, BinaryDiff};
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::dryrun::{self, ChangeAction};
+use crate::journal::{self, Step};
+use crate::{error_response, json_response, validate_write, write_journaled, StoreViewerState};
+use serde::{Deserialize, Serialize};
+
+/// Promotion records kept in the state; older ones are dropped.
+const MAX_RECORDS: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PromotedLabel {
+    pub source: String,
+    pub target: String,
+    pub action: ChangeAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_ref: Option<String>,
+    pub new_ref: String,
+    /// What changed in an overwritten target
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<BinaryDiff>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Promotion {
+    pub id: u64,
+    pub from: String,
+    pub to: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub promoted_at: u64,
+    pub labels: Vec<PromotedLabel>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Promotions {
+    next_id: u64,
+    records: Vec<Promotion>,
+}
+
+#[derive(Deserialize)]
+struct PromoteRequest {
+    from: String,
+    to: String,
+    /// Names relative to `from`; every label under `from` when omitted
+    #[serde(default)]
+    labels: Option<Vec<String>>,
+    #[serde(default)]
+    note: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PromotionSummary<'a> {
+    id: u64,
+    from: &'a str,
+    to: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<&'a str>,
+    promoted_at: u64,
+    created: usize,
+    overwritten: usize,
+    unchanged: usize,
+}
+
+pub fn handle_promote(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Promoting labels");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let promote_req: PromoteRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if promote_req.from.is_empty() || promote_req.to.is_empty() {
+        return Ok(error_response(400, "Both from and to prefixes are required"));
+    }
+    if promote_req.from == promote_req.to {
+        return Ok(error_response(400, "from and to must be different prefixes"));
+    }
+
+    let available: Vec<String> = store::list_labels(&state.store_id)?
+        .into_iter()
+        .filter_map(|label| label.strip_prefix(&promote_req.from).map(str::to_string))
+        .collect();
+
+    let names = match promote_req.labels {
+        Some(names) => {
+            let missing: Vec<&str> = names
+                .iter()
+                .filter(|name| !available.contains(name))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                return Ok(error_response(404, &format!(
+                    "Labels not found under {}: {}",
+                    promote_req.from,
+                    missing.join(", ")
+                )));
+            }
+            names
+        }
+        None => available,
+    };
+    if names.is_empty() {
+        return Ok(error_response(404, &format!("No labels under {}", promote_req.from)));
+    }
+
+    // Read and validate everything before the first write
+    let mut copies = Vec::with_capacity(names.len());
+    let mut rejected = Vec::new();
+    for name in names {
+        let source = format!("{}{}", promote_req.from, name);
+        let target = format!("{}{}", promote_req.to, name);
+        let source_ref = store::get_by_label(&state.store_id, &source)?
+            .ok_or_else(|| format!("Label not found: {}", source))?;
+        let content = store::get(&state.store_id, &source_ref)?;
+        if let Err((_, e)) = validate_write(state, &target, &content) {
+            rejected.push(e);
+        }
+        copies.push((source, target, content));
+    }
+    if !rejected.is_empty() {
+        return Ok(error_response(422, &format!("Promotion rejected: {}", rejected.join("; "))));
+    }
+
+    let mut planned = Vec::with_capacity(copies.len());
+    for (_, target, content) in &copies {
+        planned.push(dryrun::plan_write(&state.store_id, target, content)?);
+    }
+    if dryrun::is_dry_run(req) {
+        return dryrun::dry_run_response(&planned);
+    }
+
+    let mut steps = Vec::new();
+    for ((_, target, content), plan) in copies.iter().zip(&planned) {
+        if plan.action != ChangeAction::Unchanged {
+            steps.push(Step::write(&state.store_id, target, content)?);
+        }
+    }
+    let journal_id = journal::begin(state, "promote", steps)?;
+
+    let mut labels = Vec::with_capacity(copies.len());
+    for ((source, target, content), plan) in copies.into_iter().zip(planned) {
+        let (new_ref, diff) = match plan.action {
+            ChangeAction::Unchanged => (plan.previous_ref.clone().unwrap_or_default(), None),
+            _ => {
+                let diff = match &plan.previous_ref {
+                    Some(hash) => {
+                        let old = store::get(&state.store_id, &ContentRef { hash: hash.clone() })?;
+                        Some(bindiff::diff(&old, &content))
+                    }
+                    None => None,
+                };
+                let new_ref = write_journaled(state, &target, &content)?;
+                (new_ref.hash, diff)
+            }
+        };
+        labels.push(PromotedLabel {
+            source,
+            target,
+            action: plan.action,
+            previous_ref: plan.previous_ref,
+            new_ref,
+            diff,
+        });
+    }
+    journal::complete(state, journal_id)?;
+
+    let promotions = &mut state.promotions;
+    let promotion = Promotion {
+        id: promotions.next_id,
+        from: promote_req.from,
+        to: promote_req.to,
+        note: promote_req.note,
+        promoted_at: state.clock_ms,
+        labels,
+    };
+    promotions.next_id += 1;
+
+    log(&format!(
+        "Promotion {} copied {} labels from {} to {}",
+        promotion.id,
+        promotion.labels.len(),
+        promotion.from,
+        promotion.to
+    ));
+
+    let body = serde_json::to_vec(&promotion)
+        .map_err(|e| format!("Failed to serialize promotion: {}", e))?;
+
+    promotions.records.push(promotion);
+    let excess = promotions.records.len().saturating_sub(MAX_RECORDS);
+    promotions.records.drain(..excess);
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_list_promotions(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing promotions");
+
+    let count = |p: &Promotion, action: ChangeAction| p.labels.iter().filter(|l| l.action == action).count();
+    let summaries: Vec<PromotionSummary> = state
+        .promotions
+        .records
+        .iter()
+        .rev()
+        .map(|p| PromotionSummary {
+            id: p.id,
+            from: &p.from,
+            to: &p.to,
+            note: p.note.as_deref(),
+            promoted_at: p.promoted_at,
+            created: count(p, ChangeAction::Create),
+            overwritten: count(p, ChangeAction::Overwrite),
+            unchanged: count(p, ChangeAction::Unchanged),
+        })
+        .collect();
+
+    let body = serde_json::to_vec(&summaries)
+        .map_err(|e| format!("Failed to serialize promotions: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_get_promotion(state: &StoreViewerState, id: &str) -> Result<HttpResponse, String> {
+    log(&format!("Getting promotion: {}", id));
+
+    let promotion = id
+        .parse::<u64>()
+        .ok()
+        .and_then(|id| state.promotions.records.iter().find(|p| p.id == id));
+
+    match promotion {
+        Some(promotion) => {
+            let body = serde_json::to_vec(promotion)
+                .map_err(|e| format!("Failed to serialize promotion: {}", e))?;
+            Ok(json_response(200, body))
+        }
+        None => Ok(error_response(404, &format!("Promotion not found: {}", id))),
+    }
+}
@@ -0,0 +1,422 @@
+//! Daily request and byte quotas per API key.
+//!
+//! Clients identify themselves with an API key in `X-API-Key` (or as a bearer
+//! token). Only keys an admin has given a quota are counted on their own;
+//! requests presenting the API token are counted under `token`, and all
+//! others under `anonymous`. Keys are kept and reported only as a hash, so
+//! neither the state nor the admin endpoints reveal a credential. Every API
+//! request counts against the key's daily request quota, and the request and
+//! response bodies against its daily byte quota. A key without its own quota
+//! falls back to the default quota, if one is set. Once a quota is used up
+//! the key gets `429` until the next UTC day; every counted response carries
+//! `X-RateLimit-*` headers with the key's remaining budget. Admin endpoints
+//! are neither counted nor blocked, so a locked-out operator can always lift
+//! a quota.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{auth, error_response, header, json_response, query_param, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Key used for requests that carry no API key.
+const ANONYMOUS: &str = "anonymous";
+
+/// Key used for requests that present the API token.
+const AUTHENTICATED: &str = "token";
+
+/// Start of the hash a key is kept and reported as.
+const KEY_ID_PREFIX: &str = "key:";
+
+/// Bytes of a key's digest kept in its id.
+const KEY_ID_BYTES: usize = 16;
+
+/// Days of past usage kept per key for the report.
+const HISTORY_DAYS: usize = 30;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Quota {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_requests: Option<u64>,
+    /// Request plus response body bytes per day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DayUsage {
+    /// Start of the UTC day, in epoch milliseconds
+    pub day: u64,
+    pub requests: u64,
+    pub bytes: u64,
+    /// Requests rejected for being over quota
+    #[serde(default)]
+    pub rejected: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Quotas {
+    /// Quota for keys without their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Quota>,
+    /// Quotas by key hash
+    #[serde(default, deserialize_with = "hashed_keys")]
+    pub keys: BTreeMap<String, Quota>,
+    /// Usage per key hash, `token` or `anonymous`, oldest day first
+    #[serde(default, deserialize_with = "counted_usage")]
+    usage: BTreeMap<String, Vec<DayUsage>>,
+}
+
+/// The hash an API key is kept and reported as: the first 128 bits of its
+/// SHA-256 digest. Recovering a key from it means guessing the key, so listing
+/// it reveals nothing a client could present.
+fn key_id(key: &str) -> String {
+    let hex: String = sha256(key.as_bytes())[..KEY_ID_BYTES].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", KEY_ID_PREFIX, hex)
+}
+
+fn is_key_id(value: &str) -> bool {
+    value
+        .strip_prefix(KEY_ID_PREFIX)
+        .is_some_and(|hash| hash.len() == 2 * KEY_ID_BYTES && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// SHA-256 round constants.
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 digest of `data` (FIPS 180-4).
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+
+    // Pad to a whole number of 64-byte blocks, ending with the bit length
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh.wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Quotas saved before keys were hashed get their keys hashed on load.
+fn hashed_keys<'de, D>(deserializer: D) -> Result<BTreeMap<String, Quota>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let keys = BTreeMap::<String, Quota>::deserialize(deserializer)?;
+    Ok(keys
+        .into_iter()
+        .map(|(key, quota)| if is_key_id(&key) { (key, quota) } else { (key_id(&key), quota) })
+        .collect())
+}
+
+/// Usage saved under raw keys is dropped on load rather than kept.
+fn counted_usage<'de, D>(deserializer: D) -> Result<BTreeMap<String, Vec<DayUsage>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut usage = BTreeMap::<String, Vec<DayUsage>>::deserialize(deserializer)?;
+    usage.retain(|key, _| key == ANONYMOUS || key == AUTHENTICATED || is_key_id(key));
+    Ok(usage)
+}
+
+#[derive(Deserialize)]
+struct QuotaRequest {
+    /// Key the quota applies to; the default quota when omitted
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(flatten)]
+    quota: Quota,
+}
+
+#[derive(Serialize)]
+struct QuotaList<'a> {
+    default: Option<&'a Quota>,
+    keys: &'a BTreeMap<String, Quota>,
+}
+
+#[derive(Serialize)]
+struct KeyUsageReport<'a> {
+    key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quota: Option<&'a Quota>,
+    today: DayUsage,
+    history: &'a [DayUsage],
+}
+
+#[derive(Serialize)]
+struct UsageReport<'a> {
+    day: u64,
+    keys: Vec<KeyUsageReport<'a>>,
+}
+
+/// The key a request is metered under: the hash of an API key with a quota
+/// of its own, `token` for the API token, or `anonymous`.
+pub fn client_key(state: &StoreViewerState, req: &HttpRequest) -> String {
+    let key = header(req, "x-api-key")
+        .or_else(|| header(req, "authorization").and_then(|value| value.strip_prefix("Bearer ")))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(key_id);
+    match key {
+        Some(id) if state.quotas.keys.contains_key(&id) => id,
+        _ if state.auth.token.is_some() && auth::is_authenticated(&state.auth, req) => AUTHENTICATED.to_string(),
+        _ => ANONYMOUS.to_string(),
+    }
+}
+
+/// Whether requests to `path` count against quotas.
+pub fn is_metered(path: &str) -> bool {
+    path.starts_with("/api/") && !path.starts_with("/api/admin/")
+}
+
+impl Quotas {
+    fn quota_for(&self, key: &str) -> Option<&Quota> {
+        self.keys.get(key).or(self.default.as_ref())
+    }
+
+    /// Today's usage entry for a key, starting a new day when needed.
+    fn today(&mut self, key: &str, day: u64) -> &mut DayUsage {
+        let days = self.usage.entry(key.to_string()).or_default();
+        if days.last().is_none_or(|usage| usage.day < day) {
+            days.push(DayUsage { day, ..DayUsage::default() });
+            let excess = days.len().saturating_sub(HISTORY_DAYS);
+            days.drain(..excess);
+        }
+        days.last_mut().unwrap()
+    }
+
+    fn limit_headers(&self, key: &str, usage: &DayUsage) -> Vec<(String, String)> {
+        let quota = match self.quota_for(key) {
+            Some(quota) => quota,
+            None => return Vec::new(),
+        };
+        let reset = ((usage.day + DAY_MS) / 1000).to_string();
+        let mut headers = Vec::new();
+        if let Some(limit) = quota.daily_requests {
+            headers.push(("X-RateLimit-Limit".to_string(), limit.to_string()));
+            headers.push(("X-RateLimit-Remaining".to_string(), limit.saturating_sub(usage.requests).to_string()));
+        }
+        if let Some(limit) = quota.daily_bytes {
+            headers.push(("X-RateLimit-Bytes-Limit".to_string(), limit.to_string()));
+            headers.push(("X-RateLimit-Bytes-Remaining".to_string(), limit.saturating_sub(usage.bytes).to_string()));
+        }
+        headers.push(("X-RateLimit-Reset".to_string(), reset));
+        headers
+    }
+}
+
+fn current_day(state: &StoreViewerState) -> u64 {
+    state.clock_ms - state.clock_ms % DAY_MS
+}
+
+/// Count a metered request before it is handled; returns the `429` response
+/// if the key has used up its quota.
+pub fn admit(state: &mut StoreViewerState, req: &HttpRequest, key: &str) -> Option<HttpResponse> {
+    let day = current_day(state);
+    let quota = state.quotas.quota_for(key).cloned().unwrap_or_default();
+    let usage = state.quotas.today(key, day);
+
+    let over = quota.daily_requests.is_some_and(|limit| usage.requests >= limit)
+        || quota.daily_bytes.is_some_and(|limit| usage.bytes >= limit);
+    if over {
+        usage.rejected += 1;
+        let usage = usage.clone();
+        log(&format!("Rejected request from over-quota key {}", key));
+        let mut response = error_response(429, "Daily quota exceeded for this API key");
+        response.headers.push(("Retry-After".to_string(), ((day + DAY_MS - state.clock_ms) / 1000).to_string()));
+        response.headers.extend(state.quotas.limit_headers(key, &usage));
+        return Some(response);
+    }
+
+    usage.requests += 1;
+    usage.bytes += req.body.as_ref().map_or(0, |body| body.len() as u64);
+    None
+}
+
+/// Count the response body of a metered request and attach the quota
+/// headers.
+pub fn record_response(state: &mut StoreViewerState, key: &str, response: &mut HttpResponse) {
+    if response.status == 429 {
+        return;
+    }
+    let day = current_day(state);
+    let usage = state.quotas.today(key, day);
+    usage.bytes += response.body.as_ref().map_or(0, |body| body.len() as u64);
+    let usage = usage.clone();
+    response.headers.extend(state.quotas.limit_headers(key, &usage));
+}
+
+pub fn handle_list_quotas(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing quotas");
+
+    let response = QuotaList {
+        default: state.quotas.default.as_ref(),
+        keys: &state.quotas.keys,
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize quotas: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_put_quota(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving quota");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let quota_req: QuotaRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if quota_req.quota == Quota::default() {
+        return Ok(error_response(400, "A quota needs daily_requests or daily_bytes"));
+    }
+
+    match quota_req.key {
+        Some(key) if key.is_empty() => return Ok(error_response(400, "key cannot be empty")),
+        Some(key) => {
+            let id = key_id(&key);
+            log(&format!("Set quota for key {}", id));
+            state.quotas.keys.insert(id, quota_req.quota);
+        }
+        None => {
+            log("Set default quota");
+            state.quotas.default = Some(quota_req.quota);
+        }
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+/// `DELETE /api/admin/quotas?key=`, with the key or its hash as listed;
+/// without `key` the default quota is removed.
+pub fn handle_delete_quota(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Deleting quota");
+
+    let removed = match query_param(&req.uri, "key") {
+        Some(key) if is_key_id(&key) => state.quotas.keys.remove(&key).is_some(),
+        Some(key) => state.quotas.keys.remove(&key_id(&key)).is_some(),
+        None => state.quotas.default.take().is_some(),
+    };
+    if !removed {
+        return Ok(error_response(404, "No such quota"));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+/// `GET /api/admin/usage`: today's usage and recent history of every key.
+pub fn handle_usage_report(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Building usage report");
+
+    let day = current_day(state);
+    let keys = state
+        .quotas
+        .usage
+        .iter()
+        .map(|(key, days)| KeyUsageReport {
+            key,
+            quota: state.quotas.quota_for(key),
+            today: days
+                .last()
+                .filter(|usage| usage.day == day)
+                .cloned()
+                .unwrap_or(DayUsage { day, ..DayUsage::default() }),
+            history: days,
+        })
+        .collect();
+
+    let body = serde_json::to_vec(&UsageReport { day, keys })
+        .map_err(|e| format!("Failed to serialize usage report: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn sha256_matches_known_digests() {
+        assert_eq!(hex(&sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(hex(&sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(
+            hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn sha256_pads_across_a_block_boundary() {
+        // 56 bytes leave no room for the length in the first block
+        let data = [b'a'; 56];
+        assert_eq!(hex(&sha256(&data)), "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a");
+    }
+
+    #[test]
+    fn key_ids_are_truncated_digests() {
+        let id = key_id("abc");
+        assert_eq!(id, "key:ba7816bf8f01cfea414140de5dae2223");
+        assert!(is_key_id(&id));
+        assert!(!is_key_id("abc"));
+        assert!(!is_key_id("key:ba7816bf8f01cfea"));
+    }
+}
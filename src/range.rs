@@ -0,0 +1,155 @@
+//! Partial content for raw responses.
+//!
+//! `GET /api/labels/{name}/raw` and `GET /api/content/{hash}` honour a
+//! single `Range: bytes=` range (`first-last`, `first-` or `-suffix`) and
+//! answer `206` with the bytes asked for, so large content can be fetched a
+//! page at a time instead of as one base64 blob. Clients that cannot set
+//! headers pass `?offset=&length=` instead. A range past the end gets `416`;
+//! several ranges at once, a unit other than `bytes` or an `If-Range` naming
+//! older content get the whole content, as HTTP allows.
+//!
+//! The store has no partial reads, so the actor still loads the whole
+//! content; what a range saves is the transfer and the client's memory.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::{error_response, header, query_param};
+use std::ops::Range;
+
+enum Selection {
+    Full,
+    Partial(Range<usize>),
+    Unsatisfiable,
+}
+
+/// Parse a `Range` value against content of `len` bytes.
+fn parse_header(value: &str, len: usize) -> Selection {
+    let spec = match value.split_once('=') {
+        Some((unit, spec)) if unit.trim().eq_ignore_ascii_case("bytes") && !spec.contains(',') => spec.trim(),
+        _ => return Selection::Full,
+    };
+    let (first, last) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return Selection::Full,
+    };
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        // The last `suffix` bytes
+        return match last.parse::<usize>() {
+            Ok(0) => Selection::Unsatisfiable,
+            Ok(_) if len == 0 => Selection::Unsatisfiable,
+            Ok(suffix) => Selection::Partial(len.saturating_sub(suffix)..len),
+            Err(_) => Selection::Full,
+        };
+    }
+    let first = match first.parse::<usize>() {
+        Ok(first) => first,
+        Err(_) => return Selection::Full,
+    };
+    let end = match last {
+        "" => len,
+        last => match last.parse::<usize>() {
+            Ok(last) if last >= first => last.saturating_add(1).min(len),
+            _ => return Selection::Full,
+        },
+    };
+    if first >= len {
+        return Selection::Unsatisfiable;
+    }
+    Selection::Partial(first..end)
+}
+
+/// The part of content of `len` bytes, tagged `etag`, that the request asks
+/// for. `Err` holds a response for malformed query parameters.
+fn select(req: &HttpRequest, etag: &str, len: usize) -> Result<Selection, HttpResponse> {
+    let offset = query_param(&req.uri, "offset");
+    let length = query_param(&req.uri, "length");
+    if offset.is_some() || length.is_some() {
+        let offset = match offset.map(|offset| offset.parse::<usize>()) {
+            None => 0,
+            Some(Ok(offset)) => offset,
+            Some(Err(_)) => return Err(error_response(400, "offset must be a byte position")),
+        };
+        let length = match length.map(|length| length.parse::<usize>()) {
+            None => len.saturating_sub(offset),
+            Some(Ok(length)) if length > 0 => length,
+            Some(_) => return Err(error_response(400, "length must be a positive number of bytes")),
+        };
+        if len == 0 && offset == 0 {
+            return Ok(Selection::Full);
+        }
+        if offset >= len {
+            return Ok(Selection::Unsatisfiable);
+        }
+        return Ok(Selection::Partial(offset..offset.saturating_add(length).min(len)));
+    }
+
+    let value = match header(req, "range") {
+        Some(value) => value,
+        None => return Ok(Selection::Full),
+    };
+    // A range into other content than the client has would splice two versions
+    if header(req, "if-range").is_some_and(|tag| tag != etag) {
+        return Ok(Selection::Full);
+    }
+    Ok(parse_header(value, len))
+}
+
+/// Answer with `content`, or the part of it the request asks for. `headers`
+/// describe the whole content and are sent either way.
+pub fn respond(req: &HttpRequest, etag: &str, mut headers: Vec<(String, String)>, content: Vec<u8>) -> HttpResponse {
+    let len = content.len();
+    headers.push(("Accept-Ranges".to_string(), "bytes".to_string()));
+    match select(req, etag, len) {
+        Err(resp) => resp,
+        Ok(Selection::Full) => HttpResponse { status: 200, headers, body: Some(content) },
+        Ok(Selection::Partial(range)) => {
+            headers.push((
+                "Content-Range".to_string(),
+                format!("bytes {}-{}/{}", range.start, range.end - 1, len),
+            ));
+            HttpResponse { status: 206, headers, body: Some(content[range].to_vec()) }
+        }
+        Ok(Selection::Unsatisfiable) => {
+            let mut resp = error_response(416, &format!("Range is outside the content ({} bytes)", len));
+            resp.headers.push(("Content-Range".to_string(), format!("bytes */{}", len)));
+            resp
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(value: &str, len: usize) -> Option<Range<usize>> {
+        match parse_header(value, len) {
+            Selection::Partial(range) => Some(range),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn byte_ranges_are_clamped_to_the_content() {
+        assert_eq!(partial("bytes=0-9", 100), Some(0..10));
+        assert_eq!(partial("bytes=90-", 100), Some(90..100));
+        assert_eq!(partial("bytes=90-200", 100), Some(90..100));
+        assert_eq!(partial("bytes=-10", 100), Some(90..100));
+        assert_eq!(partial("bytes=-200", 100), Some(0..100));
+        assert_eq!(partial(" Bytes = 5 - 6 ", 100), Some(5..7));
+    }
+
+    #[test]
+    fn ranges_past_the_end_are_unsatisfiable() {
+        assert!(matches!(parse_header("bytes=100-", 100), Selection::Unsatisfiable));
+        assert!(matches!(parse_header("bytes=-0", 100), Selection::Unsatisfiable));
+        assert!(matches!(parse_header("bytes=-5", 0), Selection::Unsatisfiable));
+    }
+
+    #[test]
+    fn unsupported_ranges_get_the_whole_content() {
+        for value in ["items=0-9", "bytes=0-9,20-29", "bytes=9-0", "bytes=a-b", "bytes=5", "bytes"] {
+            assert!(matches!(parse_header(value, 100), Selection::Full), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Label dependency/reference graph.
+//!
+//! A label references another by mentioning a `label://<name>` URI anywhere in
+//! its text (typically as a JSON string value), by including it with
+//! `{{label:<name>}}`, or by listing names under a `references` field in its
+//! YAML front matter. The graph is computed from
+//! content on demand rather than stored, so it can never go stale.
+//!
+//! Deleting a label others still reference is refused with `409`, naming
+//! them, unless the request passes `?force=true`.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, frontmatter, json_response, query_param, template, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const LABEL_URI_SCHEME: &str = "label://";
+
+/// Front-matter field listing referenced labels.
+const FRONT_MATTER_FIELD: &str = "references";
+
+#[derive(Serialize)]
+struct Reference {
+    name: String,
+    exists: bool,
+}
+
+#[derive(Serialize)]
+struct ReferencesResponse {
+    name: String,
+    references: Vec<Reference>,
+}
+
+/// Names of all labels referenced by `content`.
+pub fn extract_references(content: &[u8]) -> BTreeSet<String> {
+    let mut refs = BTreeSet::new();
+
+    let text = match std::str::from_utf8(content) {
+        Ok(text) => text,
+        Err(_) => return refs,
+    };
+
+    let mut rest = text;
+    while let Some(pos) = rest.find(LABEL_URI_SCHEME) {
+        rest = &rest[pos + LABEL_URI_SCHEME.len()..];
+        let end = rest
+            .find(|c: char| c.is_whitespace() || "\"'`<>()[]{},;".contains(c))
+            .unwrap_or(rest.len());
+        if end > 0 {
+            refs.insert(rest[..end].to_string());
+        }
+        rest = &rest[end..];
+    }
+
+    let mut rest = text;
+    while let Some(pos) = rest.find(template::OPEN) {
+        rest = &rest[pos + template::OPEN.len()..];
+        let end = match rest.find(template::CLOSE) {
+            Some(end) => end,
+            None => break,
+        };
+        let name = rest[..end].trim();
+        if !name.is_empty() {
+            refs.insert(name.to_string());
+        }
+        rest = &rest[end + template::CLOSE.len()..];
+    }
+
+    if let Some(fm) = frontmatter::extract(content) {
+        match fm.get(FRONT_MATTER_FIELD) {
+            Some(serde_json::Value::String(name)) => {
+                refs.insert(name.clone());
+            }
+            Some(serde_json::Value::Array(names)) => {
+                refs.extend(names.iter().filter_map(|n| n.as_str()).map(String::from));
+            }
+            _ => {}
+        }
+    }
+
+    refs
+}
+
+/// Labels referenced by `label`, or `None` if it does not exist.
+pub fn dependencies_of(store_id: &str, label: &str) -> Result<Option<BTreeSet<String>>, String> {
+    match store::get_by_label(store_id, label)? {
+        Some(content_ref) => Ok(Some(extract_references(&store::get(store_id, &content_ref)?))),
+        None => Ok(None),
+    }
+}
+
+/// Labels whose content references `label`. The viewer's own bookkeeping,
+/// such as trashed copies, does not count.
+pub fn dependents_of(store_id: &str, label: &str) -> Result<Vec<String>, String> {
+    let mut dependents = Vec::new();
+    for other in store::list_labels(store_id)? {
+        if other == label || other.starts_with(SYSTEM_LABEL_PREFIX) {
+            continue;
+        }
+        if let Some(refs) = dependencies_of(store_id, &other)? {
+            if refs.contains(label) {
+                dependents.push(other);
+            }
+        }
+    }
+    dependents.sort();
+    Ok(dependents)
+}
+
+/// The labels outside `labels` that reference each of them, for those that
+/// have any: what deleting the whole set would leave dangling.
+pub fn dependents_outside(store_id: &str, labels: &BTreeSet<String>) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let mut dependents: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for other in store::list_labels(store_id)? {
+        if labels.contains(&other) || other.starts_with(SYSTEM_LABEL_PREFIX) {
+            continue;
+        }
+        if let Some(refs) = dependencies_of(store_id, &other)? {
+            for referenced in refs.intersection(labels) {
+                dependents.entry(referenced.clone()).or_default().push(other.clone());
+            }
+        }
+    }
+    Ok(dependents)
+}
+
+/// Whether a delete should go ahead even if other labels reference its labels.
+pub fn is_forced(req: &HttpRequest) -> bool {
+    query_param(&req.uri, "force").as_deref() == Some("true")
+}
+
+/// Why deleting `label` is refused while `dependents` reference it.
+pub fn still_referenced(label: &str, dependents: &[String]) -> String {
+    format!(
+        "{} is referenced by {}; pass ?force=true to delete it anyway",
+        label,
+        dependents.join(", ")
+    )
+}
+
+fn references_response(state: &StoreViewerState, name: &str, names: Vec<String>) -> Result<HttpResponse, String> {
+    let mut references = Vec::with_capacity(names.len());
+    for name in names {
+        let exists = store::get_by_label(&state.store_id, &name)?.is_some();
+        references.push(Reference { name, exists });
+    }
+
+    let body = serde_json::to_vec(&ReferencesResponse { name: name.to_string(), references })
+        .map_err(|e| format!("Failed to serialize references: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_dependencies(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Getting dependencies of label: {}", label_name));
+
+    match dependencies_of(&state.store_id, label_name)? {
+        Some(refs) => references_response(state, label_name, refs.into_iter().collect()),
+        None => Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    }
+}
+
+pub fn handle_dependents(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Getting dependents of label: {}", label_name));
+
+    let dependents = dependents_of(&state.store_id, label_name)?;
+    references_response(state, label_name, dependents)
+}
@@ -0,0 +1,104 @@
+//! Renaming labels.
+//!
+//! `POST /api/labels/{name}/rename` points the new name at the label's
+//! existing content ref and removes the old name in one journal entry, so
+//! nothing is copied and large binary labels move as cheaply as small ones.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::dryrun;
+use crate::events;
+use crate::journal::{self, Step};
+use crate::undo;
+use crate::{error_response, json_response, naming, validate_write, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+struct RenameRequest {
+    /// New name for the label
+    to: String,
+}
+
+#[derive(Serialize)]
+struct RenameResponse {
+    from: String,
+    to: String,
+    #[serde(rename = "ref")]
+    content_ref: String,
+}
+
+/// `POST /api/labels/{name}/rename` with `{ "to" }`
+pub fn handle_rename(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let rename_req: RenameRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+    let to = rename_req.to;
+
+    log(&format!("Renaming label {} to {}", label_name, to));
+
+    if label_name.starts_with(SYSTEM_LABEL_PREFIX) {
+        return Ok(error_response(403, &format!("Labels under {} are reserved", SYSTEM_LABEL_PREFIX)));
+    }
+    if to.is_empty() {
+        return Ok(error_response(400, "New name cannot be empty"));
+    }
+    if to == label_name {
+        return Ok(error_response(400, "New name is the same as the current one"));
+    }
+
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    if store::get_by_label(&state.store_id, &to)?.is_some() {
+        return Ok(error_response(409, &format!("Label already exists: {}", to)));
+    }
+    if let Err(e) = naming::check_name(&state.naming, &to) {
+        return Ok(error_response(422, &e));
+    }
+    // Rules under the new name may differ from those the content was written under
+    let content = store::get(&state.store_id, &content_ref)?;
+    if let Err((status, e)) = validate_write(state, &to, &content) {
+        return Ok(error_response(status, &e));
+    }
+
+    if dryrun::is_dry_run(req) {
+        let changes = dryrun::plan_move(&state.store_id, label_name, &to, &content_ref)?;
+        return dryrun::dry_run_response(&changes);
+    }
+
+    let steps = vec![Step::point(&to, &content_ref), Step::delete(label_name)];
+    let journal_id = journal::begin(state, "rename", steps)?;
+    store::replace_at_label(&state.store_id, &to, &content_ref)?;
+    store::remove_label(&state.store_id, label_name)?;
+    journal::complete(state, journal_id)?;
+
+    state.stats.rename(label_name, &to);
+    if let Some(meta) = state.label_meta.remove(label_name) {
+        state.label_meta.insert(to.clone(), meta);
+    }
+    if let Some(channels) = state.channels.remove(label_name) {
+        state.channels.insert(to.clone(), channels);
+    }
+    // A renamed archive entry is live again under its new name
+    state.archived.remove(label_name);
+    undo::record(state, label_name, Some(&content_ref), None);
+    undo::record(state, &to, None, Some(&content_ref));
+    events::label_deleted(state, label_name, Some(&content_ref.hash));
+    events::label_written(state, &to, None, &content_ref);
+
+    log(&format!("Renamed label {} to {}", label_name, to));
+
+    let response = RenameResponse {
+        from: label_name.to_string(),
+        to,
+        content_ref: content_ref.hash,
+    };
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,365 @@
+//! Label-level retention policies.
+//!
+//! A policy applies to every label under a prefix (the longest matching prefix
+//! wins) and can limit how large a label may be, how long it may go without
+//! being accessed, and how many versions of it are kept. Size limits are
+//! enforced on write and version limits whenever history is recorded; idle
+//! labels are deleted (through the trash, like any other delete) by the task
+//! runner, which evaluates policies at most once per `EVALUATION_INTERVAL_MS`.
+//! Idle time is measured on the actor's clock, so a policy with an idle limit
+//! can only be saved once a client has set it.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{history, trash};
+use crate::{error_response, json_response, longest_prefix_match, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// How often the task runner re-evaluates policies.
+const EVALUATION_INTERVAL_MS: u64 = 60 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RetentionPolicy {
+    pub prefix: String,
+    /// Number of versions kept in each label's history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_versions: Option<u32>,
+    /// Delete labels that have not been read or written for this many days
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_idle_days: Option<u32>,
+    /// Reject writes larger than this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size_bytes: Option<u64>,
+    /// Drop history versions recorded more than this many days ago
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_history_days: Option<u32>,
+    /// When the policy was registered; idle time is never counted from
+    /// before this, so adding a policy does not instantly delete old labels.
+    /// 0 for a policy saved before the clock was known, which never deletes
+    #[serde(default)]
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Retention {
+    pub policies: Vec<RetentionPolicy>,
+    #[serde(default)]
+    pub last_run_at: u64,
+}
+
+impl Retention {
+    pub fn policy_for(&self, label: &str) -> Option<&RetentionPolicy> {
+        longest_prefix_match(&self.policies, label, |policy| &policy.prefix)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyActionKind {
+    /// The label will be deleted for being idle
+    Delete,
+    /// The label is larger than the policy allows
+    Oversize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PolicyAction {
+    pub label: String,
+    pub policy_prefix: String,
+    pub action: PolicyActionKind,
+    pub reason: String,
+    /// When the action takes effect; `None` for violations with no deadline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct RetentionReport {
+    generated_at: u64,
+    last_run_at: u64,
+    actions: Vec<PolicyAction>,
+}
+
+/// Reject a write that would exceed the governing policy's size limit.
+pub fn check_write(retention: &Retention, label: &str, content: &[u8]) -> Result<(), String> {
+    match retention.policy_for(label) {
+        Some(RetentionPolicy { max_size_bytes: Some(max), prefix, .. }) if content.len() as u64 > *max => Err(format!(
+            "Label {} would be {} bytes, over the {} byte limit of the retention policy for '{}'",
+            label,
+            content.len(),
+            max,
+            prefix
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Time at which an idle label governed by `policy` becomes eligible for
+/// deletion.
+fn idle_deadline(state: &StoreViewerState, policy: &RetentionPolicy, label: &str) -> Option<u64> {
+    let max_idle = u64::from(policy.max_idle_days?) * DAY_MS;
+    // Without a registration time every unread label would be long overdue
+    if policy.created_at == 0 {
+        return None;
+    }
+    let last_access = state.stats.get(label).last_access_at().unwrap_or(0);
+    Some(last_access.max(policy.created_at) + max_idle)
+}
+
+/// When the governing policy will delete `label` for being idle, if ever.
+pub fn expires_at(state: &StoreViewerState, label: &str) -> Option<u64> {
+    idle_deadline(state, state.retention.policy_for(label)?, label)
+}
+
+/// Work out every pending policy action, sorted by due time.
+pub fn plan(state: &StoreViewerState) -> Result<Vec<PolicyAction>, String> {
+    let mut actions = Vec::new();
+    if state.retention.policies.is_empty() {
+        return Ok(actions);
+    }
+
+    for label in store::list_labels(&state.store_id)? {
+        if label.starts_with(SYSTEM_LABEL_PREFIX) {
+            continue;
+        }
+        let policy = match state.retention.policy_for(&label) {
+            Some(policy) => policy,
+            None => continue,
+        };
+
+        if let Some(due_at) = idle_deadline(state, policy, &label) {
+            actions.push(PolicyAction {
+                label: label.clone(),
+                policy_prefix: policy.prefix.clone(),
+                action: PolicyActionKind::Delete,
+                reason: format!("Not accessed for {} days", policy.max_idle_days.unwrap_or_default()),
+                due_at: Some(due_at),
+            });
+        }
+
+        if let Some(max) = policy.max_size_bytes {
+            if let Some(content_ref) = store::get_by_label(&state.store_id, &label)? {
+                let size = store::get(&state.store_id, &content_ref)?.len() as u64;
+                if size > max {
+                    actions.push(PolicyAction {
+                        label: label.clone(),
+                        policy_prefix: policy.prefix.clone(),
+                        action: PolicyActionKind::Oversize,
+                        reason: format!("{} bytes exceeds the {} byte limit", size, max),
+                        due_at: None,
+                    });
+                }
+            }
+        }
+    }
+
+    actions.sort_by_key(|a| (a.due_at.is_none(), a.due_at, a.label.clone()));
+    Ok(actions)
+}
+
+/// Apply due policy actions; called by the task runner. Returns how many
+/// labels were deleted.
+pub fn run_due(state: &mut StoreViewerState) -> Result<usize, String> {
+    let now = state.clock_ms;
+    if state.retention.policies.is_empty() || now < state.retention.last_run_at + EVALUATION_INTERVAL_MS {
+        return Ok(0);
+    }
+    state.retention.last_run_at = now;
+
+    // Versions age out even for labels that are no longer written
+    if state.retention.policies.iter().any(|policy| policy.max_history_days.is_some()) {
+        let pruned = history::prune_all(state)?;
+        if pruned > 0 {
+            log(&format!("Retention policies pruned {} history versions", pruned));
+        }
+    }
+
+    let due: Vec<PolicyAction> = plan(state)?
+        .into_iter()
+        .filter(|action| action.action == PolicyActionKind::Delete && action.due_at.is_some_and(|due| due <= now))
+        .collect();
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    let mut deleted = 0;
+    for action in &due {
+        let current = match store::get_by_label(&state.store_id, &action.label)? {
+            Some(current) => current,
+            None => continue,
+        };
+        trash::delete(state, "retention", &action.label, &current)?;
+        deleted += 1;
+        log(&format!("Retention policy '{}' deleted {}", action.policy_prefix, action.label));
+    }
+    Ok(deleted)
+}
+
+pub fn handle_list_policies(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing retention policies");
+
+    let body = serde_json::to_vec(&state.retention.policies)
+        .map_err(|e| format!("Failed to serialize policies: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// Check a policy about to be saved, with the status to refuse it with.
+pub fn validate_policy(state: &StoreViewerState, policy: &RetentionPolicy) -> Result<(), (u16, String)> {
+    if policy.keep_versions.is_none()
+        && policy.max_idle_days.is_none()
+        && policy.max_size_bytes.is_none()
+        && policy.max_history_days.is_none()
+    {
+        return Err((400, "Policy must set keep_versions, max_idle_days, max_size_bytes or max_history_days".to_string()));
+    }
+    if policy.keep_versions == Some(0) || policy.max_history_days == Some(0) {
+        return Err((400, "keep_versions and max_history_days must be at least 1".to_string()));
+    }
+    if policy.max_idle_days.is_some() && state.clock_ms == 0 {
+        return Err((503, "The actor's clock is not set yet; send X-Client-Time before adding an idle limit".to_string()));
+    }
+    Ok(())
+}
+
+pub fn handle_put_policy(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving retention policy");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let mut policy: RetentionPolicy = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err((status, e)) = validate_policy(state, &policy) {
+        return Ok(error_response(status, &e));
+    }
+
+    // Keep the original registration time when a policy is updated
+    policy.created_at = state
+        .retention
+        .policies
+        .iter()
+        .find(|p| p.prefix == policy.prefix && p.created_at != 0)
+        .map_or(state.clock_ms, |p| p.created_at);
+
+    log(&format!("Saved retention policy for prefix: {}", policy.prefix));
+
+    state.retention.policies.retain(|p| p.prefix != policy.prefix);
+    state.retention.policies.push(policy);
+    state.retention.policies.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+pub fn handle_delete_policy(state: &mut StoreViewerState, prefix: &str) -> Result<HttpResponse, String> {
+    log(&format!("Deleting retention policy for prefix: {}", prefix));
+
+    let before = state.retention.policies.len();
+    state.retention.policies.retain(|p| p.prefix != prefix);
+    if state.retention.policies.len() == before {
+        return Ok(error_response(404, &format!("No retention policy for prefix: {}", prefix)));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+pub fn handle_report(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Building retention report");
+
+    let report = RetentionReport {
+        generated_at: state.clock_ms,
+        last_run_at: state.retention.last_run_at,
+        actions: plan(state)?,
+    };
+
+    let body = serde_json::to_vec(&report)
+        .map_err(|e| format!("Failed to serialize report: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with(policies: Vec<RetentionPolicy>, clock_ms: u64) -> StoreViewerState {
+        let mut state: StoreViewerState = serde_json::from_str(r#"{"store_id": "test", "server_id": 0}"#).unwrap();
+        state.retention.policies = policies;
+        state.clock_ms = clock_ms;
+        state
+    }
+
+    fn policy(prefix: &str) -> RetentionPolicy {
+        RetentionPolicy {
+            prefix: prefix.to_string(),
+            keep_versions: None,
+            max_idle_days: None,
+            max_size_bytes: None,
+            max_history_days: None,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn idle_labels_expire_counting_from_the_later_of_access_and_registration() {
+        let idle = RetentionPolicy { max_idle_days: Some(2), created_at: 10 * DAY_MS, ..policy("tmp/") };
+        let mut state = state_with(vec![idle], 20 * DAY_MS);
+        // Never accessed: counted from the policy's registration
+        assert_eq!(expires_at(&state, "tmp/a"), Some(12 * DAY_MS));
+        // Accessed after registration
+        state.stats.record_read("tmp/a", 15 * DAY_MS);
+        assert_eq!(expires_at(&state, "tmp/a"), Some(17 * DAY_MS));
+        // Accessed before registration
+        state.stats.record_write("tmp/b", 3 * DAY_MS);
+        assert_eq!(expires_at(&state, "tmp/b"), Some(12 * DAY_MS));
+        // Not governed by the policy
+        assert_eq!(expires_at(&state, "keep/a"), None);
+    }
+
+    #[test]
+    fn policies_without_a_registration_time_never_expire_labels() {
+        let idle = RetentionPolicy { max_idle_days: Some(2), ..policy("tmp/") };
+        assert_eq!(expires_at(&state_with(vec![idle], 20 * DAY_MS), "tmp/a"), None);
+        let sized = RetentionPolicy { max_size_bytes: Some(10), created_at: DAY_MS, ..policy("tmp/") };
+        assert_eq!(expires_at(&state_with(vec![sized], 20 * DAY_MS), "tmp/a"), None);
+    }
+
+    #[test]
+    fn the_most_specific_policy_governs() {
+        let state = state_with(
+            vec![
+                RetentionPolicy { max_idle_days: Some(30), created_at: DAY_MS, ..policy("tmp/") },
+                RetentionPolicy { max_idle_days: Some(1), created_at: DAY_MS, ..policy("tmp/scratch/") },
+            ],
+            0,
+        );
+        assert_eq!(expires_at(&state, "tmp/a"), Some(31 * DAY_MS));
+        assert_eq!(expires_at(&state, "tmp/scratch/a"), Some(2 * DAY_MS));
+    }
+
+    #[test]
+    fn writes_over_the_size_limit_are_refused() {
+        let small = RetentionPolicy { max_size_bytes: Some(4), ..policy("small/") };
+        let retention = Retention { policies: vec![small], last_run_at: 0 };
+        assert!(check_write(&retention, "small/a", b"1234").is_ok());
+        assert!(check_write(&retention, "small/a", b"12345").is_err());
+        assert!(check_write(&retention, "large/a", b"12345").is_ok());
+    }
+
+    #[test]
+    fn policies_must_set_a_usable_limit() {
+        let state = state_with(Vec::new(), 0);
+        assert_eq!(validate_policy(&state, &policy("a/")).map_err(|(status, _)| status), Err(400));
+        let no_versions = RetentionPolicy { keep_versions: Some(0), ..policy("a/") };
+        assert_eq!(validate_policy(&state, &no_versions).map_err(|(status, _)| status), Err(400));
+        // Idle limits need the clock
+        let idle = RetentionPolicy { max_idle_days: Some(1), ..policy("a/") };
+        assert_eq!(validate_policy(&state, &idle).map_err(|(status, _)| status), Err(503));
+        assert!(validate_policy(&state_with(Vec::new(), DAY_MS), &idle).is_ok());
+    }
+}
@@ -0,0 +1,228 @@
+//! The routes the viewer serves.
+//!
+//! `init` registers every route in `ROUTES` with the HTTP server and checks
+//! them against the documented endpoints, and `GET /api/admin/routes` reports
+//! the same table together with the handler each route reaches and what
+//! applies to it on the way: the authentication middleware, anonymous reads,
+//! quotas, CORS headers and store selection. So what a deployment serves can
+//! be read from the deployment itself.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::{auth, events, json_response, quotas, StoreViewerState};
+use serde::Serialize;
+
+/// Exported function every HTTP route is handled by.
+pub const REQUEST_HANDLER: &str = "handle_request";
+
+/// Exported function the authentication middleware runs in.
+pub const MIDDLEWARE_HANDLER: &str = "handle_middleware";
+
+/// Exported functions handling the event WebSocket.
+pub const WEBSOCKET_HANDLERS: [&str; 3] = [
+    "handle_websocket_connect",
+    "handle_websocket_message",
+    "handle_websocket_disconnect",
+];
+
+/// Method and path pattern of every route registered at init.
+pub const ROUTES: &[(&str, &str)] = &[
+    // Static assets
+    ("GET", "/"),
+    ("GET", "/app.css"),
+    ("GET", "/app.js"),
+
+    // The API
+    ("GET", "/api"),
+    ("GET", "/api/docs"),
+    ("GET", "/api/openapi.json"),
+    ("GET", "/api/labels"),
+    ("POST", "/api/labels"),
+    ("POST", "/api/labels/bulk-delete"),
+    ("POST", "/api/labels/head"),
+    ("POST", "/api/labels/meta/bulk"),
+    ("GET", "/api/labels/{*name}"),
+    ("HEAD", "/api/labels/{*name}"),
+    ("PUT", "/api/labels/{*name}"),
+    ("POST", "/api/labels/{*name}"),
+    ("DELETE", "/api/labels/{*name}"),
+    ("GET", "/api/admin/cors"),
+    ("POST", "/api/admin/cors"),
+    ("GET", "/api/admin/error-budget"),
+    ("POST", "/api/admin/error-budget"),
+    ("GET", "/api/admin/event-log"),
+    ("POST", "/api/admin/event-log"),
+    ("GET", "/api/admin/health"),
+    ("POST", "/api/admin/health"),
+    ("GET", "/api/admin/health/backups"),
+    ("POST", "/api/admin/health/backups"),
+    ("GET", "/api/admin/listing"),
+    ("POST", "/api/admin/listing"),
+    ("GET", "/api/admin/maintenance"),
+    ("POST", "/api/admin/maintenance"),
+    ("GET", "/api/admin/mirror"),
+    ("POST", "/api/admin/mirror"),
+    ("GET", "/api/admin/quotas"),
+    ("POST", "/api/admin/quotas"),
+    ("DELETE", "/api/admin/quotas"),
+    ("GET", "/api/admin/routes"),
+    ("GET", "/api/admin/scanning"),
+    ("POST", "/api/admin/scanning"),
+    ("POST", "/api/admin/scanning/check"),
+    ("GET", "/api/admin/startup"),
+    ("GET", "/api/admin/usage"),
+    ("GET", "/api/archive"),
+    ("POST", "/api/archive"),
+    ("POST", "/api/cli"),
+    ("GET", "/api/compare"),
+    ("POST", "/api/content"),
+    ("GET", "/api/content/{hash}"),
+    ("GET", "/api/diff"),
+    ("GET", "/api/duplicates"),
+    ("POST", "/api/duplicates/dedup"),
+    ("GET", "/api/editor"),
+    ("POST", "/api/editor"),
+    ("DELETE", "/api/editor"),
+    ("GET", "/api/audit"),
+    ("GET", "/api/event-log"),
+    ("GET", "/api/health"),
+    ("GET", "/api/export"),
+    ("GET", "/api/history"),
+    ("POST", "/api/history/prune"),
+    ("POST", "/api/event-log/replay"),
+    ("POST", "/api/import"),
+    ("POST", "/api/import/store"),
+    ("GET", "/api/journal"),
+    ("GET", "/api/lint"),
+    ("POST", "/api/lint"),
+    ("DELETE", "/api/lint"),
+    ("GET", "/api/metadata/export"),
+    ("POST", "/api/metadata/import"),
+    ("GET", "/api/naming"),
+    ("POST", "/api/naming"),
+    ("DELETE", "/api/naming"),
+    ("GET", "/api/notifications"),
+    ("POST", "/api/notifications"),
+    ("DELETE", "/api/notifications"),
+    ("POST", "/api/promote"),
+    ("GET", "/api/promotions"),
+    ("GET", "/api/promotions/{id}"),
+    ("GET", "/api/retention"),
+    ("POST", "/api/retention"),
+    ("DELETE", "/api/retention"),
+    ("GET", "/api/retention/report"),
+    ("GET", "/api/schedule"),
+    ("POST", "/api/schedule"),
+    ("DELETE", "/api/schedule/{id}"),
+    ("GET", "/api/schemas"),
+    ("POST", "/api/schemas"),
+    ("DELETE", "/api/schemas"),
+    ("GET", "/api/stores"),
+    ("POST", "/api/stores"),
+    ("GET", "/api/stores/{*path}"),
+    ("PUT", "/api/stores/{*path}"),
+    ("POST", "/api/stores/{*path}"),
+    ("DELETE", "/api/stores/{*path}"),
+    ("GET", "/api/shares"),
+    ("POST", "/api/shares"),
+    ("DELETE", "/api/shares"),
+    ("GET", "/api/staging"),
+    ("GET", "/api/staging/{id}"),
+    ("POST", "/api/staging/{id}/commit"),
+    ("DELETE", "/api/staging/{id}"),
+    ("GET", "/api/stats/timeline"),
+    ("GET", "/api/trash"),
+    ("POST", "/api/unarchive"),
+    ("GET", "/api/undo"),
+    ("POST", "/api/undo"),
+    ("GET", "/api/views"),
+    ("POST", "/api/views"),
+    ("GET", "/api/views/{*name}"),
+    ("DELETE", "/api/views/{*name}"),
+
+    // Every API path describes itself
+    ("OPTIONS", "/api/{*path}"),
+
+    // Publicly shared labels, outside the API
+    ("GET", "/public/{*name}"),
+
+    // Anything else reaches the handler so browsers get the error page
+    ("GET", "/{*path}"),
+];
+
+#[derive(Serialize)]
+struct RouteInfo {
+    method: &'static str,
+    path: &'static str,
+    handler: &'static str,
+    /// Middleware handlers requests run through first
+    middleware: Vec<&'static str>,
+    /// Request processing that applies to the route
+    features: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct WebSocketInfo {
+    path: &'static str,
+    handlers: [&'static str; 3],
+    /// `auth` when connections must carry the token as `?token=`
+    features: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct RoutesResponse {
+    routes: Vec<RouteInfo>,
+    /// The event WebSocket, if the server could enable it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    websocket: Option<WebSocketInfo>,
+}
+
+fn describe(state: &StoreViewerState, method: &'static str, path: &'static str) -> RouteInfo {
+    let api = path == auth::PROTECTED_PATH || path.starts_with("/api/");
+    let mut middleware = Vec::new();
+    if state.auth.token.is_some() && path.starts_with(auth::PROTECTED_PATH) {
+        middleware.push(MIDDLEWARE_HANDLER);
+    }
+
+    let mut features = Vec::new();
+    if api && state.auth.token.is_some() && !auth::OPEN_PATHS.contains(&path) {
+        features.push("auth");
+    }
+    if api && state.auth.anonymous.is_some() && matches!(method, "GET" | "HEAD") && !path.starts_with("/api/admin/") {
+        features.push("anonymous_read");
+    }
+    if quotas::is_metered(path) {
+        features.push("quotas");
+    }
+    if api && !state.cors.allowed_origins.is_empty() {
+        features.push("cors");
+    }
+    if path.starts_with("/api/labels") || path.starts_with("/api/stores/") {
+        features.push("store_selection");
+    }
+
+    RouteInfo { method, path, handler: REQUEST_HANDLER, middleware, features }
+}
+
+/// `GET /api/admin/routes`
+pub fn handle_list_routes(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing routes");
+
+    let routes = ROUTES.iter().map(|&(method, path)| describe(state, method, path)).collect();
+    let websocket = state
+        .startup
+        .capabilities
+        .get("websocket")
+        .copied()
+        .unwrap_or(false)
+        .then(|| WebSocketInfo {
+            path: events::WS_PATH,
+            handlers: WEBSOCKET_HANDLERS,
+            features: if state.auth.token.is_some() { vec!["auth"] } else { Vec::new() },
+        });
+
+    let body = serde_json::to_vec(&RoutesResponse { routes, websocket })
+        .map_err(|e| format!("Failed to serialize routes: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,225 @@
+//! Content scanning on write.
+//!
+//! Every write passes through the scanner before it reaches the store. It
+//! enforces a store-wide size limit and rejects content matching forbidden
+//! patterns: built-in ones for common credentials (cloud keys, private keys,
+//! access tokens) plus any configured. A rejected write gets a report naming
+//! each finding and where it is, with the matched text masked so the report
+//! does not leak the secret it caught.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{error_response, json_response, StoreViewerState};
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Credentials caught when built-in patterns are enabled.
+const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    ("aws_access_key_id", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "aws_secret_access_key",
+        r#"(?i)aws_?secret_?access_?key["']?\s*[:=]\s*["']?[A-Za-z0-9/+=]{40}"#,
+    ),
+    ("private_key", r"-----BEGIN (?:RSA |EC |DSA |OPENSSH |ENCRYPTED )?PRIVATE KEY-----"),
+    ("github_token", r"\bgh[pousr]_[A-Za-z0-9]{36}\b"),
+    ("slack_token", r"\bxox[abprs]-[A-Za-z0-9-]{10,}"),
+];
+
+/// Findings reported per rejected write.
+const MAX_FINDINGS: usize = 20;
+
+fn default_builtin_patterns() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ForbiddenPattern {
+    pub name: String,
+    /// Regular expression; matched against the raw bytes
+    pub pattern: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScanConfig {
+    /// Largest content any label may hold
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size_bytes: Option<u64>,
+    #[serde(default = "default_builtin_patterns")]
+    pub builtin_patterns: bool,
+    #[serde(default)]
+    pub patterns: Vec<ForbiddenPattern>,
+    /// Labels under these prefixes are not scanned for patterns
+    #[serde(default)]
+    pub exempt_prefixes: Vec<String>,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        ScanConfig {
+            max_size_bytes: None,
+            builtin_patterns: default_builtin_patterns(),
+            patterns: Vec::new(),
+            exempt_prefixes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Finding {
+    pub rule: String,
+    pub line: usize,
+    pub column: usize,
+    /// Start of the match with the rest masked
+    pub excerpt: String,
+}
+
+#[derive(Serialize)]
+struct ScanReport {
+    label: String,
+    clean: bool,
+    findings: Vec<Finding>,
+}
+
+#[derive(Deserialize)]
+struct ScanRequest {
+    #[serde(default)]
+    label: String,
+    content: String,
+}
+
+fn compile(name: &str, pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("Invalid pattern {}: {}", name, e))
+}
+
+fn mask(matched: &[u8]) -> String {
+    let shown: String = String::from_utf8_lossy(matched).chars().take(4).collect();
+    format!("{}***", shown)
+}
+
+/// Line and column (1-based) of a byte offset.
+fn position(content: &[u8], offset: usize) -> (usize, usize) {
+    let before = &content[..offset];
+    let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+    let line_start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    (line, offset - line_start + 1)
+}
+
+impl ScanConfig {
+    fn rules(&self) -> Result<Vec<(String, Regex)>, String> {
+        let builtin = BUILTIN_PATTERNS
+            .iter()
+            .filter(|_| self.builtin_patterns)
+            .map(|(name, pattern)| (name.to_string(), pattern.to_string()));
+        let custom = self.patterns.iter().map(|p| (p.name.clone(), p.pattern.clone()));
+        builtin
+            .chain(custom)
+            .map(|(name, pattern)| compile(&name, &pattern).map(|regex| (name, regex)))
+            .collect()
+    }
+
+    /// Every forbidden pattern found in content written to `label`.
+    pub fn scan(&self, label: &str, content: &[u8]) -> Result<Vec<Finding>, String> {
+        if self.exempt_prefixes.iter().any(|prefix| label.starts_with(prefix.as_str())) {
+            return Ok(Vec::new());
+        }
+        let mut findings = Vec::new();
+        for (rule, regex) in self.rules()? {
+            for found in regex.find_iter(content) {
+                if findings.len() == MAX_FINDINGS {
+                    return Ok(findings);
+                }
+                let (line, column) = position(content, found.start());
+                findings.push(Finding {
+                    rule: rule.clone(),
+                    line,
+                    column,
+                    excerpt: mask(found.as_bytes()),
+                });
+            }
+        }
+        Ok(findings)
+    }
+}
+
+/// Check content about to be written to `label`; on rejection returns the
+/// status and a report of every finding.
+pub fn check_write(config: &ScanConfig, label: &str, content: &[u8]) -> Result<(), (u16, String)> {
+    if let Some(max) = config.max_size_bytes {
+        if content.len() as u64 > max {
+            return Err((413, format!(
+                "Label {} is {} bytes, over the store-wide limit of {} bytes",
+                label,
+                content.len(),
+                max
+            )));
+        }
+    }
+
+    let findings = config.scan(label, content).map_err(|e| (500, e))?;
+    if findings.is_empty() {
+        return Ok(());
+    }
+    let report: Vec<String> = findings
+        .iter()
+        .map(|f| format!("{} at line {}, column {} ({})", f.rule, f.line, f.column, f.excerpt))
+        .collect();
+    Err((422, format!(
+        "Content scan rejected the write to {}: {}",
+        label,
+        report.join("; ")
+    )))
+}
+
+pub fn handle_get_config(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting scan configuration");
+
+    let body = serde_json::to_vec(&state.scanning)
+        .map_err(|e| format!("Failed to serialize scan configuration: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_put_config(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving scan configuration");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let config: ScanConfig = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err(e) = config.rules() {
+        return Ok(error_response(400, &e));
+    }
+
+    log(&format!(
+        "Scanning with {} custom patterns (built-in: {})",
+        config.patterns.len(),
+        config.builtin_patterns
+    ));
+    state.scanning = config;
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+/// `POST /api/scanning/check`: scan `{ "label"?, "content" }` without writing.
+pub fn handle_check(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Scanning content");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let scan_req: ScanRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    let findings = state.scanning.scan(&scan_req.label, scan_req.content.as_bytes())?;
+    let report = ScanReport {
+        label: scan_req.label,
+        clean: findings.is_empty(),
+        findings,
+    };
+
+    let body = serde_json::to_vec(&report)
+        .map_err(|e| format!("Failed to serialize scan report: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,229 @@
+//! Scheduled label mutations.
+//!
+//! A write can be registered to happen at a future time ("at 02:00 replace
+//! `configs/flag.json` with this content"). Pending writes are persisted in
+//! the actor state, executed by the task runner once due, and can be cancelled
+//! until then.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::dryrun;
+use crate::{error_response, json_response, validate_write, write_label, StoreViewerState};
+use serde::{Deserialize, Serialize};
+
+/// Completed and failed writes kept for inspection.
+const MAX_FINISHED: usize = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleStatus {
+    Pending,
+    Done,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduledWrite {
+    pub id: u64,
+    pub label: String,
+    pub content: String,
+    /// When the write should happen, in epoch milliseconds
+    pub run_at: u64,
+    pub status: ScheduleStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executed_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Schedule {
+    next_id: u64,
+    writes: Vec<ScheduledWrite>,
+}
+
+#[derive(Deserialize)]
+struct ScheduleWriteRequest {
+    label: String,
+    content: String,
+    /// Absolute time in epoch milliseconds
+    #[serde(default)]
+    run_at: Option<u64>,
+    /// Delay from the current time in milliseconds
+    #[serde(default)]
+    delay_ms: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ScheduledResponse {
+    id: u64,
+    run_at: u64,
+}
+
+#[derive(Serialize)]
+struct ScheduledWriteSummary<'a> {
+    id: u64,
+    label: &'a str,
+    size_bytes: usize,
+    run_at: u64,
+    status: &'a ScheduleStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    executed_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// Execute every pending write whose time has come; returns how many ran.
+pub fn run_due(state: &mut StoreViewerState) -> usize {
+    let now = state.clock_ms;
+    let mut ran = 0;
+
+    for i in 0..state.schedule.writes.len() {
+        let job = &state.schedule.writes[i];
+        if job.status != ScheduleStatus::Pending || job.run_at > now {
+            continue;
+        }
+
+        let label = job.label.clone();
+        let content = job.content.clone().into_bytes();
+        let result = validate_write(state, &label, &content)
+            .map_err(|(_, e)| e)
+            .and_then(|_| write_label(state, "scheduled_write", &label, &content).map(|_| ()));
+
+        let job = &mut state.schedule.writes[i];
+        job.executed_at = Some(now);
+        match result {
+            Ok(()) => {
+                log(&format!("Scheduled write {} applied to {}", job.id, label));
+                job.status = ScheduleStatus::Done;
+            }
+            Err(e) => {
+                log(&format!("Scheduled write {} to {} failed: {}", job.id, label, e));
+                job.status = ScheduleStatus::Failed;
+                job.error = Some(e);
+            }
+        }
+        ran += 1;
+    }
+
+    if ran > 0 {
+        prune_finished(&mut state.schedule);
+    }
+    ran
+}
+
+/// Drop the oldest finished writes beyond `MAX_FINISHED`.
+fn prune_finished(schedule: &mut Schedule) {
+    let finished = schedule
+        .writes
+        .iter()
+        .filter(|w| w.status != ScheduleStatus::Pending)
+        .count();
+    let mut excess = finished.saturating_sub(MAX_FINISHED);
+    schedule.writes.retain(|w| {
+        if excess > 0 && w.status != ScheduleStatus::Pending {
+            excess -= 1;
+            false
+        } else {
+            true
+        }
+    });
+}
+
+pub fn handle_list_scheduled(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing scheduled writes");
+
+    let writes: Vec<ScheduledWriteSummary> = state
+        .schedule
+        .writes
+        .iter()
+        .map(|w| ScheduledWriteSummary {
+            id: w.id,
+            label: &w.label,
+            size_bytes: w.content.len(),
+            run_at: w.run_at,
+            status: &w.status,
+            executed_at: w.executed_at,
+            error: w.error.as_deref(),
+        })
+        .collect();
+
+    let body = serde_json::to_vec(&writes)
+        .map_err(|e| format!("Failed to serialize schedule: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_schedule_write(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Scheduling label write");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let schedule_req: ScheduleWriteRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if schedule_req.label.is_empty() {
+        return Ok(error_response(400, "Label name cannot be empty"));
+    }
+
+    let run_at = match (schedule_req.run_at, schedule_req.delay_ms) {
+        (Some(run_at), None) => run_at,
+        (None, Some(delay)) => {
+            if state.clock_ms == 0 {
+                return Ok(error_response(400, "Current time is unknown; use run_at instead of delay_ms"));
+            }
+            state.clock_ms + delay
+        }
+        _ => return Ok(error_response(400, "Exactly one of run_at or delay_ms is required")),
+    };
+
+    // Reject content that could never be written
+    if let Err((status, e)) = validate_write(state, &schedule_req.label, schedule_req.content.as_bytes()) {
+        return Ok(error_response(status, &e));
+    }
+    // What the write would do to the label as it is now
+    if dryrun::is_dry_run(req) {
+        let change = dryrun::plan_write(&state.store_id, &schedule_req.label, schedule_req.content.as_bytes())?;
+        return dryrun::dry_run_response(&[change]);
+    }
+
+    let id = state.schedule.next_id;
+    state.schedule.next_id += 1;
+    state.schedule.writes.push(ScheduledWrite {
+        id,
+        label: schedule_req.label,
+        content: schedule_req.content,
+        run_at,
+        status: ScheduleStatus::Pending,
+        executed_at: None,
+        error: None,
+    });
+
+    log(&format!("Scheduled write {} for {}", id, run_at));
+
+    let body = serde_json::to_vec(&ScheduledResponse { id, run_at })
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_cancel_scheduled(state: &mut StoreViewerState, id: &str) -> Result<HttpResponse, String> {
+    log(&format!("Cancelling scheduled write: {}", id));
+
+    let position = id.parse::<u64>().ok().and_then(|id| {
+        state
+            .schedule
+            .writes
+            .iter()
+            .position(|w| w.id == id && w.status == ScheduleStatus::Pending)
+    });
+
+    match position {
+        Some(i) => {
+            state.schedule.writes.remove(i);
+            let success_json = r#"{"success":true}"#;
+            Ok(json_response(200, success_json.as_bytes().to_vec()))
+        }
+        None => Ok(error_response(404, &format!("No pending scheduled write: {}", id))),
+    }
+}
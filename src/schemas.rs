@@ -0,0 +1,267 @@
+//! Label schema registry.
+//!
+//! The registry maps label prefixes to the format their content must have
+//! (JSON, optionally checked against a JSON Schema, TOML, a Theater actor
+//! manifest, text, or binary).
+//! Writes to a label are validated against the rule with the longest matching
+//! prefix, so the store can hold typed configuration rather than arbitrary
+//! bytes.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::{error_response, is_text_content, json_response, longest_prefix_match, manifest, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Expected content format for labels under a prefix.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelFormat {
+    Json,
+    Toml,
+    /// A Theater actor manifest (TOML)
+    Manifest,
+    Text,
+    Binary,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SchemaRule {
+    pub prefix: String,
+    pub format: LabelFormat,
+    /// JSON Schema the content must satisfy (only for `json`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+}
+
+/// Find the rule governing a label: the one with the longest matching prefix.
+pub fn rule_for<'a>(rules: &'a [SchemaRule], label: &str) -> Option<&'a SchemaRule> {
+    longest_prefix_match(rules, label, |rule| &rule.prefix)
+}
+
+/// Validate content about to be written to `label`; returns a description of
+/// every problem found.
+pub fn check_write(rules: &[SchemaRule], label: &str, content: &[u8]) -> Result<(), String> {
+    let rule = match rule_for(rules, label) {
+        Some(rule) => rule,
+        None => return Ok(()),
+    };
+
+    let mismatch = |what: &str| {
+        format!(
+            "Label {} must contain {} (schema registered for prefix '{}')",
+            label, what, rule.prefix
+        )
+    };
+
+    match rule.format {
+        LabelFormat::Json => {
+            let value: Value = serde_json::from_slice(content)
+                .map_err(|e| format!("{}: {}", mismatch("valid JSON"), e))?;
+            if let Some(schema) = &rule.schema {
+                let mut errors = Vec::new();
+                validate_json(schema, &value, "$", &mut errors);
+                if !errors.is_empty() {
+                    return Err(format!("{}: {}", mismatch("JSON matching the schema"), errors.join("; ")));
+                }
+            }
+        }
+        LabelFormat::Toml => {
+            let text = std::str::from_utf8(content).map_err(|_| mismatch("valid TOML"))?;
+            text.parse::<toml::Table>()
+                .map_err(|e| format!("{}: {}", mismatch("valid TOML"), e.message()))?;
+        }
+        LabelFormat::Manifest => {
+            let text = std::str::from_utf8(content).map_err(|_| mismatch("a Theater manifest"))?;
+            manifest::parse(text)
+                .map_err(|errors| format!("{}: {}", mismatch("a valid Theater manifest"), errors.join("; ")))?;
+        }
+        LabelFormat::Text => {
+            if !is_text_content(content) {
+                return Err(mismatch("text"));
+            }
+        }
+        LabelFormat::Binary => {
+            if is_text_content(content) {
+                return Err(mismatch("binary data"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate `value` against a JSON Schema, supporting the commonly used
+/// keywords: `type`, `enum`, `const`, `required`, `properties`,
+/// `additionalProperties`, `items`, `minItems`/`maxItems`,
+/// `minLength`/`maxLength` and `minimum`/`maximum`.
+fn validate_json(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema = match schema.as_object() {
+        Some(schema) => schema,
+        None => return,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| json_type_matches(t, value)) {
+            errors.push(format!("{}: expected {}", path, allowed.join(" or ")));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            errors.push(format!("{}: value is not one of the allowed values", path));
+        }
+    }
+
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            errors.push(format!("{}: value must equal {}", path, constant));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(key) {
+                        errors.push(format!("{}: missing required property '{}'", path, key));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, child) in map {
+                let child_path = format!("{}.{}", path, key);
+                match properties.and_then(|p| p.get(key)) {
+                    Some(child_schema) => validate_json(child_schema, child, &child_path, errors),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{}: unexpected property", child_path))
+                        }
+                        Some(extra @ Value::Object(_)) => {
+                            validate_json(extra, child, &child_path, errors)
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    errors.push(format!("{}: expected at least {} items", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if (items.len() as u64) > max {
+                    errors.push(format!("{}: expected at most {} items", path, max));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_json(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if len < min {
+                    errors.push(format!("{}: shorter than {} characters", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if len > max {
+                    errors.push(format!("{}: longer than {} characters", path, max));
+                }
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if n < min {
+                    errors.push(format!("{}: must be at least {}", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if n > max {
+                    errors.push(format!("{}: must be at most {}", path, max));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn json_type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+pub fn handle_list_schemas(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing schema rules");
+
+    let body = serde_json::to_vec(&state.schemas)
+        .map_err(|e| format!("Failed to serialize schemas: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn validate_rule(rule: &SchemaRule) -> Result<(), String> {
+    if rule.schema.is_some() && rule.format != LabelFormat::Json {
+        return Err("A JSON Schema can only be attached to the json format".to_string());
+    }
+    if matches!(&rule.schema, Some(schema) if !schema.is_object()) {
+        return Err("schema must be a JSON object".to_string());
+    }
+    Ok(())
+}
+
+pub fn handle_register_schema(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Registering schema rule");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let rule: SchemaRule = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err(e) = validate_rule(&rule) {
+        return Ok(error_response(400, &e));
+    }
+
+    log(&format!("Registered schema rule for prefix: {}", rule.prefix));
+
+    // Registering a prefix again replaces its previous rule
+    state.schemas.retain(|existing| existing.prefix != rule.prefix);
+    state.schemas.push(rule);
+    state.schemas.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+pub fn handle_delete_schema(state: &mut StoreViewerState, prefix: &str) -> Result<HttpResponse, String> {
+    log(&format!("Deleting schema rule for prefix: {}", prefix));
+
+    let before = state.schemas.len();
+    state.schemas.retain(|rule| rule.prefix != prefix);
+    if state.schemas.len() == before {
+        return Ok(error_response(404, &format!("No schema registered for prefix: {}", prefix)));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,166 @@
+//! Find in one label.
+//!
+//! `GET /api/labels/{name}/search?q=` reports where a string occurs in a text
+//! label, by line number and byte offset with a snippet of the line around
+//! it, so the web UI can find in a document too large to load whole and then
+//! fetch only the part it needs with a `Range` request on `/raw`. Offsets
+//! count bytes of the label as stored when it is UTF-8, and of its text
+//! transcoded to UTF-8 otherwise.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, json_response, labelmeta, query_param, StoreViewerState};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+/// Matches returned when the request does not say, and the most allowed.
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// Longest query accepted.
+const MAX_QUERY_LEN: usize = 1000;
+
+/// Bytes of context kept on each side of a match in its snippet.
+const CONTEXT_BYTES: usize = 80;
+
+#[derive(Serialize)]
+struct SearchMatch {
+    /// Line the match starts on, counting from 1
+    line: usize,
+    /// Byte offset of the match in the label
+    offset: usize,
+    /// Length of the match in bytes
+    length: usize,
+    /// Byte offset of the match from the start of its line
+    column: usize,
+    /// The line around the match, cut at `CONTEXT_BYTES` either side
+    snippet: String,
+}
+
+#[derive(Serialize)]
+struct SearchResponse<'a> {
+    label: &'a str,
+    query: &'a str,
+    size_bytes: usize,
+    /// Matches in the whole label
+    total: usize,
+    matches: Vec<SearchMatch>,
+    /// Where the next page continues with `?from=`, if there are more matches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<usize>,
+}
+
+fn build_pattern(query: &str, regex: bool, case_sensitive: bool) -> Result<Regex, String> {
+    let pattern = if regex { query.to_string() } else { regex::escape(query) };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {}", e))
+}
+
+/// The nearest char boundary in `text` at or below `index`, or at or above
+/// it when `up`.
+fn boundary(text: &str, mut index: usize, up: bool) -> usize {
+    while !text.is_char_boundary(index) {
+        if up {
+            index += 1;
+        } else {
+            index -= 1;
+        }
+    }
+    index
+}
+
+/// The line holding `start..end`, which begins at `line_start`, cut to the
+/// context around the match.
+fn snippet(text: &str, line_start: usize, start: usize, end: usize) -> String {
+    let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+    let from = boundary(text, start.saturating_sub(CONTEXT_BYTES).max(line_start), false);
+    let to = boundary(text, end.saturating_add(CONTEXT_BYTES).min(line_end), true);
+    text[from..to].trim_end_matches('\r').to_string()
+}
+
+fn parse_flag(req: &HttpRequest, name: &str) -> bool {
+    query_param(&req.uri, name).as_deref() == Some("true")
+}
+
+/// `GET /api/labels/{name}/search?q=&regex=&case_sensitive=&from=&limit=`
+pub fn handle_search(state: &StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Searching label: {}", label_name));
+
+    let query = match query_param(&req.uri, "q") {
+        Some(query) if !query.is_empty() => query,
+        _ => return Ok(error_response(400, "q is required")),
+    };
+    if query.len() > MAX_QUERY_LEN {
+        return Ok(error_response(400, &format!("q must be at most {} bytes", MAX_QUERY_LEN)));
+    }
+    let pattern = match build_pattern(&query, parse_flag(req, "regex"), parse_flag(req, "case_sensitive")) {
+        Ok(pattern) => pattern,
+        Err(e) => return Ok(error_response(400, &e)),
+    };
+    let from = match query_param(&req.uri, "from").map(|from| from.parse::<usize>()) {
+        None => 0,
+        Some(Ok(from)) => from,
+        Some(Err(_)) => return Ok(error_response(400, "from must be a byte offset")),
+    };
+    let limit = match query_param(&req.uri, "limit").map(|limit| limit.parse::<usize>()) {
+        None => DEFAULT_LIMIT,
+        Some(Ok(limit)) if limit > 0 => limit.min(MAX_LIMIT),
+        Some(_) => return Ok(error_response(400, "limit must be a positive number")),
+    };
+
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+    let text = match labelmeta::decode_text(state, label_name, &content) {
+        Some(text) => text,
+        None => return Ok(error_response(415, &format!("Label {} is not text", label_name))),
+    };
+
+    // Count lines incrementally as the matches come in order
+    let mut total = 0;
+    let mut matches = Vec::new();
+    let mut next_offset = None;
+    let (mut line, mut line_start, mut counted_to) = (1, 0, 0);
+    for found in pattern.find_iter(&text).filter(|found| !found.is_empty()) {
+        total += 1;
+        if found.start() < from || next_offset.is_some() {
+            continue;
+        }
+        if matches.len() == limit {
+            next_offset = Some(found.start());
+            continue;
+        }
+        for (i, byte) in text.as_bytes()[counted_to..found.start()].iter().enumerate() {
+            if *byte == b'\n' {
+                line += 1;
+                line_start = counted_to + i + 1;
+            }
+        }
+        counted_to = found.start();
+        matches.push(SearchMatch {
+            line,
+            offset: found.start(),
+            length: found.len(),
+            column: found.start() - line_start,
+            snippet: snippet(&text, line_start, found.start(), found.end()),
+        });
+    }
+
+    let response = SearchResponse {
+        label: label_name,
+        query: &query,
+        size_bytes: content.len(),
+        total,
+        matches,
+        next_offset,
+    };
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize search results: {}", e))?;
+
+    Ok(json_response(200, body))
+}
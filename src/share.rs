@@ -0,0 +1,217 @@
+//! Public read-only sharing of label prefixes.
+//!
+//! A prefix marked as shared can be read by anyone under `/public/{name}`,
+//! without going through the API. Only labels under a shared prefix are
+//! reachable there, nothing can be written, and each client gets a small
+//! per-minute request budget per share. Shared text is always served as
+//! `text/plain` in a sandbox, so a shared document cannot run script against
+//! the viewer's own origin.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{
+    error_response, header, json_response, labelmeta, longest_prefix_match, percent_decode, StoreViewerState,
+    SYSTEM_LABEL_PREFIX,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Path under which shared labels are served.
+pub const PUBLIC_PATH: &str = "/public/";
+
+const WINDOW_MS: u64 = 60 * 1000;
+
+/// Rate-limit windows kept at once; the oldest is dropped to make room.
+const MAX_WINDOWS: usize = 10_000;
+
+fn default_requests_per_minute() -> u32 {
+    30
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PublicShare {
+    pub prefix: String,
+    /// Requests each client may make per minute
+    #[serde(default = "default_requests_per_minute")]
+    pub requests_per_minute: u32,
+    /// Whether `/public/{prefix}/` lists the labels under a path
+    #[serde(default)]
+    pub allow_listing: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Window {
+    started_at: u64,
+    count: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Shares {
+    #[serde(default)]
+    pub shares: Vec<PublicShare>,
+    /// Current rate-limit window per share prefix and client
+    #[serde(default)]
+    windows: BTreeMap<String, Window>,
+}
+
+/// Identify the client for rate limiting. The runtime does not pass the peer
+/// address, so proxies' forwarding headers are used when present and all
+/// other clients share one budget.
+fn client_key(req: &HttpRequest) -> String {
+    header(req, "x-forwarded-for")
+        .and_then(|value| value.split(',').next())
+        .or_else(|| header(req, "x-real-ip"))
+        .map(|value| value.trim().to_string())
+        .unwrap_or_else(|| "anonymous".to_string())
+}
+
+impl Shares {
+    /// Count a request against the client's budget for `share`; returns the
+    /// milliseconds until the window resets if the budget is used up.
+    fn take(&mut self, share: &PublicShare, client: &str, now: u64) -> Result<(), u64> {
+        self.windows.retain(|_, window| now < window.started_at + WINDOW_MS);
+
+        // Forwarding headers are the client's to choose, so the number of
+        // clients is bounded here rather than by who asks
+        let key = format!("{}\n{}", share.prefix, client);
+        if !self.windows.contains_key(&key) && self.windows.len() >= MAX_WINDOWS {
+            let oldest = self
+                .windows
+                .iter()
+                .min_by_key(|(_, window)| window.started_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.windows.remove(&oldest);
+            }
+        }
+
+        let window = self.windows.entry(key).or_insert(Window { started_at: now, count: 0 });
+        if window.count >= share.requests_per_minute {
+            return Err(window.started_at + WINDOW_MS - now);
+        }
+        window.count += 1;
+        Ok(())
+    }
+}
+
+fn rate_limited(retry_after_ms: u64) -> HttpResponse {
+    let mut response = error_response(429, "Too many requests to this share, try again later");
+    response
+        .headers
+        .push(("Retry-After".to_string(), retry_after_ms.div_ceil(1000).to_string()));
+    response
+}
+
+/// `GET /public/{*name}`
+pub fn handle_public(state: &mut StoreViewerState, req: &HttpRequest, path: &str) -> Result<HttpResponse, String> {
+    let name = percent_decode(path.strip_prefix(PUBLIC_PATH).unwrap_or(""), false);
+    log(&format!("Public request for: {}", name));
+
+    // Unshared names look exactly like missing ones
+    let not_found = || error_response(404, &format!("Not found: {}", name));
+    if name.is_empty() || name.starts_with(SYSTEM_LABEL_PREFIX) {
+        return Ok(not_found());
+    }
+    let share = match longest_prefix_match(&state.shares.shares, &name, |share| &share.prefix) {
+        Some(share) => share.clone(),
+        None => return Ok(not_found()),
+    };
+
+    // Public clients must not move the actor's clock, nor choose the time
+    // their own budget is measured on
+    let now = state.clock_ms;
+    if let Err(retry_after_ms) = state.shares.take(&share, &client_key(req), now) {
+        log(&format!("Rate limited public request for {}", name));
+        return Ok(rate_limited(retry_after_ms));
+    }
+
+    if name.ends_with('/') {
+        if !share.allow_listing {
+            return Ok(not_found());
+        }
+        let labels: Vec<String> = store::list_labels(&state.store_id)?
+            .into_iter()
+            .filter(|label| label.starts_with(&name))
+            .collect();
+        let body = serde_json::to_vec(&labels)
+            .map_err(|e| format!("Failed to serialize labels: {}", e))?;
+        return Ok(json_response(200, body));
+    }
+
+    let content_ref = match store::get_by_label(&state.store_id, &name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(not_found()),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+    state.stats.record_read(&name, state.clock_ms);
+
+    let (content_type, body) = match labelmeta::decode_text(state, &name, &content) {
+        Some(text) => ("text/plain; charset=utf-8", text.into_owned().into_bytes()),
+        None => ("application/octet-stream", content),
+    };
+    let mut headers = vec![
+        ("Content-Type".to_string(), content_type.to_string()),
+        ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+        ("Content-Security-Policy".to_string(), "sandbox".to_string()),
+    ];
+    if let Some(language) = labelmeta::meta_for(state, &name).and_then(|m| m.language.as_ref()) {
+        headers.push(("Content-Language".to_string(), language.clone()));
+    }
+    labelmeta::apply_headers(state, &name, &mut headers);
+
+    Ok(HttpResponse { status: 200, headers, body: Some(body) })
+}
+
+pub fn handle_list_shares(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing public shares");
+
+    let body = serde_json::to_vec(&state.shares.shares)
+        .map_err(|e| format!("Failed to serialize shares: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_put_share(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving public share");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let share: PublicShare = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    // Sharing everything, or the viewer's own bookkeeping, is never intended
+    if share.prefix.is_empty() {
+        return Ok(error_response(400, "A share needs a non-empty prefix"));
+    }
+    if share.prefix.starts_with(SYSTEM_LABEL_PREFIX) || SYSTEM_LABEL_PREFIX.starts_with(&share.prefix) {
+        return Ok(error_response(403, &format!("Labels under {} cannot be shared", SYSTEM_LABEL_PREFIX)));
+    }
+    if share.requests_per_minute == 0 {
+        return Ok(error_response(400, "requests_per_minute must be at least 1"));
+    }
+
+    log(&format!("Shared prefix publicly: {}", share.prefix));
+
+    // Sharing a prefix again replaces its settings
+    let shares = &mut state.shares.shares;
+    shares.retain(|existing| existing.prefix != share.prefix);
+    shares.push(share);
+    shares.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+pub fn handle_delete_share(state: &mut StoreViewerState, prefix: &str) -> Result<HttpResponse, String> {
+    log(&format!("Removing public share: {}", prefix));
+
+    let before = state.shares.shares.len();
+    state.shares.shares.retain(|share| share.prefix != prefix);
+    if state.shares.shares.len() == before {
+        return Ok(error_response(404, &format!("Prefix is not shared: {}", prefix)));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
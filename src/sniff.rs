@@ -0,0 +1,178 @@
+//! Format detection for label content.
+//!
+//! `GET /api/labels/{name}` reports the `detected_format` of the content so
+//! clients can pick a renderer or highlighting mode. Binary content is told
+//! apart by its magic bytes. For text the label's extension decides when it
+//! names a known format, and otherwise the text is sniffed: markup by its
+//! opening tag, structured formats by whether they parse, Markdown by its
+//! headings and fences.
+
+use crate::{archive, wasm};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+    Markdown,
+    Html,
+    Xml,
+    Svg,
+    Csv,
+    Wasm,
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Bmp,
+    Ico,
+    Pdf,
+    Mp3,
+    Mp4,
+    Ogg,
+    Woff,
+    Woff2,
+    Sqlite,
+    Zip,
+    Tar,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    Gzip,
+    Text,
+    Binary,
+}
+
+/// Formats known by their leading bytes.
+const SIGNATURES: &[(&[u8], Format)] = &[
+    (b"\x89PNG\r\n\x1a\n", Format::Png),
+    (b"\xff\xd8\xff", Format::Jpeg),
+    (b"GIF87a", Format::Gif),
+    (b"GIF89a", Format::Gif),
+    (b"%PDF-", Format::Pdf),
+    (b"ID3", Format::Mp3),
+    (b"\xff\xfb", Format::Mp3),
+    (b"OggS", Format::Ogg),
+    (b"wOFF", Format::Woff),
+    (b"wOF2", Format::Woff2),
+    (b"SQLite format 3\0", Format::Sqlite),
+    (b"\x00\x00\x01\x00", Format::Ico),
+    (b"BM", Format::Bmp),
+    (b"\x1f\x8b", Format::Gzip),
+];
+
+fn sniff_binary(content: &[u8]) -> Format {
+    if wasm::detect(content).is_some() {
+        return Format::Wasm;
+    }
+    match archive::detect(content) {
+        Some(archive::ArchiveFormat::Zip) => return Format::Zip,
+        Some(archive::ArchiveFormat::Tar) => return Format::Tar,
+        Some(archive::ArchiveFormat::TarGz) => return Format::TarGz,
+        None => {}
+    }
+    if content.len() >= 12 && content.starts_with(b"RIFF") && &content[8..12] == b"WEBP" {
+        return Format::Webp;
+    }
+    if content.len() >= 12 && &content[4..8] == b"ftyp" {
+        return Format::Mp4;
+    }
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| content.starts_with(signature))
+        .map_or(Format::Binary, |&(_, format)| format)
+}
+
+fn from_extension(label: &str) -> Option<Format> {
+    let (_, extension) = label.rsplit_once('.')?;
+    match extension.to_ascii_lowercase().as_str() {
+        "json" => Some(Format::Json),
+        "yaml" | "yml" => Some(Format::Yaml),
+        "toml" => Some(Format::Toml),
+        "md" | "markdown" => Some(Format::Markdown),
+        "html" | "htm" => Some(Format::Html),
+        "xml" => Some(Format::Xml),
+        "svg" => Some(Format::Svg),
+        "csv" => Some(Format::Csv),
+        _ => None,
+    }
+}
+
+/// A heading (`# ` to `###### `) or a code fence on any line.
+fn is_markdown(text: &str) -> bool {
+    text.lines().any(|line| {
+        let hashes = line.len() - line.trim_start_matches('#').len();
+        ((1..=6).contains(&hashes) && line[hashes..].starts_with(' ')) || line.starts_with("```")
+    })
+}
+
+/// YAML accepts nearly any text as a plain scalar, so only documents whose
+/// every line is a key, a list item or nested under one count.
+fn is_yaml(text: &str) -> bool {
+    let mut lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#') && *line != "---")
+        .peekable();
+    lines.peek().is_some()
+        && lines.all(|line| line.starts_with([' ', '\t']) || line.starts_with("- ") || line.contains(':'))
+        && matches!(
+            serde_yaml::from_str::<serde_yaml::Value>(text),
+            Ok(serde_yaml::Value::Mapping(_)) | Ok(serde_yaml::Value::Sequence(_))
+        )
+}
+
+fn sniff_text(text: &str) -> Format {
+    let start = text.trim_start();
+    let lower: String = start.chars().take(64).collect::<String>().to_ascii_lowercase();
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        return Format::Html;
+    }
+    if start.starts_with("<svg") || (start.starts_with("<?xml") && text.contains("<svg")) {
+        return Format::Svg;
+    }
+    if start.starts_with("<?xml") {
+        return Format::Xml;
+    }
+    if (start.starts_with('{') || start.starts_with('['))
+        && serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok()
+    {
+        return Format::Json;
+    }
+    if text.lines().any(|line| line.contains('=')) && text.parse::<toml::Table>().is_ok() {
+        return Format::Toml;
+    }
+    if is_yaml(text) {
+        return Format::Yaml;
+    }
+    if is_markdown(text) {
+        return Format::Markdown;
+    }
+    Format::Text
+}
+
+impl Format {
+    /// Whether content of this format is text.
+    pub fn is_text(self) -> bool {
+        matches!(
+            self,
+            Format::Json
+                | Format::Yaml
+                | Format::Toml
+                | Format::Markdown
+                | Format::Html
+                | Format::Xml
+                | Format::Svg
+                | Format::Csv
+                | Format::Text
+        )
+    }
+}
+
+/// The format of `content`, whose text is `text` when it is text at all.
+pub fn detect(label: &str, content: &[u8], text: Option<&str>) -> Format {
+    match text {
+        Some(text) => from_extension(label).unwrap_or_else(|| sniff_text(text)),
+        None => sniff_binary(content),
+    }
+}
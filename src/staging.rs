@@ -0,0 +1,275 @@
+//! Staged imports.
+//!
+//! A large import given `?stage=true` does not touch the labels it names: its
+//! content is kept under `__system/staging/{id}/` as a staging set, out of
+//! listings and out of reach of the API's writes. `GET /api/staging/{id}`
+//! reviews a set: for every label, what committing it would do to the live
+//! label and anything the naming rules, schemas and other write checks reject,
+//! checked against the rules as they are at review time.
+//! `POST /api/staging/{id}/commit` writes the whole set under one journal
+//! entry, or nothing at all if any label is rejected, and
+//! `DELETE /api/staging/{id}` discards it.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::dryrun::{self, ChangeAction, PlannedChange};
+use crate::journal::{self, Step};
+use crate::{error_response, import, json_response, query_param, write_journaled, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Staging sets kept at once; more wait for one to be committed or discarded.
+const MAX_SETS: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StagedSet {
+    pub id: u64,
+    /// What was imported: `document`, or `store:<id>` for a store import
+    pub source: String,
+    pub staged_at: u64,
+    /// Staged content ref by the label it is for
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Staging {
+    next_id: u64,
+    sets: Vec<StagedSet>,
+}
+
+#[derive(Serialize)]
+struct SetSummary<'a> {
+    id: u64,
+    source: &'a str,
+    staged_at: u64,
+    labels: usize,
+}
+
+#[derive(Serialize)]
+struct RejectedLabel {
+    label: String,
+    error: String,
+}
+
+#[derive(Serialize, Default)]
+struct ReviewSummary {
+    create: usize,
+    overwrite: usize,
+    unchanged: usize,
+    rejected: usize,
+}
+
+#[derive(Serialize)]
+struct Review<'a> {
+    id: u64,
+    source: &'a str,
+    staged_at: u64,
+    /// Whether the set can be committed as it is
+    valid: bool,
+    summary: ReviewSummary,
+    changes: Vec<PlannedChange>,
+    rejected: Vec<RejectedLabel>,
+}
+
+#[derive(Serialize, Default)]
+struct CommitResponse {
+    id: u64,
+    created: Vec<String>,
+    overwritten: Vec<String>,
+    /// Labels that already held the staged content
+    unchanged: Vec<String>,
+}
+
+/// Whether an import asks to be staged.
+pub fn is_staged(req: &HttpRequest) -> bool {
+    query_param(&req.uri, "stage").as_deref() == Some("true")
+}
+
+fn staged_label(id: u64, label: &str) -> String {
+    format!("{}staging/{}/{}", SYSTEM_LABEL_PREFIX, id, label)
+}
+
+fn find<'a>(state: &'a StoreViewerState, id: &str) -> Option<&'a StagedSet> {
+    let id = id.parse::<u64>().ok()?;
+    state.staging.sets.iter().find(|set| set.id == id)
+}
+
+/// Keep imported `labels` as a new staging set; answers with its summary.
+pub fn stage(state: &mut StoreViewerState, source: String, labels: Vec<(String, Vec<u8>)>) -> Result<HttpResponse, String> {
+    if state.staging.sets.len() >= MAX_SETS {
+        return Ok(error_response(409, &format!(
+            "{} staging sets are waiting; commit or discard one first",
+            MAX_SETS
+        )));
+    }
+    state.staging.next_id += 1;
+    let id = state.staging.next_id;
+
+    let mut staged = BTreeMap::new();
+    for (label, content) in labels {
+        let content_ref = store::store_at_label(&state.store_id, &staged_label(id, &label), &content)?;
+        staged.insert(label, content_ref.hash);
+    }
+    let set = StagedSet { id, source, staged_at: state.clock_ms, labels: staged };
+
+    log(&format!("Staged {} labels from {} as set {}", set.labels.len(), set.source, id));
+
+    let summary = SetSummary { id, source: &set.source, staged_at: set.staged_at, labels: set.labels.len() };
+    let body = serde_json::to_vec(&summary)
+        .map_err(|e| format!("Failed to serialize staging set: {}", e))?;
+    state.staging.sets.push(set);
+
+    Ok(json_response(200, body))
+}
+
+/// What committing `set` would do, and why any of it would be refused.
+fn plan(state: &StoreViewerState, set: &StagedSet) -> Result<(Vec<PlannedChange>, Vec<RejectedLabel>), String> {
+    let mut changes = Vec::with_capacity(set.labels.len());
+    let mut rejected = Vec::new();
+    for (label, hash) in &set.labels {
+        let content = store::get(&state.store_id, &ContentRef { hash: hash.clone() })?;
+        let mut change = dryrun::plan_write(&state.store_id, label, &content)?;
+        change.content_ref = Some(hash.clone());
+        for error in import::rejections(state, &change, &content) {
+            rejected.push(RejectedLabel { label: label.clone(), error });
+        }
+        changes.push(change);
+    }
+    Ok((changes, rejected))
+}
+
+/// Remove a set and its staged labels.
+fn remove(state: &mut StoreViewerState, id: u64) -> Result<(), String> {
+    let position = match state.staging.sets.iter().position(|set| set.id == id) {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+    let set = state.staging.sets.remove(position);
+    for label in set.labels.keys() {
+        store::remove_label(&state.store_id, &staged_label(id, label))?;
+    }
+    Ok(())
+}
+
+/// `GET /api/staging`
+pub fn handle_list(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing staging sets");
+
+    let summaries: Vec<SetSummary> = state
+        .staging
+        .sets
+        .iter()
+        .map(|set| SetSummary { id: set.id, source: &set.source, staged_at: set.staged_at, labels: set.labels.len() })
+        .collect();
+
+    let body = serde_json::to_vec(&summaries)
+        .map_err(|e| format!("Failed to serialize staging sets: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `GET /api/staging/{id}`
+pub fn handle_review(state: &StoreViewerState, id: &str) -> Result<HttpResponse, String> {
+    log(&format!("Reviewing staging set: {}", id));
+
+    let set = match find(state, id) {
+        Some(set) => set,
+        None => return Ok(error_response(404, &format!("Staging set not found: {}", id))),
+    };
+    let (changes, rejected) = plan(state, set)?;
+
+    let mut summary = ReviewSummary { rejected: rejected.len(), ..ReviewSummary::default() };
+    for change in &changes {
+        match change.action {
+            ChangeAction::Create => summary.create += 1,
+            ChangeAction::Overwrite => summary.overwrite += 1,
+            _ => summary.unchanged += 1,
+        }
+    }
+    let review = Review {
+        id: set.id,
+        source: &set.source,
+        staged_at: set.staged_at,
+        valid: rejected.is_empty(),
+        summary,
+        changes,
+        rejected,
+    };
+    let body = serde_json::to_vec(&review)
+        .map_err(|e| format!("Failed to serialize staging review: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/staging/{id}/commit`
+pub fn handle_commit(state: &mut StoreViewerState, id: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Committing staging set: {}", id));
+
+    let set = match find(state, id) {
+        Some(set) => set.clone(),
+        None => return Ok(error_response(404, &format!("Staging set not found: {}", id))),
+    };
+    let (changes, rejected) = plan(state, &set)?;
+    if !rejected.is_empty() {
+        let errors: Vec<&str> = rejected.iter().map(|r| r.error.as_str()).collect();
+        return Ok(error_response(422, &format!("Staging set {} rejected: {}", set.id, errors.join("; "))));
+    }
+
+    if dryrun::is_dry_run(req) {
+        return dryrun::dry_run_response(&changes);
+    }
+
+    // The staged content is stored already, so the journal only re-points labels
+    let steps = changes
+        .iter()
+        .filter(|change| change.action != ChangeAction::Unchanged)
+        .map(|change| Step::point(&change.label, &ContentRef { hash: set.labels[&change.label].clone() }))
+        .collect();
+    let journal_id = journal::begin(state, "commit_staging", steps)?;
+
+    let mut response = CommitResponse { id: set.id, ..CommitResponse::default() };
+    for change in changes {
+        if change.action == ChangeAction::Unchanged {
+            response.unchanged.push(change.label);
+            continue;
+        }
+        let content = store::get(&state.store_id, &ContentRef { hash: set.labels[&change.label].clone() })?;
+        write_journaled(state, &change.label, &content)?;
+        if change.action == ChangeAction::Create {
+            response.created.push(change.label);
+        } else {
+            response.overwritten.push(change.label);
+        }
+    }
+    journal::complete(state, journal_id)?;
+
+    remove(state, set.id)?;
+
+    log(&format!(
+        "Committed staging set {}: {} created, {} overwritten, {} unchanged",
+        set.id,
+        response.created.len(),
+        response.overwritten.len(),
+        response.unchanged.len()
+    ));
+
+    let body = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `DELETE /api/staging/{id}`
+pub fn handle_discard(state: &mut StoreViewerState, id: &str) -> Result<HttpResponse, String> {
+    log(&format!("Discarding staging set: {}", id));
+
+    let set_id = match find(state, id) {
+        Some(set) => set.id,
+        None => return Ok(error_response(404, &format!("Staging set not found: {}", id))),
+    };
+    remove(state, set_id)?;
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,139 @@
+//! Startup self-test.
+//!
+//! At the end of `init` the viewer checks that it can actually work: a probe
+//! label is written, read back and removed, every documented endpoint is
+//! matched against the routes that were registered, and optional
+//! capabilities are recorded. Failures do not stop the actor; they are logged
+//! and kept in a report served at `GET /api/admin/startup`, so a broken
+//! deployment can be diagnosed from its first request.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{discovery, json_response, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Content written to the probe label.
+const PROBE_CONTENT: &[u8] = b"store-viewer startup probe";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StartupReport {
+    /// Whether every check passed
+    pub ok: bool,
+    pub actor_id: String,
+    pub store_id: String,
+    pub checks: Vec<Check>,
+    /// Optional capabilities and whether they are available
+    pub capabilities: BTreeMap<String, bool>,
+    /// Routes registered with the HTTP server
+    pub routes: usize,
+}
+
+impl StartupReport {
+    pub fn new(actor_id: &str, store_id: &str) -> Self {
+        StartupReport {
+            ok: true,
+            actor_id: actor_id.to_string(),
+            store_id: store_id.to_string(),
+            ..StartupReport::default()
+        }
+    }
+
+    /// Record the outcome of a check.
+    pub fn check(&mut self, name: &str, result: Result<(), String>) {
+        if let Err(e) = &result {
+            log(&format!("Startup check {} failed: {}", name, e));
+            self.ok = false;
+        }
+        self.checks.push(Check {
+            name: name.to_string(),
+            ok: result.is_ok(),
+            detail: result.err(),
+        });
+    }
+
+    pub fn capability(&mut self, name: &str, available: bool) {
+        self.capabilities.insert(name.to_string(), available);
+    }
+}
+
+/// Write, read back and remove a probe label.
+pub fn probe_store(store_id: &str) -> Result<(), String> {
+    let label = format!("{}startup-probe", SYSTEM_LABEL_PREFIX);
+    let written = store::store_at_label(store_id, &label, PROBE_CONTENT)
+        .map_err(|e| format!("write failed: {}", e))?;
+    let read_ref = store::get_by_label(store_id, &label)
+        .map_err(|e| format!("label lookup failed: {}", e))?;
+    let content = store::get(store_id, &written).map_err(|e| format!("read failed: {}", e))?;
+    store::remove_label(store_id, &label).map_err(|e| format!("delete failed: {}", e))?;
+
+    if read_ref.map(|r| r.hash) != Some(written.hash) {
+        return Err("probe label did not point at the written content".to_string());
+    }
+    if content != PROBE_CONTENT {
+        return Err("probe content read back differs from what was written".to_string());
+    }
+    Ok(())
+}
+
+/// Whether the segments of a registered route pattern match a path. `{*name}`
+/// spans one or more segments, `{name}` exactly one.
+fn route_matches(route: &[&str], path: &[&str]) -> bool {
+    match route.split_first() {
+        None => path.is_empty(),
+        Some((segment, rest)) if segment.starts_with("{*") => (1..=path.len()).any(|n| route_matches(rest, &path[n..])),
+        Some((segment, rest)) if segment.starts_with('{') => !path.is_empty() && route_matches(rest, &path[1..]),
+        Some((segment, rest)) => path.first() == Some(segment) && route_matches(rest, &path[1..]),
+    }
+}
+
+/// Check that every documented endpoint is served by a registered route
+/// other than the catch-all page route.
+pub fn check_routes(registered: &[(&str, &str)]) -> Result<(), String> {
+    let routes: Vec<(&str, Vec<&str>)> = registered
+        .iter()
+        .filter(|(_, path)| *path != "/{*path}")
+        .map(|&(method, path)| (method, path.split('/').collect()))
+        .collect();
+
+    let missing: Vec<String> = discovery::ENDPOINTS
+        .iter()
+        .filter(|endpoint| {
+            // Stand in a sample segment for every placeholder
+            let sample: Vec<&str> = endpoint
+                .path
+                .split('/')
+                .map(|segment| if segment.starts_with('{') { "x" } else { segment })
+                .collect();
+            !routes
+                .iter()
+                .any(|(method, route)| *method == endpoint.method && route_matches(route, &sample))
+        })
+        .map(|endpoint| format!("{} {}", endpoint.method, endpoint.path))
+        .collect();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("no route for {}", missing.join(", ")))
+    }
+}
+
+/// `GET /api/admin/startup`
+pub fn handle_get_report(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting startup report");
+
+    let body = serde_json::to_vec(&state.startup)
+        .map_err(|e| format!("Failed to serialize startup report: {}", e))?;
+
+    Ok(json_response(200, body))
+}
@@ -0,0 +1,166 @@
+//! Per-label access statistics.
+//!
+//! Every read and write that goes through the API is counted per label along
+//! with the time of the most recent access, which makes it easy to spot hot
+//! labels as well as ones nobody has touched in a long time.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, json_response, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LabelStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub last_read_at: Option<u64>,
+    pub last_write_at: Option<u64>,
+}
+
+impl LabelStats {
+    pub fn total(&self) -> u64 {
+        self.reads + self.writes
+    }
+
+    pub fn last_access_at(&self) -> Option<u64> {
+        self.last_read_at.max(self.last_write_at)
+    }
+}
+
+/// Access counters for every label, keyed by label name.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct AccessStats {
+    labels: BTreeMap<String, LabelStats>,
+}
+
+impl AccessStats {
+    pub fn record_read(&mut self, label: &str, now: u64) {
+        let entry = self.labels.entry(label.to_string()).or_default();
+        entry.reads += 1;
+        entry.last_read_at = Some(now);
+    }
+
+    pub fn record_write(&mut self, label: &str, now: u64) {
+        let entry = self.labels.entry(label.to_string()).or_default();
+        entry.writes += 1;
+        entry.last_write_at = Some(now);
+    }
+
+    /// Number of labels with recorded statistics.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Take over another set of statistics; its entries win on conflict.
+    pub fn merge(&mut self, other: AccessStats) {
+        self.labels.extend(other.labels);
+    }
+
+    /// Forget a label, e.g. once it has been removed from the store.
+    pub fn remove(&mut self, label: &str) {
+        self.labels.remove(label);
+    }
+
+    /// Carry a label's statistics over to its new name after a move.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let Some(stats) = self.labels.remove(from) {
+            self.labels.insert(to.to_string(), stats);
+        }
+    }
+
+    /// Statistics for a label; labels never accessed through the API report zeros.
+    pub fn get(&self, label: &str) -> LabelStats {
+        self.labels.get(label).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Serialize)]
+pub struct LabelStatsResponse {
+    pub name: String,
+    pub reads: u64,
+    pub writes: u64,
+    pub total: u64,
+    pub last_read_at: Option<u64>,
+    pub last_write_at: Option<u64>,
+    pub last_access_at: Option<u64>,
+}
+
+impl LabelStatsResponse {
+    pub fn new(name: &str, stats: &LabelStats) -> Self {
+        Self {
+            name: name.to_string(),
+            reads: stats.reads,
+            writes: stats.writes,
+            total: stats.total(),
+            last_read_at: stats.last_read_at,
+            last_write_at: stats.last_write_at,
+            last_access_at: stats.last_access_at(),
+        }
+    }
+}
+
+/// Order of the label listing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListSort {
+    Name,
+    MostAccessed,
+    LeastAccessed,
+}
+
+impl ListSort {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "name" => Some(ListSort::Name),
+            "most_accessed" => Some(ListSort::MostAccessed),
+            "least_accessed" => Some(ListSort::LeastAccessed),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ListSort::Name => "name",
+            ListSort::MostAccessed => "most_accessed",
+            ListSort::LeastAccessed => "least_accessed",
+        }
+    }
+}
+
+/// Sort label names in place according to `sort`, breaking ties by name.
+pub fn sort_labels(labels: &mut [String], sort: ListSort, stats: &AccessStats) {
+    match sort {
+        ListSort::Name => labels.sort(),
+        ListSort::MostAccessed => labels.sort_by(|a, b| {
+            let (sa, sb) = (stats.get(a), stats.get(b));
+            sb.total()
+                .cmp(&sa.total())
+                .then_with(|| sb.last_access_at().cmp(&sa.last_access_at()))
+                .then_with(|| a.cmp(b))
+        }),
+        ListSort::LeastAccessed => labels.sort_by(|a, b| {
+            let (sa, sb) = (stats.get(a), stats.get(b));
+            sa.total()
+                .cmp(&sb.total())
+                .then_with(|| sa.last_access_at().cmp(&sb.last_access_at()))
+                .then_with(|| a.cmp(b))
+        }),
+    }
+}
+
+pub fn handle_label_stats(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Getting stats for label: {}", label_name));
+
+    if store::get_by_label(&state.store_id, label_name)?.is_none() {
+        return Ok(error_response(404, &format!("Label not found: {}", label_name)));
+    }
+
+    let response_data = LabelStatsResponse::new(label_name, &state.stats.get(label_name));
+
+    let body = serde_json::to_vec(&response_data)
+        .map_err(|e| format!("Failed to serialize stats: {}", e))?;
+
+    Ok(json_response(200, body))
+}
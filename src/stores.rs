@@ -0,0 +1,220 @@
+//! Browsing several stores from one viewer.
+//!
+//! The viewer has a store of its own, and further stores can be registered
+//! at init (`stores`) or through `POST /api/stores`. A label request is
+//! directed at a registered store either with `?store=<id>` or by prefixing
+//! its path with `/api/stores/{id}`, so `/api/stores/{id}/labels/a` is
+//! `/api/labels/a` in that store. For the duration of such a request the
+//! selected store stands in for the viewer's own, so every label endpoint
+//! works unchanged, and the access statistics, metadata, archive records and
+//! channel pins the viewer keeps about that store's labels stand in for those
+//! of its own. Deletions there skip the trash and are not remembered for undo,
+//! as both keep their records for the viewer's own store.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::archival::ArchivedLabel;
+use crate::channels::ChannelPin;
+use crate::labelmeta::LabelMeta;
+use crate::stats::AccessStats;
+use crate::{error_response, json_response, percent_decode, query_param, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Paths that can be directed at another store.
+const LABELS_PATH: &str = "/api/labels";
+
+/// Prefix of the per-store routes.
+pub const STORES_PATH: &str = "/api/stores/";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StoreEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub added_at: u64,
+    #[serde(default)]
+    pub records: LabelRecords,
+}
+
+/// What the viewer records about the labels of one store, under the same
+/// names as the fields of the state that hold them for its own.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LabelRecords {
+    #[serde(default)]
+    stats: AccessStats,
+    #[serde(default)]
+    label_meta: BTreeMap<String, LabelMeta>,
+    #[serde(default)]
+    archived: BTreeMap<String, ArchivedLabel>,
+    #[serde(default)]
+    channels: BTreeMap<String, BTreeMap<String, ChannelPin>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Stores {
+    /// Stores other than the viewer's own, by id
+    #[serde(default)]
+    pub registered: BTreeMap<String, StoreEntry>,
+    /// The viewer's own store while a request works on another
+    #[serde(skip)]
+    home: Option<String>,
+}
+
+impl Stores {
+    pub fn new(registered: BTreeMap<String, StoreEntry>) -> Self {
+        Stores { registered, home: None }
+    }
+}
+
+#[derive(Deserialize)]
+struct RegisterRequest {
+    /// Existing store to add; a new store is created when omitted
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Serialize)]
+struct StoreListEntry<'a> {
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    /// Whether this is the viewer's own store
+    default: bool,
+}
+
+#[derive(Serialize)]
+struct RegisterResponse {
+    id: String,
+    created: bool,
+}
+
+/// Whether the request works on the viewer's own store.
+pub fn is_home(state: &StoreViewerState) -> bool {
+    state.stores.home.is_none()
+}
+
+/// The viewer's own store, whichever one the request works on.
+pub fn home_id(state: &StoreViewerState) -> &str {
+    state.stores.home.as_deref().unwrap_or(&state.store_id)
+}
+
+/// Whether `store_id` is the viewer's own store or one registered with it.
+pub fn is_known(state: &StoreViewerState, store_id: &str) -> bool {
+    store_id == home_id(state) || state.stores.registered.contains_key(store_id)
+}
+
+/// The store a request works on, when it is not the viewer's own.
+pub fn selected(state: &StoreViewerState) -> Option<&str> {
+    state.stores.home.as_ref().map(|_| state.store_id.as_str())
+}
+
+/// Direct the request at the store it names, if any. Returns the path to
+/// route when another store was selected; `restore` must follow.
+pub fn select(state: &mut StoreViewerState, req: &HttpRequest, path: &str) -> Result<Option<String>, HttpResponse> {
+    let (store_id, routed) = match path.strip_prefix(STORES_PATH).and_then(|rest| rest.split_once('/')) {
+        Some((id, rest)) if rest == "labels" || rest.starts_with("labels/") => {
+            (percent_decode(id, false), format!("/api/{}", rest))
+        }
+        Some(_) => return Err(error_response(404, &format!("Not found: {}", path))),
+        None => match query_param(&req.uri, "store") {
+            Some(id) if path == LABELS_PATH || path.starts_with("/api/labels/") => (id, path.to_string()),
+            Some(_) => return Err(error_response(400, "?store= only applies to /api/labels")),
+            None => return Ok(None),
+        },
+    };
+
+    // The viewer's own store, named explicitly, needs no swap
+    if store_id == state.store_id {
+        return Ok(Some(routed));
+    }
+    if !is_known(state, &store_id) {
+        return Err(error_response(404, &format!("Store not registered: {}", store_id)));
+    }
+    swap_records(state, &store_id);
+    let home = std::mem::replace(&mut state.store_id, store_id);
+    state.stores.home = Some(home);
+    Ok(Some(routed))
+}
+
+/// Put the viewer's own store back after a request selected another.
+pub fn restore(state: &mut StoreViewerState) {
+    if let Some(home) = state.stores.home.take() {
+        let selected = std::mem::replace(&mut state.store_id, home);
+        swap_records(state, &selected);
+    }
+}
+
+/// Exchange the label records in the state with those kept for `store_id`.
+fn swap_records(state: &mut StoreViewerState, store_id: &str) {
+    if let Some(entry) = state.stores.registered.get_mut(store_id) {
+        let records = &mut entry.records;
+        std::mem::swap(&mut state.stats, &mut records.stats);
+        std::mem::swap(&mut state.label_meta, &mut records.label_meta);
+        std::mem::swap(&mut state.archived, &mut records.archived);
+        std::mem::swap(&mut state.channels, &mut records.channels);
+    }
+}
+
+/// `GET /api/stores`
+pub fn handle_list_stores(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing stores");
+
+    let home = StoreListEntry { id: &state.store_id, name: None, default: true };
+    let others = state.stores.registered.iter().map(|(id, entry)| StoreListEntry {
+        id,
+        name: entry.name.as_deref(),
+        default: false,
+    });
+    let entries: Vec<StoreListEntry> = std::iter::once(home).chain(others).collect();
+
+    let body = serde_json::to_vec(&entries)
+        .map_err(|e| format!("Failed to serialize stores: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/stores` with `{ "id"?, "name"? }`
+pub fn handle_register_store(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Registering store");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let register: RegisterRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    let (id, created) = match register.id {
+        Some(id) if id.is_empty() => return Ok(error_response(400, "Store id cannot be empty")),
+        Some(id) if id == state.store_id => return Ok(error_response(409, "The viewer's own store is always available")),
+        Some(id) => {
+            if let Err(e) = store::list_labels(&id) {
+                return Ok(error_response(404, &format!("Store {} is not accessible: {}", id, e)));
+            }
+            (id, false)
+        }
+        None => (store::new()?, true),
+    };
+
+    log(&format!("Registered store {}", id));
+    state.stores.registered.insert(id.clone(), StoreEntry { name: register.name, added_at: state.clock_ms, ..StoreEntry::default() });
+
+    let body = serde_json::to_vec(&RegisterResponse { id, created })
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `DELETE /api/stores/{id}`: stop offering a store. Its labels are kept.
+pub fn handle_unregister_store(state: &mut StoreViewerState, store_id: &str) -> Result<HttpResponse, String> {
+    log(&format!("Unregistering store {}", store_id));
+
+    if state.stores.registered.remove(store_id).is_none() {
+        return Ok(error_response(404, &format!("Store not registered: {}", store_id)));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,72 @@
+//! Support for sync clients.
+//!
+//! A client holding copies of labels sends the refs it has and learns in one
+//! request which of them are out of date, so it only fetches what changed.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{json_response, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Deserialize)]
+struct HeadRequest {
+    /// Ref the client holds for each label; `null` if it has none
+    labels: BTreeMap<String, Option<String>>,
+    /// Also report server labels under this prefix the client did not list
+    #[serde(default)]
+    prefix: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DifferingLabel {
+    name: String,
+    /// `null` when the label does not exist on the server
+    server_ref: Option<String>,
+    /// `null` when the client does not have the label
+    client_ref: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HeadResponse {
+    differing: Vec<DifferingLabel>,
+    unchanged: usize,
+}
+
+/// `POST /api/labels/head`
+pub fn handle_bulk_head(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Comparing client label refs");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let head_req: HeadRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    let mut labels = head_req.labels;
+    if let Some(prefix) = &head_req.prefix {
+        for name in store::list_labels(&state.store_id)? {
+            if name.starts_with(prefix.as_str()) && !name.starts_with(SYSTEM_LABEL_PREFIX) {
+                labels.entry(name).or_insert(None);
+            }
+        }
+    }
+
+    let mut differing = Vec::new();
+    let mut unchanged = 0;
+    for (name, client_ref) in labels {
+        let server_ref = store::get_by_label(&state.store_id, &name)?.map(|r| r.hash);
+        if server_ref == client_ref {
+            unchanged += 1;
+        } else {
+            differing.push(DifferingLabel { name, server_ref, client_ref });
+        }
+    }
+
+    log(&format!("{} labels differ, {} unchanged", differing.len(), unchanged));
+
+    let body = serde_json::to_vec(&HeadResponse { differing, unchanged })
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
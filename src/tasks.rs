@@ -0,0 +1,57 @@
+//! Periodic task runner.
+//!
+//! The actor cannot set timers, so background work is driven by the requests
+//! it serves: every request first advances the clock and then gives each
+//! subsystem a chance to run whatever has become due. A task scheduled for a
+//! given time therefore runs with the first request at or after that time.
+
+use crate::bindings::theater::simple::runtime::log;
+use crate::{expiry, maintenance, mirror, retention, scheduler, timeline, trash, StoreViewerState};
+
+/// Run every task that has become due at the current clock. Tasks changing
+/// labels wait while the store is in maintenance.
+pub fn run_due(state: &mut StoreViewerState) {
+    if state.clock_ms == 0 {
+        // No time has been observed yet, nothing can be due
+        return;
+    }
+    let writable = !maintenance::is_active(state);
+
+    if writable {
+        let ran = scheduler::run_due(state);
+        if ran > 0 {
+            log(&format!("Ran {} scheduled writes", ran));
+        }
+
+        match retention::run_due(state) {
+            Ok(0) => {}
+            Ok(deleted) => log(&format!("Retention policies deleted {} labels", deleted)),
+            Err(e) => log(&format!("Error applying retention policies: {}", e)),
+        }
+    }
+
+    match expiry::run_due(state) {
+        Ok(0) => {}
+        Ok(sent) => log(&format!("Sent {} label expiry warnings", sent)),
+        Err(e) => log(&format!("Error checking label expiry: {}", e)),
+    }
+
+    if writable {
+        match trash::run_due(state) {
+            Ok(0) => {}
+            Ok(purged) => log(&format!("Purged {} deleted labels", purged)),
+            Err(e) => log(&format!("Error purging deleted labels: {}", e)),
+        }
+    }
+
+    let mirrored = mirror::run_due(state);
+    if mirrored > 0 {
+        log(&format!("Mirrored {} queued changes", mirrored));
+    }
+
+    match timeline::run_due(state) {
+        Ok(false) => {}
+        Ok(true) => log("Recorded daily store usage sample"),
+        Err(e) => log(&format!("Error sampling store usage: {}", e)),
+    }
+}
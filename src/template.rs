@@ -0,0 +1,89 @@
+//! Server-side inclusion of labels into others.
+//!
+//! A text label can pull in another label's content with
+//! `{{label:other/name}}`. `GET /api/labels/{name}/resolved` replaces every
+//! such reference with the referenced label's content, itself resolved first,
+//! so a config composed from fragments can be fetched fully materialized. A
+//! label that ends up including itself is an error rather than a loop, and
+//! the nesting depth and size of the result are bounded. References count as
+//! dependencies in the label reference graph.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, labelmeta, StoreViewerState};
+
+pub const OPEN: &str = "{{label:";
+pub const CLOSE: &str = "}}";
+
+/// Labels a resolution may be nested through.
+const MAX_DEPTH: usize = 32;
+
+/// Largest resolved document, in bytes.
+const MAX_RESOLVED_BYTES: usize = 16 * 1024 * 1024;
+
+/// Append the content of `label`, with its references resolved, to `out`.
+/// `chain` holds the labels being resolved around this one, outermost first.
+/// On failure, returns the HTTP status and message to report.
+fn resolve(state: &StoreViewerState, label: &str, chain: &mut Vec<String>, out: &mut String) -> Result<(), (u16, String)> {
+    if chain.iter().any(|outer| outer == label) {
+        return Err((422, format!("Reference cycle: {} -> {}", chain.join(" -> "), label)));
+    }
+    if chain.len() >= MAX_DEPTH {
+        return Err((422, format!("References are nested more than {} deep at {}", MAX_DEPTH, label)));
+    }
+
+    let content_ref = match store::get_by_label(&state.store_id, label).map_err(|e| (500, e))? {
+        Some(content_ref) => content_ref,
+        None => match chain.last() {
+            Some(outer) => return Err((422, format!("Label {} referenced from {} does not exist", label, outer))),
+            None => return Err((404, format!("Label not found: {}", label))),
+        },
+    };
+    let content = store::get(&state.store_id, &content_ref).map_err(|e| (500, e))?;
+    let text = match labelmeta::decode_text(state, label, &content) {
+        Some(text) => text,
+        None => return Err((415, format!("Label {} is binary and cannot be resolved", label))),
+    };
+
+    chain.push(label.to_string());
+    let mut rest: &str = &text;
+    while let Some(start) = rest.find(OPEN) {
+        let after = &rest[start + OPEN.len()..];
+        let end = match after.find(CLOSE) {
+            Some(end) => end,
+            // An unterminated reference is left as written
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        resolve(state, after[..end].trim(), chain, out)?;
+        if out.len() > MAX_RESOLVED_BYTES {
+            return Err((422, format!("Resolved content is larger than {} bytes", MAX_RESOLVED_BYTES)));
+        }
+        rest = &after[end + CLOSE.len()..];
+    }
+    out.push_str(rest);
+    chain.pop();
+    Ok(())
+}
+
+/// `GET /api/labels/{name}/resolved`
+pub fn handle_resolved(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Resolving label: {}", label_name));
+
+    let mut resolved = String::new();
+    match resolve(state, label_name, &mut Vec::new(), &mut resolved) {
+        Ok(()) => {}
+        Err((500, e)) => return Err(e),
+        Err((status, e)) => return Ok(error_response(status, &e)),
+    }
+
+    Ok(HttpResponse {
+        status: 200,
+        headers: vec![
+            ("Content-Type".to_string(), "text/plain; charset=utf-8".to_string()),
+            ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+        ],
+        body: Some(resolved.into_bytes()),
+    })
+}
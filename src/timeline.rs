@@ -0,0 +1,142 @@
+//! Store usage over time.
+//!
+//! Once a day the task runner counts the labels in the store and the bytes
+//! they reference, and appends the sample to the `__system/timeline` label.
+//! The samples let the UI chart how the store grows.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{clock, error_response, history, json_response, query_param, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Samples kept in the timeline label; older ones are dropped.
+const MAX_SAMPLES: usize = 2 * 366;
+
+pub fn timeline_label() -> String {
+    format!("{}timeline", SYSTEM_LABEL_PREFIX)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UsageTimeline {
+    /// Start of the last day a sample was taken for, in epoch milliseconds
+    #[serde(default)]
+    last_sampled_day: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UsageSample {
+    /// Start of the (UTC) day the sample belongs to
+    pub day: u64,
+    pub taken_at: u64,
+    pub labels: usize,
+    /// Sum of the content sizes of all labels
+    pub bytes: u64,
+    /// Size of the distinct content, counting shared content once
+    pub unique_bytes: u64,
+    /// Everything held by the store, including old versions and system labels
+    pub stored_bytes: u64,
+    /// Of that, what label history takes: its index and the old content only
+    /// it points at
+    #[serde(default)]
+    pub history_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Samples {
+    samples: Vec<UsageSample>,
+}
+
+#[derive(Serialize)]
+struct TimelineResponse {
+    label: String,
+    samples: Vec<UsageSample>,
+}
+
+fn load(store_id: &str) -> Result<Samples, String> {
+    match store::get_by_label(store_id, &timeline_label())? {
+        Some(content_ref) => serde_json::from_slice(&store::get(store_id, &content_ref)?)
+            .map_err(|e| format!("Corrupt usage timeline: {}", e)),
+        None => Ok(Samples::default()),
+    }
+}
+
+/// Measure the store as it is now. System labels are not counted.
+fn sample(state: &StoreViewerState, day: u64) -> Result<UsageSample, String> {
+    let mut labels = 0;
+    let mut bytes = 0;
+    let mut sizes = BTreeMap::new();
+    for label in store::list_labels(&state.store_id)? {
+        if label.starts_with(SYSTEM_LABEL_PREFIX) {
+            continue;
+        }
+        let content_ref = match store::get_by_label(&state.store_id, &label)? {
+            Some(content_ref) => content_ref,
+            None => continue,
+        };
+        let size = match sizes.get(&content_ref.hash) {
+            Some(size) => *size,
+            None => {
+                let size = store::get(&state.store_id, &content_ref)?.len() as u64;
+                sizes.insert(content_ref.hash, size);
+                size
+            }
+        };
+        labels += 1;
+        bytes += size;
+    }
+    let history_usage = history::usage(state)?;
+    Ok(UsageSample {
+        day,
+        taken_at: state.clock_ms,
+        labels,
+        bytes,
+        unique_bytes: sizes.values().sum(),
+        stored_bytes: store::calculate_total_size(&state.store_id)?,
+        history_bytes: history_usage.index_bytes + history_usage.retained_bytes,
+    })
+}
+
+/// Take today's sample if it has not been taken yet; called by the task
+/// runner. Returns whether a sample was recorded.
+pub fn run_due(state: &mut StoreViewerState) -> Result<bool, String> {
+    let today = state.clock_ms - state.clock_ms % DAY_MS;
+    if state.timeline.last_sampled_day.is_some_and(|day| day >= today) {
+        return Ok(false);
+    }
+    state.timeline.last_sampled_day = Some(today);
+
+    let mut timeline = load(&state.store_id)?;
+    timeline.samples.push(sample(state, today)?);
+    let excess = timeline.samples.len().saturating_sub(MAX_SAMPLES);
+    timeline.samples.drain(..excess);
+
+    let bytes = serde_json::to_vec(&timeline)
+        .map_err(|e| format!("Failed to serialize usage timeline: {}", e))?;
+    store::store_at_label(&state.store_id, &timeline_label(), &bytes)?;
+    Ok(true)
+}
+
+/// `GET /api/stats/timeline?since=<time>`
+pub fn handle_timeline(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Getting usage timeline");
+
+    let since = match query_param(&req.uri, "since") {
+        None => 0,
+        Some(since) => match clock::parse_time(&since) {
+            Some(ms) => ms,
+            None => return Ok(error_response(400, &format!("Invalid since time: {}", since))),
+        },
+    };
+
+    let mut samples = load(&state.store_id)?.samples;
+    samples.retain(|sample| sample.day + DAY_MS > since);
+
+    let body = serde_json::to_vec(&TimelineResponse { label: timeline_label(), samples })
+        .map_err(|e| format!("Failed to serialize usage timeline: {}", e))?;
+
+    Ok(json_response(200, body))
+}
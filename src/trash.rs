@@ -0,0 +1,258 @@
+//! Deferred deletion.
+//!
+//! Deleting a label does not remove its content straight away: the label is
+//! moved under `__system/trash/` and kept there for a grace period, during
+//! which `POST /api/labels/{name}/undelete` puts it back. The task runner
+//! purges labels whose grace period has passed. The name is free for new
+//! labels as soon as it is deleted, so a name can have several deletions
+//! pending at once; each keeps its content under its own id.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::dryrun;
+use crate::events;
+use crate::journal::{self, Step};
+use crate::stores;
+use crate::undo;
+use crate::{
+    delete_label, error_response, json_response, labelmeta::LabelMeta, query_param, StoreViewerState, SYSTEM_LABEL_PREFIX,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Grace period when none is configured: one hour.
+pub const DEFAULT_GRACE_MS: u64 = 60 * 60 * 1000;
+
+fn default_grace_ms() -> u64 {
+    DEFAULT_GRACE_MS
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingDeletion {
+    /// Tells deletions of the same name apart; `None` for a deletion trashed
+    /// before names could have several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub content_ref: String,
+    pub deleted_at: u64,
+    pub purge_at: u64,
+    /// Language and encoding to restore with the label
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<LabelMeta>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Trash {
+    /// How long deleted labels can be restored; 0 deletes immediately
+    #[serde(default = "default_grace_ms")]
+    pub grace_ms: u64,
+    /// Deletions awaiting purge by their original name, oldest first
+    #[serde(default, deserialize_with = "pending_lists")]
+    pub pending: BTreeMap<String, Vec<PendingDeletion>>,
+    #[serde(default)]
+    pub next_id: u64,
+}
+
+impl Default for Trash {
+    fn default() -> Self {
+        Trash { grace_ms: DEFAULT_GRACE_MS, pending: BTreeMap::new(), next_id: 0 }
+    }
+}
+
+/// Accept the single deletion per name kept before names could have several.
+fn pending_lists<'de, D>(deserializer: D) -> Result<BTreeMap<String, Vec<PendingDeletion>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Pending {
+        List(Vec<PendingDeletion>),
+        Single(PendingDeletion),
+    }
+
+    let pending = BTreeMap::<String, Pending>::deserialize(deserializer)?;
+    Ok(pending
+        .into_iter()
+        .map(|(label, pending)| match pending {
+            Pending::List(list) => (label, list),
+            Pending::Single(single) => (label, vec![single]),
+        })
+        .collect())
+}
+
+#[derive(Serialize)]
+struct PendingEntry<'a> {
+    label: &'a str,
+    #[serde(flatten)]
+    pending: &'a PendingDeletion,
+}
+
+/// Label holding a deleted label's content until it is purged.
+fn trash_label(label: &str, id: Option<u64>) -> String {
+    match id {
+        Some(id) => format!("{}trash/{}/{}", SYSTEM_LABEL_PREFIX, id, label),
+        None => format!("{}trash/{}", SYSTEM_LABEL_PREFIX, label),
+    }
+}
+
+/// Delete `label`, which points at `content_ref`. With a grace period the
+/// label is moved to the trash and the time it will be purged is returned.
+pub fn delete(state: &mut StoreViewerState, operation: &str, label: &str, content_ref: &ContentRef) -> Result<Option<u64>, String> {
+    // The trash is kept in the viewer's own store only
+    if state.trash.grace_ms == 0 || !stores::is_home(state) {
+        delete_label(state, operation, label)?;
+        return Ok(None);
+    }
+
+    let id = state.trash.next_id;
+    let trashed = trash_label(label, Some(id));
+    let steps = vec![Step::point(&trashed, content_ref), Step::delete(label)];
+    let journal_id = journal::begin(state, operation, steps)?;
+    store::replace_at_label(&state.store_id, &trashed, content_ref)?;
+    store::remove_label(&state.store_id, label)?;
+    journal::complete(state, journal_id)?;
+
+    let purge_at = state.clock_ms + state.trash.grace_ms;
+    let pending = PendingDeletion {
+        id: Some(id),
+        content_ref: content_ref.hash.clone(),
+        deleted_at: state.clock_ms,
+        purge_at,
+        meta: state.label_meta.remove(label),
+    };
+    state.trash.next_id += 1;
+    state.trash.pending.entry(label.to_string()).or_default().push(pending);
+    state.stats.rename(label, &trashed);
+    state.archived.remove(label);
+    undo::record(state, label, Some(content_ref), None);
+    events::label_deleted(state, label, Some(&content_ref.hash));
+    Ok(Some(purge_at))
+}
+
+/// The latest deletion of `label` that removed `content_ref` and still
+/// awaits purge.
+pub fn pending_with_ref<'a>(state: &'a StoreViewerState, label: &str, content_ref: &str) -> Option<&'a PendingDeletion> {
+    state
+        .trash
+        .pending
+        .get(label)?
+        .iter()
+        .rev()
+        .find(|pending| pending.content_ref == content_ref)
+}
+
+/// Move a deleted label back out of the trash.
+pub fn restore(state: &mut StoreViewerState, label: &str, pending: &PendingDeletion) -> Result<(), String> {
+    let trashed = trash_label(label, pending.id);
+    let content_ref = ContentRef { hash: pending.content_ref.clone() };
+    let steps = vec![Step::point(label, &content_ref), Step::delete(&trashed)];
+    let journal_id = journal::begin(state, "undelete", steps)?;
+    store::replace_at_label(&state.store_id, label, &content_ref)?;
+    store::remove_label(&state.store_id, &trashed)?;
+    journal::complete(state, journal_id)?;
+
+    forget(state, label, pending.id);
+    if let Some(meta) = pending.meta.clone() {
+        state.label_meta.insert(label.to_string(), meta);
+    }
+    state.stats.rename(&trashed, label);
+    undo::record(state, label, None, Some(&content_ref));
+    events::label_written(state, label, None, &content_ref);
+    Ok(())
+}
+
+/// Drop the pending deletion `id` of `label`.
+fn forget(state: &mut StoreViewerState, label: &str, id: Option<u64>) {
+    if let Some(list) = state.trash.pending.get_mut(label) {
+        list.retain(|pending| pending.id != id);
+        if list.is_empty() {
+            state.trash.pending.remove(label);
+        }
+    }
+}
+
+/// Purge labels whose grace period has passed; called by the task runner.
+/// Returns how many were purged.
+pub fn run_due(state: &mut StoreViewerState) -> Result<usize, String> {
+    let now = state.clock_ms;
+    let due: Vec<(String, Option<u64>)> = state
+        .trash
+        .pending
+        .iter()
+        .flat_map(|(label, list)| list.iter().map(move |pending| (label, pending)))
+        .filter(|(_, pending)| pending.purge_at <= now)
+        .map(|(label, pending)| (label.clone(), pending.id))
+        .collect();
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    let steps = due.iter().map(|(label, id)| Step::delete(&trash_label(label, *id))).collect();
+    let journal_id = journal::begin(state, "purge", steps)?;
+    for (label, id) in &due {
+        let trashed = trash_label(label, *id);
+        store::remove_label(&state.store_id, &trashed)?;
+        state.stats.remove(&trashed);
+        forget(state, label, *id);
+        log(&format!("Purged deleted label {}", label));
+    }
+    journal::complete(state, journal_id)?;
+    Ok(due.len())
+}
+
+/// `POST /api/labels/{name}/undelete`, the latest deletion of the name
+/// unless `?id=` picks another.
+pub fn handle_undelete(state: &mut StoreViewerState, label_name: &str, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log(&format!("Restoring deleted label: {}", label_name));
+
+    let id = match query_param(&req.uri, "id").map(|id| id.parse::<u64>()) {
+        None => None,
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => return Ok(error_response(400, "id must be the number of a pending deletion")),
+    };
+    let list = state.trash.pending.get(label_name).map(Vec::as_slice).unwrap_or_default();
+    let pending = match id {
+        Some(id) => list.iter().find(|pending| pending.id == Some(id)),
+        None => list.last(),
+    };
+    let pending = match pending {
+        Some(pending) => pending.clone(),
+        None => return Ok(error_response(404, &format!("No pending deletion of {}", label_name))),
+    };
+    if store::get_by_label(&state.store_id, label_name)?.is_some() {
+        return Ok(error_response(409, &format!("A label named {} exists again; move it first", label_name)));
+    }
+
+    if dryrun::is_dry_run(req) {
+        let content_ref = ContentRef { hash: pending.content_ref.clone() };
+        let trashed = trash_label(label_name, pending.id);
+        let changes = dryrun::plan_move(&state.store_id, &trashed, label_name, &content_ref)?;
+        return dryrun::dry_run_response(&changes);
+    }
+
+    restore(state, label_name, &pending)?;
+
+    log(&format!("Restored deleted label: {}", label_name));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+/// `GET /api/trash`: deleted labels that can still be restored.
+pub fn handle_list_pending(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing pending deletions");
+
+    let entries: Vec<PendingEntry> = state
+        .trash
+        .pending
+        .iter()
+        .flat_map(|(label, list)| list.iter().map(move |pending| PendingEntry { label, pending }))
+        .collect();
+
+    let body = serde_json::to_vec(&entries)
+        .map_err(|e| format!("Failed to serialize pending deletions: {}", e))?;
+
+    Ok(json_response(200, body))
+}
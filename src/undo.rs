@@ -0,0 +1,259 @@
+//! Per-session undo.
+//!
+//! Every request that changes labels is remembered for the session it came
+//! from, named by its `X-Session-Id` header or else its API key: for each
+//! label touched, the ref it pointed at before and after. `POST /api/undo`
+//! reverts the session's most recent change by pointing each label back at
+//! its previous ref, provided none of them has been changed since. Only the
+//! last few changes per session are kept; this is a safety net for
+//! interactive edits rather than a version history.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::dryrun::{self, ChangeAction, PlannedChange};
+use crate::events;
+use crate::journal::{self, Step};
+use crate::stores;
+use crate::{delete_label, error_response, header, json_response, trash, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Changes remembered per session.
+const MAX_ENTRIES: usize = 20;
+
+/// Sessions remembered; the least recently active is forgotten first.
+const MAX_SESSIONS: usize = 200;
+
+/// Longest session id accepted.
+const MAX_SESSION_ID_LEN: usize = 128;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LabelChange {
+    pub label: String,
+    /// Ref before the change; `None` when the change created the label
+    pub previous_ref: Option<String>,
+    /// Ref after the change; `None` when the change deleted the label
+    pub current_ref: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Mutation {
+    /// Method and path of the request that made the change
+    pub operation: String,
+    pub at: u64,
+    pub changes: Vec<LabelChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Session {
+    last_active: u64,
+    /// Oldest first
+    entries: VecDeque<Mutation>,
+}
+
+#[derive(Debug, Clone)]
+struct Active {
+    session: String,
+    mutation: Mutation,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UndoHistory {
+    #[serde(default)]
+    sessions: BTreeMap<String, Session>,
+    /// The request being handled, while it is being handled
+    #[serde(skip)]
+    active: Option<Active>,
+}
+
+#[derive(Serialize)]
+struct HistoryResponse<'a> {
+    session: &'a str,
+    /// Newest first
+    entries: Vec<&'a Mutation>,
+}
+
+#[derive(Serialize)]
+struct UndoResponse {
+    undone: Mutation,
+    remaining: usize,
+}
+
+/// The session a request belongs to, if it names one.
+fn session_id(req: &HttpRequest) -> Option<&str> {
+    header(req, "x-session-id")
+        .or_else(|| header(req, "x-api-key"))
+        .or_else(|| header(req, "authorization").and_then(|value| value.strip_prefix("Bearer ")))
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_SESSION_ID_LEN)
+}
+
+/// Start remembering the changes the request makes.
+pub fn begin(state: &mut StoreViewerState, req: &HttpRequest, method: &str, path: &str) {
+    state.undo.active = session_id(req).map(|session| Active {
+        session: session.to_string(),
+        mutation: Mutation {
+            operation: format!("{} {}", method, path),
+            at: state.clock_ms,
+            changes: Vec::new(),
+        },
+    });
+}
+
+/// Note that `label` went from `previous` to `current` during the request.
+pub fn record(state: &mut StoreViewerState, label: &str, previous: Option<&ContentRef>, current: Option<&ContentRef>) {
+    if !stores::is_home(state) {
+        return;
+    }
+    let active = match &mut state.undo.active {
+        Some(active) if !label.starts_with(SYSTEM_LABEL_PREFIX) => active,
+        _ => return,
+    };
+    let current = current.map(|r| r.hash.clone());
+    let changes = &mut active.mutation.changes;
+    // A label changed twice in one request is undone to its first state
+    match changes.iter_mut().find(|change| change.label == label) {
+        Some(change) => change.current_ref = current,
+        None => changes.push(LabelChange {
+            label: label.to_string(),
+            previous_ref: previous.map(|r| r.hash.clone()),
+            current_ref: current,
+        }),
+    }
+}
+
+/// Keep the changes the request made, if any, as the session's latest.
+pub fn finish(state: &mut StoreViewerState) {
+    let Active { session, mut mutation } = match state.undo.active.take() {
+        Some(active) => active,
+        None => return,
+    };
+    mutation.changes.retain(|change| change.previous_ref != change.current_ref);
+    if mutation.changes.is_empty() {
+        return;
+    }
+
+    let sessions = &mut state.undo.sessions;
+    if !sessions.contains_key(&session) && sessions.len() >= MAX_SESSIONS {
+        let idle = sessions.iter().min_by_key(|(_, s)| s.last_active).map(|(id, _)| id.clone());
+        if let Some(idle) = idle {
+            sessions.remove(&idle);
+        }
+    }
+    let entry = sessions.entry(session).or_default();
+    entry.last_active = mutation.at;
+    if entry.entries.len() >= MAX_ENTRIES {
+        entry.entries.pop_front();
+    }
+    entry.entries.push_back(mutation);
+}
+
+/// Point `label` back at `previous`, or remove it when it did not exist.
+fn revert(state: &mut StoreViewerState, change: &LabelChange) -> Result<(), String> {
+    let label = change.label.as_str();
+    let previous = match &change.previous_ref {
+        Some(hash) => ContentRef { hash: hash.clone() },
+        None => return delete_label(state, "undo", label),
+    };
+    // A deleted label still in the trash is restored with its metadata
+    if change.current_ref.is_none() {
+        if let Some(pending) = trash::pending_with_ref(state, label, &previous.hash).cloned() {
+            trash::restore(state, label, &pending)?;
+            return Ok(());
+        }
+    }
+
+    let journal_id = journal::begin(state, "undo", vec![Step::point(label, &previous)])?;
+    store::replace_at_label(&state.store_id, label, &previous)?;
+    journal::complete(state, journal_id)?;
+    state.stats.record_write(label, state.clock_ms);
+    events::label_written(state, label, change.current_ref.as_deref(), &previous);
+    Ok(())
+}
+
+/// `GET /api/undo`: the changes the session can undo.
+pub fn handle_history(state: &StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Getting undo history");
+
+    let session = match session_id(req) {
+        Some(session) => session,
+        None => return Ok(error_response(400, "Undo history needs an X-Session-Id header or API key")),
+    };
+    let entries = state
+        .undo
+        .sessions
+        .get(session)
+        .map(|s| s.entries.iter().rev().collect())
+        .unwrap_or_default();
+
+    let body = serde_json::to_vec(&HistoryResponse { session, entries })
+        .map_err(|e| format!("Failed to serialize undo history: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/undo`: revert the session's most recent change.
+pub fn handle_undo(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Undoing last change");
+
+    // Reverting is not itself a change that can be undone
+    state.undo.active = None;
+
+    let session = match session_id(req) {
+        Some(session) => session.to_string(),
+        None => return Ok(error_response(400, "Undo needs an X-Session-Id header or API key")),
+    };
+    let mutation = match state.undo.sessions.get(&session).and_then(|s| s.entries.back()) {
+        Some(mutation) => mutation.clone(),
+        None => return Ok(error_response(404, "Nothing to undo")),
+    };
+
+    for change in &mutation.changes {
+        let current = store::get_by_label(&state.store_id, &change.label)?.map(|r| r.hash);
+        if current != change.current_ref {
+            return Ok(error_response(409, &format!(
+                "Label {} has changed since {}; nothing was undone",
+                change.label, mutation.operation
+            )));
+        }
+    }
+
+    if dryrun::is_dry_run(req) {
+        let mut changes = Vec::new();
+        for change in mutation.changes.iter().rev() {
+            let planned = match (&change.previous_ref, &change.current_ref) {
+                (None, Some(current)) => dryrun::plan_delete(&state.store_id, &change.label, &ContentRef { hash: current.clone() })?,
+                (previous, current) => PlannedChange {
+                    label: change.label.clone(),
+                    action: if current.is_some() { ChangeAction::Alias } else { ChangeAction::Create },
+                    size_bytes: None,
+                    content_ref: previous.clone(),
+                    previous_ref: current.clone(),
+                    previous_size_bytes: None,
+                },
+            };
+            changes.push(planned);
+        }
+        return dryrun::dry_run_response(&changes);
+    }
+
+    // Later changes may depend on earlier ones, so revert newest first
+    for change in mutation.changes.iter().rev() {
+        revert(state, change)?;
+    }
+    let remaining = match state.undo.sessions.get_mut(&session) {
+        Some(s) => {
+            s.entries.pop_back();
+            s.entries.len()
+        }
+        None => 0,
+    };
+
+    log(&format!("Undid {} ({} labels)", mutation.operation, mutation.changes.len()));
+
+    let body = serde_json::to_vec(&UndoResponse { undone: mutation, remaining })
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    Ok(json_response(200, body))
+}
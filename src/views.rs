@@ -0,0 +1,239 @@
+//! Saved searches ("views").
+//!
+//! A view is a named label query that is stored in the actor state so that a
+//! curated slice of a large store can be bookmarked and re-run by anyone.
+//! The default view applies to `GET /api/labels` itself: the order, detail
+//! level and hidden prefixes a listing gets when the request does not say.
+
+use crate::bindings::theater::simple::http_framework::{HttpRequest, HttpResponse};
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::stats::{self, ListSort};
+use crate::{archival, frontmatter};
+use crate::{error_response, is_text_content, json_response, StoreViewerState, SYSTEM_LABEL_PREFIX};
+use serde::{Deserialize, Serialize};
+
+/// Filters and ordering applied to the label listing.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LabelQuery {
+    /// Only include labels starting with this prefix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Only include labels carrying this tag in their front matter's `tags`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Listing order, as accepted by `GET /api/labels?sort=`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    /// Only include labels whose content is of this format (`text` or `binary`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+impl LabelQuery {
+    /// Check that every field holds a value the query runner understands.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(sort) = &self.sort {
+            ListSort::parse(sort).ok_or_else(|| format!("Unknown sort: {}", sort))?;
+        }
+        if let Some(format) = &self.format {
+            if format != "text" && format != "binary" {
+                return Err(format!("Unknown format: {}", format));
+            }
+        }
+        if self.tag.as_deref() == Some("") {
+            return Err("tag cannot be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Run a (validated) query against the store and return matching label names.
+/// Like the label listing, it leaves out the viewer's own and archived labels.
+pub fn run_query(state: &StoreViewerState, query: &LabelQuery) -> Result<Vec<String>, String> {
+    let mut labels = store::list_labels(&state.store_id)?;
+    labels.retain(|label| !label.starts_with(SYSTEM_LABEL_PREFIX) && !archival::is_archived(state, label));
+
+    if let Some(prefix) = &query.prefix {
+        labels.retain(|label| label.starts_with(prefix.as_str()));
+    }
+
+    if query.format.is_some() || query.tag.is_some() {
+        let want_text = query.format.as_deref().map(|format| format == "text");
+        let mut matching = Vec::with_capacity(labels.len());
+        for label in labels {
+            if let Some(content_ref) = store::get_by_label(&state.store_id, &label)? {
+                let content = store::get(&state.store_id, &content_ref)?;
+                if want_text.is_some_and(|want_text| is_text_content(&content) != want_text) {
+                    continue;
+                }
+                // As `?fm.tags=` matches in the listing
+                let tagged = |tag: &str| {
+                    frontmatter::extract(&content).is_some_and(|fm| frontmatter::field_matches(&fm, "tags", tag))
+                };
+                if query.tag.as_deref().is_some_and(|tag| !tagged(tag)) {
+                    continue;
+                }
+                matching.push(label);
+            }
+        }
+        labels = matching;
+    }
+
+    let sort = query
+        .sort
+        .as_deref()
+        .and_then(ListSort::parse)
+        .unwrap_or(ListSort::Name);
+    stats::sort_labels(&mut labels, sort, &state.stats);
+
+    Ok(labels)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveViewRequest {
+    pub name: String,
+    #[serde(flatten)]
+    pub query: LabelQuery,
+}
+
+#[derive(Serialize)]
+struct ViewSummary<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    query: &'a LabelQuery,
+}
+
+/// Listing settings used when a request to `GET /api/labels` leaves them out.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ListingDefaults {
+    /// Order, as accepted by `?sort=`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    /// Include sizes, refs and icons, as with `?details=true`
+    #[serde(default)]
+    pub details: bool,
+    /// Labels under these prefixes are left out unless asked for with
+    /// `?hidden=true` or a `?prefix=` inside them
+    #[serde(default)]
+    pub hidden_prefixes: Vec<String>,
+}
+
+impl ListingDefaults {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(sort) = &self.sort {
+            ListSort::parse(sort).ok_or_else(|| format!("Unknown sort: {}", sort))?;
+        }
+        if self.hidden_prefixes.iter().any(String::is_empty) {
+            return Err("hidden_prefixes cannot contain an empty prefix".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn sort(&self) -> Option<ListSort> {
+        self.sort.as_deref().and_then(ListSort::parse)
+    }
+
+    /// Whether a listing under `prefix` should leave `label` out.
+    pub fn hides(&self, label: &str, prefix: Option<&str>) -> bool {
+        self.hidden_prefixes.iter().any(|hidden| {
+            label.starts_with(hidden.as_str()) && !prefix.is_some_and(|prefix| prefix.starts_with(hidden.as_str()))
+        })
+    }
+}
+
+/// `GET /api/admin/listing`
+pub fn handle_get_defaults(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Getting listing defaults");
+
+    let body = serde_json::to_vec(&state.listing)
+        .map_err(|e| format!("Failed to serialize listing defaults: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+/// `POST /api/admin/listing` with `{ "sort"?, "details"?, "hidden_prefixes"? }`
+pub fn handle_put_defaults(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving listing defaults");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let defaults: ListingDefaults = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if let Err(e) = defaults.validate() {
+        return Ok(error_response(400, &e));
+    }
+
+    state.listing = defaults;
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+pub fn handle_list_views(state: &StoreViewerState) -> Result<HttpResponse, String> {
+    log("Listing saved views");
+
+    let views: Vec<ViewSummary> = state
+        .views
+        .iter()
+        .map(|(name, query)| ViewSummary { name, query })
+        .collect();
+
+    let body = serde_json::to_vec(&views)
+        .map_err(|e| format!("Failed to serialize views: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_save_view(state: &mut StoreViewerState, req: &HttpRequest) -> Result<HttpResponse, String> {
+    log("Saving view");
+
+    let body = req.body.as_ref().ok_or("Request body is required")?;
+
+    let save_req: SaveViewRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if save_req.name.is_empty() || save_req.name.contains('/') {
+        return Ok(error_response(400, "View name must be non-empty and contain no '/'"));
+    }
+
+    if let Err(e) = save_req.query.validate() {
+        return Ok(error_response(400, &e));
+    }
+
+    state.views.insert(save_req.name.clone(), save_req.query);
+
+    log(&format!("Saved view: {}", save_req.name));
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
+
+pub fn handle_run_view(state: &StoreViewerState, view_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Running view: {}", view_name));
+
+    let query = match state.views.get(view_name) {
+        Some(query) => query,
+        None => return Ok(error_response(404, &format!("View not found: {}", view_name))),
+    };
+
+    let labels = run_query(state, query)?;
+
+    let body = serde_json::to_vec(&labels)
+        .map_err(|e| format!("Failed to serialize labels: {}", e))?;
+
+    Ok(json_response(200, body))
+}
+
+pub fn handle_delete_view(state: &mut StoreViewerState, view_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Deleting view: {}", view_name));
+
+    if state.views.remove(view_name).is_none() {
+        return Ok(error_response(404, &format!("View not found: {}", view_name)));
+    }
+
+    let success_json = r#"{"success":true}"#;
+    Ok(json_response(200, success_json.as_bytes().to_vec()))
+}
@@ -0,0 +1,212 @@
+//! Inspection of WebAssembly modules and components.
+//!
+//! Labels whose content starts with the WASM magic bytes are recognised as
+//! binaries (Theater actors are stored as components) and can be parsed to
+//! list what they import and export and which custom sections they carry.
+//! Only the top level of a component is described; core modules and
+//! components nested inside it are counted but not expanded.
+
+use crate::bindings::theater::simple::http_framework::HttpResponse;
+use crate::bindings::theater::simple::runtime::log;
+use crate::bindings::theater::simple::store;
+use crate::{error_response, json_response, StoreViewerState};
+use serde::{Deserialize, Serialize};
+use wasmparser::{Encoding, ExternalKind, Parser, Payload, TypeRef};
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WasmKind {
+    Module,
+    Component,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WasmImport {
+    /// Module an import comes from (core modules only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    pub name: String,
+    pub kind: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WasmExport {
+    pub name: String,
+    pub kind: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CustomSection {
+    pub name: String,
+    pub size_bytes: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WasmInfo {
+    pub kind: WasmKind,
+    pub version: u16,
+    pub size_bytes: usize,
+    pub imports: Vec<WasmImport>,
+    pub exports: Vec<WasmExport>,
+    pub custom_sections: Vec<CustomSection>,
+    /// Core modules embedded in a component
+    pub nested_modules: usize,
+    /// Components embedded in a component
+    pub nested_components: usize,
+}
+
+/// Whether content looks like a WebAssembly binary.
+pub fn is_wasm(bytes: &[u8]) -> bool {
+    bytes.starts_with(WASM_MAGIC)
+}
+
+/// Kind of binary declared by the header, without parsing the sections.
+pub fn detect(bytes: &[u8]) -> Option<WasmKind> {
+    if !is_wasm(bytes) || bytes.len() < 8 {
+        return None;
+    }
+    // The layout field follows the 16-bit version; 1 marks a component
+    match u16::from_le_bytes([bytes[6], bytes[7]]) {
+        0 => Some(WasmKind::Module),
+        1 => Some(WasmKind::Component),
+        _ => None,
+    }
+}
+
+fn core_kind(kind: ExternalKind) -> &'static str {
+    match kind {
+        ExternalKind::Func | ExternalKind::FuncExact => "func",
+        ExternalKind::Table => "table",
+        ExternalKind::Memory => "memory",
+        ExternalKind::Global => "global",
+        ExternalKind::Tag => "tag",
+    }
+}
+
+fn type_ref_kind(ty: &TypeRef) -> &'static str {
+    match ty {
+        TypeRef::Func(_) | TypeRef::FuncExact(_) => "func",
+        TypeRef::Table(_) => "table",
+        TypeRef::Memory(_) => "memory",
+        TypeRef::Global(_) => "global",
+        TypeRef::Tag(_) => "tag",
+    }
+}
+
+/// Parse a module or component and describe its top-level interface.
+pub fn inspect(bytes: &[u8]) -> Result<WasmInfo, String> {
+    let mut info = WasmInfo {
+        kind: WasmKind::Module,
+        version: 0,
+        size_bytes: bytes.len(),
+        imports: Vec::new(),
+        exports: Vec::new(),
+        custom_sections: Vec::new(),
+        nested_modules: 0,
+        nested_components: 0,
+    };
+
+    // Payloads of nested modules and components are yielded inline, between
+    // their section payload and a matching `End`
+    let mut depth = 0usize;
+
+    for payload in Parser::new(0).parse_all(bytes) {
+        let payload = payload.map_err(|e| format!("Invalid WebAssembly: {}", e))?;
+        match payload {
+            Payload::ModuleSection { .. } => {
+                if depth == 0 {
+                    info.nested_modules += 1;
+                }
+                depth += 1;
+            }
+            Payload::ComponentSection { .. } => {
+                if depth == 0 {
+                    info.nested_components += 1;
+                }
+                depth += 1;
+            }
+            Payload::End(_) => depth = depth.saturating_sub(1),
+            _ if depth > 0 => {}
+            Payload::Version { num, encoding, .. } => {
+                info.version = num;
+                info.kind = match encoding {
+                    Encoding::Module => WasmKind::Module,
+                    Encoding::Component => WasmKind::Component,
+                };
+            }
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    let import = import.map_err(|e| format!("Invalid import section: {}", e))?;
+                    info.imports.push(WasmImport {
+                        module: Some(import.module.to_string()),
+                        name: import.name.to_string(),
+                        kind: type_ref_kind(&import.ty),
+                    });
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export.map_err(|e| format!("Invalid export section: {}", e))?;
+                    info.exports.push(WasmExport {
+                        name: export.name.to_string(),
+                        kind: core_kind(export.kind),
+                    });
+                }
+            }
+            Payload::ComponentImportSection(reader) => {
+                for import in reader {
+                    let import = import.map_err(|e| format!("Invalid component import section: {}", e))?;
+                    info.imports.push(WasmImport {
+                        module: None,
+                        name: import.name.full_name().into_owned(),
+                        kind: import.ty.kind().desc(),
+                    });
+                }
+            }
+            Payload::ComponentExportSection(reader) => {
+                for export in reader {
+                    let export = export.map_err(|e| format!("Invalid component export section: {}", e))?;
+                    info.exports.push(WasmExport {
+                        name: export.name.full_name().into_owned(),
+                        kind: export.kind.desc(),
+                    });
+                }
+            }
+            Payload::CustomSection(reader) => {
+                info.custom_sections.push(CustomSection {
+                    name: reader.name().to_string(),
+                    size_bytes: reader.data().len(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(info)
+}
+
+pub fn handle_wasm_info(state: &StoreViewerState, label_name: &str) -> Result<HttpResponse, String> {
+    log(&format!("Inspecting WebAssembly label: {}", label_name));
+
+    let content_ref = match store::get_by_label(&state.store_id, label_name)? {
+        Some(content_ref) => content_ref,
+        None => return Ok(error_response(404, &format!("Label not found: {}", label_name))),
+    };
+    let content = store::get(&state.store_id, &content_ref)?;
+
+    if !is_wasm(&content) {
+        return Ok(error_response(415, &format!("Label {} is not a WebAssembly binary", label_name)));
+    }
+
+    let info = match inspect(&content) {
+        Ok(info) => info,
+        Err(e) => return Ok(error_response(422, &e)),
+    };
+
+    let body = serde_json::to_vec(&info)
+        .map_err(|e| format!("Failed to serialize WebAssembly info: {}", e))?;
+
+    Ok(json_response(200, body))
+}